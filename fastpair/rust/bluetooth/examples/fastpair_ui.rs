@@ -20,26 +20,70 @@ use std::{
     thread,
 };
 
+use async_trait::async_trait;
 use futures::{
     executor::{self, block_on},
     lock::Mutex,
+    StreamExt,
 };
 
 extern crate bluetooth;
 
 use bluetooth::{
     api::{BleAdapter, BleDevice, ClassicDevice},
-    BleDataTypeId, ClassicAddress, Platform,
+    BleAddress, BtUuid, ClassicAddress, PairingHandler, ScanFilter,
+    ScanSettings,
 };
 
-async fn get_user_input(
-    device_vec: Arc<Mutex<Vec<impl BleDevice>>>,
+/// Answers pairing requests on the console.
+struct ConsolePairingHandler;
+
+impl ConsolePairingHandler {
+    /// Print `prompt` and read the user's answer, or `None` once stdin is
+    /// closed.
+    fn ask(prompt: &str) -> Option<String> {
+        print!("{} ", prompt);
+        io::stdout().flush().ok()?;
+        let mut buffer = String::new();
+        match io::stdin().read_line(&mut buffer) {
+            Ok(0) | Err(_) => None,
+            Ok(_) => Some(String::from(buffer.trim())),
+        }
+    }
+}
+
+#[async_trait]
+impl PairingHandler for ConsolePairingHandler {
+    async fn confirm(&self) -> bool {
+        Self::ask("Pair with the device? [y/n]").is_some_and(|a| a == "y")
+    }
+
+    async fn display_pin(&self, pin: &str) {
+        println!("Enter PIN {} on the device.", pin);
+    }
+
+    async fn provide_pin(&self) -> Option<String> {
+        Self::ask("Enter the PIN displayed by the device:")
+    }
+
+    async fn confirm_pin_match(&self, pin: &str) -> bool {
+        Self::ask(&format!("Does the device display PIN {}? [y/n]", pin))
+            .is_some_and(|a| a == "y")
+    }
+}
+
+async fn get_user_input<A: BleAdapter>(
+    adapter: Arc<A>,
+    addr_vec: Arc<Mutex<Vec<BleAddress>>>,
 ) -> Result<(), Box<dyn Error>> {
     let mut buffer = String::new();
     loop {
         io::stdout().flush()?;
         buffer.clear();
-        io::stdin().read_line(&mut buffer)?;
+        // Stop reading once stdin is closed, e.g. when running headless.
+        if io::stdin().read_line(&mut buffer)? == 0 {
+            break Ok(());
+        }
 
         let val = match buffer.trim().parse::<usize>() {
             Ok(val) => val,
@@ -49,16 +93,16 @@ async fn get_user_input(
             }
         };
 
-        let index_to_device = device_vec.lock().await;
-        match index_to_device.get(val) {
-            Some(device) => {
-                let addr = device.address();
+        let index_to_addr = addr_vec.lock().await;
+        match index_to_addr.get(val) {
+            Some(&addr) => {
                 let classic_addr = ClassicAddress::try_from(addr)?;
 
                 let classic_device =
-                    Platform::new_classic_device(classic_addr).await?;
+                    adapter.classic_device(classic_addr).await?;
 
-                match classic_device.pair().await {
+                match classic_device.pair(Arc::new(ConsolePairingHandler)).await
+                {
                     Ok(_) => {
                         println!("Pairing success!");
                     }
@@ -71,48 +115,88 @@ async fn get_user_input(
     }
 }
 
-fn main() -> Result<(), Box<dyn Error>> {
-    let run = async {
-        let mut adapter = Platform::default_adapter().await?;
-        adapter.start_scan()?;
+/// Populate the simulated radio with a Fast Pair device and open its adapter,
+/// so that the example can run headless with `--features sim`. The radio is
+/// switched off after a second, which ends the scan.
+#[cfg(feature = "sim")]
+fn simulate() -> bluetooth::sim::BleAdapter {
+    use std::time::Duration;
 
-        let mut addr_set = HashSet::new();
-        let device_vec = Arc::new(Mutex::new(Vec::new()));
+    use bluetooth::{
+        sim::{SimPeripheral, SimRadio},
+        BleAddress, BleAddressKind, BleAdvertisement,
+    };
 
-        {
-            // Process user input in a separate thread.
-            let device_vec = device_vec.clone();
-            thread::spawn(|| block_on(get_user_input(device_vec)).unwrap());
-        }
+    let radio = SimRadio::global();
+    let addr = BleAddress::new(0x112233445566, BleAddressKind::Public);
+    radio.add_peripheral(SimPeripheral::new(addr, "Simulated Buds"));
 
-        let mut counter: u32 = 0;
-        let datatype_selector = vec![BleDataTypeId::ServiceData16BitUuid];
-        // Retrieve incoming device advertisements.
-        while let Ok(advertisement) =
-            adapter.next_advertisement(Some(&datatype_selector)).await
-        {
-            for service_data in advertisement.service_data_16bit_uuid()? {
-                let uuid = service_data.uuid();
-
-                // This is a Fast Pair device.
-                if uuid == 0x2cfe {
-                    let addr = advertisement.address();
-                    let ble_device = Platform::new_ble_device(addr).await?;
-                    let name = ble_device.name()?;
-
-                    if addr_set.insert(addr) {
-                        // New FP device discovered.
-                        println!("{}: {}", counter, name);
-                        device_vec.lock().await.push(ble_device);
-                        counter += 1;
-                    }
-                    break;
-                }
-            }
+    // Fast Pair service data followed by a TX Power Level of -20 dBm.
+    let adv_data = [0x06, 0x16, 0x2c, 0xfe, 0xAA, 0xBB, 0xCC, 0x02, 0x0a, 0xec];
+    let advertisement =
+        BleAdvertisement::from_raw(addr, Some(-60), &adv_data, &[]).unwrap();
+    radio.schedule_advertisement(Duration::from_millis(100), advertisement);
+
+    thread::spawn(|| {
+        thread::sleep(Duration::from_secs(1));
+        SimRadio::global().set_powered(false);
+    });
+
+    radio.adapter()
+}
+
+/// Scan for Fast Pair devices with `adapter`, listing them on the console,
+/// and pair with the one the user picks.
+async fn run<A>(adapter: A) -> Result<(), Box<dyn Error>>
+where
+    A: BleAdapter + Send + Sync + 'static,
+{
+    let adapter = Arc::new(adapter);
+
+    // Only keep advertisements of Fast Pair devices.
+    let filter = ScanFilter::default().with_service_data(
+        BtUuid::FAST_PAIR,
+        Vec::new(),
+        None,
+    );
+    let mut scan =
+        adapter.start_scan(&filter, &ScanSettings::default(), &[])?;
+
+    let mut addr_set = HashSet::new();
+    let addr_vec = Arc::new(Mutex::new(Vec::new()));
+
+    {
+        // Process user input in a separate thread.
+        let adapter = adapter.clone();
+        let addr_vec = addr_vec.clone();
+        thread::spawn(move || {
+            block_on(get_user_input(adapter, addr_vec)).unwrap()
+        });
+    }
+
+    let mut counter: u32 = 0;
+    // Retrieve incoming device advertisements.
+    while let Some(Ok(advertisement)) = scan.next().await {
+        let addr = advertisement.address();
+        if addr_set.insert(addr) {
+            // New FP device discovered.
+            let ble_device = adapter.ble_device(addr).await?;
+            println!("{}: {}", counter, ble_device.name()?);
+            addr_vec.lock().await.push(addr);
+            counter += 1;
         }
-        println!("Done scanning");
-        Ok(())
-    };
+    }
+    println!("Done scanning");
+    Ok(())
+}
 
-    executor::block_on(run)
+fn main() -> Result<(), Box<dyn Error>> {
+    executor::block_on(async {
+        #[cfg(feature = "sim")]
+        let adapter = simulate();
+        #[cfg(not(feature = "sim"))]
+        let adapter = bluetooth::Platform::default_adapter().await?;
+
+        run(adapter).await
+    })
 }
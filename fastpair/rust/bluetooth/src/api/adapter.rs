@@ -14,24 +14,80 @@
 
 use async_trait::async_trait;
 
-use crate::common::{BleAdvertisement, BleDataTypeId, BluetoothError};
+use super::{BleDevice, ClassicDevice};
+use crate::common::{
+    AdapterId, AdapterInfo, BleAddress, BleDataTypeId, BluetoothError,
+    BondEventStream, BondedDevice, ClassicAddress, RadioStateStream,
+    ScanFilter, ScanSettings, ScanStream,
+};
 
 /// Concrete types implementing this trait are Bluetooth Central devices.
 /// They provide methods for retrieving nearby connections and device info.
 #[async_trait]
 pub trait BleAdapter: Sized {
+    /// Type of the BLE devices opened through the adapter.
+    type BleDevice: BleDevice;
+
+    /// Type of the BT Classic devices opened through the adapter.
+    type ClassicDevice: ClassicDevice;
+
     /// Retrieve the system-default Bluetooth adapter.
     async fn default() -> Result<Self, BluetoothError>;
 
-    /// Begin scanning for nearby advertisements.
-    fn start_scan(&mut self) -> Result<(), BluetoothError>;
+    /// List every adapter of the system, default adapter first.
+    async fn adapters() -> Result<Vec<AdapterInfo>, BluetoothError>;
+
+    /// Open the adapter identified by `id`. Fails with
+    /// `BluetoothError::InvalidArgument` if there is no such adapter.
+    async fn with_id(id: &AdapterId) -> Result<Self, BluetoothError>;
+
+    /// Open the BLE device at `addr` through this adapter, like
+    /// `BleDevice::new()` does through the default adapter.
+    async fn ble_device(
+        &self,
+        addr: BleAddress,
+    ) -> Result<Self::BleDevice, BluetoothError>;
+
+    /// Open the BT Classic device at `addr` through this adapter, like
+    /// `ClassicDevice::new()` does through the default adapter.
+    async fn classic_device(
+        &self,
+        addr: ClassicAddress,
+    ) -> Result<Self::ClassicDevice, BluetoothError>;
+
+    /// Retrieve the capabilities of the adapter and the state of its radio.
+    async fn info(&self) -> Result<AdapterInfo, BluetoothError>;
+
+    /// Watch the radio of the adapter being switched on and off, e.g. from
+    /// the system settings.
+    async fn radio_state_changes(
+        &self,
+    ) -> Result<RadioStateStream, BluetoothError>;
+
+    /// Begin scanning for nearby advertisements with the given `settings`.
+    /// Each received advertisement matching `filter` is loaded with the data
+    /// types in `data_selector` and delivered through the returned stream.
+    /// Scanning stops once the stream is dropped. Scanning fails with
+    /// `BluetoothError::FailedPrecondition` while the radio is off, and the
+    /// stream yields that error then ends if the radio is switched off.
+    /// Backends that only start scanning once the stream is polled report
+    /// failures to start, including the radio being off, the same way.
+    fn start_scan(
+        &self,
+        filter: &ScanFilter,
+        settings: &ScanSettings,
+        data_selector: &[BleDataTypeId],
+    ) -> Result<ScanStream, BluetoothError>;
+
+    /// List the devices the adapter is bonded with, over LE or BT Classic.
+    async fn paired_devices(&self)
+        -> Result<Vec<BondedDevice>, BluetoothError>;
 
-    /// Stop scanning for nearby advertisements.
-    fn stop_scan(&mut self) -> Result<(), BluetoothError>;
+    /// Remove the bond with `device`, forgetting its keys. Succeeds if the
+    /// adapter isn't bonded with it.
+    async fn unpair(&self, device: BondedDevice) -> Result<(), BluetoothError>;
 
-    /// Poll next discovered device.
-    async fn next_advertisement(
-        &mut self,
-        data_selector: Option<&Vec<BleDataTypeId>>,
-    ) -> Result<BleAdvertisement, BluetoothError>;
+    /// Watch the bonds the adapter gains and loses, including ones made or
+    /// removed by other applications.
+    async fn bond_events(&self) -> Result<BondEventStream, BluetoothError>;
 }
@@ -0,0 +1,37 @@
+// Copyright 2023 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use async_trait::async_trait;
+
+use crate::common::{
+    AdvertisingData, AdvertisingSettings, AdvertisingStream, BluetoothError,
+};
+
+/// Concrete types implementing this trait are Bluetooth Peripheral devices.
+/// They publish advertisements, e.g. to emulate a Fast Pair provider.
+#[async_trait]
+pub trait BleAdvertiser: Sized {
+    /// Retrieve an advertiser on the system-default Bluetooth adapter.
+    async fn default() -> Result<Self, BluetoothError>;
+
+    /// Begin publishing an advertisement carrying `data`, with the given
+    /// `settings`. Fails with `BluetoothError::InvalidArgument` if `data`
+    /// doesn't fit in the advertisement. Status updates are delivered
+    /// through the returned stream, and advertising stops once it's dropped.
+    fn start_advertising(
+        &self,
+        data: &AdvertisingData,
+        settings: &AdvertisingSettings,
+    ) -> Result<AdvertisingStream, BluetoothError>;
+}
@@ -12,10 +12,15 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+use std::sync::Arc;
+
 use async_trait::async_trait;
 
 use crate::common::{
-    BleAddress, BluetoothError, ClassicAddress, PairingResult,
+    BleAddress, BluetoothError, BondStateStream, BtUuid, ClassicAddress,
+    ConnectionState, ConnectionStateStream, GattCharacteristic, GattService,
+    NotificationStream, PairingHandler, PairingResult, ProtectionLevel,
+    RfcommSocket, WriteType,
 };
 
 /// Concrete types implementing this trait represent BLE Peripheral devices.
@@ -33,6 +38,85 @@ pub trait BleDevice: Sized {
 
     /// Retrieve this device's Bluetooth address information.
     fn address(&self) -> BleAddress;
+
+    /// Connect to the device, which GATT operations require. Returns once
+    /// the device's services have been resolved.
+    async fn connect(&self) -> Result<(), BluetoothError>;
+
+    /// Disconnect from the device, ending every subscription.
+    async fn disconnect(&self) -> Result<(), BluetoothError>;
+
+    /// Discover the primary services of the device, only keeping the ones
+    /// identified by `uuid` if set.
+    async fn discover_services(
+        &self,
+        uuid: Option<BtUuid>,
+    ) -> Result<Vec<GattService>, BluetoothError>;
+
+    /// Discover the characteristics of `service`, only keeping the ones
+    /// identified by `uuid` if set.
+    async fn discover_characteristics(
+        &self,
+        service: &GattService,
+        uuid: Option<BtUuid>,
+    ) -> Result<Vec<GattCharacteristic>, BluetoothError>;
+
+    /// Read the value of `characteristic` from the device.
+    async fn read(
+        &self,
+        characteristic: &GattCharacteristic,
+    ) -> Result<Vec<u8>, BluetoothError>;
+
+    /// Write `value` to `characteristic`.
+    async fn write(
+        &self,
+        characteristic: &GattCharacteristic,
+        value: &[u8],
+        write_type: WriteType,
+    ) -> Result<(), BluetoothError>;
+
+    /// Subscribe to the values `characteristic` sends, with notifications if
+    /// it supports them and indications otherwise.
+    async fn subscribe(
+        &self,
+        characteristic: &GattCharacteristic,
+    ) -> Result<NotificationStream, BluetoothError>;
+
+    /// Retrieve the ATT MTU negotiated with the connected device. Platforms
+    /// negotiate the largest MTU both sides support when connecting.
+    async fn mtu(&self) -> Result<u16, BluetoothError>;
+
+    /// Pair and bond with the device over LE, so the link gets at least
+    /// `protection_level`, answering the requests of the pairing ceremony
+    /// with `handler`.
+    async fn pair(
+        &self,
+        protection_level: ProtectionLevel,
+        handler: Arc<dyn PairingHandler>,
+    ) -> Result<PairingResult, BluetoothError>;
+
+    /// Whether the platform is bonded with the device.
+    async fn is_paired(&self) -> Result<bool, BluetoothError>;
+
+    /// Remove the bond with the device, forgetting its keys.
+    async fn unpair(&self) -> Result<(), BluetoothError>;
+
+    /// Watch the bond state of the device, e.g. to notice it being unpaired
+    /// from the system settings.
+    async fn bond_state_changes(
+        &self,
+    ) -> Result<BondStateStream, BluetoothError>;
+
+    /// Whether the platform has a link with the device, whether or not this
+    /// `BleDevice` made it.
+    async fn connection_state(&self)
+        -> Result<ConnectionState, BluetoothError>;
+
+    /// Watch the connection state of the device, e.g. to notice it
+    /// disconnecting.
+    async fn connection_events(
+        &self,
+    ) -> Result<ConnectionStateStream, BluetoothError>;
 }
 
 /// Concrete types implementing this trait represent BT Classic Peripheral
@@ -51,6 +135,28 @@ pub trait ClassicDevice: Sized {
     /// Retrieve this device's Bluetooth address information.
     fn address(&self) -> ClassicAddress;
 
-    /// Attempt pairing with the peripheral device.
-    async fn pair(&self) -> Result<PairingResult, BluetoothError>;
+    /// Attempt pairing with the peripheral device, answering the requests of
+    /// the pairing ceremony with `handler`.
+    async fn pair(
+        &self,
+        handler: Arc<dyn PairingHandler>,
+    ) -> Result<PairingResult, BluetoothError>;
+
+    /// Open an RFCOMM channel to the service identified by `service_uuid`,
+    /// e.g. `BtUuid::MESSAGE_STREAM`. The channel number is looked up with SDP.
+    async fn connect_rfcomm(
+        &self,
+        service_uuid: BtUuid,
+    ) -> Result<RfcommSocket, BluetoothError>;
+
+    /// Whether the platform has a link with the device, e.g. because the
+    /// system connected its audio profiles.
+    async fn connection_state(&self)
+        -> Result<ConnectionState, BluetoothError>;
+
+    /// Watch the connection state of the device, e.g. to open the Message
+    /// Stream once it connects.
+    async fn connection_events(
+        &self,
+    ) -> Result<ConnectionStateStream, BluetoothError>;
 }
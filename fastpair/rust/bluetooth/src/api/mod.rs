@@ -1,5 +1,7 @@
 mod adapter;
+mod advertiser;
 mod device;
 
 pub use adapter::*;
+pub use advertiser::*;
 pub use device::*;
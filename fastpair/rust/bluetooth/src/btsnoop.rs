@@ -0,0 +1,702 @@
+// Copyright 2023 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::{
+    collections::{HashMap, VecDeque},
+    fs::File,
+    io::{self, BufReader, Read},
+    path::Path,
+};
+
+use futures::stream;
+
+use crate::common::{
+    AdStructures, BleAddress, BleAddressKind, BleAdvertisement,
+    BleAdvertisementType, BleDataTypeId, BluetoothError, ScanStream,
+};
+
+/// Identification pattern btsnoop files start with.
+const MAGIC: &[u8; 8] = b"btsnoop\0";
+
+/// Version of the btsnoop format, the only one there is.
+const VERSION: u32 = 1;
+
+/// Length of the file header: magic, version and datalink type.
+const FILE_HEADER_LEN: usize = 16;
+
+/// Length of the header of each packet record: original and included
+/// lengths, flags, cumulative drops and timestamp.
+const RECORD_HEADER_LEN: usize = 24;
+
+/// Largest packet record accepted, above any HCI packet, so that a corrupted
+/// length doesn't allocate gigabytes.
+const MAX_PACKET_LEN: usize = 1 << 17;
+
+/// Packet type indicator of HCI events on the UART transport.
+/// Bluetooth Core Specification, Vol 4, Part A, Section 2.
+const H4_EVENT: u8 = 0x04;
+
+/// Flags of received HCI events in unencapsulated captures.
+const UNENCAPSULATED_EVENT: u32 = 0x03;
+
+/// Opcode of HCI events in Linux monitor captures, in the low half of the
+/// flags.
+const MONITOR_EVENT: u32 = 0x0003;
+
+/// Bluetooth Core Specification, Vol 4, Part E, Section 7.7.65.
+const LE_META_EVENT: u8 = 0x3e;
+const LE_ADVERTISING_REPORT: u8 = 0x02;
+const LE_EXTENDED_ADVERTISING_REPORT: u8 = 0x0d;
+
+/// Data_Status of an extended advertising report whose data continues in
+/// the next report, and of one whose data was truncated.
+const DATA_INCOMPLETE: u16 = 0b01;
+const DATA_TRUNCATED: u16 = 0b10;
+
+/// Address_Type of extended advertisements sent without an address.
+const ANONYMOUS: u8 = 0xff;
+
+/// RSSI or TX_Power of reports that don't have one.
+const NOT_AVAILABLE: i8 = 127;
+
+/// Link layer the packets of a btsnoop file were captured at.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+enum Datalink {
+    /// HCI packets without a type indicator, which the flags give instead.
+    Unencapsulated,
+    /// HCI packets on the UART transport (H4), as written by Android.
+    Uart,
+    /// Linux monitor packets, as written by `btmon -w`.
+    Monitor,
+}
+
+impl TryFrom<u32> for Datalink {
+    type Error = BluetoothError;
+
+    fn try_from(datalink: u32) -> Result<Self, Self::Error> {
+        match datalink {
+            1001 => Ok(Datalink::Unencapsulated),
+            1002 => Ok(Datalink::Uart),
+            2001 => Ok(Datalink::Monitor),
+            _ => Err(BluetoothError::NotSupported(format!(
+                "btsnoop datalink type {}",
+                datalink
+            ))),
+        }
+    }
+}
+
+/// Reader of btsnoop files, such as Android's Bluetooth HCI snoop logs and
+/// `btmon -w` captures, yielding the advertisements of the LE Advertising
+/// Report and LE Extended Advertising Report events they hold, with every
+/// data section loaded. Other packets are skipped, as are anonymous
+/// advertisements. Extended advertisements reported in fragments are
+/// reassembled, and only the whole AD structures of truncated ones are kept.
+///
+/// A report that can't be parsed yields an error, and reading goes on with
+/// the next one. A file that is truncated or can't be read yields an error,
+/// then ends.
+pub struct BtSnoopReader<R> {
+    reader: R,
+    datalink: Datalink,
+    /// Advertisements parsed from the last event, not yielded yet.
+    pending: VecDeque<Result<BleAdvertisement, BluetoothError>>,
+    /// Data of extended advertisements received so far, keyed by advertiser
+    /// and advertising set.
+    fragments: HashMap<(BleAddress, u8), Vec<u8>>,
+    done: bool,
+}
+
+impl BtSnoopReader<BufReader<File>> {
+    /// Open the btsnoop file at `path`.
+    pub fn open(path: impl AsRef<Path>) -> Result<Self, BluetoothError> {
+        BtSnoopReader::new(BufReader::new(File::open(path)?))
+    }
+}
+
+impl<R: Read> BtSnoopReader<R> {
+    /// Read a btsnoop file from `reader`, starting with its header. Fails
+    /// with `BluetoothError::InvalidArgument` if it isn't a btsnoop file, and
+    /// `BluetoothError::NotSupported` if its packets aren't HCI packets.
+    pub fn new(mut reader: R) -> Result<Self, BluetoothError> {
+        let mut header = [0u8; FILE_HEADER_LEN];
+        reader.read_exact(&mut header).map_err(truncated)?;
+
+        let (magic, header) = header.split_at(MAGIC.len());
+        if magic != MAGIC {
+            return Err(BluetoothError::InvalidArgument(String::from(
+                "not a btsnoop file",
+            )));
+        }
+        let version = u32::from_be_bytes(header[..4].try_into().unwrap());
+        if version != VERSION {
+            return Err(BluetoothError::NotSupported(format!(
+                "btsnoop version {}",
+                version
+            )));
+        }
+        let datalink = u32::from_be_bytes(header[4..].try_into().unwrap());
+
+        Ok(BtSnoopReader {
+            reader,
+            datalink: Datalink::try_from(datalink)?,
+            pending: VecDeque::new(),
+            fragments: HashMap::new(),
+            done: false,
+        })
+    }
+
+    /// Read the next packet record, returning its flags and data, or `None`
+    /// at the end of the file.
+    fn next_packet(
+        &mut self,
+    ) -> Result<Option<(u32, Vec<u8>)>, BluetoothError> {
+        let mut header = [0u8; RECORD_HEADER_LEN];
+        // Files may end after any record, but not within one.
+        match read_full(&mut self.reader, &mut header)? {
+            0 => return Ok(None),
+            RECORD_HEADER_LEN => (),
+            _ => return Err(truncated(io::ErrorKind::UnexpectedEof.into())),
+        }
+
+        let included_len =
+            u32::from_be_bytes(header[4..8].try_into().unwrap()) as usize;
+        let flags = u32::from_be_bytes(header[8..12].try_into().unwrap());
+        if included_len > MAX_PACKET_LEN {
+            return Err(BluetoothError::MalformedData(format!(
+                "btsnoop packet of {} bytes",
+                included_len
+            )));
+        }
+
+        let mut packet = vec![0u8; included_len];
+        self.reader.read_exact(&mut packet).map_err(truncated)?;
+        Ok(Some((flags, packet)))
+    }
+
+    /// Retrieve the HCI event held by `packet`, if it holds one.
+    fn event<'a>(&self, flags: u32, packet: &'a [u8]) -> Option<&'a [u8]> {
+        match self.datalink {
+            Datalink::Unencapsulated => {
+                (flags == UNENCAPSULATED_EVENT).then_some(packet)
+            }
+            Datalink::Uart => match packet {
+                [H4_EVENT, event @ ..] => Some(event),
+                _ => None,
+            },
+            Datalink::Monitor => {
+                (flags & 0xffff == MONITOR_EVENT).then_some(packet)
+            }
+        }
+    }
+
+    /// Queue the advertisements of `event`, if it's an advertising report.
+    /// Bluetooth Core Specification, Vol 4, Part E, Section 7.7.65.
+    fn parse_event(&mut self, event: &[u8]) -> Result<(), BluetoothError> {
+        let mut event = Cursor(event);
+        if event.u8()? != LE_META_EVENT {
+            return Ok(());
+        }
+        let len = event.u8()?;
+        let mut params = Cursor(event.take(len as usize)?);
+
+        match params.u8()? {
+            LE_ADVERTISING_REPORT => self.parse_reports(params),
+            LE_EXTENDED_ADVERTISING_REPORT => {
+                self.parse_extended_reports(params)
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Parse the reports of an LE Advertising Report event, which controllers
+    /// send one after the other.
+    /// Bluetooth Core Specification, Vol 4, Part E, Section 7.7.65.2.
+    fn parse_reports(
+        &mut self,
+        mut params: Cursor,
+    ) -> Result<(), BluetoothError> {
+        for _ in 0..params.u8()? {
+            let event_type = params.u8()?;
+            let address_type = params.u8()?;
+            let address = params.take(6)?;
+            let data_len = params.u8()?;
+            let data = params.take(data_len as usize)?;
+            let rssi = params.i8()?;
+
+            let advertisement_type = match event_type {
+                0x00 => BleAdvertisementType::ADV_IND,
+                0x01 => BleAdvertisementType::ADV_DIRECT_IND,
+                0x02 => BleAdvertisementType::ADV_SCAN_IND,
+                0x03 => BleAdvertisementType::ADV_NONCONN_IND,
+                0x04 => BleAdvertisementType::SCAN_RSP,
+                _ => {
+                    return Err(BluetoothError::MalformedData(format!(
+                        "advertising report event type {:#04x}",
+                        event_type
+                    )))
+                }
+            };
+            let Some(address) = parse_address(address_type, address)? else {
+                continue;
+            };
+
+            self.pending.push_back(advertisement(
+                address,
+                advertisement_type,
+                rssi,
+                NOT_AVAILABLE,
+                data,
+                false,
+            ));
+        }
+
+        Ok(())
+    }
+
+    /// Parse the reports of an LE Extended Advertising Report event.
+    /// Bluetooth Core Specification, Vol 4, Part E, Section 7.7.65.13.
+    fn parse_extended_reports(
+        &mut self,
+        mut params: Cursor,
+    ) -> Result<(), BluetoothError> {
+        for _ in 0..params.u8()? {
+            let event_type = params.u16()?;
+            let address_type = params.u8()?;
+            let address = params.take(6)?;
+            // Primary_PHY and Secondary_PHY.
+            params.take(2)?;
+            let sid = params.u8()?;
+            let tx_power = params.i8()?;
+            let rssi = params.i8()?;
+            // Periodic_Advertising_Interval, Direct_Address_Type and
+            // Direct_Address.
+            params.take(9)?;
+            let data_len = params.u8()?;
+            let data = params.take(data_len as usize)?;
+
+            let Some(address) = parse_address(address_type, address)? else {
+                continue;
+            };
+
+            // Every fragment holds the same fields, only the data is split.
+            let data_status = (event_type >> 5) & 0b11;
+            let fragments = self.fragments.entry((address, sid)).or_default();
+            fragments.extend_from_slice(data);
+            if data_status == DATA_INCOMPLETE {
+                continue;
+            }
+
+            let data =
+                self.fragments.remove(&(address, sid)).unwrap_or_default();
+            self.pending.push_back(advertisement(
+                address,
+                BleAdvertisementType::from_bits(event_type & 0x1f),
+                rssi,
+                tx_power,
+                &data,
+                data_status == DATA_TRUNCATED,
+            ));
+        }
+
+        Ok(())
+    }
+}
+
+impl<R: Read + Send + 'static> BtSnoopReader<R> {
+    /// Turn the reader into a stream of the advertisements, e.g. to feed a
+    /// capture to code consuming the scans of `api::BleAdapter` in tests. The
+    /// file is read with blocking reads.
+    pub fn into_stream(self) -> ScanStream {
+        ScanStream::new(stream::iter(self))
+    }
+}
+
+impl<R: Read> Iterator for BtSnoopReader<R> {
+    type Item = Result<BleAdvertisement, BluetoothError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some(advertisement) = self.pending.pop_front() {
+                return Some(advertisement);
+            }
+            if self.done {
+                return None;
+            }
+
+            match self.next_packet() {
+                Ok(Some((flags, packet))) => {
+                    if let Some(event) = self.event(flags, &packet) {
+                        if let Err(err) = self.parse_event(event) {
+                            self.pending.push_back(Err(err));
+                        }
+                    }
+                }
+                Ok(None) => self.done = true,
+                Err(err) => {
+                    self.done = true;
+                    return Some(Err(err));
+                }
+            }
+        }
+    }
+}
+
+/// Reader of the parameters of an HCI event.
+struct Cursor<'a>(&'a [u8]);
+
+impl<'a> Cursor<'a> {
+    fn take(&mut self, len: usize) -> Result<&'a [u8], BluetoothError> {
+        if len > self.0.len() {
+            return Err(BluetoothError::MalformedData(format!(
+                "HCI event overrun by {} bytes",
+                len - self.0.len()
+            )));
+        }
+
+        let (taken, rest) = self.0.split_at(len);
+        self.0 = rest;
+        Ok(taken)
+    }
+
+    fn u8(&mut self) -> Result<u8, BluetoothError> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn i8(&mut self) -> Result<i8, BluetoothError> {
+        Ok(self.u8()? as i8)
+    }
+
+    fn u16(&mut self) -> Result<u16, BluetoothError> {
+        Ok(u16::from_le_bytes(self.take(2)?.try_into().unwrap()))
+    }
+}
+
+/// Parse the address of a report, sent in little-endian byte order. Returns
+/// `None` for anonymous advertisements. Identity addresses the controller
+/// resolved are reported as such.
+fn parse_address(
+    address_type: u8,
+    address: &[u8],
+) -> Result<Option<BleAddress>, BluetoothError> {
+    let kind = match address_type {
+        0x00 | 0x02 => BleAddressKind::Public,
+        0x01 | 0x03 => BleAddressKind::Random,
+        ANONYMOUS => return Ok(None),
+        _ => {
+            return Err(BluetoothError::MalformedData(format!(
+                "advertising report address type {:#04x}",
+                address_type
+            )))
+        }
+    };
+
+    let mut bytes = [0u8; 8];
+    bytes[..6].copy_from_slice(address);
+    Ok(Some(BleAddress::new(u64::from_le_bytes(bytes), kind)))
+}
+
+/// Build the advertisement of a report. If `truncated`, AD structures cut
+/// short are dropped rather than rejected.
+fn advertisement(
+    address: BleAddress,
+    advertisement_type: BleAdvertisementType,
+    rssi: i8,
+    tx_power: i8,
+    data: &[u8],
+    truncated: bool,
+) -> Result<BleAdvertisement, BluetoothError> {
+    let structures = AdStructures::new(data);
+    let structures = match truncated {
+        true => structures.map_while(Result::ok).collect(),
+        false => structures.collect::<Result<Vec<_>, _>>()?,
+    };
+    let available =
+        |value: i8| (value != NOT_AVAILABLE).then_some(i16::from(value));
+
+    let mut advertisement =
+        BleAdvertisement::new(address, available(rssi), available(tx_power));
+    advertisement.load_ad_structures(&structures, BleDataTypeId::ALL)?;
+    advertisement.set_advertisement_type(advertisement_type);
+    if !truncated {
+        advertisement.set_raw_data(data.to_vec());
+    }
+
+    Ok(advertisement)
+}
+
+/// Read into `buf` until it's full or the end of `reader`, returning the
+/// number of bytes read.
+fn read_full(reader: &mut impl Read, buf: &mut [u8]) -> io::Result<usize> {
+    let mut len = 0;
+    while len < buf.len() {
+        match reader.read(&mut buf[len..]) {
+            Ok(0) => break,
+            Ok(read) => len += read,
+            Err(err) if err.kind() == io::ErrorKind::Interrupted => (),
+            Err(err) => return Err(err),
+        }
+    }
+
+    Ok(len)
+}
+
+/// Build the error of a read that failed, reporting a file ending early as
+/// malformed data.
+fn truncated(err: io::Error) -> BluetoothError {
+    match err.kind() {
+        io::ErrorKind::UnexpectedEof => BluetoothError::MalformedData(
+            String::from("btsnoop file is truncated"),
+        ),
+        _ => err.into(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use futures::{executor::block_on, StreamExt};
+
+    use super::*;
+    use crate::common::{BtUuid, ServiceData};
+
+    /// Build a btsnoop file of `datalink` holding `packets`, with their
+    /// flags.
+    fn btsnoop(datalink: u32, packets: &[(u32, Vec<u8>)]) -> Vec<u8> {
+        let mut file = MAGIC.to_vec();
+        file.extend_from_slice(&VERSION.to_be_bytes());
+        file.extend_from_slice(&datalink.to_be_bytes());
+
+        for (flags, packet) in packets {
+            let len = (packet.len() as u32).to_be_bytes();
+            file.extend_from_slice(&len);
+            file.extend_from_slice(&len);
+            file.extend_from_slice(&flags.to_be_bytes());
+            file.extend_from_slice(&[0; 12]);
+            file.extend_from_slice(packet);
+        }
+
+        file
+    }
+
+    /// Build an LE Meta event with the `subevent` and its `params`.
+    fn le_meta_event(subevent: u8, params: &[u8]) -> Vec<u8> {
+        let mut event = vec![LE_META_EVENT, params.len() as u8 + 1, subevent];
+        event.extend_from_slice(params);
+        event
+    }
+
+    /// LE Advertising Report event with an ADV_IND from random address
+    /// 11:22:33:44:55:66 carrying Fast Pair service data, received at -60
+    /// dBm.
+    fn advertising_report() -> Vec<u8> {
+        le_meta_event(
+            LE_ADVERTISING_REPORT,
+            &[
+                0x01, // Num_Reports
+                0x00, // ADV_IND
+                0x01, // Random
+                0x66, 0x55, 0x44, 0x33, 0x22, 0x11, // Address
+                0x07, 0x06, 0x16, 0x2c, 0xfe, 0x01, 0x02, 0x03, // Data
+                0xc4, // RSSI
+            ],
+        )
+    }
+
+    /// An LE Extended Advertising Report from `address_type` 66:55:44:33:22:11
+    /// with `event_type` and `data`.
+    fn extended_report(
+        event_type: u16,
+        address_type: u8,
+        data: &[u8],
+    ) -> Vec<u8> {
+        let mut report = event_type.to_le_bytes().to_vec();
+        report.push(address_type);
+        report.extend_from_slice(&[0x11, 0x22, 0x33, 0x44, 0x55, 0x66]);
+        report.extend_from_slice(&[
+            0x01, 0x03, // Primary_PHY, Secondary_PHY
+            0x02, // Advertising_SID
+            0xf6, // TX_Power
+            0xc9, // RSSI
+            0x00, 0x00, // Periodic_Advertising_Interval
+            0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, // Direct_Address
+        ]);
+        report.push(data.len() as u8);
+        report.extend_from_slice(data);
+        report
+    }
+
+    fn extended_reports(reports: &[Vec<u8>]) -> Vec<u8> {
+        let mut params = vec![reports.len() as u8];
+        reports
+            .iter()
+            .for_each(|report| params.extend_from_slice(report));
+        le_meta_event(LE_EXTENDED_ADVERTISING_REPORT, &params)
+    }
+
+    fn h4(packet_type: u8, packet: &[u8]) -> Vec<u8> {
+        let mut bytes = vec![packet_type];
+        bytes.extend_from_slice(packet);
+        bytes
+    }
+
+    #[test]
+    fn reads_advertising_reports() {
+        let file = btsnoop(
+            1002,
+            &[
+                // HCI_LE_Set_Scan_Enable command, then the report.
+                (0x02, h4(0x01, &[0x0c, 0x20, 0x02, 0x01, 0x00])),
+                (0x03, h4(H4_EVENT, &advertising_report())),
+            ],
+        );
+
+        let ads = BtSnoopReader::new(file.as_slice())
+            .unwrap()
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap();
+        assert_eq!(ads.len(), 1);
+        let ad = &ads[0];
+        assert_eq!(ad.address().to_string(), "11:22:33:44:55:66");
+        assert_eq!(ad.address().get_kind(), BleAddressKind::Random);
+        assert_eq!(ad.rssi(), Some(-60));
+        assert_eq!(ad.tx_power(), None);
+        assert_eq!(
+            ad.advertisement_type(),
+            Some(BleAdvertisementType::ADV_IND)
+        );
+        assert_eq!(
+            *ad.service_data_16bit_uuid().unwrap(),
+            vec![ServiceData::new(BtUuid::FAST_PAIR, vec![0x01, 0x02, 0x03])]
+        );
+        assert_eq!(ad.local_name().unwrap(), None);
+    }
+
+    #[test]
+    fn reassembles_extended_advertising_reports() {
+        let name = [0x05, 0x09, 0x42, 0x75, 0x64, 0x73];
+        let file = btsnoop(
+            2001,
+            &[
+                (
+                    MONITOR_EVENT,
+                    extended_reports(&[
+                        // Connectable extended advertisement, in two parts.
+                        extended_report(0x0021, 0x02, &name[..2]),
+                        // Anonymous advertisement.
+                        extended_report(0x0000, ANONYMOUS, &[]),
+                    ]),
+                ),
+                // Same event sent to the controller, which isn't an event.
+                (0x0002, extended_reports(&[extended_report(0, 0, &[])])),
+                (
+                    MONITOR_EVENT,
+                    extended_reports(&[
+                        extended_report(0x0001, 0x02, &name[2..]),
+                        // Truncated non-connectable advertisement.
+                        extended_report(0x0040, 0x01, &name[..4]),
+                    ]),
+                ),
+            ],
+        );
+
+        let ads = BtSnoopReader::new(file.as_slice())
+            .unwrap()
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap();
+        assert_eq!(ads.len(), 2);
+        assert_eq!(ads[0].address().to_string(), "66:55:44:33:22:11");
+        assert_eq!(ads[0].address().get_kind(), BleAddressKind::Public);
+        assert_eq!(ads[0].rssi(), Some(-55));
+        assert_eq!(ads[0].tx_power(), Some(-10));
+        assert_eq!(
+            ads[0].advertisement_type(),
+            Some(BleAdvertisementType::from_bits(
+                BleAdvertisementType::CONNECTABLE
+            ))
+        );
+        assert_eq!(ads[0].local_name().unwrap(), Some("Buds"));
+
+        assert_eq!(ads[1].address().get_kind(), BleAddressKind::Random);
+        assert!(!ads[1].advertisement_type().unwrap().is_connectable());
+        assert_eq!(ads[1].local_name().unwrap(), None);
+    }
+
+    #[test]
+    fn reads_unencapsulated_packets() {
+        let file = btsnoop(
+            1001,
+            &[
+                (0x02, advertising_report()),
+                (UNENCAPSULATED_EVENT, advertising_report()),
+            ],
+        );
+
+        let ads = block_on(
+            BtSnoopReader::new(io::Cursor::new(file))
+                .unwrap()
+                .into_stream()
+                .collect::<Vec<_>>(),
+        );
+        assert_eq!(ads.len(), 1);
+        assert_eq!(ads[0].as_ref().unwrap().rssi(), Some(-60));
+    }
+
+    #[test]
+    fn rejects_other_files() {
+        let file = btsnoop(1002, &[]);
+        assert!(BtSnoopReader::new(file.as_slice())
+            .unwrap()
+            .next()
+            .is_none());
+
+        let mut other = file.clone();
+        other[0] = b'B';
+        assert!(matches!(
+            BtSnoopReader::new(other.as_slice()).err(),
+            Some(BluetoothError::InvalidArgument(_))
+        ));
+        assert!(matches!(
+            BtSnoopReader::new(btsnoop(1004, &[]).as_slice()).err(),
+            Some(BluetoothError::NotSupported(_))
+        ));
+        assert!(matches!(
+            BtSnoopReader::new(&file[..10]).err(),
+            Some(BluetoothError::MalformedData(_))
+        ));
+    }
+
+    #[test]
+    fn skips_malformed_reports() {
+        let mut malformed = advertising_report();
+        // Overruns the RSSI.
+        malformed[12] = 0x08;
+        let mut file = btsnoop(
+            1002,
+            &[
+                (0x03, h4(H4_EVENT, &malformed)),
+                (0x03, h4(H4_EVENT, &advertising_report())),
+                (0x03, h4(H4_EVENT, &advertising_report())),
+            ],
+        );
+        // Cut the last record short.
+        file.truncate(file.len() - 1);
+
+        let results: Vec<_> =
+            BtSnoopReader::new(file.as_slice()).unwrap().collect();
+        assert_eq!(results.len(), 3);
+        assert!(matches!(results[0], Err(BluetoothError::MalformedData(_))));
+        assert!(results[1].is_ok());
+        assert!(matches!(results[2], Err(BluetoothError::MalformedData(_))));
+    }
+}
@@ -0,0 +1,228 @@
+// Copyright 2023 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+mod recorder;
+mod replay;
+
+pub use recorder::*;
+pub use replay::*;
+
+use std::{
+    io::{self, Write},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::common::{
+    serialization::hex_bytes, AdStructures, BleAddress, BleAdvertisement,
+    BleAdvertisementType, BleDataTypeId, BluetoothError,
+};
+
+/// Version of the capture format, written on every line. Bumped whenever a
+/// change would make older captures read differently.
+const CAPTURE_VERSION: u32 = 1;
+
+/// A captured advertisement. Captures are written in JSON Lines: one record
+/// per line, as a JSON object such as
+///
+/// ```text
+/// {"version":1,"timestamp_us":1697500000000000,
+///  "address":{"address":"11:22:33:44:55:66","kind":"random"},
+///  "rssi":-60,"tx_power":-10,"advertisement_type":19,
+///  "data":"03032cfe06162cfe010203"}
+/// ```
+///
+/// without the line breaks, where:
+/// - `version` is the version of the format, currently 1.
+/// - `timestamp_us` is when the advertisement was received, in microseconds
+///   since the UNIX epoch.
+/// - `address` is the address the advertisement was sent from.
+/// - `rssi` and `tx_power` are in dBm, and omitted if unknown.
+/// - `advertisement_type` holds the bits of `BleAdvertisementType`, and is
+///   omitted if the platform doesn't report it.
+/// - `data` holds the AD structures of the advertising data and scan
+///   response, in lowercase hex. They are the ones the platform received
+///   where it exposes them, e.g. on Windows. BlueZ only exposes the parsed
+///   advertisement, so on Linux they are rebuilt from its data sections,
+///   which drops AD structures of unknown types.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+struct CaptureRecord {
+    version: u32,
+    timestamp_us: u64,
+    address: BleAddress,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    rssi: Option<i16>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    tx_power: Option<i16>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    advertisement_type: Option<BleAdvertisementType>,
+    #[serde(with = "hex_bytes")]
+    data: Vec<u8>,
+}
+
+impl CaptureRecord {
+    /// Record `advertisement`, received at `timestamp`, with the AD
+    /// structures it was received in, or else every data section it has
+    /// loaded. Fails with `BluetoothError::InvalidArgument` if a section
+    /// doesn't fit in an AD structure.
+    fn new(
+        timestamp: SystemTime,
+        advertisement: &BleAdvertisement,
+    ) -> Result<Self, BluetoothError> {
+        let timestamp =
+            timestamp.duration_since(UNIX_EPOCH).unwrap_or_default();
+
+        Ok(CaptureRecord {
+            version: CAPTURE_VERSION,
+            timestamp_us: timestamp.as_micros() as u64,
+            address: advertisement.address(),
+            rssi: advertisement.rssi(),
+            tx_power: advertisement.tx_power(),
+            advertisement_type: advertisement.advertisement_type(),
+            data: match advertisement.raw_data() {
+                Some(data) => data.to_vec(),
+                None => advertisement.to_ad_bytes()?,
+            },
+        })
+    }
+
+    /// Parse a line of a capture.
+    fn parse(line: &str) -> Result<Self, BluetoothError> {
+        let record: CaptureRecord = serde_json::from_str(line)
+            .map_err(|err| BluetoothError::InvalidArgument(err.to_string()))?;
+        if record.version != CAPTURE_VERSION {
+            return Err(BluetoothError::NotSupported(format!(
+                "capture version {}",
+                record.version
+            )));
+        }
+
+        Ok(record)
+    }
+
+    /// Write the record as a line of a capture, and flush it so that the
+    /// capture survives a crash.
+    fn write(&self, writer: &mut dyn Write) -> io::Result<()> {
+        serde_json::to_writer(&mut *writer, self)?;
+        writer.write_all(b"\n")?;
+        writer.flush()
+    }
+
+    /// Time elapsed since the UNIX epoch when the advertisement was received.
+    fn timestamp(&self) -> Duration {
+        Duration::from_micros(self.timestamp_us)
+    }
+
+    /// Rebuild the advertisement, with every data section loaded.
+    fn to_advertisement(&self) -> Result<BleAdvertisement, BluetoothError> {
+        let structures =
+            AdStructures::new(&self.data).collect::<Result<Vec<_>, _>>()?;
+
+        let mut advertisement =
+            BleAdvertisement::new(self.address, self.rssi, self.tx_power);
+        advertisement.load_ad_structures(&structures, BleDataTypeId::ALL)?;
+        advertisement.set_raw_data(self.data.clone());
+        if let Some(advertisement_type) = self.advertisement_type {
+            advertisement.set_advertisement_type(advertisement_type);
+        }
+
+        Ok(advertisement)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::common::{BleAddressKind, BtUuid, ServiceData};
+
+    #[test]
+    fn record_round_trip() {
+        let address = BleAddress::new(0x112233445566, BleAddressKind::Random);
+        let mut advertisement =
+            BleAdvertisement::from_raw(address, Some(-60), &[], &[]).unwrap();
+        advertisement.set_service_data_16bit_uuid(vec![ServiceData::new(
+            BtUuid::FAST_PAIR,
+            vec![0x01, 0x02, 0x03],
+        )]);
+        advertisement.set_advertisement_type(BleAdvertisementType::ADV_IND);
+
+        let timestamp = UNIX_EPOCH + Duration::from_micros(1_697_500_000_000);
+        let record = CaptureRecord::new(timestamp, &advertisement).unwrap();
+        let mut line = Vec::new();
+        record.write(&mut line).unwrap();
+        assert_eq!(
+            String::from_utf8(line.clone()).unwrap(),
+            concat!(
+                r#"{"version":1,"timestamp_us":1697500000000,"#,
+                r#""address":{"address":"11:22:33:44:55:66","kind":"random"},"#,
+                r#""rssi":-60,"advertisement_type":19,"data":"06162cfe010203"}"#,
+                "\n"
+            )
+        );
+
+        let parsed =
+            CaptureRecord::parse(std::str::from_utf8(&line).unwrap()).unwrap();
+        assert_eq!(parsed, record);
+        assert_eq!(
+            parsed.timestamp(),
+            Duration::from_micros(1_697_500_000_000)
+        );
+
+        let replayed = parsed.to_advertisement().unwrap();
+        assert_eq!(replayed.address(), address);
+        assert_eq!(replayed.rssi(), Some(-60));
+        assert_eq!(replayed.tx_power(), None);
+        assert_eq!(
+            replayed.advertisement_type(),
+            Some(BleAdvertisementType::ADV_IND)
+        );
+        assert_eq!(
+            replayed.service_data_16bit_uuid().unwrap(),
+            advertisement.service_data_16bit_uuid().unwrap()
+        );
+        assert_eq!(replayed.local_name().unwrap(), None);
+    }
+
+    #[test]
+    fn parse_rejects_invalid_records() {
+        let address = r#"{"address":"11:22:33:44:55:66","kind":"public"}"#;
+        let record = |version: u32, data: &str| {
+            format!(
+                r#"{{"version":{},"timestamp_us":0,"address":{},"data":"{}"}}"#,
+                version, address, data
+            )
+        };
+
+        assert!(CaptureRecord::parse(&record(1, "")).is_ok());
+        assert!(matches!(
+            CaptureRecord::parse(&record(2, "")),
+            Err(BluetoothError::NotSupported(_))
+        ));
+        assert!(matches!(
+            CaptureRecord::parse(&record(1, "0")),
+            Err(BluetoothError::InvalidArgument(_))
+        ));
+        assert!(matches!(
+            CaptureRecord::parse("{}"),
+            Err(BluetoothError::InvalidArgument(_))
+        ));
+        assert!(matches!(
+            CaptureRecord::parse(&record(1, "0516"))
+                .unwrap()
+                .to_advertisement(),
+            Err(BluetoothError::MalformedData(_))
+        ));
+    }
+}
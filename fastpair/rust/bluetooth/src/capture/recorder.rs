@@ -0,0 +1,345 @@
+// Copyright 2023 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::{
+    fs::File,
+    io::{BufWriter, Write},
+    path::Path,
+    sync::mpsc::{self, Sender},
+    thread,
+    time::SystemTime,
+};
+
+use async_trait::async_trait;
+use futures::StreamExt;
+use tracing::warn;
+
+use super::CaptureRecord;
+use crate::{
+    api,
+    common::{
+        AdapterId, AdapterInfo, BleAddress, BleDataTypeId, BluetoothError,
+        BondEventStream, BondedDevice, ClassicAddress, RadioStateStream,
+        ScanFilter, ScanSettings, ScanStream,
+    },
+};
+
+/// Adapter wrapping another one, which writes every advertisement delivered
+/// by its scans to a capture, in the format described by `CaptureRecord`.
+/// The capture can be played back with a `ReplayAdapter`.
+///
+/// Scans of the wrapped adapter load every data type, so the capture holds
+/// the whole advertisement whatever `data_selector` the caller passed. Other
+/// operations are forwarded unchanged.
+pub struct Recorder<A> {
+    adapter: A,
+    /// Hands records over to the thread writing the capture.
+    records: Sender<CaptureRecord>,
+}
+
+impl<A> Recorder<A> {
+    /// Record the scans of `adapter` to `writer`. Records are written and
+    /// flushed on a thread of their own, so that scans don't wait for I/O.
+    /// The thread ends once the recorder and its scans are dropped.
+    pub fn new(adapter: A, mut writer: impl Write + Send + 'static) -> Self {
+        let (records, received) = mpsc::channel::<CaptureRecord>();
+        thread::spawn(move || {
+            for record in received {
+                if let Err(err) = record.write(&mut writer) {
+                    warn!("Failed to record advertisement: {}", err);
+                }
+            }
+        });
+
+        Recorder { adapter, records }
+    }
+
+    /// Record the scans of `adapter` to the file at `path`, replacing it if
+    /// it exists.
+    pub fn create(
+        adapter: A,
+        path: impl AsRef<Path>,
+    ) -> Result<Self, BluetoothError> {
+        let file = File::create(path)?;
+        Ok(Recorder::new(adapter, BufWriter::new(file)))
+    }
+
+    /// Retrieve the wrapped adapter.
+    pub fn adapter(&self) -> &A {
+        &self.adapter
+    }
+}
+
+#[async_trait]
+impl<A: api::BleAdapter + Send + Sync> api::BleAdapter for Recorder<A> {
+    type BleDevice = A::BleDevice;
+    type ClassicDevice = A::ClassicDevice;
+
+    /// Fails with `BluetoothError::NotSupported`, as a recorder needs a
+    /// capture to write to. Use `Recorder::new()` instead.
+    async fn default() -> Result<Self, BluetoothError> {
+        Err(BluetoothError::NotSupported(String::from(
+            "recorders are created with Recorder::new()",
+        )))
+    }
+
+    async fn adapters() -> Result<Vec<AdapterInfo>, BluetoothError> {
+        A::adapters().await
+    }
+
+    /// Fails with `BluetoothError::NotSupported`, as a recorder needs a
+    /// capture to write to. Use `Recorder::new()` instead.
+    async fn with_id(_id: &AdapterId) -> Result<Self, BluetoothError> {
+        Err(BluetoothError::NotSupported(String::from(
+            "recorders are created with Recorder::new()",
+        )))
+    }
+
+    async fn ble_device(
+        &self,
+        addr: BleAddress,
+    ) -> Result<Self::BleDevice, BluetoothError> {
+        self.adapter.ble_device(addr).await
+    }
+
+    async fn classic_device(
+        &self,
+        addr: ClassicAddress,
+    ) -> Result<Self::ClassicDevice, BluetoothError> {
+        self.adapter.classic_device(addr).await
+    }
+
+    async fn info(&self) -> Result<AdapterInfo, BluetoothError> {
+        self.adapter.info().await
+    }
+
+    async fn radio_state_changes(
+        &self,
+    ) -> Result<RadioStateStream, BluetoothError> {
+        self.adapter.radio_state_changes().await
+    }
+
+    fn start_scan(
+        &self,
+        filter: &ScanFilter,
+        settings: &ScanSettings,
+        data_selector: &[BleDataTypeId],
+    ) -> Result<ScanStream, BluetoothError> {
+        let scan =
+            self.adapter
+                .start_scan(filter, settings, BleDataTypeId::ALL)?;
+        let records = self.records.clone();
+        let data_selector = data_selector.to_vec();
+
+        // A capture that can't be written to shouldn't break the scan of the
+        // application being debugged, so failures are only logged.
+        let stream = scan.map(move |advertisement| {
+            let mut advertisement = advertisement?;
+            let recorded =
+                CaptureRecord::new(SystemTime::now(), &advertisement).and_then(
+                    |record| {
+                        records.send(record).map_err(|_| {
+                            BluetoothError::Internal(String::from(
+                                "capture writer stopped",
+                            ))
+                        })
+                    },
+                );
+            if let Err(err) = recorded {
+                warn!("Failed to record advertisement: {}", err);
+            }
+
+            advertisement.retain_data(&data_selector);
+            Ok(advertisement)
+        });
+
+        Ok(ScanStream::new(stream))
+    }
+
+    async fn paired_devices(
+        &self,
+    ) -> Result<Vec<BondedDevice>, BluetoothError> {
+        self.adapter.paired_devices().await
+    }
+
+    async fn unpair(&self, device: BondedDevice) -> Result<(), BluetoothError> {
+        self.adapter.unpair(device).await
+    }
+
+    async fn bond_events(&self) -> Result<BondEventStream, BluetoothError> {
+        self.adapter.bond_events().await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        io,
+        sync::{Arc, Mutex},
+        time::{Duration, Instant},
+    };
+
+    use futures::executor::block_on;
+
+    use super::*;
+    use crate::{
+        api::BleAdapter as _,
+        common::{BleAddressKind, BleAdvertisement, BtUuid, ServiceData},
+        sim::SimRadio,
+    };
+
+    /// Writer whose output stays readable once it's moved into a recorder.
+    #[derive(Clone, Default)]
+    struct SharedBuffer(Arc<Mutex<Vec<u8>>>);
+
+    impl Write for SharedBuffer {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.lock().unwrap().write(buf)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    impl SharedBuffer {
+        /// Wait for the recorder to write `count` records, and parse them.
+        fn records(&self, count: usize) -> Vec<CaptureRecord> {
+            let deadline = Instant::now() + Duration::from_secs(5);
+            loop {
+                let output =
+                    String::from_utf8(self.0.lock().unwrap().clone()).unwrap();
+                let lines: Vec<&str> = output.lines().collect();
+                if lines.len() >= count || Instant::now() > deadline {
+                    return lines
+                        .into_iter()
+                        .map(|line| CaptureRecord::parse(line).unwrap())
+                        .collect();
+                }
+                std::thread::sleep(Duration::from_millis(5));
+            }
+        }
+    }
+
+    fn advertisement(addr: u64) -> BleAdvertisement {
+        let mut ad = BleAdvertisement::new(
+            BleAddress::new(addr, BleAddressKind::Public),
+            Some(-60),
+            None,
+        );
+        ad.set_local_name(Some(String::from("Buds")));
+        ad.set_service_data_16bit_uuid(vec![ServiceData::new(
+            BtUuid::FAST_PAIR,
+            vec![0x01, 0x02, 0x03],
+        )]);
+        ad
+    }
+
+    #[test]
+    fn records_delivered_advertisements() {
+        let radio = SimRadio::new();
+        let buffer = SharedBuffer::default();
+        let recorder = Recorder::new(radio.adapter(), buffer.clone());
+        let filter = ScanFilter::default()
+            .with_address(BleAddress::new(2, BleAddressKind::Public));
+        let mut scan = recorder
+            .start_scan(
+                &filter,
+                &ScanSettings::default(),
+                &[BleDataTypeId::ServiceData16BitUuid],
+            )
+            .unwrap();
+
+        radio.advertise(advertisement(1));
+        radio.advertise(advertisement(2));
+        let ad = block_on(scan.next()).unwrap().unwrap();
+        assert_eq!(u64::from(ad.address()), 2);
+        assert!(ad.service_data_16bit_uuid().is_ok());
+        assert!(ad.local_name().is_err());
+
+        let records = buffer.records(1);
+        assert_eq!(records.len(), 1);
+
+        // The capture holds every data section, not only the selected ones.
+        let recorded = records[0].to_advertisement().unwrap();
+        assert_eq!(recorded.address(), ad.address());
+        assert_eq!(recorded.local_name().unwrap(), Some("Buds"));
+        assert_eq!(
+            recorded.service_data_16bit_uuid().unwrap(),
+            ad.service_data_16bit_uuid().unwrap()
+        );
+    }
+
+    #[test]
+    fn records_received_payload() {
+        let radio = SimRadio::new();
+        let buffer = SharedBuffer::default();
+        let recorder = Recorder::new(radio.adapter(), buffer.clone());
+        let mut scan = recorder
+            .start_scan(&ScanFilter::default(), &ScanSettings::default(), &[])
+            .unwrap();
+
+        // The Broadcast Code structure isn't parsed, but is still recorded.
+        let adv_data = [
+            0x02, 0x2d, 0x01, // Broadcast Code
+            0x06, 0x16, 0x2c, 0xfe, 0x01, 0x02, 0x03, // Service Data
+        ];
+        let address = BleAddress::new(1, BleAddressKind::Public);
+        radio.advertise(
+            BleAdvertisement::from_raw(address, Some(-60), &adv_data, &[])
+                .unwrap(),
+        );
+        block_on(scan.next()).unwrap().unwrap();
+
+        let records = buffer.records(1);
+        assert_eq!(records.len(), 1);
+        assert_eq!(records[0].data, adv_data);
+    }
+
+    #[test]
+    fn skips_advertisements_it_cant_record() {
+        let radio = SimRadio::new();
+        let buffer = SharedBuffer::default();
+        let recorder = Recorder::new(radio.adapter(), buffer.clone());
+        let mut scan = recorder
+            .start_scan(&ScanFilter::default(), &ScanSettings::default(), &[])
+            .unwrap();
+
+        // The name doesn't fit in an AD structure.
+        let mut ad = advertisement(1);
+        ad.set_local_name(Some("a".repeat(255)));
+        radio.advertise(ad);
+        let ad = block_on(scan.next()).unwrap().unwrap();
+        assert_eq!(u64::from(ad.address()), 1);
+
+        radio.advertise(advertisement(2));
+        block_on(scan.next()).unwrap().unwrap();
+        let records = buffer.records(1);
+        assert_eq!(records.len(), 1);
+        assert_eq!(u64::from(records[0].address), 2);
+    }
+
+    #[test]
+    fn forwards_to_adapter() {
+        let radio = SimRadio::new();
+        let recorder = Recorder::new(radio.adapter(), io::sink());
+
+        let info = block_on(recorder.info()).unwrap();
+        assert_eq!(info.address(), radio.address());
+        assert!(matches!(
+            block_on(Recorder::<crate::sim::BleAdapter>::default()),
+            Err(BluetoothError::NotSupported(_))
+        ));
+    }
+}
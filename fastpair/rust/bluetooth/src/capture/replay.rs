@@ -0,0 +1,358 @@
+// Copyright 2023 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::{
+    fs::File,
+    io::{BufRead, BufReader},
+    path::Path,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use async_trait::async_trait;
+use futures::{future, stream, StreamExt};
+
+use super::CaptureRecord;
+use crate::{
+    api,
+    common::{
+        sleep_until, AdapterId, AdapterInfo, BleAddress, BleAddressKind,
+        BleAdvertisement, BleDataTypeId, BluetoothError, BondEventStream,
+        BondedDevice, ClassicAddress, RadioState, RadioStateStream, ScanFilter,
+        ScanSettings, ScanState, ScanStream,
+    },
+    platform,
+};
+
+/// Identifier of the adapter `ReplayAdapter::info()` reports.
+const REPLAY_ID: &str = "replay";
+
+/// Adapter playing back a capture written by a `Recorder`, e.g. to debug
+/// code consuming scans offline. Each scan replays the capture from its
+/// start, with the delays between advertisements they were received with,
+/// then ends. Scans apply their filter, settings and data selector like a
+/// platform would.
+///
+/// The adapter has no radio: it can't open devices, has no bonds, and isn't
+/// listed by `api::BleAdapter::adapters()`.
+#[derive(Clone)]
+pub struct ReplayAdapter {
+    /// Captured advertisements, with the time they were received at since
+    /// the first one.
+    advertisements: Arc<Vec<(Duration, BleAdvertisement)>>,
+    speed: f64,
+}
+
+impl ReplayAdapter {
+    /// Load the capture at `path`.
+    pub fn open(path: impl AsRef<Path>) -> Result<Self, BluetoothError> {
+        ReplayAdapter::from_reader(BufReader::new(File::open(path)?))
+    }
+
+    /// Load the capture read from `reader`. Fails with
+    /// `BluetoothError::InvalidArgument` if a line isn't a valid record, and
+    /// `BluetoothError::NotSupported` if it was written in a newer version
+    /// of the format. Blank lines are skipped.
+    pub fn from_reader(reader: impl BufRead) -> Result<Self, BluetoothError> {
+        let mut advertisements = Vec::new();
+        let mut start = None;
+
+        for (index, line) in reader.lines().enumerate() {
+            let line = line?;
+            if line.trim().is_empty() {
+                continue;
+            }
+
+            let invalid = |err: BluetoothError| {
+                BluetoothError::InvalidArgument(format!(
+                    "line {} of capture: {}",
+                    index + 1,
+                    err
+                ))
+            };
+            let record =
+                CaptureRecord::parse(&line).map_err(|err| match err {
+                    BluetoothError::NotSupported(_) => err,
+                    err => invalid(err),
+                })?;
+            let advertisement = record.to_advertisement().map_err(invalid)?;
+
+            // Records are written in order, but the clock may have been set
+            // back while recording.
+            let timestamp = record.timestamp();
+            let start = *start.get_or_insert(timestamp);
+            advertisements
+                .push((timestamp.saturating_sub(start), advertisement));
+        }
+
+        Ok(ReplayAdapter {
+            advertisements: Arc::new(advertisements),
+            speed: 1.0,
+        })
+    }
+
+    /// Play the capture back `speed` times faster than it was recorded, or
+    /// without delays if `speed` is `f64::INFINITY`. Captures are played at
+    /// the speed they were recorded by default. Duplicates are suppressed as
+    /// if the capture was played at the speed it was recorded.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `speed` isn't positive.
+    pub fn with_speed(mut self, speed: f64) -> Self {
+        assert!(speed > 0.0, "replay speed must be positive, got {}", speed);
+        self.speed = speed;
+        self
+    }
+
+    /// Retrieve the number of advertisements in the capture.
+    pub fn len(&self) -> usize {
+        self.advertisements.len()
+    }
+
+    /// Whether the capture holds no advertisements.
+    pub fn is_empty(&self) -> bool {
+        self.advertisements.is_empty()
+    }
+}
+
+#[async_trait]
+impl api::BleAdapter for ReplayAdapter {
+    type BleDevice = platform::BleDevice;
+    type ClassicDevice = platform::ClassicDevice;
+
+    /// Fails with `BluetoothError::NotSupported`, as there is no default
+    /// capture. Use `ReplayAdapter::open()` instead.
+    async fn default() -> Result<Self, BluetoothError> {
+        Err(BluetoothError::NotSupported(String::from(
+            "replay adapters are opened with ReplayAdapter::open()",
+        )))
+    }
+
+    async fn adapters() -> Result<Vec<AdapterInfo>, BluetoothError> {
+        Ok(Vec::new())
+    }
+
+    async fn with_id(id: &AdapterId) -> Result<Self, BluetoothError> {
+        Err(BluetoothError::InvalidArgument(format!(
+            "no replay adapter {}",
+            id
+        )))
+    }
+
+    async fn ble_device(
+        &self,
+        addr: BleAddress,
+    ) -> Result<Self::BleDevice, BluetoothError> {
+        Err(BluetoothError::NotSupported(format!(
+            "can't open device {} from a replay",
+            addr
+        )))
+    }
+
+    async fn classic_device(
+        &self,
+        addr: ClassicAddress,
+    ) -> Result<Self::ClassicDevice, BluetoothError> {
+        Err(BluetoothError::NotSupported(format!(
+            "can't open device {} from a replay",
+            addr
+        )))
+    }
+
+    async fn info(&self) -> Result<AdapterInfo, BluetoothError> {
+        Ok(AdapterInfo {
+            id: AdapterId::from(REPLAY_ID),
+            address: BleAddress::new(0, BleAddressKind::Public),
+            name: String::from("Replay"),
+            central_role_supported: true,
+            peripheral_role_supported: false,
+            extended_advertising_supported: false,
+            coded_phy_supported: false,
+            max_advertisement_length: 0,
+            radio_state: RadioState::On,
+        })
+    }
+
+    /// The radio of a replay is always on.
+    async fn radio_state_changes(
+        &self,
+    ) -> Result<RadioStateStream, BluetoothError> {
+        let on = stream::once(future::ready(Ok(RadioState::On)));
+        Ok(RadioStateStream::new(on.chain(stream::pending())))
+    }
+
+    fn start_scan(
+        &self,
+        filter: &ScanFilter,
+        settings: &ScanSettings,
+        data_selector: &[BleDataTypeId],
+    ) -> Result<ScanStream, BluetoothError> {
+        let advertisements = self.advertisements.clone();
+        let speed = self.speed;
+        let state = ScanState::new(filter, settings, data_selector);
+        let start = Instant::now();
+
+        let stream = stream::unfold(
+            (advertisements, 0, state),
+            move |(advertisements, mut index, mut state)| async move {
+                while let Some((received, advertisement)) =
+                    advertisements.get(index)
+                {
+                    index += 1;
+                    sleep_until(start + received.div_f64(speed)).await;
+
+                    // Captures hold every data type, so only keep what a
+                    // real platform would have loaded.
+                    let mut advertisement = advertisement.clone();
+                    advertisement.retain_data(state.datatype_ids());
+
+                    if let Some(advertisement) =
+                        state.process_at(advertisement, start + *received)
+                    {
+                        return Some((
+                            Ok(advertisement),
+                            (advertisements, index, state),
+                        ));
+                    }
+                }
+
+                None
+            },
+        );
+
+        Ok(ScanStream::new(stream))
+    }
+
+    async fn paired_devices(
+        &self,
+    ) -> Result<Vec<BondedDevice>, BluetoothError> {
+        Ok(Vec::new())
+    }
+
+    async fn unpair(
+        &self,
+        _device: BondedDevice,
+    ) -> Result<(), BluetoothError> {
+        Ok(())
+    }
+
+    async fn bond_events(&self) -> Result<BondEventStream, BluetoothError> {
+        Ok(BondEventStream::new(stream::pending()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use futures::executor::block_on;
+
+    use super::*;
+    use crate::{api::BleAdapter as _, common::BleAdvertisementType};
+
+    /// Capture of two Fast Pair advertisements from 11:22:33:44:55:66, 50 ms
+    /// apart, then a named advertisement from another address.
+    const CAPTURE: &str = concat!(
+        r#"{"version":1,"timestamp_us":1000000,"#,
+        r#""address":{"address":"11:22:33:44:55:66","kind":"public"},"#,
+        r#""rssi":-60,"advertisement_type":19,"data":"06162cfe010203"}"#,
+        "\n",
+        r#"{"version":1,"timestamp_us":1050000,"#,
+        r#""address":{"address":"11:22:33:44:55:66","kind":"public"},"#,
+        r#""rssi":-55,"advertisement_type":19,"data":"06162cfe010203"}"#,
+        "\n\n",
+        r#"{"version":1,"timestamp_us":1100000,"#,
+        r#""address":{"address":"66:55:44:33:22:11","kind":"public"},"#,
+        r#""advertisement_type":16,"data":"05094275647303192000"}"#,
+        "\n",
+    );
+
+    fn scan(
+        adapter: &ReplayAdapter,
+        settings: &ScanSettings,
+        data_selector: &[BleDataTypeId],
+    ) -> Vec<BleAdvertisement> {
+        let scan = adapter
+            .start_scan(&ScanFilter::default(), settings, data_selector)
+            .unwrap();
+        block_on(scan.map(Result::unwrap).collect())
+    }
+
+    #[test]
+    fn replays_at_recorded_speed() {
+        let adapter = ReplayAdapter::from_reader(CAPTURE.as_bytes()).unwrap();
+        assert_eq!(adapter.len(), 3);
+
+        let start = Instant::now();
+        let ads = scan(&adapter, &ScanSettings::default(), &[]);
+        assert!(start.elapsed() >= Duration::from_millis(100));
+
+        // The last advertisement isn't connectable.
+        let rssi: Vec<_> = ads.iter().map(BleAdvertisement::rssi).collect();
+        assert_eq!(rssi, vec![Some(-60), Some(-55)]);
+        assert_eq!(
+            ads[0].advertisement_type(),
+            Some(BleAdvertisementType::ADV_IND)
+        );
+        assert!(ads[0].service_data_16bit_uuid().is_err());
+    }
+
+    #[test]
+    fn replays_accelerated() {
+        let adapter = ReplayAdapter::from_reader(CAPTURE.as_bytes())
+            .unwrap()
+            .with_speed(f64::INFINITY);
+        let settings = ScanSettings::default()
+            .with_non_connectable(true)
+            .with_duplicate_window(Duration::from_millis(60));
+
+        let start = Instant::now();
+        let ads =
+            scan(&adapter, &settings, &[BleDataTypeId::CompleteLocalName]);
+        assert!(start.elapsed() < Duration::from_millis(50));
+
+        // Duplicates are judged on the time of the capture.
+        let names: Vec<_> =
+            ads.iter().map(|ad| ad.local_name().unwrap()).collect();
+        assert_eq!(names, vec![None, Some("Buds")]);
+    }
+
+    #[test]
+    fn rejects_invalid_captures() {
+        let err = ReplayAdapter::from_reader("{}\n".as_bytes()).err();
+        assert!(matches!(err, Some(BluetoothError::InvalidArgument(_))));
+
+        let newer = CAPTURE.replace(r#""version":1"#, r#""version":2"#);
+        let err = ReplayAdapter::from_reader(newer.as_bytes()).err();
+        assert!(matches!(err, Some(BluetoothError::NotSupported(_))));
+
+        // Overruns the payload.
+        let malformed = CAPTURE.replace("0319", "0519");
+        let err = ReplayAdapter::from_reader(malformed.as_bytes()).err();
+        assert!(
+            matches!(err, Some(BluetoothError::InvalidArgument(message)) if message.starts_with("line 4"))
+        );
+    }
+
+    #[test]
+    fn has_no_devices() {
+        let adapter = ReplayAdapter::from_reader(CAPTURE.as_bytes()).unwrap();
+        let addr = BleAddress::new(0x112233445566, BleAddressKind::Public);
+
+        assert!(block_on(adapter.ble_device(addr)).is_err());
+        assert_eq!(block_on(adapter.paired_devices()), Ok(Vec::new()));
+        assert_eq!(block_on(ReplayAdapter::adapters()), Ok(Vec::new()));
+        let mut changes = block_on(adapter.radio_state_changes()).unwrap();
+        assert_eq!(block_on(changes.next()), Some(Ok(RadioState::On)));
+    }
+}
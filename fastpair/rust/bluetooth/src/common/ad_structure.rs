@@ -0,0 +1,144 @@
+// Copyright 2023 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use super::BluetoothError;
+
+/// A single AD structure of an advertising or scan response payload, made of
+/// a length octet, an AD type octet and `length - 1` octets of AD data.
+/// Bluetooth Core Specification, Vol 3, Part C, Section 11.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct AdStructure<'a> {
+    data_type: u8,
+    data: &'a [u8],
+}
+
+impl<'a> AdStructure<'a> {
+    /// Construct an AD structure from its type and data, e.g. for platforms
+    /// that report advertisements already split into data sections.
+    pub fn new(data_type: u8, data: &'a [u8]) -> Self {
+        AdStructure { data_type, data }
+    }
+
+    /// Retrieve the AD type, see Bluetooth Assigned Numbers, Section 2.3.
+    pub fn data_type(&self) -> u8 {
+        self.data_type
+    }
+
+    /// Retrieve the AD data, excluding the length and AD type octets.
+    pub fn data(&self) -> &'a [u8] {
+        self.data
+    }
+}
+
+/// Iterator splitting a raw advertising or scan response payload into its
+/// AD structures. Yields an error, then stops, if a length octet points past
+/// the end of the payload.
+#[derive(Clone, Debug)]
+pub struct AdStructures<'a> {
+    remaining: &'a [u8],
+}
+
+impl<'a> AdStructures<'a> {
+    /// Iterate over the AD structures in `payload`.
+    pub fn new(payload: &'a [u8]) -> Self {
+        AdStructures { remaining: payload }
+    }
+}
+
+impl<'a> Iterator for AdStructures<'a> {
+    type Item = Result<AdStructure<'a>, BluetoothError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let (&length, rest) = self.remaining.split_first()?;
+        let length = usize::from(length);
+
+        // A zero length octet ends the significant part of the payload, the
+        // remaining octets are padding.
+        if length == 0 {
+            self.remaining = &[];
+            return None;
+        }
+
+        if length > rest.len() {
+            self.remaining = &[];
+            return Some(Err(BluetoothError::MalformedData(format!(
+                "AD structure of length {} overruns payload with {} bytes left",
+                length,
+                rest.len()
+            ))));
+        }
+
+        let (structure, rest) = rest.split_at(length);
+        self.remaining = rest;
+
+        Some(Ok(AdStructure::new(structure[0], &structure[1..])))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ad_structures_split() {
+        let payload = [0x02, 0x01, 0x06, 0x05, 0x16, 0x2c, 0xfe, 0xaa, 0xbb];
+        let structures = AdStructures::new(&payload)
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap();
+
+        assert_eq!(
+            structures,
+            vec![
+                AdStructure::new(0x01, &[0x06]),
+                AdStructure::new(0x16, &[0x2c, 0xfe, 0xaa, 0xbb]),
+            ]
+        );
+    }
+
+    #[test]
+    fn ad_structures_empty_data() {
+        let payload = [0x01, 0x09];
+        let structures = AdStructures::new(&payload)
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap();
+        assert_eq!(structures, vec![AdStructure::new(0x09, &[])]);
+
+        assert_eq!(AdStructures::new(&[]).count(), 0);
+    }
+
+    #[test]
+    fn ad_structures_stop_at_padding() {
+        let payload = [0x02, 0x0a, 0xf4, 0x00, 0x00, 0x00, 0x07];
+        let structures = AdStructures::new(&payload)
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap();
+        assert_eq!(structures, vec![AdStructure::new(0x0a, &[0xf4])]);
+    }
+
+    #[test]
+    fn ad_structures_overrun() {
+        let payload = [0x02, 0x01, 0x06, 0x04, 0x16, 0x2c, 0xfe];
+        let mut structures = AdStructures::new(&payload);
+
+        assert_eq!(
+            structures.next(),
+            Some(Ok(AdStructure::new(0x01, &[0x06])))
+        );
+        assert!(matches!(
+            structures.next(),
+            Some(Err(BluetoothError::MalformedData(_)))
+        ));
+        assert_eq!(structures.next(), None);
+    }
+}
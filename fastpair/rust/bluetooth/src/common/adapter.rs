@@ -0,0 +1,130 @@
+// Copyright 2023 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::fmt;
+
+use super::{stream::WatchStream, BleAddress};
+
+/// Identifier of a local adapter, as listed by `api::BleAdapter::adapters()`.
+/// Its format is platform-specific, e.g. `hci0` on Linux.
+#[derive(Clone, PartialEq, Eq, Hash, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(transparent))]
+pub struct AdapterId(String);
+
+impl AdapterId {
+    /// Retrieve the platform-specific identifier.
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+}
+
+impl From<&str> for AdapterId {
+    fn from(id: &str) -> Self {
+        AdapterId(String::from(id))
+    }
+}
+
+impl From<String> for AdapterId {
+    fn from(id: String) -> Self {
+        AdapterId(id)
+    }
+}
+
+impl fmt::Display for AdapterId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+/// Whether the Bluetooth radio of an adapter is switched on.
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "snake_case"))]
+pub enum RadioState {
+    Off,
+    On,
+}
+
+/// Capabilities and state of a local adapter, as returned by
+/// `api::BleAdapter::info()`. Capabilities a platform can't query are
+/// reported as unsupported.
+#[derive(Clone, PartialEq, Eq, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct AdapterInfo {
+    pub(crate) id: AdapterId,
+    pub(crate) address: BleAddress,
+    pub(crate) name: String,
+    pub(crate) central_role_supported: bool,
+    pub(crate) peripheral_role_supported: bool,
+    pub(crate) extended_advertising_supported: bool,
+    pub(crate) coded_phy_supported: bool,
+    pub(crate) max_advertisement_length: usize,
+    pub(crate) radio_state: RadioState,
+}
+
+impl AdapterInfo {
+    /// Retrieve the identifier `api::BleAdapter::with_id()` opens the adapter
+    /// with.
+    pub fn id(&self) -> &AdapterId {
+        &self.id
+    }
+
+    /// Retrieve the address the adapter uses over LE.
+    pub fn address(&self) -> BleAddress {
+        self.address
+    }
+
+    /// Retrieve the name other devices see the adapter as.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Whether the adapter can scan for and connect to peripherals.
+    pub fn is_central_role_supported(&self) -> bool {
+        self.central_role_supported
+    }
+
+    /// Whether the adapter can advertise and accept connections.
+    pub fn is_peripheral_role_supported(&self) -> bool {
+        self.peripheral_role_supported
+    }
+
+    /// Whether the adapter can publish extended advertisements.
+    pub fn is_extended_advertising_supported(&self) -> bool {
+        self.extended_advertising_supported
+    }
+
+    /// Whether the adapter supports the LE Coded PHY, used by long range
+    /// advertising.
+    pub fn is_coded_phy_supported(&self) -> bool {
+        self.coded_phy_supported
+    }
+
+    /// Retrieve the largest advertising payload the adapter can publish, in
+    /// bytes. Adapters without extended advertising support 31 bytes.
+    pub fn max_advertisement_length(&self) -> usize {
+        self.max_advertisement_length
+    }
+
+    /// Retrieve the state of the radio when the information was read.
+    pub fn radio_state(&self) -> RadioState {
+        self.radio_state
+    }
+}
+
+/// Stream of the radio states of an adapter, as returned by
+/// `api::BleAdapter::radio_state_changes()`. It yields the current state
+/// first, then every change.
+pub type RadioStateStream = WatchStream<RadioState>;
@@ -12,16 +12,41 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+use std::{fmt, str::FromStr};
+
+use aes::{
+    cipher::{generic_array::GenericArray, BlockEncrypt, KeyInit},
+    Aes128,
+};
+
 use super::BluetoothError;
 
 /// BLE Addresses can either be the peripheral's public MAC address, or various
 /// types of random addresses.
 #[derive(PartialEq, Eq, Clone, Copy, Debug, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "snake_case"))]
 pub enum BleAddressKind {
     Public,
     Random,
 }
 
+/// Subtypes of random BLE addresses, told apart by the two most significant
+/// bits of the address.
+/// Bluetooth Core Specification, Vol 6, Part B, Section 1.3.2.
+#[derive(PartialEq, Eq, Clone, Copy, Debug, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "snake_case"))]
+pub enum RandomAddressKind {
+    /// Fixed for the lifetime of the device, or until it power cycles.
+    Static,
+    /// Rotated periodically, and resolvable to the device's identity with its
+    /// Identity Resolving Key.
+    ResolvablePrivate,
+    /// Rotated periodically, and not resolvable to the device's identity.
+    NonResolvablePrivate,
+}
+
 /// Struct representing a 48-bit BLE Address and its type.
 #[derive(PartialEq, Eq, Clone, Copy, Debug, Hash)]
 pub struct BleAddress {
@@ -29,6 +54,19 @@ pub struct BleAddress {
     kind: BleAddressKind,
 }
 
+/// Address a BLE device is identified by across address rotations: its public
+/// address, or its static random address.
+#[derive(PartialEq, Eq, Clone, Copy, Debug, Hash)]
+pub struct IdentityAddress(BleAddress);
+
+/// Identity Resolving Keys of known devices, keyed by their identity address,
+/// which resolve the resolvable private addresses the devices rotate through.
+/// IRKs are typically distributed while bonding.
+#[derive(Clone, Debug, Default)]
+pub struct IrkStore {
+    keys: Vec<(IdentityAddress, u128)>,
+}
+
 /// Struct representing a 48-bit BT Classic address.
 #[derive(PartialEq, Eq, Clone, Copy, Debug, Hash)]
 pub struct ClassicAddress([u8; 6]);
@@ -41,10 +79,125 @@ impl BleAddress {
         BleAddress { val: addr, kind }
     }
 
+    /// Parse an address in the `AA:BB:CC:DD:EE:FF` form. The form doesn't
+    /// carry the type of the address, so it must be given as `kind`.
+    pub fn parse(
+        addr: &str,
+        kind: BleAddressKind,
+    ) -> Result<Self, BluetoothError> {
+        Ok(BleAddress {
+            val: parse_address(addr)?,
+            kind,
+        })
+    }
+
     /// Retrieve the type of BLE Address (public or random).
     pub fn get_kind(&self) -> BleAddressKind {
         self.kind
     }
+
+    /// Retrieve the subtype of a random address. Returns `None` for public
+    /// addresses and for random addresses using the reserved subtype.
+    pub fn random_kind(&self) -> Option<RandomAddressKind> {
+        if self.kind != BleAddressKind::Random {
+            return None;
+        }
+
+        match self.val[5] >> 6 {
+            0b11 => Some(RandomAddressKind::Static),
+            0b01 => Some(RandomAddressKind::ResolvablePrivate),
+            0b00 => Some(RandomAddressKind::NonResolvablePrivate),
+            _ => None,
+        }
+    }
+}
+
+impl IdentityAddress {
+    /// Retrieve the public or static random address.
+    pub fn address(&self) -> BleAddress {
+        self.0
+    }
+}
+
+/// Fails for private addresses, which don't identify a device.
+impl TryFrom<BleAddress> for IdentityAddress {
+    type Error = BluetoothError;
+
+    fn try_from(addr: BleAddress) -> Result<Self, Self::Error> {
+        match (addr.kind, addr.random_kind()) {
+            (BleAddressKind::Public, _)
+            | (_, Some(RandomAddressKind::Static)) => Ok(IdentityAddress(addr)),
+            _ => Err(BluetoothError::BadTypeConversion(format!(
+                "{} isn't an identity address",
+                addr
+            ))),
+        }
+    }
+}
+
+impl fmt::Display for IdentityAddress {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+impl IrkStore {
+    /// Construct an empty store.
+    pub fn new() -> Self {
+        IrkStore::default()
+    }
+
+    /// Store the IRK of the device with address `identity`, replacing its
+    /// previous IRK. `irk` is the 128-bit key, as numbered by the Bluetooth
+    /// Core Specification.
+    pub fn insert(&mut self, identity: IdentityAddress, irk: u128) {
+        match self.keys.iter_mut().find(|(known, _)| *known == identity) {
+            Some((_, key)) => *key = irk,
+            None => self.keys.push((identity, irk)),
+        }
+    }
+
+    /// Forget the IRK of the device with address `identity`, returning it.
+    pub fn remove(&mut self, identity: &IdentityAddress) -> Option<u128> {
+        let index =
+            self.keys.iter().position(|(known, _)| known == identity)?;
+        Some(self.keys.remove(index).1)
+    }
+
+    /// Find the identity of the device using `addr`. Resolvable private
+    /// addresses are resolved with every stored IRK, and identity addresses of
+    /// stored devices resolve to themselves.
+    pub fn resolve(&self, addr: &BleAddress) -> Option<IdentityAddress> {
+        if addr.random_kind() != Some(RandomAddressKind::ResolvablePrivate) {
+            return self
+                .keys
+                .iter()
+                .map(|(identity, _)| *identity)
+                .find(|identity| identity.address() == *addr);
+        }
+
+        // The random part takes the upper half of the address, and its hash
+        // the lower half.
+        let addr = u64::from(*addr);
+        let prand = (addr >> 24) as u32;
+        let hash = (addr & 0xffffff) as u32;
+        self.keys
+            .iter()
+            .find(|(_, irk)| ah(*irk, prand) == hash)
+            .map(|(identity, _)| *identity)
+    }
+}
+
+/// Random address hash function, hashing the 24-bit `prand` with `irk`.
+/// Bluetooth Core Specification, Vol 3, Part H, Section 2.2.2.
+pub(crate) fn ah(irk: u128, prand: u32) -> u32 {
+    // The key and padded plaintext are encrypted most significant byte first.
+    let mut block =
+        GenericArray::from(u128::from(prand & 0xffffff).to_be_bytes());
+    Aes128::new(&GenericArray::from(irk.to_be_bytes()))
+        .encrypt_block(&mut block);
+
+    u32::from_be_bytes([0, block[13], block[14], block[15]])
 }
 
 /// Function for converting the six LSB of a u64 into a 6-byte array.
@@ -55,6 +208,63 @@ fn u64_to_6lsb(num: u64) -> [u8; 6] {
         .expect("Sanity check, slice length matches array length")
 }
 
+/// Parse an address in the `AA:BB:CC:DD:EE:FF` form, most significant byte
+/// first, into its little-endian bytes.
+fn parse_address(addr: &str) -> Result<[u8; 6], BluetoothError> {
+    let invalid = || {
+        BluetoothError::BadTypeConversion(format!(
+            "invalid Bluetooth address {}",
+            addr
+        ))
+    };
+
+    let mut bytes = [0u8; 6];
+    let mut groups = addr.split(':');
+    for byte in bytes.iter_mut().rev() {
+        let group = groups.next().ok_or_else(invalid)?;
+        if group.len() != 2 || !group.chars().all(|c| c.is_ascii_hexdigit()) {
+            return Err(invalid());
+        }
+        *byte = u8::from_str_radix(group, 16).map_err(|_| invalid())?;
+    }
+
+    match groups.next() {
+        Some(_) => Err(invalid()),
+        None => Ok(bytes),
+    }
+}
+
+/// Format little-endian address bytes in the `AA:BB:CC:DD:EE:FF` form.
+fn format_address(bytes: &[u8; 6], f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    let [b0, b1, b2, b3, b4, b5] = bytes;
+    write!(
+        f,
+        "{:02X}:{:02X}:{:02X}:{:02X}:{:02X}:{:02X}",
+        b5, b4, b3, b2, b1, b0
+    )
+}
+
+/// Formats the address alone, without its type.
+impl fmt::Display for BleAddress {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        format_address(&self.val, f)
+    }
+}
+
+impl fmt::Display for ClassicAddress {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        format_address(&self.0, f)
+    }
+}
+
+impl FromStr for ClassicAddress {
+    type Err = BluetoothError;
+
+    fn from_str(addr: &str) -> Result<Self, Self::Err> {
+        Ok(ClassicAddress(parse_address(addr)?))
+    }
+}
+
 impl From<u64> for ClassicAddress {
     fn from(addr: u64) -> Self {
         let addr = u64_to_6lsb(addr);
@@ -149,6 +359,121 @@ mod tests {
         ));
     }
 
+    #[test]
+    fn format_addresses() {
+        let addr = BleAddress::new(0xAABBCCDDEE0F, BleAddressKind::Random);
+        assert_eq!(addr.to_string(), "AA:BB:CC:DD:EE:0F");
+
+        let addr = ClassicAddress::from(0x112233445566);
+        assert_eq!(addr.to_string(), "11:22:33:44:55:66");
+    }
+
+    #[test]
+    fn parse_addresses() {
+        assert_eq!(
+            BleAddress::parse("aa:BB:cc:DD:ee:0F", BleAddressKind::Random),
+            Ok(BleAddress::new(0xAABBCCDDEE0F, BleAddressKind::Random))
+        );
+        assert_eq!(
+            "11:22:33:44:55:66".parse::<ClassicAddress>(),
+            Ok(ClassicAddress::from(0x112233445566))
+        );
+
+        for addr in [
+            "",
+            "11:22:33:44:55",
+            "11:22:33:44:55:66:77",
+            "11:22:33:44:55:GG",
+            "1:22:33:44:55:666",
+            "+1:22:33:44:55:66",
+            "11-22-33-44-55-66",
+        ] {
+            assert!(
+                matches!(
+                    addr.parse::<ClassicAddress>(),
+                    Err(BluetoothError::BadTypeConversion(_))
+                ),
+                "{}",
+                addr
+            );
+        }
+    }
+
+    #[test]
+    fn random_address_kinds() {
+        let kind = |addr| BleAddress::new(addr, BleAddressKind::Random);
+        assert_eq!(
+            kind(0xC00000000001).random_kind(),
+            Some(RandomAddressKind::Static)
+        );
+        assert_eq!(
+            kind(0x7FFFFFFFFFFF).random_kind(),
+            Some(RandomAddressKind::ResolvablePrivate)
+        );
+        assert_eq!(
+            kind(0x3FFFFFFFFFFF).random_kind(),
+            Some(RandomAddressKind::NonResolvablePrivate)
+        );
+        assert_eq!(kind(0x800000000000).random_kind(), None);
+
+        let public = BleAddress::new(0xC00000000001, BleAddressKind::Public);
+        assert_eq!(public.random_kind(), None);
+    }
+
+    /// Sample data of the random address hash function.
+    /// Bluetooth Core Specification, Vol 3, Part H, Appendix D.7.
+    const SAMPLE_IRK: u128 = 0xec0234a357c8ad05341010a60a397d9b;
+    const SAMPLE_PRAND: u32 = 0x708194;
+    const SAMPLE_HASH: u32 = 0x0dfbaa;
+
+    #[test]
+    fn ah_sample_data() {
+        assert_eq!(ah(SAMPLE_IRK, SAMPLE_PRAND), SAMPLE_HASH);
+    }
+
+    #[test]
+    fn irk_store_resolves_private_addresses() {
+        let identity = IdentityAddress::try_from(BleAddress::new(
+            0x112233445566,
+            BleAddressKind::Public,
+        ))
+        .unwrap();
+        let rpa = BleAddress::new(0x7081940dfbaa, BleAddressKind::Random);
+        assert_eq!(
+            rpa.random_kind(),
+            Some(RandomAddressKind::ResolvablePrivate)
+        );
+
+        let mut store = IrkStore::new();
+        assert_eq!(store.resolve(&rpa), None);
+
+        store.insert(identity, SAMPLE_IRK ^ 1);
+        assert_eq!(store.resolve(&rpa), None);
+        store.insert(identity, SAMPLE_IRK);
+        assert_eq!(store.resolve(&rpa), Some(identity));
+        assert_eq!(store.resolve(&identity.address()), Some(identity));
+
+        // Public addresses with the same bits aren't resolved.
+        let public = BleAddress::new(0x7081940dfbaa, BleAddressKind::Public);
+        assert_eq!(store.resolve(&public), None);
+
+        assert_eq!(store.remove(&identity), Some(SAMPLE_IRK));
+        assert_eq!(store.resolve(&rpa), None);
+    }
+
+    #[test]
+    fn identity_addresses() {
+        let addr =
+            |addr, kind| IdentityAddress::try_from(BleAddress::new(addr, kind));
+        assert!(addr(0x7081940dfbaa, BleAddressKind::Public).is_ok());
+        assert!(addr(0xC00000000001, BleAddressKind::Random).is_ok());
+        assert!(matches!(
+            addr(0x7081940dfbaa, BleAddressKind::Random),
+            Err(BluetoothError::BadTypeConversion(_))
+        ));
+        assert!(addr(0x3FFFFFFFFFFF, BleAddressKind::Random).is_err());
+    }
+
     #[test]
     fn test_u64_to_6lsb() {
         // Test a case where the input number is smaller than 6 bytes
@@ -12,18 +12,40 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
-use super::{BleAddress, BluetoothError};
+use std::time::Duration;
+
+use super::{AdStructure, AdStructures, BleAddress, BluetoothError, BtUuid};
 
 /// Holds data related to an incoming BLE Advertisement. This includes
 /// information about the advertisement (e.g. address of sender) as well as
 /// data sections extracted from the advertisement. Platform-specific methods
 /// should be written to load in data sections from incoming advertisements.
+///
+/// Data sections are only available once loaded, i.e. when selected through
+/// the `data_selector` of `api::BleAdapter::start_scan()`. Getters of data
+/// sections that weren't loaded return `BluetoothError::FailedPrecondition`.
 #[derive(Clone, Debug)]
 pub struct BleAdvertisement {
     address: BleAddress,
     rssi: Option<DecibelMilliwatts>,
     tx_power: Option<DecibelMilliwatts>,
-    service_data_16bit_uuid: Option<Vec<ServiceData<u16>>>,
+    advertisement_type: Option<BleAdvertisementType>,
+    flags: Option<Option<u8>>,
+    local_name: Option<Option<String>>,
+    shortened_local_name: Option<Option<String>>,
+    service_uuids_16bit: Option<Vec<BtUuid>>,
+    service_uuids_32bit: Option<Vec<BtUuid>>,
+    service_uuids_128bit: Option<Vec<BtUuid>>,
+    service_data_16bit_uuid: Option<Vec<ServiceData>>,
+    service_data_32bit_uuid: Option<Vec<ServiceData>>,
+    service_data_128bit_uuid: Option<Vec<ServiceData>>,
+    manufacturer_data: Option<Vec<ManufacturerData>>,
+    appearance: Option<Option<u16>>,
+    advertising_interval: Option<Option<Duration>>,
+    /// AD structures of the advertising data and scan response as the
+    /// platform received them, if it exposes them. Dropped once a data
+    /// section is set or dropped, see `retain_data()`.
+    raw_data: Option<Vec<u8>>,
 }
 
 /// Decibel-milliwatt or dBm is a dimensionless absolute unit expressing the
@@ -31,6 +53,14 @@ pub struct BleAdvertisement {
 /// 1 mW is 0 dBm and a 10 dBm increase represents a ten-fold increase in power.
 type DecibelMilliwatts = i16;
 
+/// Build the error returned by getters of data sections that weren't loaded.
+fn not_loaded(data: &str) -> BluetoothError {
+    BluetoothError::FailedPrecondition(format!(
+        "No {} has been loaded into this advertisement.",
+        data
+    ))
+}
+
 impl BleAdvertisement {
     /// Construct a new `BleAdvertisement` instance.
     pub fn new(
@@ -42,8 +72,247 @@ impl BleAdvertisement {
             address,
             rssi,
             tx_power,
+            advertisement_type: None,
+            flags: None,
+            local_name: None,
+            shortened_local_name: None,
+            service_uuids_16bit: None,
+            service_uuids_32bit: None,
+            service_uuids_128bit: None,
             service_data_16bit_uuid: None,
+            service_data_32bit_uuid: None,
+            service_data_128bit_uuid: None,
+            manufacturer_data: None,
+            appearance: None,
+            advertising_interval: None,
+            raw_data: None,
+        }
+    }
+
+    /// Construct a fully populated `BleAdvertisement` from the raw advertising
+    /// and scan response payloads, as sent over the air. Pass an empty scan
+    /// response for passive scans or non-scannable advertisements. The
+    /// transmit power is taken from the TX Power Level AD structure, if any.
+    /// Bluetooth Core Specification, Vol 3, Part C, Section 11.
+    pub fn from_raw(
+        address: BleAddress,
+        rssi: Option<DecibelMilliwatts>,
+        adv_data: &[u8],
+        scan_response: &[u8],
+    ) -> Result<Self, BluetoothError> {
+        let structures = AdStructures::new(adv_data)
+            .chain(AdStructures::new(scan_response))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let mut adv = BleAdvertisement::new(address, rssi, None);
+        adv.load_ad_structures(&structures, BleDataTypeId::ALL)?;
+        adv.set_raw_data([adv_data, scan_response].concat());
+
+        Ok(adv)
+    }
+
+    /// Load data of selected data types into self from already split AD
+    /// structures. Shared by every platform so that data sections are parsed
+    /// the same way regardless of where the raw bytes come from.
+    /// See: Supplement to the Bluetooth Core Specification Part A, Section 1.
+    pub(crate) fn load_ad_structures(
+        &mut self,
+        structures: &[AdStructure],
+        datatype_ids: &[BleDataTypeId],
+    ) -> Result<(), BluetoothError> {
+        for datatype_id in datatype_ids {
+            let sections = structures
+                .iter()
+                .filter(|structure| datatype_id.matches(structure.data_type()))
+                .map(AdStructure::data);
+
+            match datatype_id {
+                BleDataTypeId::Flags => {
+                    self.flags = Some(first(sections, parse_flags)?)
+                }
+                BleDataTypeId::ServiceUuids16Bit => {
+                    self.service_uuids_16bit =
+                        Some(flat(sections, parse_uuids::<2>)?)
+                }
+                BleDataTypeId::ServiceUuids32Bit => {
+                    self.service_uuids_32bit =
+                        Some(flat(sections, parse_uuids::<4>)?)
+                }
+                BleDataTypeId::ServiceUuids128Bit => {
+                    self.service_uuids_128bit =
+                        Some(flat(sections, parse_uuids::<16>)?)
+                }
+                BleDataTypeId::ShortenedLocalName => {
+                    self.shortened_local_name =
+                        Some(first(sections, parse_local_name)?)
+                }
+                BleDataTypeId::CompleteLocalName => {
+                    self.local_name = Some(first(sections, parse_local_name)?)
+                }
+                BleDataTypeId::TxPowerLevel => {
+                    // Platforms may report the transmit power out of band, so
+                    // only override it when it's actually advertised.
+                    if let Some(tx_power) = first(sections, parse_tx_power)? {
+                        self.tx_power = Some(tx_power);
+                    }
+                }
+                BleDataTypeId::ServiceData16BitUuid => {
+                    self.service_data_16bit_uuid =
+                        Some(all(sections, parse_service_data::<2>)?)
+                }
+                BleDataTypeId::Appearance => {
+                    self.appearance = Some(first(sections, parse_appearance)?)
+                }
+                BleDataTypeId::AdvertisingInterval => {
+                    self.advertising_interval =
+                        Some(first(sections, parse_advertising_interval)?)
+                }
+                BleDataTypeId::ServiceData32BitUuid => {
+                    self.service_data_32bit_uuid =
+                        Some(all(sections, parse_service_data::<4>)?)
+                }
+                BleDataTypeId::ServiceData128BitUuid => {
+                    self.service_data_128bit_uuid =
+                        Some(all(sections, parse_service_data::<16>)?)
+                }
+                BleDataTypeId::ManufacturerSpecificData => {
+                    self.manufacturer_data =
+                        Some(all(sections, parse_manufacturer_data)?)
+                }
+            };
+        }
+
+        Ok(())
+    }
+
+    /// Keep the AD structures the platform received the advertisement in.
+    pub(crate) fn set_raw_data(&mut self, data: Vec<u8>) {
+        self.raw_data = Some(data);
+    }
+
+    /// Retrieve the AD structures the platform received the advertisement
+    /// in, including the ones of unknown types, if it exposes them and no
+    /// data section was dropped since.
+    #[cfg(any(test, feature = "capture"))]
+    pub(crate) fn raw_data(&self) -> Option<&[u8]> {
+        self.raw_data.as_deref()
+    }
+
+    /// Serialize the loaded data sections back into AD structures, e.g. to
+    /// record advertisements whose original payload the platform doesn't
+    /// expose. The result parses back to the same data sections, but may not
+    /// be the bytes sent over the air, e.g. incomplete UUID lists are sent as
+    /// complete ones. The transmit power isn't included, see `tx_power()`.
+    ///
+    /// An AD structure holds at most 254 bytes of data. UUID lists longer
+    /// than that are split across several structures, which parse back to the
+    /// same list. Fails with `BluetoothError::InvalidArgument` if another
+    /// data section is longer.
+    #[cfg(any(test, feature = "capture"))]
+    pub(crate) fn to_ad_bytes(&self) -> Result<Vec<u8>, BluetoothError> {
+        let mut sections: Vec<(BleDataTypeId, Vec<u8>)> = Vec::new();
+        let mut uuids = |datatype_id, uuids: &Option<Vec<BtUuid>>, len| {
+            if let Some(uuids) = uuids.as_ref().filter(|u| !u.is_empty()) {
+                for uuids in uuids.chunks(MAX_AD_DATA_LEN / len) {
+                    let data = uuids.iter().flat_map(|u| uuid_bytes(*u, len));
+                    sections.push((datatype_id, data.collect()));
+                }
+            }
+        };
+        uuids(
+            BleDataTypeId::ServiceUuids16Bit,
+            &self.service_uuids_16bit,
+            2,
+        );
+        uuids(
+            BleDataTypeId::ServiceUuids32Bit,
+            &self.service_uuids_32bit,
+            4,
+        );
+        uuids(
+            BleDataTypeId::ServiceUuids128Bit,
+            &self.service_uuids_128bit,
+            16,
+        );
+
+        let service_data = [
+            (
+                BleDataTypeId::ServiceData16BitUuid,
+                &self.service_data_16bit_uuid,
+                2,
+            ),
+            (
+                BleDataTypeId::ServiceData32BitUuid,
+                &self.service_data_32bit_uuid,
+                4,
+            ),
+            (
+                BleDataTypeId::ServiceData128BitUuid,
+                &self.service_data_128bit_uuid,
+                16,
+            ),
+        ];
+        for (datatype_id, service_data, len) in service_data {
+            for service_data in service_data.iter().flatten() {
+                let mut data = uuid_bytes(service_data.uuid(), len);
+                data.extend_from_slice(service_data.data());
+                sections.push((datatype_id, data));
+            }
+        }
+
+        for manufacturer_data in self.manufacturer_data.iter().flatten() {
+            let mut data =
+                manufacturer_data.company_id().to_le_bytes().to_vec();
+            data.extend_from_slice(manufacturer_data.data());
+            sections.push((BleDataTypeId::ManufacturerSpecificData, data));
+        }
+
+        let names = [
+            (
+                BleDataTypeId::ShortenedLocalName,
+                &self.shortened_local_name,
+            ),
+            (BleDataTypeId::CompleteLocalName, &self.local_name),
+        ];
+        for (datatype_id, name) in names {
+            if let Some(Some(name)) = name {
+                sections.push((datatype_id, name.as_bytes().to_vec()));
+            }
+        }
+
+        if let Some(Some(flags)) = self.flags {
+            sections.push((BleDataTypeId::Flags, vec![flags]));
+        }
+        if let Some(Some(appearance)) = self.appearance {
+            let data = appearance.to_le_bytes().to_vec();
+            sections.push((BleDataTypeId::Appearance, data));
+        }
+        if let Some(Some(interval)) = self.advertising_interval {
+            let units =
+                interval.as_micros() / ADVERTISING_INTERVAL_UNIT.as_micros();
+            let units = u16::try_from(units).unwrap_or(u16::MAX);
+            let data = units.to_le_bytes().to_vec();
+            sections.push((BleDataTypeId::AdvertisingInterval, data));
+        }
+
+        let mut bytes = Vec::new();
+        for (datatype_id, data) in sections {
+            if data.len() > MAX_AD_DATA_LEN {
+                return Err(BluetoothError::InvalidArgument(format!(
+                    "{:?} data of {} bytes exceeds {} bytes",
+                    datatype_id,
+                    data.len(),
+                    MAX_AD_DATA_LEN
+                )));
+            }
+
+            // The length covers the type byte too.
+            bytes.push(data.len() as u8 + 1);
+            bytes.push(datatype_id as u8);
+            bytes.extend_from_slice(&data);
         }
+
+        Ok(bytes)
     }
 
     /// Retrieve the `BleAddress` that emitted this advertisement.
@@ -64,50 +333,566 @@ impl BleAdvertisement {
         self.tx_power
     }
 
+    /// Setter for the type of PDU this advertisement was received in.
+    pub fn set_advertisement_type(
+        &mut self,
+        advertisement_type: BleAdvertisementType,
+    ) {
+        self.advertisement_type = Some(advertisement_type);
+    }
+
+    /// Retrieve the type of PDU this advertisement was received in, if the
+    /// platform reports it.
+    pub fn advertisement_type(&self) -> Option<BleAdvertisementType> {
+        self.advertisement_type
+    }
+
+    /// Drop every loaded data section whose type isn't in `datatype_ids`, e.g.
+    /// data only loaded to check a `ScanFilter`.
+    pub(crate) fn retain_data(&mut self, datatype_ids: &[BleDataTypeId]) {
+        for datatype_id in BleDataTypeId::ALL {
+            if datatype_ids.contains(datatype_id) {
+                continue;
+            }
+
+            self.raw_data = None;
+            match datatype_id {
+                BleDataTypeId::Flags => self.flags = None,
+                BleDataTypeId::ServiceUuids16Bit => {
+                    self.service_uuids_16bit = None
+                }
+                BleDataTypeId::ServiceUuids32Bit => {
+                    self.service_uuids_32bit = None
+                }
+                BleDataTypeId::ServiceUuids128Bit => {
+                    self.service_uuids_128bit = None
+                }
+                BleDataTypeId::ShortenedLocalName => {
+                    self.shortened_local_name = None
+                }
+                BleDataTypeId::CompleteLocalName => self.local_name = None,
+                // The transmit power isn't a data section, see `tx_power()`.
+                BleDataTypeId::TxPowerLevel => (),
+                BleDataTypeId::ServiceData16BitUuid => {
+                    self.service_data_16bit_uuid = None
+                }
+                BleDataTypeId::Appearance => self.appearance = None,
+                BleDataTypeId::AdvertisingInterval => {
+                    self.advertising_interval = None
+                }
+                BleDataTypeId::ServiceData32BitUuid => {
+                    self.service_data_32bit_uuid = None
+                }
+                BleDataTypeId::ServiceData128BitUuid => {
+                    self.service_data_128bit_uuid = None
+                }
+                BleDataTypeId::ManufacturerSpecificData => {
+                    self.manufacturer_data = None
+                }
+            }
+        }
+    }
+
+    /// Setter for the Flags field. Public so that tests can build
+    /// advertisements to inject into the simulated platform, as are all other
+    /// data section setters.
+    pub fn set_flags(&mut self, flags: Option<u8>) {
+        self.raw_data = None;
+        self.flags = Some(flags);
+    }
+
+    /// Getter for the Flags field, a bitmask of `flags` constants. `None` if
+    /// the advertisement didn't include one.
+    pub fn flags(&self) -> Result<Option<u8>, BluetoothError> {
+        self.flags.ok_or_else(|| not_loaded("flags"))
+    }
+
+    /// Setter for the Complete Local Name field.
+    pub fn set_local_name(&mut self, name: Option<String>) {
+        self.raw_data = None;
+        self.local_name = Some(name);
+    }
+
+    /// Getter for the Complete Local Name field.
+    pub fn local_name(&self) -> Result<Option<&str>, BluetoothError> {
+        match &self.local_name {
+            Some(name) => Ok(name.as_deref()),
+            None => Err(not_loaded("complete local name")),
+        }
+    }
+
+    /// Setter for the Shortened Local Name field.
+    pub fn set_shortened_local_name(&mut self, name: Option<String>) {
+        self.raw_data = None;
+        self.shortened_local_name = Some(name);
+    }
+
+    /// Getter for the Shortened Local Name field, a prefix of the complete
+    /// local name sent when the full name doesn't fit.
+    pub fn shortened_local_name(&self) -> Result<Option<&str>, BluetoothError> {
+        match &self.shortened_local_name {
+            Some(name) => Ok(name.as_deref()),
+            None => Err(not_loaded("shortened local name")),
+        }
+    }
+
+    /// Setter for the list of 16-bit Service Class UUIDs.
+    pub fn set_service_uuids_16bit(&mut self, uuids: Vec<BtUuid>) {
+        self.raw_data = None;
+        self.service_uuids_16bit = Some(uuids);
+    }
+
+    /// Getter for the list of 16-bit Service Class UUIDs, merging complete
+    /// and incomplete lists.
+    pub fn service_uuids_16bit(&self) -> Result<&Vec<BtUuid>, BluetoothError> {
+        self.service_uuids_16bit
+            .as_ref()
+            .ok_or_else(|| not_loaded("16-bit service UUID list"))
+    }
+
+    /// Setter for the list of 32-bit Service Class UUIDs.
+    pub fn set_service_uuids_32bit(&mut self, uuids: Vec<BtUuid>) {
+        self.raw_data = None;
+        self.service_uuids_32bit = Some(uuids);
+    }
+
+    /// Getter for the list of 32-bit Service Class UUIDs, merging complete
+    /// and incomplete lists.
+    pub fn service_uuids_32bit(&self) -> Result<&Vec<BtUuid>, BluetoothError> {
+        self.service_uuids_32bit
+            .as_ref()
+            .ok_or_else(|| not_loaded("32-bit service UUID list"))
+    }
+
+    /// Setter for the list of 128-bit Service Class UUIDs.
+    pub fn set_service_uuids_128bit(&mut self, uuids: Vec<BtUuid>) {
+        self.raw_data = None;
+        self.service_uuids_128bit = Some(uuids);
+    }
+
+    /// Getter for the list of 128-bit Service Class UUIDs, merging complete
+    /// and incomplete lists.
+    pub fn service_uuids_128bit(&self) -> Result<&Vec<BtUuid>, BluetoothError> {
+        self.service_uuids_128bit
+            .as_ref()
+            .ok_or_else(|| not_loaded("128-bit service UUID list"))
+    }
+
     /// Setter for `ServiceData` field with 16bit UUID.
-    pub(crate) fn set_service_data_16bit_uuid(
+    pub fn set_service_data_16bit_uuid(
         &mut self,
-        data_sections: Vec<ServiceData<u16>>,
+        data_sections: Vec<ServiceData>,
     ) {
+        self.raw_data = None;
         self.service_data_16bit_uuid = Some(data_sections);
     }
 
     /// Getter for `ServiceData` field with 16bit UUID.
     pub fn service_data_16bit_uuid(
         &self,
-    ) -> Result<&Vec<ServiceData<u16>>, BluetoothError> {
+    ) -> Result<&Vec<ServiceData>, BluetoothError> {
         match &self.service_data_16bit_uuid {
-            Some(service_data) => Ok(&service_data),
+            Some(service_data) => Ok(service_data),
             None => Err(BluetoothError::FailedPrecondition(String::from(
                 "No service data has been loaded into this advertisement.",
             ))),
         }
     }
+
+    /// Setter for `ServiceData` field with 32bit UUID.
+    pub fn set_service_data_32bit_uuid(
+        &mut self,
+        data_sections: Vec<ServiceData>,
+    ) {
+        self.raw_data = None;
+        self.service_data_32bit_uuid = Some(data_sections);
+    }
+
+    /// Getter for `ServiceData` field with 32bit UUID.
+    pub fn service_data_32bit_uuid(
+        &self,
+    ) -> Result<&Vec<ServiceData>, BluetoothError> {
+        self.service_data_32bit_uuid
+            .as_ref()
+            .ok_or_else(|| not_loaded("32-bit UUID service data"))
+    }
+
+    /// Setter for `ServiceData` field with 128bit UUID.
+    pub fn set_service_data_128bit_uuid(
+        &mut self,
+        data_sections: Vec<ServiceData>,
+    ) {
+        self.raw_data = None;
+        self.service_data_128bit_uuid = Some(data_sections);
+    }
+
+    /// Getter for `ServiceData` field with 128bit UUID.
+    pub fn service_data_128bit_uuid(
+        &self,
+    ) -> Result<&Vec<ServiceData>, BluetoothError> {
+        self.service_data_128bit_uuid
+            .as_ref()
+            .ok_or_else(|| not_loaded("128-bit UUID service data"))
+    }
+
+    /// Setter for the Manufacturer Specific Data field.
+    pub fn set_manufacturer_data(
+        &mut self,
+        data_sections: Vec<ManufacturerData>,
+    ) {
+        self.raw_data = None;
+        self.manufacturer_data = Some(data_sections);
+    }
+
+    /// Getter for the Manufacturer Specific Data field.
+    pub fn manufacturer_data(
+        &self,
+    ) -> Result<&Vec<ManufacturerData>, BluetoothError> {
+        self.manufacturer_data
+            .as_ref()
+            .ok_or_else(|| not_loaded("manufacturer specific data"))
+    }
+
+    /// Setter for the Appearance field.
+    pub fn set_appearance(&mut self, appearance: Option<u16>) {
+        self.raw_data = None;
+        self.appearance = Some(appearance);
+    }
+
+    /// Getter for the Appearance field, the external appearance category of
+    /// the device as listed in Bluetooth Assigned Numbers, Section 2.6.
+    pub fn appearance(&self) -> Result<Option<u16>, BluetoothError> {
+        self.appearance.ok_or_else(|| not_loaded("appearance"))
+    }
+
+    /// Setter for the Advertising Interval field.
+    pub fn set_advertising_interval(&mut self, interval: Option<Duration>) {
+        self.raw_data = None;
+        self.advertising_interval = Some(interval);
+    }
+
+    /// Getter for the Advertising Interval field.
+    pub fn advertising_interval(
+        &self,
+    ) -> Result<Option<Duration>, BluetoothError> {
+        self.advertising_interval
+            .ok_or_else(|| not_loaded("advertising interval"))
+    }
 }
 
 /// Enum denoting the assigned number of Bluetooth common data types. Used for
 /// fetching specific data sections from a Bluetooth advertisement.
 /// Bluetooth Assigned Numbers, Section 2.3
-#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
 pub enum BleDataTypeId {
+    Flags = 0x01,
+    /// Complete List of 16-bit Service Class UUIDs. Selecting it also loads
+    /// the Incomplete List (0x02).
+    ServiceUuids16Bit = 0x03,
+    /// Complete List of 32-bit Service Class UUIDs. Selecting it also loads
+    /// the Incomplete List (0x04).
+    ServiceUuids32Bit = 0x05,
+    /// Complete List of 128-bit Service Class UUIDs. Selecting it also loads
+    /// the Incomplete List (0x06).
+    ServiceUuids128Bit = 0x07,
+    ShortenedLocalName = 0x08,
+    CompleteLocalName = 0x09,
+    TxPowerLevel = 0x0a,
     ServiceData16BitUuid = 0x16,
+    Appearance = 0x19,
+    AdvertisingInterval = 0x1a,
+    ServiceData32BitUuid = 0x20,
+    ServiceData128BitUuid = 0x21,
+    ManufacturerSpecificData = 0xff,
+}
+
+impl BleDataTypeId {
+    /// Every supported data type, for loading all data sections at once.
+    pub const ALL: &'static [BleDataTypeId] = &[
+        BleDataTypeId::Flags,
+        BleDataTypeId::ServiceUuids16Bit,
+        BleDataTypeId::ServiceUuids32Bit,
+        BleDataTypeId::ServiceUuids128Bit,
+        BleDataTypeId::ShortenedLocalName,
+        BleDataTypeId::CompleteLocalName,
+        BleDataTypeId::TxPowerLevel,
+        BleDataTypeId::ServiceData16BitUuid,
+        BleDataTypeId::Appearance,
+        BleDataTypeId::AdvertisingInterval,
+        BleDataTypeId::ServiceData32BitUuid,
+        BleDataTypeId::ServiceData128BitUuid,
+        BleDataTypeId::ManufacturerSpecificData,
+    ];
+
+    /// Whether an AD structure of type `data_type` holds data of this type.
+    fn matches(&self, data_type: u8) -> bool {
+        let id = *self as u8;
+        match self {
+            BleDataTypeId::ServiceUuids16Bit
+            | BleDataTypeId::ServiceUuids32Bit
+            | BleDataTypeId::ServiceUuids128Bit => {
+                data_type == id || data_type == id - 1
+            }
+            _ => data_type == id,
+        }
+    }
+}
+
+impl TryFrom<u8> for BleDataTypeId {
+    type Error = BluetoothError;
+
+    fn try_from(data_type: u8) -> Result<Self, Self::Error> {
+        BleDataTypeId::ALL
+            .iter()
+            .find(|datatype_id| datatype_id.matches(data_type))
+            .copied()
+            .ok_or_else(|| {
+                BluetoothError::NotSupported(format!(
+                    "AD type {:#04x} isn't supported",
+                    data_type
+                ))
+            })
+    }
+}
+
+/// Type of the advertising PDU an advertisement was received in, as the
+/// Event_Type bit field of an HCI LE Extended Advertising Report. Legacy PDU
+/// types are provided as constants.
+/// Bluetooth Core Specification, Vol 4, Part E, Section 7.7.65.13.
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(transparent))]
+pub struct BleAdvertisementType(u16);
+
+impl BleAdvertisementType {
+    pub const CONNECTABLE: u16 = 1 << 0;
+    pub const SCANNABLE: u16 = 1 << 1;
+    pub const DIRECTED: u16 = 1 << 2;
+    pub const SCAN_RESPONSE: u16 = 1 << 3;
+    pub const LEGACY: u16 = 1 << 4;
+
+    /// Connectable and scannable undirected legacy advertising.
+    pub const ADV_IND: Self =
+        Self(Self::LEGACY | Self::CONNECTABLE | Self::SCANNABLE);
+    /// Connectable directed legacy advertising.
+    pub const ADV_DIRECT_IND: Self =
+        Self(Self::LEGACY | Self::CONNECTABLE | Self::DIRECTED);
+    /// Scannable undirected legacy advertising.
+    pub const ADV_SCAN_IND: Self = Self(Self::LEGACY | Self::SCANNABLE);
+    /// Non-connectable and non-scannable undirected legacy advertising.
+    pub const ADV_NONCONN_IND: Self = Self(Self::LEGACY);
+    /// Scan response to a legacy `ADV_IND` or `ADV_SCAN_IND`.
+    pub const SCAN_RSP: Self =
+        Self(Self::LEGACY | Self::SCAN_RESPONSE | Self::SCANNABLE);
+
+    /// Construct from the Event_Type bits. Bits past the ones defined as
+    /// constants are ignored.
+    pub fn from_bits(bits: u16) -> Self {
+        BleAdvertisementType(bits & 0x1f)
+    }
+
+    pub fn bits(&self) -> u16 {
+        self.0
+    }
+
+    pub fn is_connectable(&self) -> bool {
+        self.0 & Self::CONNECTABLE != 0
+    }
+
+    pub fn is_scannable(&self) -> bool {
+        self.0 & Self::SCANNABLE != 0
+    }
+
+    pub fn is_directed(&self) -> bool {
+        self.0 & Self::DIRECTED != 0
+    }
+
+    pub fn is_scan_response(&self) -> bool {
+        self.0 & Self::SCAN_RESPONSE != 0
+    }
+
+    /// Whether the advertisement used legacy rather than extended PDUs.
+    pub fn is_legacy(&self) -> bool {
+        self.0 & Self::LEGACY != 0
+    }
+}
+
+/// Bits of the Flags data type.
+/// Bluetooth Supplement to the Core Specification, Part A, Section 1.3.
+pub mod flags {
+    pub const LE_LIMITED_DISCOVERABLE: u8 = 1 << 0;
+    pub const LE_GENERAL_DISCOVERABLE: u8 = 1 << 1;
+    pub const BR_EDR_NOT_SUPPORTED: u8 = 1 << 2;
+    pub const SIMULTANEOUS_LE_BR_EDR_CONTROLLER: u8 = 1 << 3;
+}
+
+/// Unit of the Advertising Interval data type.
+/// Bluetooth Supplement to the Core Specification, Part A, Section 1.15.
+const ADVERTISING_INTERVAL_UNIT: Duration = Duration::from_micros(625);
+
+/// Largest data an AD structure holds, as its length byte covers the data
+/// type too.
+#[cfg(any(test, feature = "capture"))]
+const MAX_AD_DATA_LEN: usize = u8::MAX as usize - 1;
+
+/// Parse the first of `sections`, for data types that appear at most once.
+fn first<'a, T>(
+    mut sections: impl Iterator<Item = &'a [u8]>,
+    parse: impl Fn(&[u8]) -> Result<T, BluetoothError>,
+) -> Result<Option<T>, BluetoothError> {
+    sections.next().map(parse).transpose()
 }
 
-/// Struct representing the Bluetooth Service Data common data type. `U` should
-/// be one of the valid uuid sizes, specified in:
+/// Parse every one of `sections`, for data types that may be repeated.
+fn all<'a, T>(
+    sections: impl Iterator<Item = &'a [u8]>,
+    parse: impl Fn(&[u8]) -> Result<T, BluetoothError>,
+) -> Result<Vec<T>, BluetoothError> {
+    sections.map(parse).collect()
+}
+
+/// Parse every one of `sections` into a list, concatenating the lists.
+fn flat<'a, T>(
+    sections: impl Iterator<Item = &'a [u8]>,
+    parse: impl Fn(&[u8]) -> Result<Vec<T>, BluetoothError>,
+) -> Result<Vec<T>, BluetoothError> {
+    Ok(all(sections, parse)?.into_iter().flatten().collect())
+}
+
+/// Check that fixed-size AD data is exactly `N` bytes long.
+fn exact<const N: usize>(
+    data: &[u8],
+    name: &str,
+) -> Result<[u8; N], BluetoothError> {
+    data.try_into().map_err(|_| {
+        BluetoothError::MalformedData(format!(
+            "{} must be {} bytes, got {}",
+            name,
+            N,
+            data.len()
+        ))
+    })
+}
+
+/// Bluetooth Supplement to the Core Specification, Part A, Section 1.3.
+fn parse_flags(data: &[u8]) -> Result<u8, BluetoothError> {
+    // Only the first octet has flags assigned, later ones must be ignored.
+    data.first().copied().ok_or_else(|| {
+        BluetoothError::MalformedData(String::from("flags must not be empty"))
+    })
+}
+
+/// Parse a list of `N`-byte UUIDs, all sent in little-endian byte order.
+/// Bluetooth Supplement to the Core Specification, Part A, Section 1.1.
+fn parse_uuids<const N: usize>(
+    data: &[u8],
+) -> Result<Vec<BtUuid>, BluetoothError> {
+    if !data.len().is_multiple_of(N) {
+        return Err(BluetoothError::MalformedData(format!(
+            "service UUID list of {} bytes isn't a multiple of {}",
+            data.len(),
+            N
+        )));
+    }
+
+    Ok(data
+        .chunks_exact(N)
+        .filter_map(BtUuid::from_le_bytes)
+        .collect())
+}
+
+/// Serialize `uuid` in little-endian byte order on `len` bytes, the inverse
+/// of `parse_uuids()`. 16 and 32-bit UUIDs are sent as the bits the Bluetooth
+/// Base UUID doesn't fix.
+#[cfg(any(test, feature = "capture"))]
+fn uuid_bytes(uuid: BtUuid, len: usize) -> Vec<u8> {
+    match len {
+        16 => uuid.as_u128().to_le_bytes().to_vec(),
+        _ => ((uuid.as_u128() >> 96) as u32).to_le_bytes()[..len].to_vec(),
+    }
+}
+
+/// Bluetooth Supplement to the Core Specification, Part A, Section 1.2.
+/// Shortened names may be cut in the middle of a UTF-8 sequence, so invalid
+/// sequences are replaced instead of rejected.
+fn parse_local_name(data: &[u8]) -> Result<String, BluetoothError> {
+    Ok(String::from_utf8_lossy(data).into_owned())
+}
+
+/// Bluetooth Supplement to the Core Specification, Part A, Section 1.5.
+fn parse_tx_power(data: &[u8]) -> Result<DecibelMilliwatts, BluetoothError> {
+    let [power] = exact::<1>(data, "TX power level")?;
+    Ok(DecibelMilliwatts::from(power as i8))
+}
+
+/// Parse Service Data, which the AD data starts with the UUID of in
+/// little-endian byte order.
+/// Bluetooth Supplement to the Core Specification, Part A, Section 1.11.
+fn parse_service_data<const N: usize>(
+    data: &[u8],
+) -> Result<ServiceData, BluetoothError> {
+    let uuid =
+        data.get(..N)
+            .and_then(BtUuid::from_le_bytes)
+            .ok_or_else(|| {
+                BluetoothError::MalformedData(format!(
+                    "{}-bit UUID service data needs at least {} bytes, got {}",
+                    N * 8,
+                    N,
+                    data.len()
+                ))
+            })?;
+
+    Ok(ServiceData::new(uuid, data[N..].to_vec()))
+}
+
+/// Bluetooth Supplement to the Core Specification, Part A, Section 1.4.
+fn parse_manufacturer_data(
+    data: &[u8],
+) -> Result<ManufacturerData, BluetoothError> {
+    match data {
+        [lo, hi, data @ ..] => Ok(ManufacturerData::new(
+            u16::from_le_bytes([*lo, *hi]),
+            data.to_vec(),
+        )),
+        _ => Err(BluetoothError::MalformedData(format!(
+            "manufacturer specific data needs at least 2 bytes, got {}",
+            data.len()
+        ))),
+    }
+}
+
+/// Bluetooth Supplement to the Core Specification, Part A, Section 1.12.
+fn parse_appearance(data: &[u8]) -> Result<u16, BluetoothError> {
+    Ok(u16::from_le_bytes(exact(data, "appearance")?))
+}
+
+/// Bluetooth Supplement to the Core Specification, Part A, Section 1.15.
+fn parse_advertising_interval(data: &[u8]) -> Result<Duration, BluetoothError> {
+    let interval = u16::from_le_bytes(exact(data, "advertising interval")?);
+    Ok(ADVERTISING_INTERVAL_UNIT * u32::from(interval))
+}
+
+/// Struct representing the Bluetooth Service Data common data type, sent with
+/// a 16, 32 or 128-bit UUID.
 /// Bluetooth Supplement to the Core Specification, Part A, Section 1.11.
 #[derive(Clone, PartialEq, Eq, Debug)]
-pub struct ServiceData<U: Copy> {
-    uuid: U,
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ServiceData {
+    uuid: BtUuid,
+    #[cfg_attr(
+        feature = "serde",
+        serde(with = "super::serialization::hex_bytes")
+    )]
     data: Vec<u8>,
 }
 
-impl<U: Copy> ServiceData<U> {
-    pub fn new(uuid: U, data: Vec<u8>) -> Self {
+impl ServiceData {
+    pub fn new(uuid: BtUuid, data: Vec<u8>) -> Self {
         ServiceData { uuid, data }
     }
 
-    pub fn uuid(&self) -> U {
+    pub fn uuid(&self) -> BtUuid {
         self.uuid
     }
 
@@ -116,6 +901,34 @@ impl<U: Copy> ServiceData<U> {
     }
 }
 
+/// Struct representing the Bluetooth Manufacturer Specific Data common data
+/// type, identified by a company identifier from Bluetooth Assigned Numbers.
+/// Bluetooth Supplement to the Core Specification, Part A, Section 1.4.
+#[derive(Clone, PartialEq, Eq, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ManufacturerData {
+    company_id: u16,
+    #[cfg_attr(
+        feature = "serde",
+        serde(with = "super::serialization::hex_bytes")
+    )]
+    data: Vec<u8>,
+}
+
+impl ManufacturerData {
+    pub fn new(company_id: u16, data: Vec<u8>) -> Self {
+        ManufacturerData { company_id, data }
+    }
+
+    pub fn company_id(&self) -> u16 {
+        self.company_id
+    }
+
+    pub fn data(&self) -> &Vec<u8> {
+        &self.data
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -137,8 +950,8 @@ mod tests {
         let mut ad = BleAdvertisement::new(address, Some(-60), Some(10));
 
         let service_data = vec![
-            ServiceData::new(0x1234, vec![0x01, 0x02, 0x03]),
-            ServiceData::new(0x5678, vec![0x04, 0x05]),
+            ServiceData::new(BtUuid::from_u16(0x1234), vec![0x01, 0x02, 0x03]),
+            ServiceData::new(BtUuid::from_u16(0x5678), vec![0x04, 0x05]),
         ];
 
         ad.set_service_data_16bit_uuid(service_data.clone());
@@ -160,9 +973,358 @@ mod tests {
         ));
     }
 
+    #[test]
+    fn ble_advertisement_retain_data() {
+        let address = BleAddress::new(0x112233445566, BleAddressKind::Public);
+        let mut ad = BleAdvertisement::new(address, Some(-60), Some(10));
+        let service_data =
+            vec![ServiceData::new(BtUuid::from_u16(0x1234), vec![0x01])];
+        ad.set_service_data_16bit_uuid(service_data.clone());
+        ad.set_local_name(Some(String::from("Buds")));
+
+        ad.retain_data(&[BleDataTypeId::ServiceData16BitUuid]);
+        assert_eq!(*ad.service_data_16bit_uuid().unwrap(), service_data);
+        assert!(ad.local_name().is_err());
+
+        ad.retain_data(&[]);
+        assert!(ad.service_data_16bit_uuid().is_err());
+        assert_eq!(ad.tx_power(), Some(10));
+    }
+
+    #[test]
+    fn ble_advertisement_from_raw() {
+        let address = BleAddress::new(0x112233445566, BleAddressKind::Random);
+        let adv_data = [
+            0x02, 0x01, 0x06, // Flags
+            0x06, 0x16, 0x2c, 0xfe, 0x01, 0x02, 0x03, // Service Data
+        ];
+        let scan_response = [
+            0x02, 0x0a, 0xf4, // TX Power Level
+            0x03, 0x16, 0x0f, 0x18, // Service Data, no payload
+        ];
+
+        let mut ad = BleAdvertisement::from_raw(
+            address,
+            Some(-70),
+            &adv_data,
+            &scan_response,
+        )
+        .unwrap();
+
+        assert_eq!(ad.address(), address);
+        assert_eq!(ad.rssi(), Some(-70));
+        assert_eq!(ad.tx_power(), Some(-12));
+        assert_eq!(
+            *ad.service_data_16bit_uuid().unwrap(),
+            vec![
+                ServiceData::new(BtUuid::FAST_PAIR, vec![0x01, 0x02, 0x03]),
+                ServiceData::new(BtUuid::BATTERY_SERVICE, vec![]),
+            ]
+        );
+
+        assert_eq!(
+            ad.raw_data(),
+            Some([adv_data.as_slice(), &scan_response].concat().as_slice())
+        );
+        ad.retain_data(BleDataTypeId::ALL);
+        assert!(ad.raw_data().is_some());
+        ad.retain_data(&[BleDataTypeId::ServiceData16BitUuid]);
+        assert_eq!(ad.raw_data(), None);
+    }
+
+    #[test]
+    fn ble_advertisement_from_raw_without_data() {
+        let address = BleAddress::new(0x112233445566, BleAddressKind::Public);
+        let ad = BleAdvertisement::from_raw(address, None, &[], &[]).unwrap();
+
+        assert_eq!(ad.tx_power(), None);
+        assert!(ad.service_data_16bit_uuid().unwrap().is_empty());
+    }
+
+    #[test]
+    fn ble_advertisement_from_raw_malformed() {
+        let address = BleAddress::new(0x112233445566, BleAddressKind::Public);
+
+        // Length octet overruns the payload.
+        let result =
+            BleAdvertisement::from_raw(address, None, &[0x05, 0x16, 0x2c], &[]);
+        assert!(matches!(result, Err(BluetoothError::MalformedData(_))));
+
+        // Service data too short to hold a 16-bit UUID.
+        let result =
+            BleAdvertisement::from_raw(address, None, &[], &[0x02, 0x16, 0x2c]);
+        assert!(matches!(result, Err(BluetoothError::MalformedData(_))));
+    }
+
+    #[test]
+    fn ble_advertisement_load_selected_ad_structures() {
+        let address = BleAddress::new(0x112233445566, BleAddressKind::Public);
+        let structures = [AdStructure::new(0x16, &[0x2c, 0xfe, 0x01])];
+
+        let mut ad = BleAdvertisement::new(address, None, None);
+        ad.load_ad_structures(&structures, &[]).unwrap();
+        assert!(ad.service_data_16bit_uuid().is_err());
+
+        ad.load_ad_structures(
+            &structures,
+            &[BleDataTypeId::ServiceData16BitUuid],
+        )
+        .unwrap();
+        assert_eq!(
+            *ad.service_data_16bit_uuid().unwrap(),
+            vec![ServiceData::new(BtUuid::FAST_PAIR, vec![0x01])]
+        );
+    }
+
+    #[test]
+    fn ble_advertisement_from_raw_all_data_types() {
+        let address = BleAddress::new(0x112233445566, BleAddressKind::Public);
+        let adv_data = [
+            0x02, 0x01, 0x06, // Flags
+            0x03, 0x02, 0x0f, 0x18, // Incomplete 16-bit UUIDs
+            0x03, 0x03, 0x0a, 0x18, // Complete 16-bit UUIDs
+            0x05, 0x05, 0x04, 0x03, 0x02, 0x01, // Complete 32-bit UUIDs
+            0x11, 0x07, 0x7c, 0x92, 0x67, 0x4d, 0x2c, 0xf1, 0x86, 0x88, 0xdb,
+            0x4f, 0x15, 0x25, 0x2c, 0xfe, 0x21,
+            0xdf, // Complete 128-bit UUIDs
+            0x04, 0x08, 0x42, 0x75, 0x64, // Shortened Local Name
+            0x03, 0x19, 0x41, 0x09, // Appearance
+            0x03, 0x1a, 0xa0, 0x00, // Advertising Interval
+        ];
+        let scan_response = [
+            0x05, 0x09, 0x42, 0x75, 0x64, 0x73, // Complete Local Name
+            0x06, 0x20, 0x04, 0x03, 0x02, 0x01,
+            0xaa, // 32-bit Service Data
+            0x12, 0x21, 0x7c, 0x92, 0x67, 0x4d, 0x2c, 0xf1, 0x86, 0x88, 0xdb,
+            0x4f, 0x15, 0x25, 0x2c, 0xfe, 0x21, 0xdf, 0xbb, // 128-bit SD
+            0x05, 0xff, 0xe0, 0x00, 0x01, 0x02, // Manufacturer Data
+        ];
+
+        let ad = BleAdvertisement::from_raw(
+            address,
+            None,
+            &adv_data,
+            &scan_response,
+        )
+        .unwrap();
+
+        assert_eq!(
+            ad.flags().unwrap(),
+            Some(flags::LE_GENERAL_DISCOVERABLE | flags::BR_EDR_NOT_SUPPORTED)
+        );
+        assert_eq!(
+            *ad.service_uuids_16bit().unwrap(),
+            vec![BtUuid::BATTERY_SERVICE, BtUuid::DEVICE_INFORMATION]
+        );
+        assert_eq!(
+            *ad.service_uuids_32bit().unwrap(),
+            vec![BtUuid::from_u32(0x01020304)]
+        );
+        assert_eq!(
+            *ad.service_uuids_128bit().unwrap(),
+            vec![BtUuid::MESSAGE_STREAM]
+        );
+        assert_eq!(ad.shortened_local_name().unwrap(), Some("Bud"));
+        assert_eq!(ad.local_name().unwrap(), Some("Buds"));
+        assert_eq!(ad.tx_power(), None);
+        assert!(ad.service_data_16bit_uuid().unwrap().is_empty());
+        assert_eq!(
+            *ad.service_data_32bit_uuid().unwrap(),
+            vec![ServiceData::new(BtUuid::from_u32(0x01020304), vec![0xaa])]
+        );
+        assert_eq!(
+            *ad.service_data_128bit_uuid().unwrap(),
+            vec![ServiceData::new(BtUuid::MESSAGE_STREAM, vec![0xbb])]
+        );
+        assert_eq!(
+            *ad.manufacturer_data().unwrap(),
+            vec![ManufacturerData::new(0x00e0, vec![0x01, 0x02])]
+        );
+        assert_eq!(ad.appearance().unwrap(), Some(0x0941));
+        assert_eq!(
+            ad.advertising_interval().unwrap(),
+            Some(Duration::from_millis(100))
+        );
+    }
+
+    #[test]
+    fn ble_advertisement_to_ad_bytes_round_trip() {
+        let address = BleAddress::new(0x112233445566, BleAddressKind::Public);
+        let mut ad = BleAdvertisement::new(address, Some(-60), Some(-10));
+        ad.load_ad_structures(&[], BleDataTypeId::ALL).unwrap();
+        ad.set_flags(Some(flags::LE_GENERAL_DISCOVERABLE));
+        ad.set_local_name(Some(String::from("Buds")));
+        ad.set_service_uuids_16bit(vec![BtUuid::FAST_PAIR]);
+        ad.set_service_uuids_128bit(vec![BtUuid::MESSAGE_STREAM]);
+        ad.set_service_data_16bit_uuid(vec![ServiceData::new(
+            BtUuid::FAST_PAIR,
+            vec![0x01, 0x02],
+        )]);
+        ad.set_service_data_32bit_uuid(vec![ServiceData::new(
+            BtUuid::from_u32(0x01020304),
+            vec![0x03],
+        )]);
+        ad.set_manufacturer_data(vec![ManufacturerData::new(
+            0x00e0,
+            vec![0x04],
+        )]);
+        ad.set_appearance(Some(0x0941));
+        ad.set_advertising_interval(Some(Duration::from_millis(100)));
+
+        let bytes = ad.to_ad_bytes().unwrap();
+        assert_eq!(&bytes[..4], &[0x03, 0x03, 0x2c, 0xfe]);
+
+        let parsed =
+            BleAdvertisement::from_raw(address, Some(-60), &bytes, &[])
+                .unwrap();
+        assert_eq!(parsed.to_ad_bytes(), Ok(bytes));
+        assert_eq!(
+            parsed.flags().unwrap(),
+            Some(flags::LE_GENERAL_DISCOVERABLE)
+        );
+        assert_eq!(parsed.local_name().unwrap(), Some("Buds"));
+        assert_eq!(parsed.shortened_local_name().unwrap(), None);
+        assert_eq!(
+            *parsed.service_uuids_128bit().unwrap(),
+            vec![BtUuid::MESSAGE_STREAM]
+        );
+        assert_eq!(
+            parsed.service_data_32bit_uuid().unwrap(),
+            ad.service_data_32bit_uuid().unwrap()
+        );
+        assert_eq!(
+            parsed.advertising_interval().unwrap(),
+            Some(Duration::from_millis(100))
+        );
+        // The transmit power is reported out of band.
+        assert_eq!(parsed.tx_power(), None);
+    }
+
+    #[test]
+    fn ble_advertisement_to_ad_bytes_long_sections() {
+        let address = BleAddress::new(0x112233445566, BleAddressKind::Public);
+        let mut ad = BleAdvertisement::new(address, None, None);
+
+        // 16 UUIDs take 256 bytes, so the last one gets its own structure.
+        let uuids: Vec<_> = (0..16).map(BtUuid::from_u128).collect();
+        ad.set_service_uuids_128bit(uuids.clone());
+        let bytes = ad.to_ad_bytes().unwrap();
+        assert_eq!(bytes.len(), 2 + 15 * 16 + 2 + 16);
+        assert_eq!(&bytes[..2], &[0xf1, 0x07]);
+        assert_eq!(&bytes[2 + 15 * 16..][..2], &[0x11, 0x07]);
+        let parsed =
+            BleAdvertisement::from_raw(address, None, &bytes, &[]).unwrap();
+        assert_eq!(*parsed.service_uuids_128bit().unwrap(), uuids);
+
+        // Other data types can't be split.
+        ad.set_local_name(Some("a".repeat(254)));
+        assert!(ad.to_ad_bytes().is_ok());
+        ad.set_local_name(Some("a".repeat(255)));
+        assert!(matches!(
+            ad.to_ad_bytes(),
+            Err(BluetoothError::InvalidArgument(_))
+        ));
+    }
+
+    #[test]
+    fn ble_advertisement_from_raw_missing_data_types() {
+        let address = BleAddress::new(0x112233445566, BleAddressKind::Public);
+        let ad = BleAdvertisement::from_raw(address, None, &[], &[]).unwrap();
+
+        assert_eq!(ad.flags().unwrap(), None);
+        assert_eq!(ad.local_name().unwrap(), None);
+        assert_eq!(ad.shortened_local_name().unwrap(), None);
+        assert!(ad.service_uuids_128bit().unwrap().is_empty());
+        assert!(ad.manufacturer_data().unwrap().is_empty());
+        assert_eq!(ad.appearance().unwrap(), None);
+        assert_eq!(ad.advertising_interval().unwrap(), None);
+    }
+
+    #[test]
+    fn ble_advertisement_from_raw_malformed_data_types() {
+        let address = BleAddress::new(0x112233445566, BleAddressKind::Public);
+        let malformed: [&[u8]; 6] = [
+            &[0x01, 0x01],                   // Empty flags
+            &[0x04, 0x03, 0x0f, 0x18, 0x0a], // Odd 16-bit UUID list
+            &[0x03, 0x0a, 0xf4, 0x00],       // Long TX power level
+            &[0x03, 0x20, 0x01, 0x02],       // Short 32-bit UUID service data
+            &[0x02, 0xff, 0xe0],             // Short manufacturer data
+            &[0x02, 0x19, 0x41],             // Short appearance
+        ];
+
+        for adv_data in malformed {
+            let result =
+                BleAdvertisement::from_raw(address, None, adv_data, &[]);
+            assert!(matches!(result, Err(BluetoothError::MalformedData(_))));
+        }
+    }
+
+    #[test]
+    fn ble_advertisement_tx_power_level() {
+        let address = BleAddress::new(0x112233445566, BleAddressKind::Public);
+        let structures = [AdStructure::new(0x0a, &[0x04])];
+
+        // Out of band transmit power is kept if none is advertised.
+        let mut ad = BleAdvertisement::new(address, None, Some(-20));
+        ad.load_ad_structures(&[], &[BleDataTypeId::TxPowerLevel])
+            .unwrap();
+        assert_eq!(ad.tx_power(), Some(-20));
+
+        ad.load_ad_structures(&structures, &[BleDataTypeId::TxPowerLevel])
+            .unwrap();
+        assert_eq!(ad.tx_power(), Some(4));
+    }
+
+    #[test]
+    fn ble_data_type_id_from_u8() {
+        for datatype_id in BleDataTypeId::ALL {
+            assert_eq!(
+                BleDataTypeId::try_from(*datatype_id as u8),
+                Ok(*datatype_id)
+            );
+        }
+        assert_eq!(
+            BleDataTypeId::try_from(0x06),
+            Ok(BleDataTypeId::ServiceUuids128Bit)
+        );
+        assert!(matches!(
+            BleDataTypeId::try_from(0x24),
+            Err(BluetoothError::NotSupported(_))
+        ));
+    }
+
+    #[test]
+    fn ble_advertisement_type() {
+        let address = BleAddress::new(0x112233445566, BleAddressKind::Public);
+        let mut ad = BleAdvertisement::new(address, None, None);
+        assert_eq!(ad.advertisement_type(), None);
+
+        ad.set_advertisement_type(BleAdvertisementType::ADV_NONCONN_IND);
+        let adv_type = ad.advertisement_type().unwrap();
+        assert!(adv_type.is_legacy());
+        assert!(!adv_type.is_connectable());
+        assert!(!adv_type.is_scannable());
+
+        let adv_type = BleAdvertisementType::from_bits(0xff05);
+        assert_eq!(adv_type.bits(), 0x05);
+        assert!(adv_type.is_connectable());
+        assert!(adv_type.is_directed());
+        assert!(!adv_type.is_scan_response());
+        assert!(!adv_type.is_legacy());
+        assert!(BleAdvertisementType::SCAN_RSP.is_scan_response());
+    }
+
+    #[test]
+    fn manufacturer_data_new() {
+        let data = vec![0x01, 0x02];
+        let manufacturer_data = ManufacturerData::new(0x00e0, data.clone());
+        assert_eq!(manufacturer_data.company_id(), 0x00e0);
+        assert_eq!(*manufacturer_data.data(), data);
+    }
+
     #[test]
     fn service_data_new() {
-        let uuid = 0x1234;
+        let uuid = BtUuid::FAST_PAIR;
         let data = vec![0x01, 0x02, 0x03];
 
         let service_data = ServiceData::new(uuid, data.clone());
@@ -0,0 +1,267 @@
+// Copyright 2023 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::time::Duration;
+
+use super::{
+    stream::WatchStream, BleDataTypeId, BluetoothError, BtUuid,
+    ManufacturerData, ServiceData,
+};
+
+/// Maximum length of the data of a legacy advertisement.
+/// Bluetooth Core Specification, Vol 6, Part B, Section 2.3.1.
+const LEGACY_MAX_LEN: usize = 31;
+
+/// Maximum length of the data of an extended advertisement.
+/// Bluetooth Core Specification, Vol 4, Part E, Section 7.8.54.
+const EXTENDED_MAX_LEN: usize = 1650;
+
+/// Length of the Flags AD structure, which platforms add to every
+/// advertisement they publish.
+const FLAGS_LEN: usize = 3;
+
+/// Payload of an advertisement published with
+/// `api::BleAdvertiser::start_advertising()`.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct AdvertisingData {
+    service_data: Vec<ServiceData>,
+    manufacturer_data: Vec<ManufacturerData>,
+}
+
+impl AdvertisingData {
+    /// Advertise `data` for the service identified by `uuid`. UUIDs derived
+    /// from the Bluetooth Base UUID are sent in their shortest form.
+    pub fn with_service_data(mut self, uuid: BtUuid, data: Vec<u8>) -> Self {
+        self.service_data.push(ServiceData::new(uuid, data));
+        self
+    }
+
+    /// Advertise `data` on behalf of the company identified by `company_id`.
+    pub fn with_manufacturer_data(
+        mut self,
+        company_id: u16,
+        data: Vec<u8>,
+    ) -> Self {
+        self.manufacturer_data
+            .push(ManufacturerData::new(company_id, data));
+        self
+    }
+
+    pub fn service_data(&self) -> &[ServiceData] {
+        &self.service_data
+    }
+
+    pub fn manufacturer_data(&self) -> &[ManufacturerData] {
+        &self.manufacturer_data
+    }
+
+    /// Build the (type, data) pairs of the AD structures carrying the
+    /// payload, in the order they were added.
+    pub(crate) fn ad_sections(&self) -> Vec<(BleDataTypeId, Vec<u8>)> {
+        let service_data = self.service_data.iter().map(|service_data| {
+            let mut data = service_data.uuid().to_le_bytes();
+            let datatype_id = match data.len() {
+                2 => BleDataTypeId::ServiceData16BitUuid,
+                4 => BleDataTypeId::ServiceData32BitUuid,
+                _ => BleDataTypeId::ServiceData128BitUuid,
+            };
+            data.extend_from_slice(service_data.data());
+            (datatype_id, data)
+        });
+        let manufacturer_data = self.manufacturer_data.iter().map(|data| {
+            let mut bytes = data.company_id().to_le_bytes().to_vec();
+            bytes.extend_from_slice(data.data());
+            (BleDataTypeId::ManufacturerSpecificData, bytes)
+        });
+
+        service_data.chain(manufacturer_data).collect()
+    }
+
+    /// Serialize the payload into AD structures, adding a TX Power Level
+    /// structure if `tx_power` is set.
+    /// See: Supplement to the Bluetooth Core Specification Part A, Section 1.
+    pub(crate) fn to_bytes(&self, tx_power: Option<i8>) -> Vec<u8> {
+        let tx_power = tx_power.map(|tx_power| {
+            (BleDataTypeId::TxPowerLevel, tx_power.to_le_bytes().to_vec())
+        });
+
+        let mut bytes = Vec::new();
+        for (datatype_id, data) in
+            self.ad_sections().into_iter().chain(tx_power)
+        {
+            // The length covers the type byte too.
+            bytes.push(data.len() as u8 + 1);
+            bytes.push(datatype_id as u8);
+            bytes.extend_from_slice(&data);
+        }
+
+        bytes
+    }
+
+    /// Check that the payload fits in an advertisement published with
+    /// `settings`, next to the Flags platforms add.
+    pub(crate) fn check_len(
+        &self,
+        settings: &AdvertisingSettings,
+    ) -> Result<(), BluetoothError> {
+        if let Some((_, data)) = self
+            .ad_sections()
+            .iter()
+            .find(|(_, data)| data.len() > u8::MAX as usize - 1)
+        {
+            return Err(BluetoothError::InvalidArgument(format!(
+                "AD structure data of {} bytes exceeds 254 bytes",
+                data.len()
+            )));
+        }
+
+        let max_len = match settings.extended() {
+            true => EXTENDED_MAX_LEN,
+            false => LEGACY_MAX_LEN,
+        };
+        let len = FLAGS_LEN + self.to_bytes(settings.tx_power()).len();
+        if len > max_len {
+            return Err(BluetoothError::InvalidArgument(format!(
+                "advertisement of {} bytes exceeds {} bytes",
+                len, max_len
+            )));
+        }
+
+        Ok(())
+    }
+}
+
+/// Parameters of a published advertisement. Platforms ignore the ones the OS
+/// doesn't let applications choose.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct AdvertisingSettings {
+    extended: bool,
+    tx_power: Option<i8>,
+    interval: Option<Duration>,
+}
+
+impl AdvertisingSettings {
+    /// Whether to publish with extended PDUs, which carry larger payloads but
+    /// aren't received by older scanners.
+    pub fn with_extended(mut self, extended: bool) -> Self {
+        self.extended = extended;
+        self
+    }
+
+    /// Transmit at `tx_power` dBm and advertise the level, so scanners can
+    /// estimate their distance from the path loss.
+    pub fn with_tx_power(mut self, tx_power: i8) -> Self {
+        self.tx_power = Some(tx_power);
+        self
+    }
+
+    /// Hint that the advertisement should be sent every `interval`.
+    pub fn with_interval(mut self, interval: Duration) -> Self {
+        self.interval = Some(interval);
+        self
+    }
+
+    pub fn extended(&self) -> bool {
+        self.extended
+    }
+
+    pub fn tx_power(&self) -> Option<i8> {
+        self.tx_power
+    }
+
+    pub fn interval(&self) -> Option<Duration> {
+        self.interval
+    }
+}
+
+/// State of a published advertisement.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "snake_case"))]
+pub enum AdvertisingStatus {
+    /// The OS accepted the advertisement, but is waiting for radio resources
+    /// to send it.
+    Waiting,
+    /// The advertisement is being sent.
+    Started,
+    /// The platform stopped sending the advertisement.
+    Stopped,
+}
+
+/// Stream of status updates of a published advertisement, as returned by
+/// `api::BleAdvertiser::start_advertising()`. Advertising stops once the
+/// stream is dropped. The stream ends after `AdvertisingStatus::Stopped` or
+/// an error, e.g. because the radio was switched off.
+pub type AdvertisingStream = WatchStream<AdvertisingStatus>;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::common::{BleAddress, BleAddressKind, BleAdvertisement};
+
+    #[test]
+    fn to_bytes_round_trip() {
+        let uuid = BtUuid::from_u128(0x0123456789abcdef0123456789abcdef);
+        let data = AdvertisingData::default()
+            .with_service_data(BtUuid::FAST_PAIR, vec![0x01, 0x02])
+            .with_service_data(uuid, vec![0x03])
+            .with_manufacturer_data(0x00e0, vec![0x04]);
+
+        let bytes = data.to_bytes(Some(-10));
+        assert_eq!(
+            &bytes[..10],
+            &[0x05, 0x16, 0x2c, 0xfe, 0x01, 0x02, 0x12, 0x21, 0xef, 0xcd]
+        );
+
+        let address = BleAddress::new(1, BleAddressKind::Random);
+        let ad =
+            BleAdvertisement::from_raw(address, None, &bytes, &[]).unwrap();
+        assert_eq!(
+            ad.service_data_16bit_uuid().unwrap(),
+            &vec![ServiceData::new(BtUuid::FAST_PAIR, vec![0x01, 0x02])]
+        );
+        assert_eq!(
+            ad.service_data_128bit_uuid().unwrap(),
+            &vec![ServiceData::new(uuid, vec![0x03])]
+        );
+        assert_eq!(
+            ad.manufacturer_data().unwrap(),
+            &vec![ManufacturerData::new(0x00e0, vec![0x04])]
+        );
+        assert_eq!(ad.tx_power(), Some(-10));
+    }
+
+    #[test]
+    fn check_len() {
+        let legacy = AdvertisingSettings::default();
+        let extended = AdvertisingSettings::default().with_extended(true);
+
+        // Flags, plus 2 bytes of header, 2 of company ID and 22 of data.
+        let data = AdvertisingData::default()
+            .with_manufacturer_data(0x00e0, vec![0; 22]);
+        assert_eq!(data.check_len(&legacy), Ok(()));
+        assert!(matches!(
+            data.check_len(&legacy.clone().with_tx_power(0)),
+            Err(BluetoothError::InvalidArgument(_))
+        ));
+        assert_eq!(data.check_len(&extended.clone().with_tx_power(0)), Ok(()));
+
+        let data = AdvertisingData::default()
+            .with_manufacturer_data(0x00e0, vec![0; 253]);
+        assert!(matches!(
+            data.check_len(&extended),
+            Err(BluetoothError::InvalidArgument(_))
+        ));
+    }
+}
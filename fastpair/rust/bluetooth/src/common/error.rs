@@ -16,7 +16,12 @@ use thiserror::Error;
 
 /// Library error type.
 #[non_exhaustive]
-#[derive(Error, Debug, PartialEq)]
+#[derive(Error, Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(
+    feature = "serde",
+    serde(tag = "kind", content = "message", rename_all = "snake_case")
+)]
 pub enum BluetoothError {
     /// Reported when the user attempts a bad type conversion, e.g. converting
     /// a BLE random address to a BT Classic address.
@@ -30,6 +35,18 @@ pub enum BluetoothError {
     /// E.g. The user calls `stop_scan()` or polls the advertisement stream
     #[error("failed precondition: {0}")]
     FailedPrecondition(String),
+    /// Reported when a GATT operation fails, e.g. because the device rejected
+    /// a write or doesn't have the requested characteristic.
+    #[error("GATT error: {0}")]
+    Gatt(String),
+    /// Reported when the user passes an argument the operation can't accept,
+    /// e.g. an advertising payload too large to be published.
+    #[error("invalid argument: {0}")]
+    InvalidArgument(String),
+    /// Reported when raw data received over the air, e.g. an advertising
+    /// payload, doesn't follow the format mandated by the specification.
+    #[error("malformed data: {0}")]
+    MalformedData(String),
     /// Reported when the user calls an operation that is supported by their
     /// Operating System, but is not supported by their device.
     /// E.g. a Windows machine with an old BT Classic adapter that
@@ -47,11 +64,22 @@ pub enum BluetoothError {
     Internal(String),
 }
 
+impl From<std::io::Error> for BluetoothError {
+    fn from(err: std::io::Error) -> Self {
+        BluetoothError::System(err.to_string())
+    }
+}
+
 /// Abstraction around platform-specific pairing status enums.
 /// `PairingResult::Failure` should eventually be converted to
 /// `BluetoothError::PairingFailed`.
 #[non_exhaustive]
-#[derive(Debug)]
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(
+    feature = "serde",
+    serde(tag = "result", content = "reason", rename_all = "snake_case")
+)]
 pub enum PairingResult {
     Success,
     AlreadyPaired,
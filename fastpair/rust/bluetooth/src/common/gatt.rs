@@ -0,0 +1,147 @@
+// Copyright 2023 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use super::{stream::WatchStream, BtUuid};
+
+/// A primary GATT service discovered on a remote device with
+/// `api::BleDevice::discover_services()`.
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct GattService {
+    uuid: BtUuid,
+    handle: u16,
+}
+
+impl GattService {
+    pub(crate) fn new(uuid: BtUuid, handle: u16) -> Self {
+        GattService { uuid, handle }
+    }
+
+    pub fn uuid(&self) -> BtUuid {
+        self.uuid
+    }
+
+    /// Retrieve the attribute handle of the service declaration, which
+    /// identifies the service on its device.
+    pub fn handle(&self) -> u16 {
+        self.handle
+    }
+}
+
+/// A GATT characteristic discovered on a remote device with
+/// `api::BleDevice::discover_characteristics()`.
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct GattCharacteristic {
+    uuid: BtUuid,
+    handle: u16,
+    properties: GattCharacteristicProperties,
+}
+
+impl GattCharacteristic {
+    pub(crate) fn new(
+        uuid: BtUuid,
+        handle: u16,
+        properties: GattCharacteristicProperties,
+    ) -> Self {
+        GattCharacteristic {
+            uuid,
+            handle,
+            properties,
+        }
+    }
+
+    pub fn uuid(&self) -> BtUuid {
+        self.uuid
+    }
+
+    /// Retrieve the attribute handle of the characteristic value, which
+    /// identifies the characteristic on its device.
+    pub fn handle(&self) -> u16 {
+        self.handle
+    }
+
+    pub fn properties(&self) -> GattCharacteristicProperties {
+        self.properties
+    }
+}
+
+/// Operations a characteristic supports, as the bit field of its declaration.
+/// See: Bluetooth Core Specification, Vol 3, Part G, Section 3.3.1.1.
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(transparent))]
+pub struct GattCharacteristicProperties(u8);
+
+impl GattCharacteristicProperties {
+    pub const BROADCAST: u8 = 1 << 0;
+    pub const READ: u8 = 1 << 1;
+    pub const WRITE_WITHOUT_RESPONSE: u8 = 1 << 2;
+    pub const WRITE: u8 = 1 << 3;
+    pub const NOTIFY: u8 = 1 << 4;
+    pub const INDICATE: u8 = 1 << 5;
+    pub const AUTHENTICATED_SIGNED_WRITES: u8 = 1 << 6;
+    pub const EXTENDED_PROPERTIES: u8 = 1 << 7;
+
+    pub fn from_bits(bits: u8) -> Self {
+        GattCharacteristicProperties(bits)
+    }
+
+    pub fn bits(&self) -> u8 {
+        self.0
+    }
+
+    /// Whether every property bit set in `bits` is set.
+    pub fn contains(&self, bits: u8) -> bool {
+        self.0 & bits == bits
+    }
+}
+
+/// Whether a characteristic write waits for the device to acknowledge it.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum WriteType {
+    /// ATT Write Request, failing if the device rejects the value.
+    WithResponse,
+    /// ATT Write Command, which the device doesn't acknowledge.
+    WithoutResponse,
+}
+
+/// Stream of values a characteristic notified or indicated, as returned by
+/// `api::BleDevice::subscribe()`. The subscription ends once the stream is
+/// dropped. The stream ends if the device disconnects.
+pub type NotificationStream = WatchStream<Vec<u8>>;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn characteristic_properties() {
+        let properties = GattCharacteristicProperties::from_bits(
+            GattCharacteristicProperties::WRITE
+                | GattCharacteristicProperties::NOTIFY,
+        );
+
+        assert!(properties.contains(GattCharacteristicProperties::WRITE));
+        assert!(properties.contains(
+            GattCharacteristicProperties::WRITE
+                | GattCharacteristicProperties::NOTIFY
+        ));
+        assert!(!properties.contains(
+            GattCharacteristicProperties::WRITE
+                | GattCharacteristicProperties::READ
+        ));
+        assert_eq!(properties.bits(), 0x18);
+    }
+}
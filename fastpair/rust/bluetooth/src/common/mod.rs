@@ -13,10 +13,35 @@
 // limitations under the License.
 
 /// Module for shared functionality between all Bluetooth platforms.
+mod ad_structure;
+mod adapter;
 mod address;
 mod advertisement;
+mod advertising;
+mod connection;
 mod error;
+mod gatt;
+mod pairing;
+mod rfcomm;
+mod scan;
+#[cfg(feature = "serde")]
+pub(crate) mod serialization;
+mod stream;
+#[cfg(any(test, feature = "sim", feature = "capture"))]
+mod timer;
+mod uuid;
 
+pub use ad_structure::*;
+pub use adapter::*;
 pub use address::*;
 pub use advertisement::*;
+pub use advertising::*;
+pub use connection::*;
 pub use error::*;
+pub use gatt::*;
+pub use pairing::*;
+pub use rfcomm::*;
+pub use scan::*;
+#[cfg(any(test, feature = "sim", feature = "capture"))]
+pub(crate) use timer::*;
+pub use uuid::*;
@@ -0,0 +1,96 @@
+// Copyright 2023 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use async_trait::async_trait;
+
+use super::{stream::WatchStream, BleAddress, ClassicAddress};
+
+/// Answers the requests a pairing ceremony makes, as passed to
+/// `api::ClassicDevice::pair()` and `api::BleDevice::pair()`. Which request
+/// is made depends on the association model the devices negotiated from
+/// their I/O capabilities.
+/// Pairing waits for each answer, so implementations may do I/O, e.g. check
+/// a passkey with the device over GATT.
+///
+/// PINs are the digits of a 6-digit passkey, zero-padded, or the legacy PIN
+/// code of devices without Secure Simple Pairing.
+#[async_trait]
+pub trait PairingHandler: Send + Sync {
+    /// Just Works: accept pairing without any PIN. Returns whether to pair.
+    async fn confirm(&self) -> bool;
+
+    /// Display `pin` for the user to enter on the device. Pairing continues
+    /// once this returns.
+    async fn display_pin(&self, pin: &str);
+
+    /// Provide the PIN the device displays, or `None` to reject pairing.
+    async fn provide_pin(&self) -> Option<String>;
+
+    /// Numeric Comparison: check that `pin` matches the one the device
+    /// displays. Returns whether to pair.
+    async fn confirm_pin_match(&self, pin: &str) -> bool;
+}
+
+/// Minimum protection the link with a device must get from LE pairing, as
+/// passed to `api::BleDevice::pair()`.
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "snake_case"))]
+pub enum ProtectionLevel {
+    /// Encrypt the link, even with keys exchanged without authentication
+    /// (Just Works).
+    Encryption,
+    /// Encrypt the link with keys exchanged with authentication against
+    /// man-in-the-middle attacks, i.e. with a passkey or Numeric Comparison.
+    EncryptionAndAuthentication,
+}
+
+/// Whether the platform has stored keys for a device.
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "snake_case"))]
+pub enum BondState {
+    NotBonded,
+    Bonded,
+}
+
+/// Stream of the bond states of a device, as returned by
+/// `api::BleDevice::bond_state_changes()`. It yields the current state first,
+/// then every change.
+pub type BondStateStream = WatchStream<BondState>;
+
+/// A device bonded with an adapter, identified by the address of the
+/// transport it bonded over.
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "snake_case"))]
+pub enum BondedDevice {
+    Ble(BleAddress),
+    Classic(ClassicAddress),
+}
+
+/// A bond an adapter gained or lost, as reported by
+/// `api::BleAdapter::bond_events()`.
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "snake_case"))]
+pub enum BondEvent {
+    Added(BondedDevice),
+    Removed(BondedDevice),
+}
+
+/// Stream of the bonds an adapter gains and loses, as returned by
+/// `api::BleAdapter::bond_events()`. Watching stops once the stream is
+/// dropped.
+pub type BondEventStream = WatchStream<BondEvent>;
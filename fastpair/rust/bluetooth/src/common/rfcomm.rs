@@ -0,0 +1,80 @@
+// Copyright 2023 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::{
+    io,
+    pin::Pin,
+    task::{Context, Poll},
+};
+
+use futures::{AsyncRead, AsyncWrite};
+
+/// Platform-specific byte stream under an `RfcommSocket`.
+trait Socket: AsyncRead + AsyncWrite + Send {}
+
+impl<T: AsyncRead + AsyncWrite + Send> Socket for T {}
+
+/// An RFCOMM channel to a remote device, as returned by
+/// `api::ClassicDevice::connect_rfcomm()`. Reads return 0 bytes once the
+/// device closed the channel. The channel is closed once the socket is
+/// dropped.
+pub struct RfcommSocket {
+    inner: Pin<Box<dyn Socket>>,
+}
+
+impl RfcommSocket {
+    /// Wrap a platform-specific stream, which must close the channel when
+    /// dropped.
+    pub(crate) fn new(
+        inner: impl AsyncRead + AsyncWrite + Send + 'static,
+    ) -> Self {
+        RfcommSocket {
+            inner: Box::pin(inner),
+        }
+    }
+}
+
+impl AsyncRead for RfcommSocket {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut [u8],
+    ) -> Poll<io::Result<usize>> {
+        self.inner.as_mut().poll_read(cx, buf)
+    }
+}
+
+impl AsyncWrite for RfcommSocket {
+    fn poll_write(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        self.inner.as_mut().poll_write(cx, buf)
+    }
+
+    fn poll_flush(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<io::Result<()>> {
+        self.inner.as_mut().poll_flush(cx)
+    }
+
+    fn poll_close(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<io::Result<()>> {
+        self.inner.as_mut().poll_close(cx)
+    }
+}
@@ -0,0 +1,758 @@
+// Copyright 2023 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::{
+    collections::HashMap,
+    time::{Duration, Instant},
+};
+
+use super::{
+    stream::WatchStream, BleAddress, BleAdvertisement, BleAdvertisementType,
+    BleDataTypeId, BtUuid,
+};
+
+/// Number of addresses remembered for duplicate suppression before the ones
+/// outside the suppression window are forgotten.
+const DUPLICATE_PRUNE_THRESHOLD: usize = 256;
+
+/// Criteria that received advertisements must match to be delivered by a
+/// scan. Every criterion that is set must match, so the default filter lets
+/// every advertisement through. Platforms push criteria down to the OS where
+/// possible and check the rest in software.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct ScanFilter {
+    service_uuid: Option<BtUuid>,
+    service_data: Option<ServiceDataFilter>,
+    manufacturer_id: Option<u16>,
+    address: Option<BleAddress>,
+    local_name: Option<String>,
+    min_rssi: Option<i16>,
+}
+
+/// Criterion on the Service Data of an advertisement: the data of `uuid` must
+/// start with `prefix`, comparing only the bits set in `mask`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ServiceDataFilter {
+    uuid: BtUuid,
+    prefix: Vec<u8>,
+    mask: Vec<u8>,
+}
+
+impl ScanFilter {
+    /// Only match advertisements listing the service `uuid` in their 16, 32
+    /// or 128-bit Service Class UUIDs.
+    pub fn with_service_uuid(mut self, uuid: BtUuid) -> Self {
+        self.service_uuid = Some(uuid);
+        self
+    }
+
+    /// Only match advertisements with Service Data for `uuid`, sent with a
+    /// 16, 32 or 128-bit UUID, starting with `prefix`. If given, only the
+    /// bits set in `mask` are compared, and bytes of `prefix` past the end
+    /// of `mask` are compared in full.
+    pub fn with_service_data(
+        mut self,
+        uuid: BtUuid,
+        prefix: Vec<u8>,
+        mask: Option<Vec<u8>>,
+    ) -> Self {
+        let mut mask = mask.unwrap_or_default();
+        mask.resize(prefix.len(), 0xff);
+        self.service_data = Some(ServiceDataFilter { uuid, prefix, mask });
+        self
+    }
+
+    /// Only match advertisements with Manufacturer Specific Data from the
+    /// company with the assigned number `company_id`.
+    pub fn with_manufacturer_id(mut self, company_id: u16) -> Self {
+        self.manufacturer_id = Some(company_id);
+        self
+    }
+
+    /// Only match advertisements sent from `address`.
+    pub fn with_address(mut self, address: BleAddress) -> Self {
+        self.address = Some(address);
+        self
+    }
+
+    /// Only match advertisements whose local name is exactly `name`. The
+    /// shortened local name is used if the complete one isn't advertised.
+    pub fn with_local_name(mut self, name: impl Into<String>) -> Self {
+        self.local_name = Some(name.into());
+        self
+    }
+
+    /// Only match advertisements received with an RSSI of at least
+    /// `min_rssi` dBm.
+    pub fn with_min_rssi(mut self, min_rssi: i16) -> Self {
+        self.min_rssi = Some(min_rssi);
+        self
+    }
+
+    pub fn service_uuid(&self) -> Option<BtUuid> {
+        self.service_uuid
+    }
+
+    pub fn service_data(&self) -> Option<&ServiceDataFilter> {
+        self.service_data.as_ref()
+    }
+
+    pub fn manufacturer_id(&self) -> Option<u16> {
+        self.manufacturer_id
+    }
+
+    pub fn address(&self) -> Option<BleAddress> {
+        self.address
+    }
+
+    pub fn local_name(&self) -> Option<&str> {
+        self.local_name.as_deref()
+    }
+
+    pub fn min_rssi(&self) -> Option<i16> {
+        self.min_rssi
+    }
+
+    /// Whether `advertisement` matches every criterion of the filter. Data
+    /// types a criterion depends on that weren't loaded never match.
+    pub fn matches(&self, advertisement: &BleAdvertisement) -> bool {
+        self.address
+            .is_none_or(|addr| advertisement.address() == addr)
+            && self.min_rssi.is_none_or(|min_rssi| {
+                advertisement.rssi().is_some_and(|rssi| rssi >= min_rssi)
+            })
+            && self
+                .service_uuid
+                .is_none_or(|uuid| has_service_uuid(advertisement, uuid))
+            && self
+                .service_data
+                .as_ref()
+                .is_none_or(|filter| filter.matches(advertisement))
+            && self.manufacturer_id.is_none_or(|company_id| {
+                advertisement.manufacturer_data().is_ok_and(|data| {
+                    data.iter().any(|data| data.company_id() == company_id)
+                })
+            })
+            && self
+                .local_name
+                .as_deref()
+                .is_none_or(|name| local_name(advertisement) == Some(name))
+    }
+
+    /// Data types to load into advertisements for `data_selector` to be
+    /// honored and the filter to be checked.
+    pub(crate) fn data_types(
+        &self,
+        data_selector: &[BleDataTypeId],
+    ) -> Vec<BleDataTypeId> {
+        let mut datatype_ids = data_selector.to_vec();
+        let mut require = |datatype_id| {
+            if !datatype_ids.contains(&datatype_id) {
+                datatype_ids.push(datatype_id);
+            }
+        };
+
+        if self.service_uuid.is_some() {
+            require(BleDataTypeId::ServiceUuids16Bit);
+            require(BleDataTypeId::ServiceUuids32Bit);
+            require(BleDataTypeId::ServiceUuids128Bit);
+        }
+        if self.service_data.is_some() {
+            require(BleDataTypeId::ServiceData16BitUuid);
+            require(BleDataTypeId::ServiceData32BitUuid);
+            require(BleDataTypeId::ServiceData128BitUuid);
+        }
+        if self.manufacturer_id.is_some() {
+            require(BleDataTypeId::ManufacturerSpecificData);
+        }
+        if self.local_name.is_some() {
+            require(BleDataTypeId::CompleteLocalName);
+            require(BleDataTypeId::ShortenedLocalName);
+        }
+
+        datatype_ids
+    }
+}
+
+impl ServiceDataFilter {
+    pub fn uuid(&self) -> BtUuid {
+        self.uuid
+    }
+
+    pub fn prefix(&self) -> &[u8] {
+        &self.prefix
+    }
+
+    /// Retrieve the mask, which is always as long as the prefix.
+    pub fn mask(&self) -> &[u8] {
+        &self.mask
+    }
+
+    fn matches_data(&self, data: &[u8]) -> bool {
+        data.len() >= self.prefix.len()
+            && self
+                .prefix
+                .iter()
+                .zip(&self.mask)
+                .zip(data)
+                .all(|((prefix, mask), data)| prefix & mask == data & mask)
+    }
+
+    fn matches(&self, advertisement: &BleAdvertisement) -> bool {
+        let data_16bit = advertisement.service_data_16bit_uuid().ok();
+        let data_32bit = advertisement.service_data_32bit_uuid().ok();
+        let data_128bit = advertisement.service_data_128bit_uuid().ok();
+
+        data_16bit
+            .into_iter()
+            .chain(data_32bit)
+            .chain(data_128bit)
+            .flatten()
+            .filter(|data| data.uuid() == self.uuid)
+            .any(|data| self.matches_data(data.data()))
+    }
+}
+
+/// Whether `advertisement` lists `uuid` among its Service Class UUIDs.
+fn has_service_uuid(advertisement: &BleAdvertisement, uuid: BtUuid) -> bool {
+    let uuids_16bit = advertisement.service_uuids_16bit().ok();
+    let uuids_32bit = advertisement.service_uuids_32bit().ok();
+    let uuids_128bit = advertisement.service_uuids_128bit().ok();
+
+    uuids_16bit
+        .into_iter()
+        .chain(uuids_32bit)
+        .chain(uuids_128bit)
+        .flatten()
+        .any(|u| *u == uuid)
+}
+
+/// Retrieve the complete local name, or the shortened one if that's all the
+/// advertisement has.
+fn local_name(advertisement: &BleAdvertisement) -> Option<&str> {
+    match advertisement.local_name() {
+        Ok(Some(name)) => Some(name),
+        _ => advertisement.shortened_local_name().ok().flatten(),
+    }
+}
+
+/// Whether the scanner requests scan responses from scannable advertisers.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub enum ScanMode {
+    /// Only listen, so scan responses are never received.
+    Passive,
+    /// Send scan requests to scannable advertisers.
+    #[default]
+    Active,
+}
+
+/// Parameters of a scan. Platforms apply them where the OS allows it and
+/// emulate the rest in software, except for timing hints, which are ignored
+/// if the OS picks its own timing, and the advertisement types to deliver,
+/// which can only be checked if the OS reports the PDU type of
+/// advertisements. See the `start_scan()` documentation of each platform.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ScanSettings {
+    mode: ScanMode,
+    interval: Option<Duration>,
+    window: Option<Duration>,
+    deliver_non_connectable: bool,
+    extended: bool,
+    duplicate_window: Option<Duration>,
+}
+
+impl Default for ScanSettings {
+    /// Active scanning for connectable advertisements, including extended
+    /// ones, delivering every received advertisement.
+    fn default() -> Self {
+        ScanSettings {
+            mode: ScanMode::Active,
+            interval: None,
+            window: None,
+            deliver_non_connectable: false,
+            extended: true,
+            duplicate_window: None,
+        }
+    }
+}
+
+impl ScanSettings {
+    pub fn with_mode(mut self, mode: ScanMode) -> Self {
+        self.mode = mode;
+        self
+    }
+
+    /// Hint that the controller should listen for `window` every `interval`.
+    pub fn with_interval_and_window(
+        mut self,
+        interval: Duration,
+        window: Duration,
+    ) -> Self {
+        self.interval = Some(interval);
+        self.window = Some(window);
+        self
+    }
+
+    /// Whether to deliver non-connectable advertisements, e.g. beacons.
+    pub fn with_non_connectable(mut self, deliver: bool) -> Self {
+        self.deliver_non_connectable = deliver;
+        self
+    }
+
+    /// Whether to receive advertisements sent with extended PDUs.
+    pub fn with_extended(mut self, extended: bool) -> Self {
+        self.extended = extended;
+        self
+    }
+
+    /// Only deliver one advertisement per address every `window`, dropping
+    /// the ones received in between. Scan responses are judged apart from
+    /// advertisements, so that the scan response following an advertisement
+    /// is still delivered.
+    pub fn with_duplicate_window(mut self, window: Duration) -> Self {
+        self.duplicate_window = Some(window);
+        self
+    }
+
+    pub fn mode(&self) -> ScanMode {
+        self.mode
+    }
+
+    pub fn interval(&self) -> Option<Duration> {
+        self.interval
+    }
+
+    pub fn window(&self) -> Option<Duration> {
+        self.window
+    }
+
+    pub fn deliver_non_connectable(&self) -> bool {
+        self.deliver_non_connectable
+    }
+
+    pub fn extended(&self) -> bool {
+        self.extended
+    }
+
+    pub fn duplicate_window(&self) -> Option<Duration> {
+        self.duplicate_window
+    }
+
+    /// Whether advertisements of `advertisement_type` may be delivered.
+    fn accepts(&self, advertisement_type: BleAdvertisementType) -> bool {
+        // Scan responses are only ever sent to active scanners, and belong to
+        // an advertisement that may be connectable.
+        if advertisement_type.is_scan_response() {
+            return self.mode == ScanMode::Active
+                && (self.extended || advertisement_type.is_legacy());
+        }
+
+        (self.deliver_non_connectable || advertisement_type.is_connectable())
+            && (self.extended || advertisement_type.is_legacy())
+    }
+}
+
+/// Software part of a scan, shared by every platform: checks the settings
+/// and filter the OS couldn't, suppresses duplicates and drops data that was
+/// only loaded for the filter.
+pub(crate) struct ScanState {
+    filter: ScanFilter,
+    settings: ScanSettings,
+    data_selector: Vec<BleDataTypeId>,
+    datatype_ids: Vec<BleDataTypeId>,
+    /// When an advertisement from each address was last delivered, keyed
+    /// by whether it was a scan response.
+    last_delivered: HashMap<(BleAddress, bool), Instant>,
+}
+
+impl ScanState {
+    pub(crate) fn new(
+        filter: &ScanFilter,
+        settings: &ScanSettings,
+        data_selector: &[BleDataTypeId],
+    ) -> Self {
+        ScanState {
+            filter: filter.clone(),
+            settings: settings.clone(),
+            data_selector: data_selector.to_vec(),
+            datatype_ids: filter.data_types(data_selector),
+            last_delivered: HashMap::new(),
+        }
+    }
+
+    /// Data types platforms must load into advertisements before handing
+    /// them to `process()`.
+    pub(crate) fn datatype_ids(&self) -> &[BleDataTypeId] {
+        &self.datatype_ids
+    }
+
+    /// Returns the advertisement to deliver to the end-user, or `None` if it
+    /// must be dropped.
+    pub(crate) fn process(
+        &mut self,
+        advertisement: BleAdvertisement,
+    ) -> Option<BleAdvertisement> {
+        self.process_at(advertisement, Instant::now())
+    }
+
+    /// Like `process()`, with duplicates judged as if it was `now`, e.g. the
+    /// time an advertisement was captured at when replaying it.
+    pub(crate) fn process_at(
+        &mut self,
+        mut advertisement: BleAdvertisement,
+        now: Instant,
+    ) -> Option<BleAdvertisement> {
+        // Platforms that don't report the PDU type deliver everything.
+        let accepted = advertisement
+            .advertisement_type()
+            .is_none_or(|adv_type| self.settings.accepts(adv_type));
+        if !accepted || !self.filter.matches(&advertisement) {
+            return None;
+        }
+
+        if let Some(window) = self.settings.duplicate_window {
+            let is_duplicate = |delivered: &Instant| now - *delivered < window;
+
+            let key = (
+                advertisement.address(),
+                advertisement
+                    .advertisement_type()
+                    .is_some_and(|adv_type| adv_type.is_scan_response()),
+            );
+            if self.last_delivered.get(&key).is_some_and(is_duplicate) {
+                return None;
+            }
+            if self.last_delivered.len() >= DUPLICATE_PRUNE_THRESHOLD {
+                self.last_delivered
+                    .retain(|_, delivered| is_duplicate(delivered));
+            }
+            self.last_delivered.insert(key, now);
+        }
+
+        advertisement.retain_data(&self.data_selector);
+        Some(advertisement)
+    }
+}
+
+/// Stream of advertisements received while scanning, as returned by
+/// `api::BleAdapter::start_scan()`. Scanning stops once the stream is dropped.
+/// The stream ends if the platform stops scanning on its own, e.g. because
+/// the adapter was removed.
+pub type ScanStream = WatchStream<BleAdvertisement>;
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        pin::Pin,
+        sync::{
+            atomic::{AtomicBool, Ordering},
+            Arc,
+        },
+        task::{Context, Poll},
+    };
+
+    use futures::{executor::block_on, Stream, StreamExt};
+
+    use super::*;
+    use crate::common::{BleAddress, BleAddressKind, BluetoothError};
+
+    /// Stream that records when it's dropped, like a platform scan would stop.
+    struct Scan {
+        advertisements: Vec<BleAdvertisement>,
+        stopped: Arc<AtomicBool>,
+    }
+
+    impl Stream for Scan {
+        type Item = Result<BleAdvertisement, BluetoothError>;
+
+        fn poll_next(
+            mut self: Pin<&mut Self>,
+            _cx: &mut Context<'_>,
+        ) -> Poll<Option<Self::Item>> {
+            Poll::Ready(self.advertisements.pop().map(Ok))
+        }
+    }
+
+    impl Drop for Scan {
+        fn drop(&mut self) {
+            self.stopped.store(true, Ordering::SeqCst);
+        }
+    }
+
+    fn advertisement(addr: u64) -> BleAdvertisement {
+        let addr = BleAddress::new(addr, BleAddressKind::Public);
+        BleAdvertisement::new(addr, None, None)
+    }
+
+    fn fast_pair_advertisement() -> BleAdvertisement {
+        let addr = BleAddress::new(0x112233445566, BleAddressKind::Random);
+        let adv_data = [
+            0x03, 0x03, 0x2c, 0xfe, // Complete 16-bit UUIDs
+            0x06, 0x16, 0x2c, 0xfe, 0x00, 0x12, 0x34, // Service Data
+            0x04, 0xff, 0xe0, 0x00, 0x01, // Manufacturer Data
+            0x04, 0x08, 0x42, 0x75, 0x64, // Shortened Local Name
+        ];
+        BleAdvertisement::from_raw(addr, Some(-60), &adv_data, &[]).unwrap()
+    }
+
+    #[test]
+    fn scan_filter_default_matches_all() {
+        assert!(ScanFilter::default().matches(&advertisement(1)));
+        assert!(ScanFilter::default().matches(&fast_pair_advertisement()));
+    }
+
+    #[test]
+    fn scan_filter_service_uuid() {
+        let ad = fast_pair_advertisement();
+        let filter = ScanFilter::default().with_service_uuid(BtUuid::FAST_PAIR);
+        assert!(filter.matches(&ad));
+        assert!(!filter.matches(&advertisement(1)));
+
+        let filter =
+            ScanFilter::default().with_service_uuid(BtUuid::BATTERY_SERVICE);
+        assert!(!filter.matches(&ad));
+    }
+
+    #[test]
+    fn scan_filter_service_data() {
+        let ad = fast_pair_advertisement();
+        let uuid = BtUuid::FAST_PAIR;
+
+        let filter =
+            ScanFilter::default().with_service_data(uuid, vec![], None);
+        assert!(filter.matches(&ad));
+        let filter = ScanFilter::default().with_service_data(
+            uuid,
+            vec![0x00, 0x12],
+            None,
+        );
+        assert!(filter.matches(&ad));
+        let filter = ScanFilter::default().with_service_data(
+            uuid,
+            vec![0x00, 0x13],
+            None,
+        );
+        assert!(!filter.matches(&ad));
+        let filter = ScanFilter::default().with_service_data(
+            uuid,
+            vec![0x00, 0x13],
+            Some(vec![0xff, 0xf0]),
+        );
+        assert!(filter.matches(&ad));
+        assert_eq!(filter.service_data().unwrap().mask(), &[0xff, 0xf0]);
+
+        // Longer than the advertised data.
+        let filter = ScanFilter::default().with_service_data(
+            uuid,
+            vec![0; 4],
+            Some(vec![]),
+        );
+        assert!(!filter.matches(&ad));
+        assert_eq!(filter.service_data().unwrap().mask(), &[0xff; 4]);
+
+        let filter = ScanFilter::default().with_service_data(
+            BtUuid::BATTERY_SERVICE,
+            vec![],
+            None,
+        );
+        assert!(!filter.matches(&ad));
+    }
+
+    #[test]
+    fn scan_filter_other_criteria() {
+        let ad = fast_pair_advertisement();
+        let addr = BleAddress::new(0x112233445566, BleAddressKind::Random);
+
+        assert!(ScanFilter::default()
+            .with_manufacturer_id(0x00e0)
+            .matches(&ad));
+        assert!(!ScanFilter::default()
+            .with_manufacturer_id(0x004c)
+            .matches(&ad));
+        assert!(ScanFilter::default().with_address(addr).matches(&ad));
+        assert!(!ScanFilter::default()
+            .with_address(BleAddress::new(
+                0x112233445566,
+                BleAddressKind::Public
+            ))
+            .matches(&ad));
+        assert!(ScanFilter::default().with_local_name("Bud").matches(&ad));
+        assert!(!ScanFilter::default().with_local_name("Buds").matches(&ad));
+        assert!(ScanFilter::default().with_min_rssi(-60).matches(&ad));
+        assert!(!ScanFilter::default().with_min_rssi(-59).matches(&ad));
+        // No RSSI is known.
+        assert!(!ScanFilter::default()
+            .with_min_rssi(-127)
+            .matches(&advertisement(1)));
+
+        // Every criterion must match.
+        let filter =
+            ScanFilter::default().with_address(addr).with_min_rssi(-50);
+        assert!(!filter.matches(&ad));
+    }
+
+    #[test]
+    fn scan_state_keeps_selected_data() {
+        let filter = ScanFilter::default()
+            .with_service_uuid(BtUuid::FAST_PAIR)
+            .with_local_name("Bud");
+        let data_selector = [BleDataTypeId::ServiceData16BitUuid];
+        let mut state =
+            ScanState::new(&filter, &ScanSettings::default(), &data_selector);
+
+        let data_types = state.datatype_ids();
+        assert_eq!(data_types[0], BleDataTypeId::ServiceData16BitUuid);
+        assert!(data_types.contains(&BleDataTypeId::ServiceUuids32Bit));
+        assert!(data_types.contains(&BleDataTypeId::ShortenedLocalName));
+        assert!(!data_types.contains(&BleDataTypeId::Flags));
+
+        let ad = state.process(fast_pair_advertisement()).unwrap();
+        assert!(ad.service_data_16bit_uuid().is_ok());
+        assert!(ad.service_uuids_16bit().is_err());
+        assert!(ad.shortened_local_name().is_err());
+
+        assert!(state.process(advertisement(1)).is_none());
+    }
+
+    #[test]
+    fn scan_settings_default() {
+        let settings = ScanSettings::default();
+        assert_eq!(settings.mode(), ScanMode::Active);
+        assert_eq!(settings.interval(), None);
+        assert_eq!(settings.window(), None);
+        assert!(!settings.deliver_non_connectable());
+        assert!(settings.extended());
+        assert_eq!(settings.duplicate_window(), None);
+    }
+
+    #[test]
+    fn scan_state_checks_advertisement_type() {
+        let with_type = |adv_type| {
+            let mut ad = advertisement(1);
+            ad.set_advertisement_type(adv_type);
+            ad
+        };
+        let extended_nonconn = BleAdvertisementType::from_bits(0);
+        let extended_scan_rsp = BleAdvertisementType::from_bits(
+            BleAdvertisementType::SCAN_RESPONSE,
+        );
+        let process = |settings: ScanSettings, adv_type| {
+            ScanState::new(&ScanFilter::default(), &settings, &[])
+                .process(with_type(adv_type))
+                .is_some()
+        };
+
+        let settings = ScanSettings::default();
+        assert!(process(settings.clone(), BleAdvertisementType::ADV_IND));
+        assert!(process(settings.clone(), BleAdvertisementType::SCAN_RSP));
+        assert!(process(settings.clone(), extended_scan_rsp));
+        assert!(!process(
+            settings.clone(),
+            BleAdvertisementType::ADV_SCAN_IND
+        ));
+        assert!(!process(settings.clone(), extended_nonconn));
+
+        let settings = settings.with_non_connectable(true);
+        assert!(process(
+            settings.clone(),
+            BleAdvertisementType::ADV_NONCONN_IND
+        ));
+        assert!(process(settings.clone(), extended_nonconn));
+
+        let settings = settings.with_extended(false);
+        assert!(process(
+            settings.clone(),
+            BleAdvertisementType::ADV_NONCONN_IND
+        ));
+        assert!(!process(settings.clone(), extended_nonconn));
+        assert!(!process(settings.clone(), extended_scan_rsp));
+
+        let settings = settings.with_mode(ScanMode::Passive);
+        assert!(process(settings.clone(), BleAdvertisementType::ADV_IND));
+        assert!(!process(settings.clone(), BleAdvertisementType::SCAN_RSP));
+
+        // Advertisements of unknown type are always delivered.
+        let mut state = ScanState::new(&ScanFilter::default(), &settings, &[]);
+        assert!(state.process(advertisement(1)).is_some());
+    }
+
+    #[test]
+    fn scan_state_suppresses_duplicates() {
+        let window = Duration::from_secs(1);
+        let settings = ScanSettings::default().with_duplicate_window(window);
+        let mut state = ScanState::new(&ScanFilter::default(), &settings, &[]);
+        let start = Instant::now();
+
+        assert!(state.process_at(advertisement(1), start).is_some());
+        assert!(state.process_at(advertisement(2), start).is_some());
+        let later = start + window / 2;
+        assert!(state.process_at(advertisement(1), later).is_none());
+        let later = start + window;
+        assert!(state.process_at(advertisement(1), later).is_some());
+        assert!(state.process_at(advertisement(2), later).is_some());
+
+        // Addresses outside the window are eventually forgotten.
+        let later = later + window;
+        for addr in 0..DUPLICATE_PRUNE_THRESHOLD as u64 {
+            state.process_at(advertisement(addr + 3), later);
+        }
+        assert!(!state
+            .last_delivered
+            .contains_key(&(advertisement(1).address(), false)));
+        assert_eq!(state.last_delivered.len(), DUPLICATE_PRUNE_THRESHOLD);
+        state.process_at(advertisement(1), later + window);
+        assert_eq!(state.last_delivered.len(), 1);
+    }
+
+    #[test]
+    fn scan_state_delivers_scan_response_after_advertisement() {
+        let window = Duration::from_secs(1);
+        let settings = ScanSettings::default().with_duplicate_window(window);
+        let mut state = ScanState::new(&ScanFilter::default(), &settings, &[]);
+        let with_type = |adv_type| {
+            let mut ad = advertisement(1);
+            ad.set_advertisement_type(adv_type);
+            ad
+        };
+        let start = Instant::now();
+
+        let adv_ind = with_type(BleAdvertisementType::ADV_IND);
+        assert!(state.process_at(adv_ind.clone(), start).is_some());
+        let scan_rsp = with_type(BleAdvertisementType::SCAN_RSP);
+        assert!(state.process_at(scan_rsp.clone(), start).is_some());
+
+        let later = start + window / 2;
+        assert!(state.process_at(adv_ind, later).is_none());
+        assert!(state.process_at(scan_rsp, later).is_none());
+    }
+
+    #[test]
+    fn scan_stream_with_combinators() {
+        let stopped = Arc::new(AtomicBool::new(false));
+        let scan = ScanStream::new(Scan {
+            advertisements: vec![advertisement(3), advertisement(2)],
+            stopped: stopped.clone(),
+        });
+
+        let addresses = block_on(
+            scan.filter_map(|ad| async move { ad.ok() })
+                .map(|ad| u64::from(ad.address()))
+                .take(1)
+                .collect::<Vec<_>>(),
+        );
+
+        assert_eq!(addresses, vec![2]);
+        assert!(stopped.load(Ordering::SeqCst));
+    }
+}
@@ -0,0 +1,521 @@
+// Copyright 2023 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+// `serde` support for the public data types, enabled by the `serde` feature.
+// Types whose serialized form follows from their fields derive it where they
+// are defined; this module holds the ones that need a custom form:
+// addresses and UUIDs as their usual strings, payload bytes as lowercase hex
+// in human-readable formats such as JSON, and advertisements as versioned
+// records.
+
+use std::{fmt, time::Duration};
+
+use serde::{
+    de::{self, SeqAccess, Visitor},
+    Deserialize, Deserializer, Serialize, Serializer,
+};
+
+use super::{
+    BleAddress, BleAddressKind, BleAdvertisement, BleAdvertisementType, BtUuid,
+    ClassicAddress, IdentityAddress, ManufacturerData, ServiceData,
+};
+
+/// Version of the serialized form of `BleAdvertisement`. Bumped whenever a
+/// change would make older records read differently.
+const ADVERTISEMENT_VERSION: u32 = 1;
+
+/// Serialize payload bytes as a lowercase hex string in human-readable
+/// formats, and as raw bytes otherwise. Use with `#[serde(with = ...)]`.
+pub(crate) mod hex_bytes {
+    use super::*;
+
+    pub(crate) fn serialize<S: Serializer>(
+        bytes: &[u8],
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        if serializer.is_human_readable() {
+            let hex: String =
+                bytes.iter().map(|byte| format!("{:02x}", byte)).collect();
+            serializer.serialize_str(&hex)
+        } else {
+            serializer.serialize_bytes(bytes)
+        }
+    }
+
+    pub(crate) fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<Vec<u8>, D::Error> {
+        if deserializer.is_human_readable() {
+            deserializer.deserialize_any(BytesVisitor)
+        } else {
+            deserializer.deserialize_byte_buf(BytesVisitor)
+        }
+    }
+
+    /// Accepts hex strings as well as byte arrays, so formats without a
+    /// byte type can still carry them as sequences.
+    struct BytesVisitor;
+
+    impl<'de> Visitor<'de> for BytesVisitor {
+        type Value = Vec<u8>;
+
+        fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+            f.write_str("a hex string or bytes")
+        }
+
+        fn visit_str<E: de::Error>(self, hex: &str) -> Result<Vec<u8>, E> {
+            if !hex.len().is_multiple_of(2) || !hex.is_ascii() {
+                return Err(E::invalid_value(de::Unexpected::Str(hex), &self));
+            }
+
+            (0..hex.len())
+                .step_by(2)
+                .map(|i| u8::from_str_radix(&hex[i..i + 2], 16))
+                .collect::<Result<_, _>>()
+                .map_err(|_| E::invalid_value(de::Unexpected::Str(hex), &self))
+        }
+
+        fn visit_bytes<E: de::Error>(self, bytes: &[u8]) -> Result<Vec<u8>, E> {
+            Ok(bytes.to_vec())
+        }
+
+        fn visit_byte_buf<E: de::Error>(
+            self,
+            bytes: Vec<u8>,
+        ) -> Result<Vec<u8>, E> {
+            Ok(bytes)
+        }
+
+        fn visit_seq<A: SeqAccess<'de>>(
+            self,
+            mut seq: A,
+        ) -> Result<Vec<u8>, A::Error> {
+            let mut bytes = Vec::with_capacity(seq.size_hint().unwrap_or(0));
+            while let Some(byte) = seq.next_element()? {
+                bytes.push(byte);
+            }
+            Ok(bytes)
+        }
+    }
+}
+
+/// Deserialize a field that is present, even if `null`, as `Some`, so that
+/// data sections loaded as absent stay apart from ones that weren't loaded.
+fn present<'de, D, T>(deserializer: D) -> Result<Option<Option<T>>, D::Error>
+where
+    D: Deserializer<'de>,
+    T: Deserialize<'de>,
+{
+    Option::<T>::deserialize(deserializer).map(Some)
+}
+
+/// Serialized form of a `BleAddress`, e.g.
+/// `{"address": "11:22:33:44:55:66", "kind": "random"}`.
+#[derive(Serialize, Deserialize)]
+struct BleAddressRecord {
+    address: String,
+    kind: BleAddressKind,
+}
+
+impl Serialize for BleAddress {
+    fn serialize<S: Serializer>(
+        &self,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        BleAddressRecord {
+            address: self.to_string(),
+            kind: self.get_kind(),
+        }
+        .serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for BleAddress {
+    fn deserialize<D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<Self, D::Error> {
+        let record = BleAddressRecord::deserialize(deserializer)?;
+        BleAddress::parse(&record.address, record.kind)
+            .map_err(de::Error::custom)
+    }
+}
+
+/// Serialized like the `BleAddress` it wraps, rejecting private addresses.
+impl Serialize for IdentityAddress {
+    fn serialize<S: Serializer>(
+        &self,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        self.address().serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for IdentityAddress {
+    fn deserialize<D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<Self, D::Error> {
+        IdentityAddress::try_from(BleAddress::deserialize(deserializer)?)
+            .map_err(de::Error::custom)
+    }
+}
+
+/// Serialized as a string in the `AA:BB:CC:DD:EE:FF` form.
+impl Serialize for ClassicAddress {
+    fn serialize<S: Serializer>(
+        &self,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+impl<'de> Deserialize<'de> for ClassicAddress {
+    fn deserialize<D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<Self, D::Error> {
+        String::deserialize(deserializer)?
+            .parse()
+            .map_err(de::Error::custom)
+    }
+}
+
+/// Serialized as a string in the canonical 128-bit form.
+impl Serialize for BtUuid {
+    fn serialize<S: Serializer>(
+        &self,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+impl<'de> Deserialize<'de> for BtUuid {
+    fn deserialize<D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<Self, D::Error> {
+        String::deserialize(deserializer)?
+            .parse()
+            .map_err(de::Error::custom)
+    }
+}
+
+/// Serialized form of a `BleAdvertisement`. Data sections that weren't loaded
+/// are left out, and ones loaded but absent from the advertisement are `null`.
+#[derive(Serialize, Deserialize)]
+struct AdvertisementRecord {
+    version: u32,
+    address: BleAddress,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    rssi: Option<i16>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    tx_power: Option<i16>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    advertisement_type: Option<BleAdvertisementType>,
+    #[serde(
+        default,
+        skip_serializing_if = "Option::is_none",
+        deserialize_with = "present"
+    )]
+    flags: Option<Option<u8>>,
+    #[serde(
+        default,
+        skip_serializing_if = "Option::is_none",
+        deserialize_with = "present"
+    )]
+    local_name: Option<Option<String>>,
+    #[serde(
+        default,
+        skip_serializing_if = "Option::is_none",
+        deserialize_with = "present"
+    )]
+    shortened_local_name: Option<Option<String>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    service_uuids_16bit: Option<Vec<BtUuid>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    service_uuids_32bit: Option<Vec<BtUuid>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    service_uuids_128bit: Option<Vec<BtUuid>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    service_data_16bit_uuid: Option<Vec<ServiceData>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    service_data_32bit_uuid: Option<Vec<ServiceData>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    service_data_128bit_uuid: Option<Vec<ServiceData>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    manufacturer_data: Option<Vec<ManufacturerData>>,
+    #[serde(
+        default,
+        skip_serializing_if = "Option::is_none",
+        deserialize_with = "present"
+    )]
+    appearance: Option<Option<u16>>,
+    #[serde(
+        default,
+        skip_serializing_if = "Option::is_none",
+        deserialize_with = "present"
+    )]
+    advertising_interval: Option<Option<Duration>>,
+}
+
+impl From<&BleAdvertisement> for AdvertisementRecord {
+    fn from(ad: &BleAdvertisement) -> Self {
+        let name = |name: Option<&str>| name.map(String::from);
+
+        AdvertisementRecord {
+            version: ADVERTISEMENT_VERSION,
+            address: ad.address(),
+            rssi: ad.rssi(),
+            tx_power: ad.tx_power(),
+            advertisement_type: ad.advertisement_type(),
+            flags: ad.flags().ok(),
+            local_name: ad.local_name().ok().map(name),
+            shortened_local_name: ad.shortened_local_name().ok().map(name),
+            service_uuids_16bit: ad.service_uuids_16bit().ok().cloned(),
+            service_uuids_32bit: ad.service_uuids_32bit().ok().cloned(),
+            service_uuids_128bit: ad.service_uuids_128bit().ok().cloned(),
+            service_data_16bit_uuid: ad.service_data_16bit_uuid().ok().cloned(),
+            service_data_32bit_uuid: ad.service_data_32bit_uuid().ok().cloned(),
+            service_data_128bit_uuid: ad
+                .service_data_128bit_uuid()
+                .ok()
+                .cloned(),
+            manufacturer_data: ad.manufacturer_data().ok().cloned(),
+            appearance: ad.appearance().ok(),
+            advertising_interval: ad.advertising_interval().ok(),
+        }
+    }
+}
+
+impl From<AdvertisementRecord> for BleAdvertisement {
+    fn from(record: AdvertisementRecord) -> Self {
+        let mut ad =
+            BleAdvertisement::new(record.address, record.rssi, record.tx_power);
+        if let Some(advertisement_type) = record.advertisement_type {
+            ad.set_advertisement_type(advertisement_type);
+        }
+        if let Some(flags) = record.flags {
+            ad.set_flags(flags);
+        }
+        if let Some(name) = record.local_name {
+            ad.set_local_name(name);
+        }
+        if let Some(name) = record.shortened_local_name {
+            ad.set_shortened_local_name(name);
+        }
+        if let Some(uuids) = record.service_uuids_16bit {
+            ad.set_service_uuids_16bit(uuids);
+        }
+        if let Some(uuids) = record.service_uuids_32bit {
+            ad.set_service_uuids_32bit(uuids);
+        }
+        if let Some(uuids) = record.service_uuids_128bit {
+            ad.set_service_uuids_128bit(uuids);
+        }
+        if let Some(service_data) = record.service_data_16bit_uuid {
+            ad.set_service_data_16bit_uuid(service_data);
+        }
+        if let Some(service_data) = record.service_data_32bit_uuid {
+            ad.set_service_data_32bit_uuid(service_data);
+        }
+        if let Some(service_data) = record.service_data_128bit_uuid {
+            ad.set_service_data_128bit_uuid(service_data);
+        }
+        if let Some(manufacturer_data) = record.manufacturer_data {
+            ad.set_manufacturer_data(manufacturer_data);
+        }
+        if let Some(appearance) = record.appearance {
+            ad.set_appearance(appearance);
+        }
+        if let Some(interval) = record.advertising_interval {
+            ad.set_advertising_interval(interval);
+        }
+        ad
+    }
+}
+
+/// Serialized as a record carrying the version of its format, see
+/// `AdvertisementRecord`.
+impl Serialize for BleAdvertisement {
+    fn serialize<S: Serializer>(
+        &self,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        AdvertisementRecord::from(self).serialize(serializer)
+    }
+}
+
+/// Fails for records of a format version this library doesn't know.
+impl<'de> Deserialize<'de> for BleAdvertisement {
+    fn deserialize<D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<Self, D::Error> {
+        let record = AdvertisementRecord::deserialize(deserializer)?;
+        if record.version != ADVERTISEMENT_VERSION {
+            return Err(de::Error::custom(format!(
+                "unsupported advertisement format version {}",
+                record.version
+            )));
+        }
+        Ok(BleAdvertisement::from(record))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::{json, Value};
+
+    use super::*;
+    use crate::common::{
+        BleDataTypeId, BluetoothError, BondEvent, BondedDevice, PairingResult,
+    };
+
+    fn round_trip<T>(value: &T) -> Value
+    where
+        T: Serialize + for<'de> Deserialize<'de> + PartialEq + fmt::Debug,
+    {
+        let json = serde_json::to_value(value).unwrap();
+        assert_eq!(&serde_json::from_value::<T>(json.clone()).unwrap(), value);
+        json
+    }
+
+    #[test]
+    fn addresses_as_strings() {
+        let addr = BleAddress::new(0x112233445566, BleAddressKind::Random);
+        assert_eq!(
+            round_trip(&addr),
+            json!({"address": "11:22:33:44:55:66", "kind": "random"})
+        );
+
+        let addr = ClassicAddress::from(0x112233445566);
+        assert_eq!(round_trip(&addr), json!("11:22:33:44:55:66"));
+
+        assert!(
+            serde_json::from_value::<ClassicAddress>(json!("11:22")).is_err()
+        );
+    }
+
+    #[test]
+    fn identity_address_rejects_private_addresses() {
+        let addr = BleAddress::new(0xc12233445566, BleAddressKind::Random);
+        let identity = IdentityAddress::try_from(addr).unwrap();
+        round_trip(&identity);
+
+        let private = json!({"address": "41:22:33:44:55:66", "kind": "random"});
+        assert!(serde_json::from_value::<IdentityAddress>(private).is_err());
+    }
+
+    #[test]
+    fn uuid_as_string() {
+        assert_eq!(
+            round_trip(&BtUuid::FAST_PAIR),
+            json!("0000fe2c-0000-1000-8000-00805f9b34fb")
+        );
+    }
+
+    #[test]
+    fn payload_bytes() {
+        let data = ServiceData::new(BtUuid::FAST_PAIR, vec![0x00, 0x12, 0xab]);
+        assert_eq!(
+            round_trip(&data),
+            json!({
+                "uuid": "0000fe2c-0000-1000-8000-00805f9b34fb",
+                "data": "0012ab",
+            })
+        );
+
+        let bytes = json!({"company_id": 224, "data": [1, 2]});
+        assert_eq!(
+            serde_json::from_value::<ManufacturerData>(bytes).unwrap(),
+            ManufacturerData::new(0x00e0, vec![0x01, 0x02])
+        );
+
+        for data in ["012", "0g", "é0"] {
+            let json = json!({"company_id": 224, "data": data});
+            assert!(serde_json::from_value::<ManufacturerData>(json).is_err());
+        }
+    }
+
+    #[test]
+    fn advertisement_keeps_loaded_sections() {
+        let address = BleAddress::new(0x112233445566, BleAddressKind::Random);
+        let adv_data = [
+            0x02, 0x01, 0x06, // Flags
+            0x03, 0x03, 0x2c, 0xfe, // Complete 16-bit UUIDs
+            0x06, 0x16, 0x2c, 0xfe, 0x00, 0x12, 0x34, // Service Data
+        ];
+        let mut ad =
+            BleAdvertisement::from_raw(address, Some(-60), &adv_data, &[])
+                .unwrap();
+        ad.retain_data(&[
+            BleDataTypeId::ServiceData16BitUuid,
+            BleDataTypeId::CompleteLocalName,
+        ]);
+
+        let json = serde_json::to_value(&ad).unwrap();
+        assert_eq!(
+            json,
+            json!({
+                "version": 1,
+                "address": {"address": "11:22:33:44:55:66", "kind": "random"},
+                "rssi": -60,
+                "local_name": null,
+                "service_data_16bit_uuid": [{
+                    "uuid": "0000fe2c-0000-1000-8000-00805f9b34fb",
+                    "data": "001234",
+                }],
+            })
+        );
+
+        let read: BleAdvertisement = serde_json::from_value(json).unwrap();
+        assert_eq!(read.address(), address);
+        assert_eq!(read.rssi(), Some(-60));
+        assert_eq!(read.local_name().unwrap(), None);
+        assert!(read.flags().is_err());
+        assert_eq!(
+            read.service_data_16bit_uuid().unwrap(),
+            ad.service_data_16bit_uuid().unwrap()
+        );
+    }
+
+    #[test]
+    fn advertisement_rejects_unknown_version() {
+        let json = json!({
+            "version": 2,
+            "address": {"address": "11:22:33:44:55:66", "kind": "random"},
+        });
+        assert!(serde_json::from_value::<BleAdvertisement>(json).is_err());
+    }
+
+    #[test]
+    fn results_and_errors() {
+        assert_eq!(
+            round_trip(&PairingResult::Success),
+            json!({"result": "success"})
+        );
+        assert_eq!(
+            round_trip(&PairingResult::Failure(String::from("rejected"))),
+            json!({"result": "failure", "reason": "rejected"})
+        );
+        assert_eq!(
+            round_trip(&BluetoothError::Gatt(String::from("not permitted"))),
+            json!({"kind": "gatt", "message": "not permitted"})
+        );
+
+        let addr = ClassicAddress::from(0x112233445566);
+        assert_eq!(
+            round_trip(&BondEvent::Added(BondedDevice::Classic(addr))),
+            json!({"added": {"classic": "11:22:33:44:55:66"}})
+        );
+    }
+}
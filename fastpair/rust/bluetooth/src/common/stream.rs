@@ -0,0 +1,57 @@
+// Copyright 2023 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::{
+    pin::Pin,
+    task::{Context, Poll},
+};
+
+use futures::{stream::BoxStream, Stream, StreamExt};
+
+use super::BluetoothError;
+
+/// Stream of updates a platform reports while it watches or runs something
+/// on behalf of the caller, e.g. a scan. Hides the platform-specific stream
+/// behind the types `api` returns, such as `ScanStream`. Whatever the stream
+/// watches or runs stops once it's dropped.
+pub struct WatchStream<T> {
+    inner: BoxStream<'static, Result<T, BluetoothError>>,
+}
+
+impl<T> WatchStream<T> {
+    /// Wrap a platform-specific stream, which must stop what it watches or
+    /// runs when dropped.
+    pub(crate) fn new(
+        inner: impl Stream<Item = Result<T, BluetoothError>> + Send + 'static,
+    ) -> Self {
+        WatchStream {
+            inner: inner.boxed(),
+        }
+    }
+}
+
+impl<T> Stream for WatchStream<T> {
+    type Item = Result<T, BluetoothError>;
+
+    fn poll_next(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Self::Item>> {
+        self.inner.poll_next_unpin(cx)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.inner.size_hint()
+    }
+}
@@ -0,0 +1,183 @@
+// Copyright 2023 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::{
+    cmp::Reverse,
+    collections::{BinaryHeap, HashMap},
+    future::Future,
+    pin::Pin,
+    sync::{Condvar, Mutex, OnceLock},
+    task::{Context, Poll, Waker},
+    thread,
+    time::Instant,
+};
+
+/// Future resolving once its deadline has passed, returned by
+/// `sleep_until()`. Its wakeup is cancelled when it's dropped.
+pub(crate) struct Sleep {
+    deadline: Instant,
+    /// Identifier of the wakeup registered with the timer thread, once the
+    /// future has been polled.
+    id: Option<u64>,
+}
+
+/// Wakeups pending on the timer thread.
+#[derive(Default)]
+struct Wakeups {
+    /// Deadlines of the registered wakeups, earliest first.
+    deadlines: BinaryHeap<Reverse<(Instant, u64)>>,
+    wakers: HashMap<u64, Waker>,
+    next_id: u64,
+}
+
+/// Thread waking the `Sleep` futures whose deadline has passed. The simulated
+/// platform and replays don't depend on an async runtime, so every timer of
+/// the process shares this thread instead.
+struct TimerThread {
+    wakeups: Mutex<Wakeups>,
+    /// Notified when a wakeup is registered, which may be due earlier than
+    /// the one the thread waits for.
+    registered: Condvar,
+}
+
+/// Wait until `deadline` has passed.
+pub(crate) fn sleep_until(deadline: Instant) -> Sleep {
+    Sleep { deadline, id: None }
+}
+
+impl TimerThread {
+    /// Retrieve the timer thread, starting it on first use.
+    fn get() -> &'static TimerThread {
+        static TIMER: OnceLock<TimerThread> = OnceLock::new();
+        TIMER.get_or_init(|| {
+            thread::spawn(|| TimerThread::get().run());
+            TimerThread {
+                wakeups: Mutex::new(Wakeups::default()),
+                registered: Condvar::new(),
+            }
+        })
+    }
+
+    fn run(&self) {
+        let mut wakeups = self.wakeups.lock().unwrap();
+        loop {
+            let now = Instant::now();
+            while let Some(&Reverse((deadline, id))) = wakeups.deadlines.peek()
+            {
+                if deadline > now {
+                    break;
+                }
+                wakeups.deadlines.pop();
+                if let Some(waker) = wakeups.wakers.remove(&id) {
+                    waker.wake();
+                }
+            }
+
+            wakeups = match wakeups.deadlines.peek() {
+                Some(&Reverse((deadline, _))) => {
+                    self.registered
+                        .wait_timeout(wakeups, deadline - now)
+                        .unwrap()
+                        .0
+                }
+                None => self.registered.wait(wakeups).unwrap(),
+            };
+        }
+    }
+}
+
+impl Future for Sleep {
+    type Output = ();
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<()> {
+        if Instant::now() >= self.deadline {
+            return Poll::Ready(());
+        }
+
+        let timer = TimerThread::get();
+        let mut wakeups = timer.wakeups.lock().unwrap();
+        match self.id {
+            Some(id) => match wakeups.wakers.get_mut(&id) {
+                Some(waker) => waker.clone_from(cx.waker()),
+                // The wakeup fired since the deadline was checked.
+                None => return Poll::Ready(()),
+            },
+            None => {
+                let id = wakeups.next_id;
+                wakeups.next_id += 1;
+                wakeups.deadlines.push(Reverse((self.deadline, id)));
+                wakeups.wakers.insert(id, cx.waker().clone());
+                timer.registered.notify_one();
+                self.id = Some(id);
+            }
+        }
+
+        Poll::Pending
+    }
+}
+
+impl Drop for Sleep {
+    fn drop(&mut self) {
+        if let Some(id) = self.id {
+            let mut wakeups = TimerThread::get().wakeups.lock().unwrap();
+            if wakeups.wakers.remove(&id).is_some() {
+                wakeups.deadlines.retain(|&Reverse((_, other))| other != id);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use futures::{
+        executor::block_on,
+        future::{self, Either},
+        FutureExt,
+    };
+
+    use super::*;
+
+    #[test]
+    fn sleeps_resolve_in_deadline_order() {
+        let start = Instant::now();
+        let late = sleep_until(start + Duration::from_millis(60));
+        let early = sleep_until(start + Duration::from_millis(20));
+
+        let late = match block_on(future::select(late, early)) {
+            Either::Right((_, late)) => late,
+            Either::Left(_) => panic!("the later deadline passed first"),
+        };
+        assert!(start.elapsed() >= Duration::from_millis(20));
+
+        block_on(late);
+        assert!(start.elapsed() >= Duration::from_millis(60));
+    }
+
+    #[test]
+    fn dropped_sleeps_are_cancelled() {
+        let mut sleep = sleep_until(Instant::now() + Duration::from_secs(60));
+        assert!((&mut sleep).now_or_never().is_none());
+        let id = sleep.id.unwrap();
+
+        drop(sleep);
+        let wakeups = TimerThread::get().wakeups.lock().unwrap();
+        assert!(!wakeups.wakers.contains_key(&id));
+        assert!(wakeups
+            .deadlines
+            .iter()
+            .all(|&Reverse((_, other))| other != id));
+    }
+}
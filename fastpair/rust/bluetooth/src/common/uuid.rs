@@ -0,0 +1,277 @@
+// Copyright 2023 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::{fmt, str::FromStr};
+
+use super::BluetoothError;
+
+/// A 128-bit Bluetooth UUID. 16-bit and 32-bit UUIDs are shorthands for
+/// UUIDs derived from the Bluetooth Base UUID, which this type expands on
+/// construction, so UUIDs compare equal whatever form they were built from.
+/// Bluetooth Core Specification, Vol 3, Part B, Section 2.5.1.
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct BtUuid(u128);
+
+impl BtUuid {
+    /// The Bluetooth Base UUID, from which 16-bit and 32-bit UUIDs are
+    /// derived by replacing its 32 most significant bits.
+    pub const BASE: BtUuid = BtUuid(0x00000000_0000_1000_8000_00805f9b34fb);
+
+    /// Google Fast Pair service, identifying Fast Pair advertisements and
+    /// GATT service.
+    pub const FAST_PAIR: BtUuid = BtUuid::from_u16(0xfe2c);
+
+    /// Service UUID of the Fast Pair Message Stream, served over RFCOMM.
+    /// https://developers.google.com/nearby/fast-pair/specifications/extensions/messagestream
+    pub const MESSAGE_STREAM: BtUuid =
+        BtUuid(0xdf21fe2c_2515_4fdb_8886_f12c4d67927c);
+
+    /// Google Nearby Presence service, identifying Presence advertisements.
+    pub const NEARBY_PRESENCE: BtUuid = BtUuid::from_u16(0xfcf1);
+
+    /// Battery Service. Bluetooth Assigned Numbers, Section 3.4.2.
+    pub const BATTERY_SERVICE: BtUuid = BtUuid::from_u16(0x180f);
+
+    /// Device Information Service. Bluetooth Assigned Numbers, Section 3.4.2.
+    pub const DEVICE_INFORMATION: BtUuid = BtUuid::from_u16(0x180a);
+
+    /// Mask of the bits a UUID shares with the Base UUID if derived from it.
+    const BASE_MASK: u128 = (1 << 96) - 1;
+
+    /// Expand a 16-bit UUID, e.g. `0xfe2c` for Fast Pair.
+    pub const fn from_u16(uuid: u16) -> Self {
+        BtUuid::from_u32(uuid as u32)
+    }
+
+    /// Expand a 32-bit UUID.
+    pub const fn from_u32(uuid: u32) -> Self {
+        BtUuid(((uuid as u128) << 96) | BtUuid::BASE.0)
+    }
+
+    /// Wrap a full 128-bit UUID.
+    pub const fn from_u128(uuid: u128) -> Self {
+        BtUuid(uuid)
+    }
+
+    /// Retrieve the full 128-bit form.
+    pub const fn as_u128(self) -> u128 {
+        self.0
+    }
+
+    /// Retrieve the 32-bit form, if the UUID is derived from the Base UUID.
+    pub const fn as_u32(self) -> Option<u32> {
+        if self.0 & BtUuid::BASE_MASK == BtUuid::BASE.0 {
+            Some((self.0 >> 96) as u32)
+        } else {
+            None
+        }
+    }
+
+    /// Retrieve the 16-bit form, if the UUID is derived from the Base UUID
+    /// and fits.
+    pub const fn as_u16(self) -> Option<u16> {
+        match self.as_u32() {
+            Some(uuid) if uuid <= u16::MAX as u32 => Some(uuid as u16),
+            _ => None,
+        }
+    }
+
+    /// Decode a UUID as sent over the air: 2, 4 or 16 bytes in little-endian
+    /// byte order.
+    pub(crate) fn from_le_bytes(bytes: &[u8]) -> Option<Self> {
+        match *bytes {
+            [b0, b1] => Some(BtUuid::from_u16(u16::from_le_bytes([b0, b1]))),
+            [b0, b1, b2, b3] => {
+                Some(BtUuid::from_u32(u32::from_le_bytes([b0, b1, b2, b3])))
+            }
+            _ => Some(BtUuid(u128::from_le_bytes(bytes.try_into().ok()?))),
+        }
+    }
+
+    /// Encode a UUID as sent over the air: in little-endian byte order and in
+    /// its shortest form, i.e. 2 or 4 bytes if derived from the Base UUID.
+    pub(crate) fn to_le_bytes(self) -> Vec<u8> {
+        match (self.as_u16(), self.as_u32()) {
+            (Some(uuid), _) => uuid.to_le_bytes().to_vec(),
+            (None, Some(uuid)) => uuid.to_le_bytes().to_vec(),
+            (None, None) => self.0.to_le_bytes().to_vec(),
+        }
+    }
+}
+
+impl From<u16> for BtUuid {
+    fn from(uuid: u16) -> Self {
+        BtUuid::from_u16(uuid)
+    }
+}
+
+impl From<u32> for BtUuid {
+    fn from(uuid: u32) -> Self {
+        BtUuid::from_u32(uuid)
+    }
+}
+
+impl From<u128> for BtUuid {
+    fn from(uuid: u128) -> Self {
+        BtUuid(uuid)
+    }
+}
+
+impl From<BtUuid> for u128 {
+    fn from(uuid: BtUuid) -> Self {
+        uuid.0
+    }
+}
+
+/// Formats the canonical form, e.g. `0000fe2c-0000-1000-8000-00805f9b34fb`.
+impl fmt::Display for BtUuid {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let hex = format!("{:032x}", self.0);
+        write!(
+            f,
+            "{}-{}-{}-{}-{}",
+            &hex[..8],
+            &hex[8..12],
+            &hex[12..16],
+            &hex[16..20],
+            &hex[20..]
+        )
+    }
+}
+
+impl fmt::Debug for BtUuid {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "BtUuid({})", self)
+    }
+}
+
+/// Parses the canonical form in either case, as well as the 4 and 8 hex digit
+/// forms of 16-bit and 32-bit UUIDs.
+impl FromStr for BtUuid {
+    type Err = BluetoothError;
+
+    fn from_str(uuid: &str) -> Result<Self, Self::Err> {
+        let invalid = || {
+            BluetoothError::BadTypeConversion(format!(
+                "invalid Bluetooth UUID {}",
+                uuid
+            ))
+        };
+
+        let is_canonical = uuid.len() == 36
+            && uuid.char_indices().all(|(i, c)| match i {
+                8 | 13 | 18 | 23 => c == '-',
+                _ => c.is_ascii_hexdigit(),
+            });
+        let is_short = matches!(uuid.len(), 4 | 8)
+            && uuid.chars().all(|c| c.is_ascii_hexdigit());
+        if !is_canonical && !is_short {
+            return Err(invalid());
+        }
+
+        let hex = uuid.replace('-', "");
+        let value = u128::from_str_radix(&hex, 16).map_err(|_| invalid())?;
+        Ok(match hex.len() {
+            32 => BtUuid(value),
+            _ => BtUuid::from_u32(value as u32),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn uuid_expansion() {
+        assert_eq!(
+            BtUuid::from_u16(0xfe2c).as_u128(),
+            0x0000fe2c_0000_1000_8000_00805f9b34fb
+        );
+        assert_eq!(
+            BtUuid::from_u32(0x0001fe2c).as_u128(),
+            0x0001fe2c_0000_1000_8000_00805f9b34fb
+        );
+        assert_eq!(BtUuid::from_u32(0xfe2c), BtUuid::FAST_PAIR);
+    }
+
+    #[test]
+    fn uuid_short_forms() {
+        assert_eq!(BtUuid::FAST_PAIR.as_u16(), Some(0xfe2c));
+        assert_eq!(BtUuid::FAST_PAIR.as_u32(), Some(0xfe2c));
+
+        let uuid = BtUuid::from_u32(0x0001fe2c);
+        assert_eq!(uuid.as_u16(), None);
+        assert_eq!(uuid.as_u32(), Some(0x0001fe2c));
+
+        assert_eq!(BtUuid::MESSAGE_STREAM.as_u32(), None);
+    }
+
+    #[test]
+    fn uuid_shortest_form() {
+        assert_eq!(BtUuid::FAST_PAIR.to_le_bytes(), vec![0x2c, 0xfe]);
+        assert_eq!(
+            BtUuid::from_u32(0x0001fe2c).to_le_bytes(),
+            vec![0x2c, 0xfe, 0x01, 0x00]
+        );
+        assert_eq!(BtUuid::MESSAGE_STREAM.to_le_bytes().len(), 16);
+
+        for uuid in [
+            BtUuid::FAST_PAIR,
+            BtUuid::from_u32(0x0001fe2c),
+            BtUuid::MESSAGE_STREAM,
+        ] {
+            assert_eq!(BtUuid::from_le_bytes(&uuid.to_le_bytes()), Some(uuid));
+        }
+        assert_eq!(BtUuid::from_le_bytes(&[0x2c]), None);
+    }
+
+    #[test]
+    fn format_and_parse() {
+        assert_eq!(
+            BtUuid::FAST_PAIR.to_string(),
+            "0000fe2c-0000-1000-8000-00805f9b34fb"
+        );
+        assert_eq!(
+            BtUuid::MESSAGE_STREAM.to_string(),
+            "df21fe2c-2515-4fdb-8886-f12c4d67927c"
+        );
+
+        assert_eq!(
+            "DF21FE2C-2515-4FDB-8886-F12C4D67927C".parse(),
+            Ok(BtUuid::MESSAGE_STREAM)
+        );
+        assert_eq!("fe2c".parse(), Ok(BtUuid::FAST_PAIR));
+        assert_eq!("0001fe2c".parse(), Ok(BtUuid::from_u32(0x0001fe2c)));
+
+        for uuid in [
+            "",
+            "fe2",
+            "+e2c",
+            "df21fe2c25154fdb8886f12c4d67927c",
+            "df21fe2c-2515-4fdb-8886-f12c4d67927",
+            "df21fe2c-2515-4fdb-8886_f12c4d67927c",
+            "df21fe2c-2515-4fdb-8886-f12c4d67927g",
+        ] {
+            assert!(
+                matches!(
+                    uuid.parse::<BtUuid>(),
+                    Err(BluetoothError::BadTypeConversion(_))
+                ),
+                "{}",
+                uuid
+            );
+        }
+    }
+}
@@ -13,18 +13,46 @@
 // limitations under the License.
 
 pub mod api;
+/// Import of advertisements from btsnoop HCI logs.
+pub mod btsnoop;
+/// Recording of scans to capture files and their replay, e.g. to reproduce
+/// bugs met in the field offline. Enabled by the `capture` feature.
+#[cfg(feature = "capture")]
+pub mod capture;
 mod common;
+pub mod message_stream;
+pub mod sass;
+pub mod types;
 
-use api::{BleAdapter, BleDevice, ClassicDevice};
+use api::{BleAdapter, BleAdvertiser, BleDevice, ClassicDevice};
 pub use common::{
-    BleAddress, BleAddressKind, BleAdvertisement, BleDataTypeId,
-    BluetoothError, ClassicAddress, PairingResult, ServiceData,
+    flags, AdStructure, AdStructures, AdapterId, AdapterInfo, AdvertisingData,
+    AdvertisingSettings, AdvertisingStatus, AdvertisingStream, BleAddress,
+    BleAddressKind, BleAdvertisement, BleAdvertisementType, BleDataTypeId,
+    BluetoothError, BondEvent, BondEventStream, BondState, BondStateStream,
+    BondedDevice, BtUuid, ClassicAddress, ConnectionState,
+    ConnectionStateStream, GattCharacteristic, GattCharacteristicProperties,
+    GattService, IdentityAddress, IrkStore, ManufacturerData,
+    NotificationStream, PairingHandler, PairingResult, ProtectionLevel,
+    RadioState, RadioStateStream, RandomAddressKind, RfcommSocket, ScanFilter,
+    ScanMode, ScanSettings, ScanStream, ServiceData, ServiceDataFilter,
+    WriteType,
 };
 
+/// In-memory simulated platform for deterministic tests, enabled by the `sim`
+/// feature. It is built alongside the real Bluetooth stack, which `Platform`
+/// always uses: open its types, e.g. `sim::BleAdapter::default()`, to run
+/// against the simulated radio.
+#[cfg(any(test, feature = "sim"))]
+pub mod sim;
+
 cfg_if::cfg_if! {
     if #[cfg(windows)] {
         mod windows;
         use self::windows as platform;
+    } else if #[cfg(target_os = "linux")] {
+        mod linux;
+        use linux as platform;
     } else {
         mod unsupported;
         use unsupported as platform;
@@ -39,6 +67,25 @@ impl Platform {
         platform::BleAdapter::default().await
     }
 
+    /// List every adapter of the system, default adapter first.
+    pub async fn adapters() -> Result<Vec<AdapterInfo>, BluetoothError> {
+        platform::BleAdapter::adapters().await
+    }
+
+    /// Open the adapter identified by `id`, as listed by `adapters()`.
+    /// Devices opened through it with `BleAdapter::ble_device()` and
+    /// `BleAdapter::classic_device()` are bound to it.
+    pub async fn adapter(
+        id: &AdapterId,
+    ) -> Result<impl api::BleAdapter, BluetoothError> {
+        platform::BleAdapter::with_id(id).await
+    }
+
+    pub async fn default_advertiser(
+    ) -> Result<impl api::BleAdvertiser, BluetoothError> {
+        platform::BleAdvertiser::default().await
+    }
+
     pub async fn new_ble_device(
         addr: BleAddress,
     ) -> Result<impl api::BleDevice, BluetoothError> {
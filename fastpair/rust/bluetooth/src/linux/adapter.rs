@@ -0,0 +1,1184 @@
+// Copyright 2023 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::{
+    collections::HashMap,
+    pin::Pin,
+    sync::{Arc, Mutex},
+    task::{Context, Poll},
+};
+
+use async_trait::async_trait;
+use futures::{
+    channel::oneshot,
+    lock::Mutex as AsyncMutex,
+    ready,
+    stream::{self, Select},
+    Stream, StreamExt, TryStreamExt,
+};
+use tracing::{info, warn};
+use zbus::{
+    fdo::{self, ObjectManagerProxy, PropertiesProxy},
+    message::Type as MessageType,
+    names::InterfaceName,
+    proxy::CacheProperties,
+    zvariant::{OwnedObjectPath, OwnedValue, Value},
+    Connection, MatchRule, Message, MessageStream,
+};
+
+use super::{
+    address::{parse_address, parse_address_type},
+    advertisement::{property, DeviceProperties},
+    bluez::{
+        Adapter1Proxy, ADAPTER_INTERFACE, ADVERTISING_MANAGER_INTERFACE,
+        BLUEZ_SERVICE, DEVICE_INTERFACE,
+    },
+    bond::{adapter_devices, bonded_device, is_paired, BondListener},
+    BleDevice, ClassicDevice,
+};
+use crate::{
+    api,
+    common::{
+        AdapterId, AdapterInfo, BleAddress, BleAdvertisement, BleDataTypeId,
+        BluetoothError, BondEventStream, BondedDevice, ClassicAddress,
+        RadioState, RadioStateStream, ScanFilter, ScanMode, ScanSettings,
+        ScanState, ScanStream,
+    },
+};
+
+/// `org.bluez.Device1` properties that BlueZ updates whenever it receives an
+/// advertisement from the device.
+const ADVERTISEMENT_PROPERTIES: [&str; 4] =
+    ["RSSI", "TxPower", "ManufacturerData", "ServiceData"];
+
+/// Largest legacy advertising payload, which BlueZ reports when the
+/// controller doesn't say otherwise.
+const LEGACY_ADVERTISEMENT_LENGTH: usize = 31;
+
+/// Struct holding the necessary fields for listening to and handling incoming
+/// BLE advertisements.
+struct AdvListener {
+    /// Object path of the scanning adapter.
+    adapter_path: String,
+    /// Object path prefix of devices belonging to the scanning adapter.
+    device_prefix: String,
+    /// Set once the adapter was switched off, which ends the scan.
+    powered_off: bool,
+    /// Can be polled to consume `InterfacesAdded`, `InterfacesRemoved` and
+    /// `PropertiesChanged` signals emitted by BlueZ.
+    stream: Select<MessageStream, MessageStream>,
+    /// Latest known properties of every device, since `PropertiesChanged`
+    /// signals only carry the properties that changed.
+    devices: HashMap<OwnedObjectPath, DeviceProperties>,
+    /// Checks the filter and settings of the scan.
+    state: ScanState,
+    /// Keeps discovery running while this listener is alive.
+    _discovery: Discovery,
+}
+
+/// Discovery session of a BlueZ adapter. BlueZ only tracks one session per
+/// D-Bus client, so every scan of an adapter shares it.
+#[derive(Default)]
+struct DiscoverySession {
+    /// Held while the session starts, so that concurrent scans start it once.
+    starting: AsyncMutex<()>,
+    state: Mutex<SessionState>,
+}
+
+#[derive(Default)]
+struct SessionState {
+    /// Number of scans holding the session.
+    scans: usize,
+    /// Completes once the previous session has stopped. A new session only
+    /// starts after that, or BlueZ could handle its start before the stop.
+    stopped: Option<oneshot::Receiver<()>>,
+}
+
+/// Share of a scan in the discovery session of its adapter. Stops discovery
+/// once the last scan drops its share.
+struct Discovery {
+    adapter: Adapter1Proxy<'static>,
+    session: Arc<DiscoverySession>,
+}
+
+/// Concrete type implementing `api::BleAdapter`, used for Linux BLE.
+pub struct BleAdapter {
+    conn: Connection,
+    inner: Adapter1Proxy<'static>,
+    discovery: Arc<DiscoverySession>,
+}
+
+/// List the object paths of the adapters exposed by the BlueZ daemon
+/// reachable through `conn`. BlueZ names adapters `/org/bluez/hciN`, so they
+/// are sorted to list the adapter the system would pick by default first.
+async fn adapter_paths(
+    conn: &Connection,
+) -> Result<Vec<OwnedObjectPath>, BluetoothError> {
+    let objects = ObjectManagerProxy::builder(conn)
+        .destination(BLUEZ_SERVICE)?
+        .path("/")?
+        .build()
+        .await?
+        .get_managed_objects()
+        .await?;
+
+    let mut paths: Vec<OwnedObjectPath> = objects
+        .into_iter()
+        .filter(|(_, interfaces)| {
+            interfaces
+                .keys()
+                .any(|name| name.as_str() == ADAPTER_INTERFACE)
+        })
+        .map(|(path, _)| path)
+        .collect();
+    paths.sort_by(|a, b| a.as_str().cmp(b.as_str()));
+    Ok(paths)
+}
+
+/// Find the object path of the first adapter exposed by the BlueZ daemon
+/// reachable through `conn`.
+pub(crate) async fn default_adapter_path(
+    conn: &Connection,
+) -> Result<OwnedObjectPath, BluetoothError> {
+    adapter_paths(conn).await?.into_iter().next().ok_or(
+        BluetoothError::NotSupported(String::from("no BlueZ adapter")),
+    )
+}
+
+/// Identify the adapter at `path` by its name, e.g. `hci0`.
+fn adapter_id(path: &OwnedObjectPath) -> AdapterId {
+    AdapterId::from(path.as_str().rsplit('/').next().unwrap_or_default())
+}
+
+impl BleAdapter {
+    /// Open the first adapter exposed by the BlueZ daemon reachable through
+    /// `conn`.
+    pub(crate) async fn with_connection(
+        conn: Connection,
+    ) -> Result<Self, BluetoothError> {
+        let path = default_adapter_path(&conn).await?;
+        Self::with_path(conn, path).await
+    }
+
+    /// Open the adapter identified by `id` on the BlueZ daemon reachable
+    /// through `conn`.
+    pub(crate) async fn with_id_and_connection(
+        conn: Connection,
+        id: &AdapterId,
+    ) -> Result<Self, BluetoothError> {
+        let path = adapter_paths(&conn)
+            .await?
+            .into_iter()
+            .find(|path| adapter_id(path) == *id)
+            .ok_or(BluetoothError::InvalidArgument(format!(
+                "no BlueZ adapter {}",
+                id
+            )))?;
+        Self::with_path(conn, path).await
+    }
+
+    /// List the adapters exposed by the BlueZ daemon reachable through
+    /// `conn`.
+    pub(crate) async fn adapters_with_connection(
+        conn: Connection,
+    ) -> Result<Vec<AdapterInfo>, BluetoothError> {
+        let mut adapters = Vec::new();
+        for path in adapter_paths(&conn).await? {
+            let adapter = Self::with_path(conn.clone(), path).await?;
+            adapters.push(api::BleAdapter::info(&adapter).await?);
+        }
+        Ok(adapters)
+    }
+
+    async fn with_path(
+        conn: Connection,
+        path: OwnedObjectPath,
+    ) -> Result<Self, BluetoothError> {
+        let inner = Adapter1Proxy::builder(&conn).path(path)?.build().await?;
+
+        Ok(BleAdapter {
+            inner,
+            conn,
+            discovery: Arc::default(),
+        })
+    }
+}
+
+#[async_trait]
+impl api::BleAdapter for BleAdapter {
+    type BleDevice = BleDevice;
+    type ClassicDevice = ClassicDevice;
+
+    async fn default() -> Result<Self, BluetoothError> {
+        let conn = Connection::system().await?;
+        Self::with_connection(conn).await
+    }
+
+    async fn adapters() -> Result<Vec<AdapterInfo>, BluetoothError> {
+        let conn = Connection::system().await?;
+        Self::adapters_with_connection(conn).await
+    }
+
+    async fn with_id(id: &AdapterId) -> Result<Self, BluetoothError> {
+        let conn = Connection::system().await?;
+        Self::with_id_and_connection(conn, id).await
+    }
+
+    async fn ble_device(
+        &self,
+        addr: BleAddress,
+    ) -> Result<BleDevice, BluetoothError> {
+        BleDevice::with_adapter(&self.conn, self.inner.inner().path(), addr)
+            .await
+    }
+
+    async fn classic_device(
+        &self,
+        addr: ClassicAddress,
+    ) -> Result<ClassicDevice, BluetoothError> {
+        ClassicDevice::with_adapter(&self.conn, self.inner.inner().path(), addr)
+            .await
+    }
+
+    /// BlueZ reports the advertising capabilities of the controller through
+    /// the adapter's advertising manager, which is missing on controllers
+    /// that can't advertise.
+    async fn info(&self) -> Result<AdapterInfo, BluetoothError> {
+        let adapter = self.properties(ADAPTER_INTERFACE).await?;
+        let manager = self.properties(ADVERTISING_MANAGER_INTERFACE).await?;
+
+        let missing = |name: &str| {
+            BluetoothError::Internal(format!(
+                "BlueZ adapter is missing the `{}` property.",
+                name
+            ))
+        };
+        let addr = property::<String>(&adapter, "Address")?
+            .ok_or_else(|| missing("Address"))?;
+        let kind = property::<String>(&adapter, "AddressType")?
+            .ok_or_else(|| missing("AddressType"))?;
+        let name = match property::<String>(&adapter, "Alias")? {
+            Some(alias) => alias,
+            None => property::<String>(&adapter, "Name")?.unwrap_or_default(),
+        };
+        let roles =
+            property::<Vec<String>>(&adapter, "Roles")?.unwrap_or_default();
+        let powered = property::<bool>(&adapter, "Powered")?.unwrap_or(false);
+
+        let channels =
+            property::<Vec<String>>(&manager, "SupportedSecondaryChannels")?
+                .unwrap_or_default();
+        let capabilities = property::<HashMap<String, OwnedValue>>(
+            &manager,
+            "SupportedCapabilities",
+        )?
+        .unwrap_or_default();
+        let max_length = property::<u8>(&capabilities, "MaxAdvLen")?
+            .map_or(LEGACY_ADVERTISEMENT_LENGTH, usize::from);
+
+        Ok(AdapterInfo {
+            id: adapter_id(&OwnedObjectPath::from(
+                self.inner.inner().path().to_owned(),
+            )),
+            address: BleAddress::new(
+                parse_address(&addr)?,
+                parse_address_type(&kind)?,
+            ),
+            name,
+            central_role_supported: roles.iter().any(|r| r == "central"),
+            peripheral_role_supported: roles.iter().any(|r| r == "peripheral"),
+            extended_advertising_supported: !channels.is_empty(),
+            coded_phy_supported: channels.iter().any(|c| c == "Coded"),
+            max_advertisement_length: max_length,
+            radio_state: radio_state(powered),
+        })
+    }
+
+    async fn radio_state_changes(
+        &self,
+    ) -> Result<RadioStateStream, BluetoothError> {
+        let adapter = Adapter1Proxy::builder(&self.conn)
+            .path(self.inner.inner().path().to_owned())?
+            .build()
+            .await?;
+
+        // The stream starts with the current value and keeps the proxy
+        // alive.
+        let changes = adapter
+            .receive_powered_changed()
+            .await
+            .then(|change| async move { Ok(radio_state(change.get().await?)) });
+        Ok(RadioStateStream::new(changes))
+    }
+
+    /// The filter and settings are checked in software: BlueZ keeps one
+    /// discovery filter per D-Bus client, which every scan of the adapter
+    /// shares. BlueZ always scans actively and picks its own scan timing, so
+    /// the mode, interval and window of `settings` are ignored. BlueZ doesn't
+    /// report the PDU type of advertisements either, so
+    /// `ScanSettings::with_non_connectable()` and
+    /// `ScanSettings::with_extended()` aren't supported: scans deliver
+    /// non-connectable and extended advertisements regardless.
+    ///
+    /// Scans run a BlueZ discovery session with the `DuplicateData` filter,
+    /// so that every received advertisement is reported, rather than
+    /// registering an `AdvertisementMonitor1`. Monitors need at least one AD
+    /// pattern to match, so they can't deliver the unfiltered scans
+    /// `ScanFilter` allows, and they scan passively, so devices never send
+    /// their scan responses. They also still report matches through
+    /// `Device1` properties, which discovery already updates, and older BlueZ
+    /// releases only expose them with experimental features enabled.
+    ///
+    /// Subscribing to BlueZ and starting discovery take D-Bus round trips, so
+    /// they happen once the stream is first polled, which also reports
+    /// failures to start, including the radio being off.
+    fn start_scan(
+        &self,
+        filter: &ScanFilter,
+        settings: &ScanSettings,
+        data_selector: &[BleDataTypeId],
+    ) -> Result<ScanStream, BluetoothError> {
+        if settings.mode() == ScanMode::Passive {
+            warn!(
+                "BlueZ doesn't support passive discovery, scanning actively."
+            );
+        }
+
+        let listener = AdvListener::start(
+            self.inner.clone(),
+            self.discovery.clone(),
+            ScanState::new(filter, settings, data_selector),
+        );
+        Ok(ScanStream::new(stream::once(listener).try_flatten()))
+    }
+
+    /// See `bond::bonded_device()` for how BlueZ devices are mapped onto
+    /// the transport they bonded over.
+    async fn paired_devices(
+        &self,
+    ) -> Result<Vec<BondedDevice>, BluetoothError> {
+        let devices =
+            adapter_devices(&self.conn, self.inner.inner().path()).await?;
+
+        let mut paired = Vec::new();
+        for props in devices.values() {
+            if is_paired(props)? {
+                paired.push(bonded_device(props)?);
+            }
+        }
+        Ok(paired)
+    }
+
+    async fn unpair(&self, device: BondedDevice) -> Result<(), BluetoothError> {
+        let devices =
+            adapter_devices(&self.conn, self.inner.inner().path()).await?;
+
+        for (path, props) in devices {
+            if is_paired(&props)? && bonded_device(&props)? == device {
+                let adapter = Adapter1Proxy::builder(&self.conn)
+                    .path(self.inner.inner().path().to_owned())?
+                    .build()
+                    .await?;
+                adapter.remove_device(&path).await?;
+                info!("Removed BlueZ device {}.", path.as_str());
+            }
+        }
+        Ok(())
+    }
+
+    async fn bond_events(&self) -> Result<BondEventStream, BluetoothError> {
+        let listener =
+            BondListener::new(&self.conn, self.inner.inner().path()).await?;
+        Ok(BondEventStream::new(listener))
+    }
+}
+
+impl BleAdapter {
+    /// Read every property of the adapter's `interface`, which is empty if
+    /// BlueZ doesn't serve it for this adapter.
+    async fn properties(
+        &self,
+        interface: &'static str,
+    ) -> Result<DeviceProperties, BluetoothError> {
+        let props = PropertiesProxy::builder(&self.conn)
+            .destination(BLUEZ_SERVICE)?
+            .path(self.inner.inner().path().to_owned())?
+            .cache_properties(CacheProperties::No)
+            .build()
+            .await?;
+        let interface = InterfaceName::from_static_str_unchecked(interface);
+        match props.get_all(interface).await {
+            Ok(props) => Ok(props),
+            Err(fdo::Error::UnknownInterface(_))
+            | Err(fdo::Error::InvalidArgs(_)) => Ok(HashMap::new()),
+            Err(err) => Err(BluetoothError::from(err)),
+        }
+    }
+}
+
+/// Convert the `Powered` property of a BlueZ adapter.
+fn radio_state(powered: bool) -> RadioState {
+    if powered {
+        RadioState::On
+    } else {
+        RadioState::Off
+    }
+}
+
+impl Discovery {
+    /// Join the discovery session of `adapter`, starting it if no scan is
+    /// running.
+    async fn join(
+        adapter: Adapter1Proxy<'static>,
+        session: Arc<DiscoverySession>,
+    ) -> Result<Self, BluetoothError> {
+        let starting = session.starting.lock().await;
+        let running = {
+            let mut state = session.state.lock().unwrap();
+            state.scans += 1;
+            state.scans > 1
+        };
+        // Stops discovery again if starting it fails or the scan is dropped
+        // meanwhile.
+        let discovery = Discovery {
+            adapter,
+            session: session.clone(),
+        };
+
+        if !running {
+            let stopped = session.state.lock().unwrap().stopped.take();
+            if let Some(stopped) = stopped {
+                let _ = stopped.await;
+            }
+
+            // Report every received advertisement rather than only the first
+            // one per device.
+            let filter = HashMap::from([
+                ("Transport", Value::from("le")),
+                ("DuplicateData", Value::from(true)),
+            ]);
+            let adapter = &discovery.adapter;
+            adapter.set_discovery_filter(filter).await?;
+            adapter.start_discovery().await?;
+            info!("Started BlueZ discovery on {}.", adapter.inner().path());
+        }
+
+        drop(starting);
+        Ok(discovery)
+    }
+}
+
+impl Drop for Discovery {
+    fn drop(&mut self) {
+        let mut state = self.session.state.lock().unwrap();
+        state.scans -= 1;
+        if state.scans > 0 {
+            return;
+        }
+
+        // Stopping takes a D-Bus round trip, so it runs on the executor of
+        // the connection rather than blocking whoever dropped the scan.
+        let (sender, stopped) = oneshot::channel();
+        state.stopped = Some(stopped);
+        let adapter = self.adapter.clone();
+        let stop = async move {
+            match adapter.stop_discovery().await {
+                Ok(()) => info!(
+                    "Stopped BlueZ discovery on {}.",
+                    adapter.inner().path()
+                ),
+                Err(err) => warn!("Failed to stop BlueZ discovery: {}", err),
+            }
+            let _ = sender.send(());
+        };
+        self.adapter
+            .inner()
+            .connection()
+            .executor()
+            .spawn(stop, "stop BlueZ discovery")
+            .detach();
+    }
+}
+
+impl Stream for AdvListener {
+    type Item = Result<BleAdvertisement, BluetoothError>;
+
+    fn poll_next(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Self::Item>> {
+        if self.powered_off {
+            return Poll::Ready(None);
+        }
+
+        // Most signals don't correspond to a received advertisement, so this
+        // is a loop to skip them.
+        loop {
+            let msg = match ready!(self.stream.poll_next_unpin(cx)) {
+                Some(Ok(msg)) => msg,
+                Some(Err(err)) => return Poll::Ready(Some(Err(err.into()))),
+                None => return Poll::Ready(None),
+            };
+
+            match self.advertisement(&msg).transpose() {
+                Some(advertisement) => return Poll::Ready(Some(advertisement)),
+                None => continue,
+            }
+        }
+    }
+}
+
+impl AdvListener {
+    /// Subscribe to the BlueZ signals about `adapter` and its devices, then
+    /// join its discovery session.
+    async fn start(
+        adapter: Adapter1Proxy<'static>,
+        session: Arc<DiscoverySession>,
+        state: ScanState,
+    ) -> Result<Self, BluetoothError> {
+        let conn = adapter.inner().connection();
+        let adapter_path = adapter.inner().path().to_string();
+
+        // Subscribe before discovery starts so no advertisement is missed.
+        let added_rule = MatchRule::builder()
+            .msg_type(MessageType::Signal)
+            .interface("org.freedesktop.DBus.ObjectManager")?
+            .build();
+        let changed_rule = MatchRule::builder()
+            .msg_type(MessageType::Signal)
+            .interface("org.freedesktop.DBus.Properties")?
+            .member("PropertiesChanged")?
+            .path_namespace(adapter_path.as_str())?
+            .build();
+        let added_stream =
+            MessageStream::for_match_rule(added_rule, conn, None).await?;
+        let changed_stream =
+            MessageStream::for_match_rule(changed_rule, conn, None).await?;
+
+        // Seed the property cache with devices BlueZ already knows about.
+        let device_prefix = format!("{}/", adapter_path);
+        let devices = ObjectManagerProxy::builder(conn)
+            .destination(BLUEZ_SERVICE)?
+            .path("/")?
+            .build()
+            .await?
+            .get_managed_objects()
+            .await?
+            .into_iter()
+            .filter(|(path, _)| path.as_str().starts_with(&device_prefix))
+            .filter_map(|(path, mut interfaces)| {
+                let props = interfaces.remove(DEVICE_INTERFACE)?;
+                Some((path, props))
+            })
+            .collect();
+
+        // Checked once subscribed, so being switched off later is seen.
+        if !adapter.powered().await? {
+            return Err(BluetoothError::FailedPrecondition(String::from(
+                "Bluetooth is turned off.",
+            )));
+        }
+
+        Ok(AdvListener {
+            adapter_path,
+            device_prefix,
+            powered_off: false,
+            stream: futures::stream::select(added_stream, changed_stream),
+            devices,
+            state,
+            _discovery: Discovery::join(adapter, session).await?,
+        })
+    }
+
+    /// Convert a BlueZ signal into an advertisement, if the signal was caused
+    /// by a received advertisement matching the filter.
+    fn advertisement(
+        &mut self,
+        msg: &Message,
+    ) -> Result<Option<BleAdvertisement>, BluetoothError> {
+        let props = match self.handle_signal(msg)? {
+            Some(path) => &self.devices[&path],
+            None => return Ok(None),
+        };
+
+        let mut advertisement = BleAdvertisement::try_from(props)?;
+        advertisement.load_data(props, self.state.datatype_ids())?;
+
+        Ok(self.state.process(advertisement))
+    }
+
+    /// Update the device property cache from a BlueZ signal. Returns the
+    /// path of the advertising device if the signal was caused by a received
+    /// advertisement.
+    fn handle_signal(
+        &mut self,
+        msg: &Message,
+    ) -> Result<Option<OwnedObjectPath>, BluetoothError> {
+        let header = msg.header();
+        let member = header.member().map(|member| member.as_str());
+
+        let (path, is_advertisement) = match member {
+            Some("InterfacesAdded") => {
+                let (path, mut interfaces): (
+                    OwnedObjectPath,
+                    HashMap<String, DeviceProperties>,
+                ) = msg.body().deserialize()?;
+                let props = match interfaces.remove(DEVICE_INTERFACE) {
+                    Some(props) if self.owns(&path) => props,
+                    _ => return Ok(None),
+                };
+
+                // Devices restored from BlueZ's cache have no RSSI until
+                // they are actually heard.
+                let is_advertisement = props.contains_key("RSSI");
+                self.devices.insert(path.clone(), props);
+                (path, is_advertisement)
+            }
+            Some("InterfacesRemoved") => {
+                let (path, interfaces): (OwnedObjectPath, Vec<String>) =
+                    msg.body().deserialize()?;
+                if interfaces.iter().any(|name| name == DEVICE_INTERFACE) {
+                    self.devices.remove(&path);
+                }
+                return Ok(None);
+            }
+            Some("PropertiesChanged") => {
+                let path = match header.path() {
+                    Some(path) => OwnedObjectPath::from(path.to_owned()),
+                    None => return Ok(None),
+                };
+                let (interface, changed, invalidated): (
+                    String,
+                    DeviceProperties,
+                    Vec<String>,
+                ) = msg.body().deserialize()?;
+
+                if interface == ADAPTER_INTERFACE
+                    && path.as_str() == self.adapter_path
+                    && property::<bool>(&changed, "Powered")? == Some(false)
+                {
+                    self.powered_off = true;
+                    return Err(BluetoothError::FailedPrecondition(
+                        String::from("Bluetooth was turned off."),
+                    ));
+                }
+                if interface != DEVICE_INTERFACE {
+                    return Ok(None);
+                }
+
+                let is_advertisement = changed.keys().any(|name| {
+                    ADVERTISEMENT_PROPERTIES.contains(&name.as_str())
+                });
+                let props = self.devices.entry(path.clone()).or_default();
+                props.extend(changed);
+                for name in invalidated {
+                    props.remove(&name);
+                }
+                (path, is_advertisement)
+            }
+            _ => return Ok(None),
+        };
+
+        match self.devices.get(&path) {
+            // Devices are only reported once their address is known.
+            Some(props)
+                if is_advertisement && props.contains_key("Address") =>
+            {
+                Ok(Some(path))
+            }
+            _ => Ok(None),
+        }
+    }
+
+    /// Whether the object at `path` is a device of the scanning adapter.
+    fn owns(&self, path: &OwnedObjectPath) -> bool {
+        path.as_str().starts_with(&self.device_prefix)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{collections::HashMap, time::Duration};
+
+    use async_io::Timer;
+    use futures::executor::block_on;
+
+    use super::*;
+    use crate::{
+        api::BleAdapter as _,
+        common::{
+            BleAddress, BleAddressKind, BondEvent, BtUuid, ClassicAddress,
+            ServiceData,
+        },
+        linux::mock::{MockBluez, MockBus, MockDevice},
+    };
+
+    fn fast_pair_device() -> MockDevice {
+        let mut device = MockDevice::new("11:22:33:44:55:66", "random");
+        device.service_data = HashMap::from([(
+            String::from("0000fe2c-0000-1000-8000-00805f9b34fb"),
+            vec![0x01, 0x02, 0x03],
+        )]);
+        device
+    }
+
+    /// Poll `scan` until `scans` scans share the discovery session of
+    /// `adapter` and none is still starting it, since scans only start once
+    /// polled.
+    async fn until_joined(
+        adapter: &BleAdapter,
+        scan: &mut ScanStream,
+        scans: usize,
+    ) {
+        let session = &adapter.discovery;
+        while session.state.lock().unwrap().scans < scans
+            || session.starting.try_lock().is_none()
+        {
+            assert!(futures::poll!(scan.next()).is_pending());
+            Timer::after(Duration::from_millis(5)).await;
+        }
+    }
+
+    /// Wait for the last dropped scan to stop discovery, which happens in
+    /// the background.
+    async fn until_stopped(bluez: &MockBluez) {
+        while bluez.discovering().await {
+            Timer::after(Duration::from_millis(5)).await;
+        }
+    }
+
+    #[test]
+    fn adapters_lists_every_adapter() {
+        let bus = MockBus::start();
+        block_on(async {
+            let bluez = MockBluez::start(&bus).await;
+            bluez.add_adapter("hci1", "AA:BB:CC:DD:EE:FF").await;
+            let conn = bus.connect().await;
+
+            let adapters = BleAdapter::adapters_with_connection(conn.clone())
+                .await
+                .unwrap();
+            let ids: Vec<&str> =
+                adapters.iter().map(|info| info.id().as_str()).collect();
+            assert_eq!(ids, vec!["hci0", "hci1"]);
+            assert!(!adapters[1].is_extended_advertising_supported());
+            assert_eq!(adapters[1].max_advertisement_length(), 31);
+
+            let adapter = BleAdapter::with_id_and_connection(
+                conn.clone(),
+                adapters[1].id(),
+            )
+            .await
+            .unwrap();
+            assert_eq!(
+                adapter.info().await.unwrap().address(),
+                BleAddress::new(0xaabbccddeeff, BleAddressKind::Public)
+            );
+            assert!(matches!(
+                BleAdapter::with_id_and_connection(
+                    conn,
+                    &AdapterId::from("hci2")
+                )
+                .await,
+                Err(BluetoothError::InvalidArgument(_))
+            ));
+        });
+    }
+
+    #[test]
+    fn devices_are_opened_through_adapter() {
+        let bus = MockBus::start();
+        block_on(async {
+            let bluez = MockBluez::start(&bus).await;
+            let second = bluez.add_adapter("hci1", "AA:BB:CC:DD:EE:FF").await;
+            bluez.add_device_to(&second, fast_pair_device()).await;
+            let conn = bus.connect().await;
+            let addr = BleAddress::new(0x112233445566, BleAddressKind::Random);
+
+            let first =
+                BleAdapter::with_connection(conn.clone()).await.unwrap();
+            assert!(matches!(
+                first.ble_device(addr).await,
+                Err(BluetoothError::FailedPrecondition(_))
+            ));
+
+            let second = BleAdapter::with_id_and_connection(
+                conn,
+                &AdapterId::from("hci1"),
+            )
+            .await
+            .unwrap();
+            let device = second.ble_device(addr).await.unwrap();
+            assert_eq!(api::BleDevice::address(&device), addr);
+        });
+    }
+
+    #[test]
+    fn start_and_stop_scan() {
+        let bus = MockBus::start();
+        block_on(async {
+            let bluez = MockBluez::start(&bus).await;
+            let adapter = BleAdapter::with_connection(bus.connect().await)
+                .await
+                .unwrap();
+
+            let mut scan = adapter
+                .start_scan(
+                    &ScanFilter::default(),
+                    &ScanSettings::default(),
+                    &[],
+                )
+                .unwrap();
+            assert!(!bluez.discovering().await);
+            until_joined(&adapter, &mut scan, 1).await;
+            let mut filter = bluez.discovery_filter().await;
+            filter.sort();
+            assert_eq!(filter, vec!["DuplicateData", "Transport"]);
+
+            drop(scan);
+            until_stopped(&bluez).await;
+        });
+    }
+
+    #[test]
+    fn concurrent_scans_share_discovery() {
+        let bus = MockBus::start();
+        block_on(async {
+            let bluez = MockBluez::start(&bus).await;
+            let adapter = BleAdapter::with_connection(bus.connect().await)
+                .await
+                .unwrap();
+
+            let mut first = adapter
+                .start_scan(
+                    &ScanFilter::default(),
+                    &ScanSettings::default(),
+                    &[],
+                )
+                .unwrap();
+            let mut second = adapter
+                .start_scan(
+                    &ScanFilter::default(),
+                    &ScanSettings::default(),
+                    &[],
+                )
+                .unwrap();
+            until_joined(&adapter, &mut first, 1).await;
+            until_joined(&adapter, &mut second, 2).await;
+
+            drop(first);
+            assert!(bluez.discovering().await);
+            drop(second);
+            until_stopped(&bluez).await;
+
+            // A new scan starts a new discovery session.
+            let mut scan = adapter
+                .start_scan(
+                    &ScanFilter::default(),
+                    &ScanSettings::default(),
+                    &[],
+                )
+                .unwrap();
+            until_joined(&adapter, &mut scan, 1).await;
+        });
+    }
+
+    #[test]
+    fn next_advertisement_from_new_device() {
+        let bus = MockBus::start();
+        block_on(async {
+            let bluez = MockBluez::start(&bus).await;
+            let adapter = BleAdapter::with_connection(bus.connect().await)
+                .await
+                .unwrap();
+            let mut scan = adapter
+                .start_scan(
+                    &ScanFilter::default(),
+                    &ScanSettings::default(),
+                    &[BleDataTypeId::ServiceData16BitUuid],
+                )
+                .unwrap();
+            until_joined(&adapter, &mut scan, 1).await;
+
+            bluez.add_device(fast_pair_device()).await;
+
+            let ad = scan.next().await.unwrap().unwrap();
+            assert_eq!(
+                ad.address(),
+                BleAddress::new(0x112233445566, BleAddressKind::Random)
+            );
+            assert_eq!(ad.rssi(), Some(-60));
+            assert_eq!(
+                *ad.service_data_16bit_uuid().unwrap(),
+                vec![ServiceData::new(
+                    BtUuid::FAST_PAIR,
+                    vec![0x01, 0x02, 0x03]
+                )]
+            );
+        });
+    }
+
+    #[test]
+    fn next_advertisement_from_known_device() {
+        let bus = MockBus::start();
+        block_on(async {
+            let bluez = MockBluez::start(&bus).await;
+            let path = bluez.add_device(fast_pair_device()).await;
+            let adapter = BleAdapter::with_connection(bus.connect().await)
+                .await
+                .unwrap();
+            let mut scan = adapter
+                .start_scan(
+                    &ScanFilter::default(),
+                    &ScanSettings::default(),
+                    &[],
+                )
+                .unwrap();
+            until_joined(&adapter, &mut scan, 1).await;
+
+            bluez.advertise(&path, -42).await;
+
+            let ad = scan.next().await.unwrap().unwrap();
+            assert_eq!(ad.rssi(), Some(-42));
+            assert!(ad.service_data_16bit_uuid().is_err());
+        });
+    }
+
+    #[test]
+    fn scan_applies_filter() {
+        let bus = MockBus::start();
+        block_on(async {
+            let bluez = MockBluez::start(&bus).await;
+            let path = bluez.add_device(fast_pair_device()).await;
+            let adapter = BleAdapter::with_connection(bus.connect().await)
+                .await
+                .unwrap();
+            let filter = ScanFilter::default()
+                .with_service_data(BtUuid::FAST_PAIR, vec![0x01], None)
+                .with_min_rssi(-50);
+            let mut scan = adapter
+                .start_scan(&filter, &ScanSettings::default(), &[])
+                .unwrap();
+            until_joined(&adapter, &mut scan, 1).await;
+
+            bluez.advertise(&path, -70).await;
+            bluez.advertise(&path, -42).await;
+
+            let ad = scan.next().await.unwrap().unwrap();
+            assert_eq!(ad.rssi(), Some(-42));
+            assert!(ad.service_data_16bit_uuid().is_err());
+        });
+    }
+
+    #[test]
+    fn scan_ends_when_powered_off() {
+        let bus = MockBus::start();
+        block_on(async {
+            let bluez = MockBluez::start(&bus).await;
+            let adapter = BleAdapter::with_connection(bus.connect().await)
+                .await
+                .unwrap();
+            let mut scan = adapter
+                .start_scan(
+                    &ScanFilter::default(),
+                    &ScanSettings::default(),
+                    &[],
+                )
+                .unwrap();
+            until_joined(&adapter, &mut scan, 1).await;
+
+            bluez.set_powered(false).await;
+            assert!(matches!(
+                scan.next().await,
+                Some(Err(BluetoothError::FailedPrecondition(_)))
+            ));
+            assert!(scan.next().await.is_none());
+
+            // Scans started while the radio is off fail once polled.
+            let mut scan = adapter
+                .start_scan(
+                    &ScanFilter::default(),
+                    &ScanSettings::default(),
+                    &[],
+                )
+                .unwrap();
+            assert!(matches!(
+                scan.next().await,
+                Some(Err(BluetoothError::FailedPrecondition(_)))
+            ));
+            assert!(scan.next().await.is_none());
+        });
+    }
+
+    #[test]
+    fn info_reads_adapter_and_advertising_manager() {
+        let bus = MockBus::start();
+        block_on(async {
+            let _bluez = MockBluez::start(&bus).await;
+            let adapter = BleAdapter::with_connection(bus.connect().await)
+                .await
+                .unwrap();
+
+            let info = adapter.info().await.unwrap();
+            assert_eq!(
+                info.address(),
+                BleAddress::new(0x001122334455, BleAddressKind::Public)
+            );
+            assert_eq!(info.name(), "Mock Adapter");
+            assert!(info.is_central_role_supported());
+            assert!(info.is_peripheral_role_supported());
+            assert!(info.is_extended_advertising_supported());
+            assert!(info.is_coded_phy_supported());
+            assert_eq!(info.max_advertisement_length(), 251);
+            assert_eq!(info.radio_state(), RadioState::On);
+        });
+    }
+
+    #[test]
+    fn radio_state_changes_follow_powered() {
+        let bus = MockBus::start();
+        block_on(async {
+            let bluez = MockBluez::start(&bus).await;
+            let adapter = BleAdapter::with_connection(bus.connect().await)
+                .await
+                .unwrap();
+            let mut changes = adapter.radio_state_changes().await.unwrap();
+            assert_eq!(changes.next().await.unwrap().unwrap(), RadioState::On);
+
+            bluez.set_powered(false).await;
+            assert_eq!(changes.next().await.unwrap().unwrap(), RadioState::Off);
+            assert_eq!(
+                adapter.info().await.unwrap().radio_state(),
+                RadioState::Off
+            );
+
+            bluez.set_powered(true).await;
+            assert_eq!(changes.next().await.unwrap().unwrap(), RadioState::On);
+        });
+    }
+
+    fn paired_device(address: &str, address_type: &str) -> MockDevice {
+        let mut device = MockDevice::new(address, address_type);
+        device.paired = true;
+        device
+    }
+
+    #[test]
+    fn paired_devices_by_transport() {
+        let bus = MockBus::start();
+        block_on(async {
+            let bluez = MockBluez::start(&bus).await;
+            bluez.add_device(fast_pair_device()).await;
+            bluez
+                .add_device(paired_device("11:11:11:11:11:11", "random"))
+                .await;
+            bluez
+                .add_device(paired_device("22:22:22:22:22:22", "public"))
+                .await;
+            let mut classic = paired_device("33:33:33:33:33:33", "public");
+            classic.class = 0x240404;
+            bluez.add_device(classic).await;
+            let adapter = BleAdapter::with_connection(bus.connect().await)
+                .await
+                .unwrap();
+
+            let mut devices = adapter.paired_devices().await.unwrap();
+            devices.sort_by_key(|device| match *device {
+                BondedDevice::Ble(addr) => u64::from(addr),
+                BondedDevice::Classic(addr) => u64::from(addr),
+            });
+            assert_eq!(
+                devices,
+                vec![
+                    BondedDevice::Ble(BleAddress::new(
+                        0x111111111111,
+                        BleAddressKind::Random
+                    )),
+                    BondedDevice::Ble(BleAddress::new(
+                        0x222222222222,
+                        BleAddressKind::Public
+                    )),
+                    BondedDevice::Classic(ClassicAddress::from(0x333333333333)),
+                ]
+            );
+        });
+    }
+
+    #[test]
+    fn unpair_removes_device() {
+        let bus = MockBus::start();
+        block_on(async {
+            let bluez = MockBluez::start(&bus).await;
+            let path = bluez
+                .add_device(paired_device("11:22:33:44:55:66", "random"))
+                .await;
+            let adapter = BleAdapter::with_connection(bus.connect().await)
+                .await
+                .unwrap();
+
+            // Devices the adapter isn't bonded with are left alone.
+            let device =
+                BleAddress::new(0x112233445566, BleAddressKind::Public);
+            adapter.unpair(BondedDevice::Ble(device)).await.unwrap();
+            assert!(bluez.has_device(&path).await);
+
+            let device =
+                BleAddress::new(0x112233445566, BleAddressKind::Random);
+            adapter.unpair(BondedDevice::Ble(device)).await.unwrap();
+            assert!(!bluez.has_device(&path).await);
+            assert!(adapter.paired_devices().await.unwrap().is_empty());
+        });
+    }
+
+    #[test]
+    fn bond_events_follow_bluez() {
+        let bus = MockBus::start();
+        block_on(async {
+            let bluez = MockBluez::start(&bus).await;
+            let path = bluez.add_device(fast_pair_device()).await;
+            let adapter = BleAdapter::with_connection(bus.connect().await)
+                .await
+                .unwrap();
+            let mut events = adapter.bond_events().await.unwrap();
+            let device = BondedDevice::Ble(BleAddress::new(
+                0x112233445566,
+                BleAddressKind::Random,
+            ));
+
+            // Unrelated changes are skipped.
+            bluez.advertise(&path, -42).await;
+            bluez.set_paired(&path, true).await;
+            assert_eq!(
+                events.next().await.unwrap().unwrap(),
+                BondEvent::Added(device)
+            );
+
+            adapter.unpair(device).await.unwrap();
+            assert_eq!(
+                events.next().await.unwrap().unwrap(),
+                BondEvent::Removed(device)
+            );
+
+            let mut classic = paired_device("33:33:33:33:33:33", "public");
+            classic.class = 0x240404;
+            bluez.add_device(classic).await;
+            assert_eq!(
+                events.next().await.unwrap().unwrap(),
+                BondEvent::Added(BondedDevice::Classic(ClassicAddress::from(
+                    0x333333333333
+                )))
+            );
+        });
+    }
+}
@@ -0,0 +1,84 @@
+// Copyright 2023 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use crate::common::{BleAddressKind, BluetoothError, ClassicAddress};
+
+// BlueZ exposes addresses as "AA:BB:CC:DD:EE:FF" strings, with the most
+// significant byte first, which is the form the crate addresses parse from and
+// format to.
+
+/// Parse a BlueZ address string into the 48-bit integer used by the crate API.
+pub(crate) fn parse_address(addr: &str) -> Result<u64, BluetoothError> {
+    Ok(u64::from(addr.parse::<ClassicAddress>()?))
+}
+
+/// Format a 48-bit address the way BlueZ does.
+pub(crate) fn format_address(addr: u64) -> String {
+    ClassicAddress::from(addr).to_string()
+}
+
+/// Convert the `AddressType` property of `org.bluez.Device1`.
+pub(crate) fn parse_address_type(
+    kind: &str,
+) -> Result<BleAddressKind, BluetoothError> {
+    match kind {
+        "public" => Ok(BleAddressKind::Public),
+        "random" => Ok(BleAddressKind::Random),
+        _ => Err(BluetoothError::BadTypeConversion(format!(
+            "Attempting to construct `BleAddressKind` with device \
+            advertising invalid address type {}.",
+            kind
+        ))),
+    }
+}
+
+/// Convert a crate address kind into BlueZ's `AddressType` representation.
+pub(crate) fn format_address_type(kind: BleAddressKind) -> &'static str {
+    match kind {
+        BleAddressKind::Public => "public",
+        BleAddressKind::Random => "random",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_and_format_address() {
+        let addr = parse_address("11:22:33:44:55:66").unwrap();
+        assert_eq!(addr, 0x112233445566);
+        assert_eq!(format_address(addr), "11:22:33:44:55:66");
+        assert_eq!(format_address(0xAABBCCDDEEFF), "AA:BB:CC:DD:EE:FF");
+    }
+
+    #[test]
+    fn parse_bad_address() {
+        assert!(matches!(
+            parse_address("11:22:33:44:55"),
+            Err(BluetoothError::BadTypeConversion(_))
+        ));
+        assert!(matches!(
+            parse_address("11:22:33:44:55:GG"),
+            Err(BluetoothError::BadTypeConversion(_))
+        ));
+    }
+
+    #[test]
+    fn parse_address_type_strings() {
+        assert_eq!(parse_address_type("public"), Ok(BleAddressKind::Public));
+        assert_eq!(parse_address_type("random"), Ok(BleAddressKind::Random));
+        assert!(parse_address_type("unknown").is_err());
+    }
+}
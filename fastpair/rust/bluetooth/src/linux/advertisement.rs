@@ -0,0 +1,319 @@
+// Copyright 2023 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::collections::HashMap;
+
+use zbus::zvariant::OwnedValue;
+
+use super::address::{parse_address, parse_address_type};
+use crate::common::{
+    AdStructure, BleAddress, BleAdvertisement, BleDataTypeId, BluetoothError,
+    BtUuid,
+};
+
+/// Properties of an `org.bluez.Device1` object, as returned by
+/// `org.freedesktop.DBus.Properties.GetAll` or carried in an
+/// `InterfacesAdded` signal. BlueZ refreshes these whenever an advertisement
+/// from the device is received.
+pub(crate) type DeviceProperties = HashMap<String, OwnedValue>;
+
+/// Retrieve a property of type `T`, or `None` if the device doesn't have it.
+pub(crate) fn property<T>(
+    props: &DeviceProperties,
+    name: &str,
+) -> Result<Option<T>, BluetoothError>
+where
+    T: TryFrom<OwnedValue>,
+    T::Error: Into<zbus::zvariant::Error>,
+{
+    match props.get(name) {
+        Some(value) => Ok(Some(
+            T::try_from(value.try_clone()?)
+                .map_err(|err| BluetoothError::from(err.into()))?,
+        )),
+        None => Ok(None),
+    }
+}
+
+impl TryFrom<&DeviceProperties> for BleAdvertisement {
+    type Error = BluetoothError;
+
+    fn try_from(props: &DeviceProperties) -> Result<Self, Self::Error> {
+        let addr = property::<String>(props, "Address")?.ok_or(
+            BluetoothError::Internal(String::from(
+                "BlueZ device is missing the `Address` property.",
+            )),
+        )?;
+        let kind = property::<String>(props, "AddressType")?.ok_or(
+            BluetoothError::Internal(String::from(
+                "BlueZ device is missing the `AddressType` property.",
+            )),
+        )?;
+
+        let addr =
+            BleAddress::new(parse_address(&addr)?, parse_address_type(&kind)?);
+        // BlueZ only exposes `RSSI` and `TxPower` while they are known, so
+        // these are None if the device isn't currently advertising them.
+        let rssi = property::<i16>(props, "RSSI")?;
+        let tx_power = property::<i16>(props, "TxPower")?;
+
+        Ok(BleAdvertisement::new(addr, rssi, tx_power))
+    }
+}
+
+impl BleAdvertisement {
+    /// Load data of selected data types into self from the BlueZ device
+    /// properties. BlueZ only exposes the parsed advertisement, so the AD
+    /// structures are rebuilt from it and handed to the common parser.
+    pub(crate) fn load_data(
+        &mut self,
+        props: &DeviceProperties,
+        datatype_ids: &[BleDataTypeId],
+    ) -> Result<(), BluetoothError> {
+        let sections = ad_sections(props)?;
+        let structures = sections
+            .iter()
+            .map(|(data_type, data)| AdStructure::new(*data_type, data))
+            .collect::<Vec<_>>();
+
+        self.load_ad_structures(&structures, datatype_ids)
+    }
+}
+
+/// Retrieve a map property with its entries sorted by key, so the rebuilt AD
+/// structures have a stable order.
+fn sorted_map<K>(
+    props: &DeviceProperties,
+    name: &str,
+) -> Result<Vec<(K, OwnedValue)>, BluetoothError>
+where
+    K: Ord + std::hash::Hash + zbus::zvariant::Type,
+    K: for<'a> TryFrom<zbus::zvariant::Value<'a>>,
+    HashMap<K, OwnedValue>: TryFrom<OwnedValue>,
+    <HashMap<K, OwnedValue> as TryFrom<OwnedValue>>::Error:
+        Into<zbus::zvariant::Error>,
+{
+    let mut entries = property::<HashMap<K, OwnedValue>>(props, name)?
+        .unwrap_or_default()
+        .into_iter()
+        .collect::<Vec<_>>();
+    entries.sort_by(|(a, _), (b, _)| a.cmp(b));
+
+    Ok(entries)
+}
+
+/// Rebuild the AD structures of the advertisement from the device properties
+/// BlueZ parsed it into, as (AD type, AD data) pairs.
+/// See: doc/org.bluez.Device.rst in the BlueZ source tree.
+fn ad_sections(
+    props: &DeviceProperties,
+) -> Result<Vec<(u8, Vec<u8>)>, BluetoothError> {
+    let mut sections = Vec::new();
+
+    if let Some(flags) = property::<Vec<u8>>(props, "AdvertisingFlags")? {
+        sections.push((BleDataTypeId::Flags as u8, flags));
+    }
+
+    if let Some(name) = property::<String>(props, "Name")? {
+        sections.push((BleDataTypeId::CompleteLocalName as u8, name.into()));
+    }
+
+    // BlueZ merges every UUID list into `UUIDs`, so rebuild one complete list
+    // per UUID size.
+    let mut uuid_lists = [
+        (BleDataTypeId::ServiceUuids16Bit, Vec::new()),
+        (BleDataTypeId::ServiceUuids32Bit, Vec::new()),
+        (BleDataTypeId::ServiceUuids128Bit, Vec::new()),
+    ];
+    for uuid in property::<Vec<String>>(props, "UUIDs")?.unwrap_or_default() {
+        if let Ok(uuid) = uuid.parse::<BtUuid>() {
+            let bytes = uuid.to_le_bytes();
+            let list = match bytes.len() {
+                2 => &mut uuid_lists[0].1,
+                4 => &mut uuid_lists[1].1,
+                _ => &mut uuid_lists[2].1,
+            };
+            list.extend(bytes);
+        }
+    }
+    for (datatype_id, list) in uuid_lists {
+        if !list.is_empty() {
+            sections.push((datatype_id as u8, list));
+        }
+    }
+
+    if let Some(tx_power) = property::<i16>(props, "TxPower")? {
+        sections
+            .push((BleDataTypeId::TxPowerLevel as u8, vec![tx_power as u8]));
+    }
+
+    for (uuid, data) in sorted_map::<String>(props, "ServiceData")? {
+        if let Ok(uuid) = uuid.parse::<BtUuid>() {
+            let mut section = uuid.to_le_bytes();
+            let datatype_id = match section.len() {
+                2 => BleDataTypeId::ServiceData16BitUuid,
+                4 => BleDataTypeId::ServiceData32BitUuid,
+                _ => BleDataTypeId::ServiceData128BitUuid,
+            };
+            section.extend(Vec::<u8>::try_from(data)?);
+            sections.push((datatype_id as u8, section));
+        }
+    }
+
+    if let Some(appearance) = property::<u16>(props, "Appearance")? {
+        sections.push((
+            BleDataTypeId::Appearance as u8,
+            appearance.to_le_bytes().to_vec(),
+        ));
+    }
+
+    for (company_id, data) in sorted_map::<u16>(props, "ManufacturerData")? {
+        let mut section = company_id.to_le_bytes().to_vec();
+        section.extend(Vec::<u8>::try_from(data)?);
+        sections.push((BleDataTypeId::ManufacturerSpecificData as u8, section));
+    }
+
+    // Data types BlueZ doesn't parse itself, e.g. the advertising interval.
+    for (data_type, data) in sorted_map::<u8>(props, "AdvertisingData")? {
+        sections.push((data_type, Vec::<u8>::try_from(data)?));
+    }
+
+    Ok(sections)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    use crate::common::{BleAddressKind, ManufacturerData, ServiceData};
+    use zbus::zvariant::Value;
+
+    fn owned<'a>(value: impl Into<Value<'a>>) -> OwnedValue {
+        OwnedValue::try_from(value.into()).unwrap()
+    }
+
+    fn device_properties() -> DeviceProperties {
+        let mut service_data = HashMap::new();
+        service_data.insert(
+            String::from("0000fe2c-0000-1000-8000-00805f9b34fb"),
+            owned(vec![0x01u8, 0x02, 0x03]),
+        );
+        service_data.insert(
+            String::from("df21fe2c-2515-4fdb-8886-f12c4d67927c"),
+            owned(vec![0x04u8]),
+        );
+
+        let mut props = DeviceProperties::new();
+        props.insert(String::from("Address"), owned("11:22:33:44:55:66"));
+        props.insert(String::from("AddressType"), owned("random"));
+        props.insert(String::from("RSSI"), owned(-60i16));
+        props.insert(String::from("ServiceData"), owned(service_data));
+        props
+    }
+
+    #[test]
+    fn advertisement_from_properties() {
+        let ad = BleAdvertisement::try_from(&device_properties()).unwrap();
+        assert_eq!(
+            ad.address(),
+            BleAddress::new(0x112233445566, BleAddressKind::Random)
+        );
+        assert_eq!(ad.rssi(), Some(-60));
+        assert_eq!(ad.tx_power(), None);
+    }
+
+    #[test]
+    fn advertisement_missing_address() {
+        let mut props = device_properties();
+        props.remove("Address");
+        assert!(matches!(
+            BleAdvertisement::try_from(&props),
+            Err(BluetoothError::Internal(_))
+        ));
+    }
+
+    #[test]
+    fn load_service_data_16bit_uuid() {
+        let props = device_properties();
+        let mut ad = BleAdvertisement::try_from(&props).unwrap();
+        ad.load_data(&props, &[BleDataTypeId::ServiceData16BitUuid])
+            .unwrap();
+
+        let service_data = ad.service_data_16bit_uuid().unwrap();
+        assert_eq!(
+            *service_data,
+            vec![ServiceData::new(BtUuid::FAST_PAIR, vec![0x01, 0x02, 0x03])]
+        );
+        assert!(ad.service_data_128bit_uuid().is_err());
+    }
+
+    #[test]
+    fn load_all_data_types() {
+        let mut manufacturer_data = HashMap::new();
+        manufacturer_data.insert(0x00e0u16, owned(vec![0x05u8, 0x06]));
+        let mut advertising_data = HashMap::new();
+        advertising_data.insert(0x1au8, owned(vec![0xa0u8, 0x00]));
+
+        let mut props = device_properties();
+        props.insert(String::from("AdvertisingFlags"), owned(vec![0x06u8]));
+        props.insert(String::from("Name"), owned("Buds"));
+        props.insert(
+            String::from("UUIDs"),
+            owned(vec![
+                "0000180f-0000-1000-8000-00805f9b34fb",
+                "01020304-0000-1000-8000-00805f9b34fb",
+                "df21fe2c-2515-4fdb-8886-f12c4d67927c",
+            ]),
+        );
+        props.insert(String::from("TxPower"), owned(-12i16));
+        props.insert(String::from("Appearance"), owned(0x0941u16));
+        props
+            .insert(String::from("ManufacturerData"), owned(manufacturer_data));
+        props.insert(String::from("AdvertisingData"), owned(advertising_data));
+
+        let mut ad = BleAdvertisement::try_from(&props).unwrap();
+        ad.load_data(&props, BleDataTypeId::ALL).unwrap();
+
+        assert_eq!(ad.flags().unwrap(), Some(0x06));
+        assert_eq!(ad.local_name().unwrap(), Some("Buds"));
+        assert_eq!(ad.shortened_local_name().unwrap(), None);
+        assert_eq!(
+            *ad.service_uuids_16bit().unwrap(),
+            vec![BtUuid::BATTERY_SERVICE]
+        );
+        assert_eq!(
+            *ad.service_uuids_32bit().unwrap(),
+            vec![BtUuid::from_u32(0x01020304)]
+        );
+        assert_eq!(
+            *ad.service_uuids_128bit().unwrap(),
+            vec![BtUuid::MESSAGE_STREAM]
+        );
+        assert_eq!(ad.tx_power(), Some(-12));
+        assert_eq!(
+            *ad.service_data_128bit_uuid().unwrap(),
+            vec![ServiceData::new(BtUuid::MESSAGE_STREAM, vec![0x04])]
+        );
+        assert_eq!(ad.appearance().unwrap(), Some(0x0941));
+        assert_eq!(
+            *ad.manufacturer_data().unwrap(),
+            vec![ManufacturerData::new(0x00e0, vec![0x05, 0x06])]
+        );
+        assert_eq!(
+            ad.advertising_interval().unwrap(),
+            Some(Duration::from_millis(100))
+        );
+    }
+}
@@ -0,0 +1,410 @@
+// Copyright 2023 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::{
+    collections::HashMap,
+    pin::Pin,
+    sync::atomic::{AtomicUsize, Ordering},
+    task::{Context, Poll},
+};
+
+use async_trait::async_trait;
+use futures::{
+    channel::mpsc::{self, UnboundedReceiver, UnboundedSender},
+    Stream, StreamExt,
+};
+use tracing::{info, warn};
+use zbus::{
+    blocking, fdo, interface,
+    zvariant::{OwnedObjectPath, OwnedValue, Value},
+    Connection,
+};
+
+use super::{
+    adapter::default_adapter_path,
+    bluez::{LEAdvertisingManager1Proxy, LEAdvertisingManager1ProxyBlocking},
+};
+use crate::{
+    api,
+    common::{
+        AdvertisingData, AdvertisingSettings, AdvertisingStatus,
+        AdvertisingStream, BluetoothError,
+    },
+};
+
+/// Prefix of the object paths advertisements are exported at. BlueZ tells
+/// advertisements of a client apart by path, so each gets a new number.
+const ADVERTISEMENT_PATH_PREFIX: &str = "/com/google/nearby/advertisement";
+
+/// Number of the next exported advertisement.
+static NEXT_ADVERTISEMENT: AtomicUsize = AtomicUsize::new(0);
+
+/// `org.bluez.LEAdvertisement1` object exported for BlueZ to publish.
+/// https://git.kernel.org/pub/scm/bluetooth/bluez.git/tree/doc/org.bluez.LEAdvertisement.rst
+struct LeAdvertisement {
+    data: AdvertisingData,
+    settings: AdvertisingSettings,
+    status: UnboundedSender<Result<AdvertisingStatus, BluetoothError>>,
+}
+
+/// Optional properties are left out of the object when unset, as BlueZ
+/// treats their presence as a request.
+#[interface(name = "org.bluez.LEAdvertisement1")]
+impl LeAdvertisement {
+    /// Called by BlueZ once it stops publishing the advertisement on its
+    /// own, e.g. because the adapter was powered off.
+    fn release(&self) {
+        let _ = self.status.unbounded_send(Ok(AdvertisingStatus::Stopped));
+        self.status.close_channel();
+    }
+
+    /// Advertisements are published as non-connectable, like on Windows.
+    #[zbus(property, name = "Type")]
+    fn kind(&self) -> String {
+        String::from("broadcast")
+    }
+
+    #[zbus(property)]
+    fn service_data(&self) -> HashMap<String, OwnedValue> {
+        self.data
+            .service_data()
+            .iter()
+            .map(|service_data| {
+                (
+                    service_data.uuid().to_string(),
+                    owned_bytes(service_data.data()),
+                )
+            })
+            .collect()
+    }
+
+    #[zbus(property)]
+    fn manufacturer_data(&self) -> HashMap<u16, OwnedValue> {
+        self.data
+            .manufacturer_data()
+            .iter()
+            .map(|data| (data.company_id(), owned_bytes(data.data())))
+            .collect()
+    }
+
+    #[zbus(property)]
+    fn includes(&self) -> Vec<String> {
+        match self.settings.tx_power() {
+            Some(_) => vec![String::from("tx-power")],
+            None => Vec::new(),
+        }
+    }
+
+    #[zbus(property)]
+    fn tx_power(&self) -> fdo::Result<i16> {
+        self.settings
+            .tx_power()
+            .map(i16::from)
+            .ok_or(unset_property("TxPower"))
+    }
+
+    #[zbus(property)]
+    fn min_interval(&self) -> fdo::Result<u32> {
+        self.interval_millis("MinInterval")
+    }
+
+    #[zbus(property)]
+    fn max_interval(&self) -> fdo::Result<u32> {
+        self.interval_millis("MaxInterval")
+    }
+
+    /// Setting a secondary channel makes BlueZ publish with extended PDUs.
+    #[zbus(property)]
+    fn secondary_channel(&self) -> fdo::Result<String> {
+        match self.settings.extended() {
+            true => Ok(String::from("1M")),
+            false => Err(unset_property("SecondaryChannel")),
+        }
+    }
+}
+
+impl LeAdvertisement {
+    fn interval_millis(&self, name: &str) -> fdo::Result<u32> {
+        self.settings
+            .interval()
+            .map(|interval| interval.as_millis().try_into().unwrap_or(u32::MAX))
+            .ok_or(unset_property(name))
+    }
+}
+
+fn owned_bytes(bytes: &[u8]) -> OwnedValue {
+    OwnedValue::try_from(Value::from(bytes.to_vec()))
+        .expect("Sanity check, byte arrays hold no file descriptors")
+}
+
+fn unset_property(name: &str) -> fdo::Error {
+    fdo::Error::UnknownProperty(format!("{} is unset", name))
+}
+
+/// Handle on a registered advertisement, unregistering it when dropped.
+struct Publication {
+    conn: blocking::Connection,
+    manager: LEAdvertisingManager1ProxyBlocking<'static>,
+    path: OwnedObjectPath,
+    /// Closed once BlueZ released the advertisement.
+    status: UnboundedSender<Result<AdvertisingStatus, BluetoothError>>,
+    receiver: UnboundedReceiver<Result<AdvertisingStatus, BluetoothError>>,
+}
+
+impl Stream for Publication {
+    type Item = Result<AdvertisingStatus, BluetoothError>;
+
+    fn poll_next(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Self::Item>> {
+        self.receiver.poll_next_unpin(cx)
+    }
+}
+
+impl Drop for Publication {
+    fn drop(&mut self) {
+        if !self.status.is_closed() {
+            match self.manager.unregister_advertisement(&self.path) {
+                Ok(()) => {
+                    info!("Unregistered BlueZ advertisement {}.", *self.path)
+                }
+                Err(err) => {
+                    warn!("Failed to unregister BlueZ advertisement: {}", err)
+                }
+            }
+        }
+
+        if let Err(err) = self
+            .conn
+            .object_server()
+            .remove::<LeAdvertisement, _>(&self.path)
+        {
+            warn!("Failed to remove advertisement object: {}", err);
+        }
+    }
+}
+
+/// Concrete type implementing `api::BleAdvertiser`, used for Linux BLE.
+pub struct BleAdvertiser {
+    conn: Connection,
+    manager: LEAdvertisingManager1ProxyBlocking<'static>,
+}
+
+impl BleAdvertiser {
+    /// Advertise on the first adapter exposed by the BlueZ daemon reachable
+    /// through `conn`.
+    pub(crate) async fn with_connection(
+        conn: Connection,
+    ) -> Result<Self, BluetoothError> {
+        let path = default_adapter_path(&conn).await?;
+        let manager = LEAdvertisingManager1Proxy::builder(&conn)
+            .path(path)?
+            .build()
+            .await?;
+
+        Ok(BleAdvertiser {
+            manager: LEAdvertisingManager1ProxyBlocking::from(
+                manager.into_inner(),
+            ),
+            conn,
+        })
+    }
+}
+
+#[async_trait]
+impl api::BleAdvertiser for BleAdvertiser {
+    async fn default() -> Result<Self, BluetoothError> {
+        let conn = Connection::system().await?;
+        Self::with_connection(conn).await
+    }
+
+    /// BlueZ decides on the interval and TX power within the requested
+    /// values, depending on what the controller supports.
+    fn start_advertising(
+        &self,
+        data: &AdvertisingData,
+        settings: &AdvertisingSettings,
+    ) -> Result<AdvertisingStream, BluetoothError> {
+        data.check_len(settings)?;
+
+        let conn = blocking::Connection::from(self.conn.clone());
+        let path = OwnedObjectPath::try_from(format!(
+            "{}{}",
+            ADVERTISEMENT_PATH_PREFIX,
+            NEXT_ADVERTISEMENT.fetch_add(1, Ordering::Relaxed)
+        ))?;
+        let (status, receiver) = mpsc::unbounded();
+        conn.object_server().at(
+            &path,
+            LeAdvertisement {
+                data: data.clone(),
+                settings: settings.clone(),
+                status: status.clone(),
+            },
+        )?;
+
+        // Unregisters and removes the object on failure.
+        let publication = Publication {
+            conn,
+            manager: self.manager.clone(),
+            path,
+            status,
+            receiver,
+        };
+        if let Err(err) = publication
+            .manager
+            .register_advertisement(&publication.path, HashMap::new())
+        {
+            publication.status.close_channel();
+            return Err(register_error(err));
+        }
+
+        info!("Registered BlueZ advertisement {}.", *publication.path);
+        let _ = publication
+            .status
+            .unbounded_send(Ok(AdvertisingStatus::Started));
+        Ok(AdvertisingStream::new(publication))
+    }
+}
+
+/// Convert the reply of `RegisterAdvertisement()` into a library error.
+fn register_error(err: zbus::Error) -> BluetoothError {
+    match &err {
+        zbus::Error::MethodError(name, msg, _)
+            if name.as_str() == "org.bluez.Error.InvalidLength" =>
+        {
+            BluetoothError::InvalidArgument(msg.clone().unwrap_or_default())
+        }
+        zbus::Error::MethodError(name, msg, _)
+            if name.as_str() == "org.bluez.Error.NotPermitted" =>
+        {
+            BluetoothError::FailedPrecondition(msg.clone().unwrap_or_default())
+        }
+        _ => BluetoothError::from(err),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use futures::executor::block_on;
+
+    use super::*;
+    use crate::{
+        api::BleAdvertiser as _,
+        common::BtUuid,
+        linux::mock::{MockBluez, MockBus},
+    };
+
+    fn fast_pair_data() -> AdvertisingData {
+        AdvertisingData::default()
+            .with_service_data(BtUuid::FAST_PAIR, vec![0x01, 0x02, 0x03])
+    }
+
+    #[test]
+    fn start_and_stop_advertising() {
+        let bus = MockBus::start();
+        block_on(async {
+            let bluez = MockBluez::start(&bus).await;
+            let advertiser =
+                BleAdvertiser::with_connection(bus.connect().await)
+                    .await
+                    .unwrap();
+            let settings = AdvertisingSettings::default()
+                .with_tx_power(-10)
+                .with_interval(Duration::from_millis(100));
+
+            let mut advertising = advertiser
+                .start_advertising(&fast_pair_data(), &settings)
+                .unwrap();
+            assert_eq!(
+                advertising.next().await,
+                Some(Ok(AdvertisingStatus::Started))
+            );
+
+            let advertisements = bluez.advertisements().await;
+            assert_eq!(advertisements.len(), 1);
+            let props = &advertisements[0];
+            assert_eq!(<&str>::try_from(&props["Type"]), Ok("broadcast"));
+            assert_eq!(i16::try_from(&props["TxPower"]), Ok(-10));
+            assert_eq!(u32::try_from(&props["MinInterval"]), Ok(100));
+            assert!(!props.contains_key("SecondaryChannel"));
+            let service_data = HashMap::<String, OwnedValue>::try_from(
+                props["ServiceData"].try_clone().unwrap(),
+            )
+            .unwrap();
+            assert_eq!(
+                <Vec<u8>>::try_from(
+                    service_data["0000fe2c-0000-1000-8000-00805f9b34fb"]
+                        .try_clone()
+                        .unwrap()
+                ),
+                Ok(vec![0x01, 0x02, 0x03])
+            );
+
+            drop(advertising);
+            assert!(bluez.advertisements().await.is_empty());
+        });
+    }
+
+    #[test]
+    fn release_stops_advertising() {
+        let bus = MockBus::start();
+        block_on(async {
+            let bluez = MockBluez::start(&bus).await;
+            let advertiser =
+                BleAdvertiser::with_connection(bus.connect().await)
+                    .await
+                    .unwrap();
+            let settings = AdvertisingSettings::default().with_extended(true);
+            let mut advertising = advertiser
+                .start_advertising(&fast_pair_data(), &settings)
+                .unwrap();
+            let props = &bluez.advertisements().await[0];
+            assert_eq!(<&str>::try_from(&props["SecondaryChannel"]), Ok("1M"));
+
+            bluez.release_advertisements().await;
+            assert_eq!(
+                advertising.by_ref().collect::<Vec<_>>().await,
+                vec![
+                    Ok(AdvertisingStatus::Started),
+                    Ok(AdvertisingStatus::Stopped)
+                ]
+            );
+        });
+    }
+
+    #[test]
+    fn start_advertising_too_large() {
+        let bus = MockBus::start();
+        block_on(async {
+            let bluez = MockBluez::start(&bus).await;
+            let advertiser =
+                BleAdvertiser::with_connection(bus.connect().await)
+                    .await
+                    .unwrap();
+            let data =
+                fast_pair_data().with_manufacturer_data(0x00e0, vec![0; 32]);
+
+            assert!(matches!(
+                advertiser
+                    .start_advertising(&data, &AdvertisingSettings::default()),
+                Err(BluetoothError::InvalidArgument(_))
+            ));
+            assert!(bluez.advertisements().await.is_empty());
+        });
+    }
+}
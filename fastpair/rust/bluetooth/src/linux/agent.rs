@@ -0,0 +1,253 @@
+// Copyright 2023 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::sync::{
+    atomic::{AtomicUsize, Ordering},
+    Arc,
+};
+
+use tracing::{info, warn};
+use zbus::{blocking, interface, zvariant::OwnedObjectPath, DBusError};
+
+use super::{
+    bluez::{AgentManager1Proxy, AgentManager1ProxyBlocking, Device1Proxy},
+    error::pairing_result,
+};
+use crate::common::{
+    BluetoothError, PairingHandler, PairingResult, ProtectionLevel,
+};
+
+/// Prefix of the object paths agents are exported at. Every pairing
+/// registers its own agent, so each gets a new number.
+const AGENT_PATH_PREFIX: &str = "/com/google/nearby/agent";
+
+/// IO capability registered for agents that take part in authenticated
+/// ceremonies. `PairingHandler` can both display and provide PINs, so BlueZ
+/// may pick any association model.
+const KEYBOARD_DISPLAY: &str = "KeyboardDisplay";
+
+/// IO capability registered for agents that only pair with Just Works.
+const NO_INPUT_NO_OUTPUT: &str = "NoInputNoOutput";
+
+/// Number of the next exported agent.
+static NEXT_AGENT: AtomicUsize = AtomicUsize::new(0);
+
+/// Errors replied to BlueZ by `PairingAgent`.
+#[derive(Debug, DBusError)]
+#[zbus(prefix = "org.bluez.Error")]
+enum AgentError {
+    Rejected(String),
+}
+
+/// `org.bluez.Agent1` object exported for BlueZ to ask the requests of the
+/// pairing ceremony with one device, answered by a `PairingHandler`.
+/// https://git.kernel.org/pub/scm/bluetooth/bluez.git/tree/doc/org.bluez.Agent.rst
+struct PairingAgent {
+    device: OwnedObjectPath,
+    handler: Arc<dyn PairingHandler>,
+}
+
+impl PairingAgent {
+    /// Reject requests about devices other than the one being paired, which
+    /// BlueZ sends to the default agent instead when there is one.
+    fn check_device(&self, device: &OwnedObjectPath) -> Result<(), AgentError> {
+        if *device != self.device {
+            return Err(AgentError::Rejected(format!(
+                "unexpected device {}",
+                device.as_str()
+            )));
+        }
+        Ok(())
+    }
+}
+
+/// Format a passkey as the PIN `PairingHandler` expects.
+fn format_passkey(passkey: u32) -> String {
+    format!("{:06}", passkey)
+}
+
+#[interface(name = "org.bluez.Agent1")]
+impl PairingAgent {
+    /// Called by BlueZ once it unregistered the agent on its own. The agent
+    /// is removed once pairing completes, so there is nothing to do here.
+    fn release(&self) {}
+
+    async fn request_pin_code(
+        &self,
+        device: OwnedObjectPath,
+    ) -> Result<String, AgentError> {
+        self.check_device(&device)?;
+        self.handler
+            .provide_pin()
+            .await
+            .ok_or(AgentError::Rejected(String::from("no PIN code")))
+    }
+
+    async fn display_pin_code(
+        &self,
+        device: OwnedObjectPath,
+        pincode: String,
+    ) -> Result<(), AgentError> {
+        self.check_device(&device)?;
+        self.handler.display_pin(&pincode).await;
+        Ok(())
+    }
+
+    async fn request_passkey(
+        &self,
+        device: OwnedObjectPath,
+    ) -> Result<u32, AgentError> {
+        self.check_device(&device)?;
+        let pin = self
+            .handler
+            .provide_pin()
+            .await
+            .ok_or(AgentError::Rejected(String::from("no passkey")))?;
+        match pin.parse::<u32>() {
+            Ok(passkey) if pin.len() <= 6 => Ok(passkey),
+            _ => Err(AgentError::Rejected(format!("invalid passkey {}", pin))),
+        }
+    }
+
+    /// Called again for every key the user enters on the device, with the
+    /// number of keys entered so far. Only the first call is passed on.
+    async fn display_passkey(
+        &self,
+        device: OwnedObjectPath,
+        passkey: u32,
+        entered: u16,
+    ) -> Result<(), AgentError> {
+        self.check_device(&device)?;
+        if entered == 0 {
+            self.handler.display_pin(&format_passkey(passkey)).await;
+        }
+        Ok(())
+    }
+
+    async fn request_confirmation(
+        &self,
+        device: OwnedObjectPath,
+        passkey: u32,
+    ) -> Result<(), AgentError> {
+        self.check_device(&device)?;
+        let pin = format_passkey(passkey);
+        if !self.handler.confirm_pin_match(&pin).await {
+            return Err(AgentError::Rejected(format!(
+                "passkey {} doesn't match",
+                pin
+            )));
+        }
+        Ok(())
+    }
+
+    async fn request_authorization(
+        &self,
+        device: OwnedObjectPath,
+    ) -> Result<(), AgentError> {
+        self.check_device(&device)?;
+        if !self.handler.confirm().await {
+            return Err(AgentError::Rejected(String::from("not confirmed")));
+        }
+        Ok(())
+    }
+
+    /// Services of the device being paired are authorized along with it.
+    fn authorize_service(
+        &self,
+        device: OwnedObjectPath,
+        _uuid: String,
+    ) -> Result<(), AgentError> {
+        self.check_device(&device)
+    }
+
+    fn cancel(&self) {
+        info!("BlueZ canceled the pairing request.");
+    }
+}
+
+/// Handle on a registered agent, unregistering it when dropped.
+struct Registration {
+    conn: blocking::Connection,
+    manager: AgentManager1ProxyBlocking<'static>,
+    path: OwnedObjectPath,
+    registered: bool,
+}
+
+impl Drop for Registration {
+    fn drop(&mut self) {
+        if self.registered {
+            match self.manager.unregister_agent(&self.path) {
+                Ok(()) => info!("Unregistered BlueZ agent {}.", *self.path),
+                Err(err) => warn!("Failed to unregister BlueZ agent: {}", err),
+            }
+        }
+
+        if let Err(err) = self
+            .conn
+            .object_server()
+            .remove::<PairingAgent, _>(&self.path)
+        {
+            warn!("Failed to remove agent object: {}", err);
+        }
+    }
+}
+
+/// Pair with `device`, answering the requests of the pairing ceremony with
+/// `handler`. BlueZ asks the agent registered by the client calling `Pair()`,
+/// so this registers one for the duration of the call.
+///
+/// BlueZ doesn't let clients require a protection level, but picks the
+/// association model from the IO capability of the agent. Agents pairing for
+/// `Encryption` only claim none, so the device is paired with Just Works.
+pub(crate) async fn pair(
+    device: &Device1Proxy<'static>,
+    protection_level: ProtectionLevel,
+    handler: Arc<dyn PairingHandler>,
+) -> Result<PairingResult, BluetoothError> {
+    let conn = device.inner().connection();
+    let path = OwnedObjectPath::try_from(format!(
+        "{}{}",
+        AGENT_PATH_PREFIX,
+        NEXT_AGENT.fetch_add(1, Ordering::Relaxed)
+    ))?;
+    conn.object_server()
+        .at(
+            &path,
+            PairingAgent {
+                device: OwnedObjectPath::from(device.inner().path().to_owned()),
+                handler,
+            },
+        )
+        .await?;
+
+    let capability = match protection_level {
+        ProtectionLevel::Encryption => NO_INPUT_NO_OUTPUT,
+        ProtectionLevel::EncryptionAndAuthentication => KEYBOARD_DISPLAY,
+    };
+
+    // Unregisters the agent and removes the object once paired or on failure.
+    let manager = AgentManager1Proxy::new(conn).await?;
+    let mut registration = Registration {
+        conn: blocking::Connection::from(conn.clone()),
+        manager: AgentManager1ProxyBlocking::from(manager.clone().into_inner()),
+        path,
+        registered: false,
+    };
+    manager
+        .register_agent(&registration.path, capability)
+        .await?;
+    registration.registered = true;
+
+    pairing_result(device.pair().await)
+}
@@ -0,0 +1,218 @@
+// Copyright 2023 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+// D-Bus proxies for the subset of the BlueZ API used by this crate.
+// https://git.kernel.org/pub/scm/bluetooth/bluez.git/tree/doc
+
+use std::collections::HashMap;
+
+use zbus::{
+    proxy,
+    zvariant::{ObjectPath, OwnedObjectPath, Value},
+};
+
+/// Well-known bus name of the BlueZ daemon.
+pub(crate) const BLUEZ_SERVICE: &str = "org.bluez";
+
+/// Interface name of BlueZ adapter objects.
+pub(crate) const ADAPTER_INTERFACE: &str = "org.bluez.Adapter1";
+
+/// Interface name of the LE advertising manager served next to each adapter.
+pub(crate) const ADVERTISING_MANAGER_INTERFACE: &str =
+    "org.bluez.LEAdvertisingManager1";
+
+/// Interface name of BlueZ device objects.
+pub(crate) const DEVICE_INTERFACE: &str = "org.bluez.Device1";
+
+/// Interface name of the GATT service objects of remote devices.
+pub(crate) const GATT_SERVICE_INTERFACE: &str = "org.bluez.GattService1";
+
+/// Interface name of the GATT characteristic objects of remote devices.
+pub(crate) const GATT_CHARACTERISTIC_INTERFACE: &str =
+    "org.bluez.GattCharacteristic1";
+
+// Represents a local Bluetooth controller, e.g. `/org/bluez/hci0`.
+// https://git.kernel.org/pub/scm/bluetooth/bluez.git/tree/doc/org.bluez.Adapter.rst
+#[proxy(interface = "org.bluez.Adapter1", default_service = "org.bluez")]
+pub(crate) trait Adapter1 {
+    /// Start device discovery for this adapter.
+    fn start_discovery(&self) -> zbus::Result<()>;
+
+    /// Stop device discovery started by this client.
+    fn stop_discovery(&self) -> zbus::Result<()>;
+
+    /// Set the filter used by discovery sessions started by this client.
+    fn set_discovery_filter(
+        &self,
+        filter: HashMap<&str, Value<'_>>,
+    ) -> zbus::Result<()>;
+
+    /// Remove the device object at `device`, along with its pairing
+    /// information.
+    fn remove_device(&self, device: &ObjectPath<'_>) -> zbus::Result<()>;
+
+    /// The Bluetooth address of this adapter.
+    #[zbus(property)]
+    fn address(&self) -> zbus::Result<String>;
+
+    /// Whether the adapter radio is switched on.
+    #[zbus(property)]
+    fn powered(&self) -> zbus::Result<bool>;
+}
+
+// Represents a remote device known to an adapter, e.g.
+// `/org/bluez/hci0/dev_AA_BB_CC_DD_EE_FF`.
+// https://git.kernel.org/pub/scm/bluetooth/bluez.git/tree/doc/org.bluez.Device.rst
+#[proxy(interface = "org.bluez.Device1", default_service = "org.bluez")]
+pub(crate) trait Device1 {
+    /// Initiate pairing (and bonding) with the remote device.
+    fn pair(&self) -> zbus::Result<()>;
+
+    /// Connect the profiles the remote device supports, or only GATT for LE
+    /// devices.
+    fn connect(&self) -> zbus::Result<()>;
+
+    /// Disconnect every connected profile, then the remote device.
+    fn disconnect(&self) -> zbus::Result<()>;
+
+    /// Connect the profile identified by `uuid`, which for external profiles
+    /// hands the connection to their `NewConnection()` method.
+    fn connect_profile(&self, uuid: &str) -> zbus::Result<()>;
+
+    /// The Bluetooth address of the remote device.
+    #[zbus(property)]
+    fn address(&self) -> zbus::Result<String>;
+
+    /// Either "public" or "random".
+    #[zbus(property)]
+    fn address_type(&self) -> zbus::Result<String>;
+
+    /// The remote name, as advertised or retrieved during discovery.
+    #[zbus(property)]
+    fn name(&self) -> zbus::Result<String>;
+
+    /// The remote name, or a name generated from the address if unknown.
+    #[zbus(property)]
+    fn alias(&self) -> zbus::Result<String>;
+
+    /// Whether the remote device is paired.
+    #[zbus(property)]
+    fn paired(&self) -> zbus::Result<bool>;
+
+    /// Object path of the adapter the remote device is known to.
+    #[zbus(property)]
+    fn adapter(&self) -> zbus::Result<OwnedObjectPath>;
+
+    /// Whether the remote device is connected.
+    #[zbus(property)]
+    fn connected(&self) -> zbus::Result<bool>;
+
+    /// Whether the GATT services of the remote device have been resolved.
+    #[zbus(property)]
+    fn services_resolved(&self) -> zbus::Result<bool>;
+}
+
+// Represents a characteristic of a remote device, e.g.
+// `/org/bluez/hci0/dev_AA_BB_CC_DD_EE_FF/service000a/char000b`.
+// https://git.kernel.org/pub/scm/bluetooth/bluez.git/tree/doc/org.bluez.GattCharacteristic.rst
+#[proxy(
+    interface = "org.bluez.GattCharacteristic1",
+    default_service = "org.bluez"
+)]
+pub(crate) trait GattCharacteristic1 {
+    /// Read the value of the characteristic from the remote device.
+    fn read_value(
+        &self,
+        options: HashMap<&str, Value<'_>>,
+    ) -> zbus::Result<Vec<u8>>;
+
+    /// Write a value to the characteristic. The "type" option picks between
+    /// a Write Request ("request") and a Write Command ("command").
+    fn write_value(
+        &self,
+        value: &[u8],
+        options: HashMap<&str, Value<'_>>,
+    ) -> zbus::Result<()>;
+
+    /// Subscribe to notifications or indications, which BlueZ reports as
+    /// changes of the `Value` property.
+    fn start_notify(&self) -> zbus::Result<()>;
+
+    /// Cancel the subscription of this client.
+    fn stop_notify(&self) -> zbus::Result<()>;
+}
+
+// Publishes advertisements exported by clients on an adapter, e.g.
+// `/org/bluez/hci0`.
+// https://git.kernel.org/pub/scm/bluetooth/bluez.git/tree/doc/org.bluez.LEAdvertisingManager.rst
+#[proxy(
+    interface = "org.bluez.LEAdvertisingManager1",
+    default_service = "org.bluez"
+)]
+pub(crate) trait LEAdvertisingManager1 {
+    /// Publish the `org.bluez.LEAdvertisement1` object at `advertisement`,
+    /// after reading its properties.
+    fn register_advertisement(
+        &self,
+        advertisement: &ObjectPath<'_>,
+        options: HashMap<&str, Value<'_>>,
+    ) -> zbus::Result<()>;
+
+    /// Stop publishing an advertisement registered by this client.
+    fn unregister_advertisement(
+        &self,
+        advertisement: &ObjectPath<'_>,
+    ) -> zbus::Result<()>;
+}
+
+// Registers external profiles, which BlueZ hands connections to.
+// https://git.kernel.org/pub/scm/bluetooth/bluez.git/tree/doc/org.bluez.ProfileManager.rst
+#[proxy(
+    interface = "org.bluez.ProfileManager1",
+    default_service = "org.bluez",
+    default_path = "/org/bluez"
+)]
+pub(crate) trait ProfileManager1 {
+    /// Register the `org.bluez.Profile1` object at `profile` for `uuid`.
+    fn register_profile(
+        &self,
+        profile: &ObjectPath<'_>,
+        uuid: &str,
+        options: HashMap<&str, Value<'_>>,
+    ) -> zbus::Result<()>;
+
+    /// Unregister a profile registered with `register_profile()`.
+    fn unregister_profile(&self, profile: &ObjectPath<'_>) -> zbus::Result<()>;
+}
+
+// Registers agents, which BlueZ asks to answer the pairing requests of their
+// client.
+// https://git.kernel.org/pub/scm/bluetooth/bluez.git/tree/doc/org.bluez.AgentManager.rst
+#[proxy(
+    interface = "org.bluez.AgentManager1",
+    default_service = "org.bluez",
+    default_path = "/org/bluez"
+)]
+pub(crate) trait AgentManager1 {
+    /// Register the `org.bluez.Agent1` object at `agent`, which supports the
+    /// requests of its IO `capability`.
+    fn register_agent(
+        &self,
+        agent: &ObjectPath<'_>,
+        capability: &str,
+    ) -> zbus::Result<()>;
+
+    /// Unregister an agent registered with `register_agent()`.
+    fn unregister_agent(&self, agent: &ObjectPath<'_>) -> zbus::Result<()>;
+}
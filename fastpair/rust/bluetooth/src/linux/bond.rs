@@ -0,0 +1,250 @@
+// Copyright 2023 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+// Bonds of an adapter, which BlueZ tracks as the `Paired` property of the
+// device objects under it.
+
+use std::{
+    collections::HashMap,
+    pin::Pin,
+    task::{Context, Poll},
+};
+
+use futures::{ready, stream::Select, Stream, StreamExt};
+use zbus::{
+    fdo::ObjectManagerProxy, message::Type as MessageType,
+    zvariant::OwnedObjectPath, Connection, MatchRule, Message, MessageStream,
+};
+
+use super::{
+    address::{parse_address, parse_address_type},
+    advertisement::{property, DeviceProperties},
+    bluez::{BLUEZ_SERVICE, DEVICE_INTERFACE},
+};
+use crate::common::{
+    BleAddress, BleAddressKind, BluetoothError, BondEvent, BondedDevice,
+    ClassicAddress,
+};
+
+/// Retrieve the properties of every device known to the adapter at
+/// `adapter_path`, keyed by object path.
+pub(crate) async fn adapter_devices(
+    conn: &Connection,
+    adapter_path: &str,
+) -> Result<HashMap<OwnedObjectPath, DeviceProperties>, BluetoothError> {
+    let device_prefix = format!("{}/", adapter_path);
+    let objects = ObjectManagerProxy::builder(conn)
+        .destination(BLUEZ_SERVICE)?
+        .path("/")?
+        .build()
+        .await?
+        .get_managed_objects()
+        .await?;
+
+    Ok(objects
+        .into_iter()
+        .filter(|(path, _)| path.as_str().starts_with(&device_prefix))
+        .filter_map(|(path, mut interfaces)| {
+            let props = interfaces.remove(DEVICE_INTERFACE)?;
+            Some((path, props))
+        })
+        .collect())
+}
+
+/// Whether a device is paired, according to its properties.
+pub(crate) fn is_paired(
+    props: &DeviceProperties,
+) -> Result<bool, BluetoothError> {
+    Ok(property::<bool>(props, "Paired")?.unwrap_or(false))
+}
+
+/// Identify the device with properties `props` by the transport it bonded
+/// over.
+///
+/// BlueZ merges the LE and BR/EDR identities of a device into one object
+/// without saying which transport a bond was made over, so this guesses:
+/// BR/EDR addresses are always public, and BlueZ only exposes `Class` for
+/// devices found by BR/EDR inquiry. Dual-mode devices are reported as
+/// Classic devices.
+pub(crate) fn bonded_device(
+    props: &DeviceProperties,
+) -> Result<BondedDevice, BluetoothError> {
+    let addr = property::<String>(props, "Address")?.ok_or(
+        BluetoothError::Internal(String::from(
+            "BlueZ device is missing the `Address` property.",
+        )),
+    )?;
+    let kind = property::<String>(props, "AddressType")?.ok_or(
+        BluetoothError::Internal(String::from(
+            "BlueZ device is missing the `AddressType` property.",
+        )),
+    )?;
+
+    let addr = parse_address(&addr)?;
+    let kind = parse_address_type(&kind)?;
+    let has_class = property::<u32>(props, "Class")?.is_some_and(|c| c != 0);
+
+    Ok(match kind {
+        BleAddressKind::Public if has_class => {
+            BondedDevice::Classic(ClassicAddress::from(addr))
+        }
+        _ => BondedDevice::Ble(BleAddress::new(addr, kind)),
+    })
+}
+
+/// Stream of the bonds an adapter gains and loses, following the
+/// `InterfacesAdded`, `InterfacesRemoved` and `PropertiesChanged` signals
+/// emitted by BlueZ for the adapter's devices.
+pub(crate) struct BondListener {
+    /// Object path prefix of devices belonging to the watched adapter.
+    device_prefix: String,
+    /// Can be polled to consume the signals emitted by BlueZ.
+    stream: Select<MessageStream, MessageStream>,
+    /// Latest known properties of every device, since `PropertiesChanged`
+    /// signals only carry the properties that changed and `InterfacesRemoved`
+    /// none at all.
+    devices: HashMap<OwnedObjectPath, DeviceProperties>,
+}
+
+impl BondListener {
+    /// Start watching the bonds of the adapter at `adapter_path`.
+    pub(crate) async fn new(
+        conn: &Connection,
+        adapter_path: &str,
+    ) -> Result<Self, BluetoothError> {
+        // Subscribe before seeding the cache so no change is missed.
+        let object_rule = MatchRule::builder()
+            .msg_type(MessageType::Signal)
+            .interface("org.freedesktop.DBus.ObjectManager")?
+            .build();
+        let changed_rule = MatchRule::builder()
+            .msg_type(MessageType::Signal)
+            .interface("org.freedesktop.DBus.Properties")?
+            .member("PropertiesChanged")?
+            .path_namespace(adapter_path)?
+            .arg(0, DEVICE_INTERFACE)?
+            .build();
+        let object_stream =
+            MessageStream::for_match_rule(object_rule, conn, None).await?;
+        let changed_stream =
+            MessageStream::for_match_rule(changed_rule, conn, None).await?;
+
+        Ok(BondListener {
+            device_prefix: format!("{}/", adapter_path),
+            stream: futures::stream::select(object_stream, changed_stream),
+            devices: adapter_devices(conn, adapter_path).await?,
+        })
+    }
+
+    /// Update the device property cache from a BlueZ signal, returning the
+    /// bond it reports gained or lost, if any.
+    fn handle_signal(
+        &mut self,
+        msg: &Message,
+    ) -> Result<Option<BondEvent>, BluetoothError> {
+        let header = msg.header();
+        match header.member().map(|member| member.as_str()) {
+            Some("InterfacesAdded") => {
+                let (path, mut interfaces): (
+                    OwnedObjectPath,
+                    HashMap<String, DeviceProperties>,
+                ) = msg.body().deserialize()?;
+                let props = match interfaces.remove(DEVICE_INTERFACE) {
+                    Some(props) if self.owns(&path) => props,
+                    _ => return Ok(None),
+                };
+
+                let event = if is_paired(&props)? {
+                    Some(BondEvent::Added(bonded_device(&props)?))
+                } else {
+                    None
+                };
+                self.devices.insert(path, props);
+                Ok(event)
+            }
+            Some("InterfacesRemoved") => {
+                let (path, interfaces): (OwnedObjectPath, Vec<String>) =
+                    msg.body().deserialize()?;
+                if !interfaces.iter().any(|name| name == DEVICE_INTERFACE) {
+                    return Ok(None);
+                }
+
+                // Removing the device object removes its bond too.
+                match self.devices.remove(&path) {
+                    Some(props) if is_paired(&props)? => {
+                        Ok(Some(BondEvent::Removed(bonded_device(&props)?)))
+                    }
+                    _ => Ok(None),
+                }
+            }
+            Some("PropertiesChanged") => {
+                let path = match header.path() {
+                    Some(path) => OwnedObjectPath::from(path.to_owned()),
+                    None => return Ok(None),
+                };
+                let (_, changed, invalidated): (
+                    String,
+                    DeviceProperties,
+                    Vec<String>,
+                ) = msg.body().deserialize()?;
+
+                let props = self.devices.entry(path).or_default();
+                let was_paired = is_paired(props)?;
+                props.extend(changed);
+                for name in invalidated {
+                    props.remove(&name);
+                }
+
+                match (was_paired, is_paired(props)?) {
+                    (false, true) => {
+                        Ok(Some(BondEvent::Added(bonded_device(props)?)))
+                    }
+                    (true, false) => {
+                        Ok(Some(BondEvent::Removed(bonded_device(props)?)))
+                    }
+                    _ => Ok(None),
+                }
+            }
+            _ => Ok(None),
+        }
+    }
+
+    /// Whether the object at `path` is a device of the watched adapter.
+    fn owns(&self, path: &OwnedObjectPath) -> bool {
+        path.as_str().starts_with(&self.device_prefix)
+    }
+}
+
+impl Stream for BondListener {
+    type Item = Result<BondEvent, BluetoothError>;
+
+    fn poll_next(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Self::Item>> {
+        // Most signals don't change a bond, so this is a loop to skip them.
+        loop {
+            let msg = match ready!(self.stream.poll_next_unpin(cx)) {
+                Some(Ok(msg)) => msg,
+                Some(Err(err)) => return Poll::Ready(Some(Err(err.into()))),
+                None => return Poll::Ready(None),
+            };
+
+            match self.handle_signal(&msg).transpose() {
+                Some(event) => return Poll::Ready(Some(event)),
+                None => continue,
+            }
+        }
+    }
+}
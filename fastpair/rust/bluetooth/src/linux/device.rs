@@ -0,0 +1,1288 @@
+// Copyright 2023 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+};
+
+use async_trait::async_trait;
+use futures::{future, stream, Stream, StreamExt};
+use tracing::info;
+use zbus::{
+    fdo::{self, ObjectManagerProxy, PropertiesProxy},
+    message::Type as MessageType,
+    names::InterfaceName,
+    proxy::CacheProperties,
+    zvariant::{OwnedObjectPath, OwnedValue, Value},
+    Connection, MatchRule, Message, MessageStream,
+};
+
+use super::{
+    address::{format_address, format_address_type},
+    advertisement::{property, DeviceProperties},
+    agent,
+    bluez::{
+        Adapter1Proxy, Device1Proxy, GattCharacteristic1Proxy,
+        GattCharacteristic1ProxyBlocking, BLUEZ_SERVICE, DEVICE_INTERFACE,
+        GATT_CHARACTERISTIC_INTERFACE, GATT_SERVICE_INTERFACE,
+    },
+    gatt::{characteristic_properties, gatt_error, Notifications},
+    rfcomm::connect_rfcomm,
+};
+use crate::{
+    api,
+    common::{
+        BleAddress, BleAddressKind, BluetoothError, BondState, BondStateStream,
+        BtUuid, ClassicAddress, ConnectionState, ConnectionStateStream,
+        GattCharacteristic, GattCharacteristicProperties, GattService,
+        NotificationStream, PairingHandler, PairingResult, ProtectionLevel,
+        RfcommSocket, WriteType,
+    },
+};
+
+/// Concrete type implementing `api::BleDevice`, used for Linux BLE.
+pub struct BleDevice {
+    inner: Device1Proxy<'static>,
+    addr: BleAddress,
+    /// Object paths of the services and characteristics discovered so far,
+    /// keyed by their handle.
+    attributes: Mutex<HashMap<u16, OwnedObjectPath>>,
+}
+
+/// A GATT object BlueZ exposes under a device, with the properties of its
+/// GATT interface.
+struct GattObject {
+    path: OwnedObjectPath,
+    props: DeviceProperties,
+}
+
+impl GattObject {
+    fn uuid(&self) -> Result<BtUuid, BluetoothError> {
+        property::<String>(&self.props, "UUID")?
+            .and_then(|uuid| uuid.parse().ok())
+            .ok_or(BluetoothError::Internal(format!(
+                "BlueZ GATT object {} has no valid `UUID` property.",
+                self.path.as_str()
+            )))
+    }
+
+    fn handle(&self) -> Result<u16, BluetoothError> {
+        property::<u16>(&self.props, "Handle")?.ok_or(BluetoothError::Internal(
+            format!(
+                "BlueZ GATT object {} is missing the `Handle` property.",
+                self.path.as_str()
+            ),
+        ))
+    }
+}
+
+/// Concrete type implementing `api::ClassicDevice`, used for Linux Bluetooth
+/// Classic.
+pub struct ClassicDevice {
+    inner: Device1Proxy<'static>,
+    addr: ClassicAddress,
+}
+
+/// Find the object path of a device BlueZ knows about, under the adapter at
+/// `adapter_path` if set. Unlike Windows, BlueZ can only create device objects
+/// for devices it has discovered, so this fails for devices that haven't been
+/// seen by a scan (or previously paired).
+async fn find_device(
+    conn: &Connection,
+    adapter_path: Option<&str>,
+    addr: u64,
+    kind: Option<BleAddressKind>,
+) -> Result<OwnedObjectPath, BluetoothError> {
+    let addr = format_address(addr);
+    let kind = kind.map(format_address_type);
+    let device_prefix = adapter_path.map(|path| format!("{}/", path));
+
+    let objects = ObjectManagerProxy::builder(conn)
+        .destination(BLUEZ_SERVICE)?
+        .path("/")?
+        .build()
+        .await?
+        .get_managed_objects()
+        .await?;
+
+    objects
+        .into_iter()
+        .filter(|(path, _)| {
+            device_prefix
+                .as_ref()
+                .is_none_or(|prefix| path.as_str().starts_with(prefix))
+        })
+        .filter(|(_, interfaces)| {
+            let props = interfaces
+                .iter()
+                .find(|(name, _)| name.as_str() == DEVICE_INTERFACE)
+                .map(|(_, props)| props);
+            let property_is = |name: &str, expected: &str| {
+                props
+                    .and_then(|props| props.get(name))
+                    .and_then(|value| value.downcast_ref::<&str>().ok())
+                    .is_some_and(|value| value == expected)
+            };
+
+            property_is("Address", &addr)
+                && kind.is_none_or(|kind| property_is("AddressType", kind))
+        })
+        .map(|(path, _)| path)
+        .min_by(|a, b| a.as_str().cmp(b.as_str()))
+        .ok_or(BluetoothError::FailedPrecondition(format!(
+            "device {} hasn't been discovered, please call `start_scan()`",
+            addr
+        )))
+}
+
+/// Create a proxy for the device object at `path`, with its properties cached
+/// so they can be read synchronously.
+async fn device_proxy(
+    conn: &Connection,
+    path: OwnedObjectPath,
+) -> Result<Device1Proxy<'static>, BluetoothError> {
+    Ok(Device1Proxy::builder(conn)
+        .path(path)?
+        .cache_properties(CacheProperties::Yes)
+        .build()
+        .await?)
+}
+
+/// Retrieve the device name, falling back to the alias BlueZ generates for
+/// unnamed devices.
+fn device_name(
+    inner: &Device1Proxy<'static>,
+) -> Result<String, BluetoothError> {
+    match inner.cached_name()? {
+        Some(name) => Ok(name),
+        None => {
+            inner
+                .cached_alias()?
+                .ok_or(BluetoothError::Internal(String::from(
+                    "BlueZ device is missing the `Alias` property.",
+                )))
+        }
+    }
+}
+
+/// Read a boolean property of the device behind `inner`. This asks BlueZ
+/// instead of reading the cache, which isn't updated once `unpair()` removed
+/// the device. Removed devices read as false.
+async fn device_flag(
+    inner: &Device1Proxy<'static>,
+    name: &str,
+) -> Result<bool, BluetoothError> {
+    let props = PropertiesProxy::builder(inner.inner().connection())
+        .destination(BLUEZ_SERVICE)?
+        .path(inner.inner().path().to_owned())?
+        .cache_properties(CacheProperties::No)
+        .build()
+        .await?;
+    let interface = InterfaceName::from_static_str_unchecked(DEVICE_INTERFACE);
+    match props.get(interface, name).await {
+        Ok(value) => Ok(bool::try_from(value)?),
+        Err(fdo::Error::UnknownObject(_)) => Ok(false),
+        Err(err) => Err(BluetoothError::from(err)),
+    }
+}
+
+/// Whether the device behind `inner` is paired.
+async fn device_paired(
+    inner: &Device1Proxy<'static>,
+) -> Result<bool, BluetoothError> {
+    device_flag(inner, "Paired").await
+}
+
+/// Pair with the device behind `inner` unless it is already paired.
+async fn pair_device(
+    inner: &Device1Proxy<'static>,
+    protection_level: ProtectionLevel,
+    handler: Arc<dyn PairingHandler>,
+) -> Result<PairingResult, BluetoothError> {
+    if device_paired(inner).await? {
+        info!("Device already paired");
+        return Ok(PairingResult::AlreadyPaired);
+    }
+
+    let status = agent::pair(inner, protection_level, handler).await?;
+
+    match status {
+        PairingResult::Failure(msg) => Err(BluetoothError::PairingFailed(msg)),
+        _ => Ok(status),
+    }
+}
+
+/// Watch a boolean property of the device behind `inner`, yielding its
+/// current value first, then every change. The property reads as false once
+/// the device is removed.
+async fn watch_device_flag(
+    inner: &Device1Proxy<'static>,
+    name: &'static str,
+) -> Result<impl Stream<Item = Result<bool, BluetoothError>>, BluetoothError> {
+    let conn = inner.inner().connection();
+    let path = inner.inner().path().to_owned();
+
+    // Subscribe before reading the current value so no change is missed.
+    let changed_rule = MatchRule::builder()
+        .msg_type(MessageType::Signal)
+        .interface("org.freedesktop.DBus.Properties")?
+        .member("PropertiesChanged")?
+        .path(path.clone())?
+        .arg(0, DEVICE_INTERFACE)?
+        .build();
+    let removed_rule = MatchRule::builder()
+        .msg_type(MessageType::Signal)
+        .interface("org.freedesktop.DBus.ObjectManager")?
+        .member("InterfacesRemoved")?
+        .arg_path(0, path)?
+        .build();
+    let changed_stream =
+        MessageStream::for_match_rule(changed_rule, conn, None).await?;
+    let removed_stream =
+        MessageStream::for_match_rule(removed_rule, conn, None).await?;
+    let current = device_flag(inner, name).await?;
+
+    let changes =
+        stream::select(changed_stream, removed_stream).filter_map(move |msg| {
+            future::ready(
+                msg.map_err(BluetoothError::from)
+                    .and_then(|msg| device_flag_change(&msg, name))
+                    .transpose(),
+            )
+        });
+    Ok(stream::once(future::ready(Ok(current))).chain(changes))
+}
+
+/// Extract the value of the property `name` a signal matched by
+/// `watch_device_flag()` reports, if any.
+fn device_flag_change(
+    msg: &Message,
+    name: &str,
+) -> Result<Option<bool>, BluetoothError> {
+    let header = msg.header();
+    match header.member().map(|member| member.as_str()) {
+        Some("PropertiesChanged") => {
+            let (_, changed, _): (
+                String,
+                HashMap<String, OwnedValue>,
+                Vec<String>,
+            ) = msg.body().deserialize()?;
+            match changed.get(name) {
+                Some(value) => Ok(Some(bool::try_from(value)?)),
+                None => Ok(None),
+            }
+        }
+        Some("InterfacesRemoved") => {
+            let (_, interfaces): (OwnedObjectPath, Vec<String>) =
+                msg.body().deserialize()?;
+            if interfaces.iter().any(|name| name == DEVICE_INTERFACE) {
+                Ok(Some(false))
+            } else {
+                Ok(None)
+            }
+        }
+        _ => Ok(None),
+    }
+}
+
+fn bond_state(paired: bool) -> BondState {
+    if paired {
+        BondState::Bonded
+    } else {
+        BondState::NotBonded
+    }
+}
+
+fn connection_state(connected: bool) -> ConnectionState {
+    if connected {
+        ConnectionState::Connected
+    } else {
+        ConnectionState::Disconnected
+    }
+}
+
+/// Watch the connection state of the device behind `inner`. BlueZ keeps one
+/// object for both transports of a device, so this reports whether either is
+/// connected.
+async fn connection_events(
+    inner: &Device1Proxy<'static>,
+) -> Result<ConnectionStateStream, BluetoothError> {
+    let states = watch_device_flag(inner, "Connected").await?;
+    Ok(ConnectionStateStream::new(
+        states.map(|connected| connected.map(connection_state)),
+    ))
+}
+
+impl BleDevice {
+    /// Look up `addr` on the BlueZ daemon reachable through `conn`, among the
+    /// devices of every adapter.
+    pub(crate) async fn with_connection(
+        conn: &Connection,
+        addr: BleAddress,
+    ) -> Result<Self, BluetoothError> {
+        Self::find(conn, None, addr).await
+    }
+
+    /// Look up `addr` among the devices of the adapter at `adapter_path`.
+    pub(crate) async fn with_adapter(
+        conn: &Connection,
+        adapter_path: &str,
+        addr: BleAddress,
+    ) -> Result<Self, BluetoothError> {
+        Self::find(conn, Some(adapter_path), addr).await
+    }
+
+    async fn find(
+        conn: &Connection,
+        adapter_path: Option<&str>,
+        addr: BleAddress,
+    ) -> Result<Self, BluetoothError> {
+        let kind = Some(addr.get_kind());
+        let path =
+            find_device(conn, adapter_path, u64::from(addr), kind).await?;
+        let inner = device_proxy(conn, path).await?;
+
+        Ok(BleDevice {
+            inner,
+            addr,
+            attributes: Mutex::new(HashMap::new()),
+        })
+    }
+
+    /// Fail unless the device is connected and its services resolved, since
+    /// BlueZ only exposes GATT objects once they are.
+    async fn check_resolved(&self) -> Result<(), BluetoothError> {
+        if self.inner.services_resolved().await? {
+            Ok(())
+        } else {
+            Err(BluetoothError::FailedPrecondition(String::from(
+                "device isn't connected, please call `connect()`",
+            )))
+        }
+    }
+
+    /// List the GATT objects implementing `interface` under the device,
+    /// sorted by path, which matches handle order.
+    async fn gatt_objects(
+        &self,
+        interface: &str,
+    ) -> Result<Vec<GattObject>, BluetoothError> {
+        let prefix = format!("{}/", self.inner.inner().path().as_str());
+        let mut objects: Vec<_> =
+            ObjectManagerProxy::builder(self.inner.inner().connection())
+                .destination(BLUEZ_SERVICE)?
+                .path("/")?
+                .build()
+                .await?
+                .get_managed_objects()
+                .await?
+                .into_iter()
+                .filter(|(path, _)| path.as_str().starts_with(&prefix))
+                .filter_map(|(path, mut interfaces)| {
+                    let props = interfaces.remove(interface)?;
+                    Some(GattObject { path, props })
+                })
+                .collect();
+        objects.sort_by(|a, b| a.path.as_str().cmp(b.path.as_str()));
+
+        Ok(objects)
+    }
+
+    /// Look up the object path of an attribute found by discovery.
+    fn attribute_path(
+        &self,
+        handle: u16,
+    ) -> Result<OwnedObjectPath, BluetoothError> {
+        self.attributes.lock().unwrap().get(&handle).cloned().ok_or(
+            BluetoothError::FailedPrecondition(format!(
+                "attribute {:#06x} hasn't been discovered",
+                handle
+            )),
+        )
+    }
+
+    /// Create a proxy for a characteristic found by discovery.
+    async fn characteristic(
+        &self,
+        characteristic: &GattCharacteristic,
+    ) -> Result<GattCharacteristic1Proxy<'static>, BluetoothError> {
+        let path = self.attribute_path(characteristic.handle())?;
+        Ok(
+            GattCharacteristic1Proxy::builder(self.inner.inner().connection())
+                .path(path)?
+                .cache_properties(CacheProperties::No)
+                .build()
+                .await?,
+        )
+    }
+}
+
+impl ClassicDevice {
+    /// Look up `addr` on the BlueZ daemon reachable through `conn`, among the
+    /// devices of every adapter.
+    pub(crate) async fn with_connection(
+        conn: &Connection,
+        addr: ClassicAddress,
+    ) -> Result<Self, BluetoothError> {
+        Self::find(conn, None, addr).await
+    }
+
+    /// Look up `addr` among the devices of the adapter at `adapter_path`.
+    pub(crate) async fn with_adapter(
+        conn: &Connection,
+        adapter_path: &str,
+        addr: ClassicAddress,
+    ) -> Result<Self, BluetoothError> {
+        Self::find(conn, Some(adapter_path), addr).await
+    }
+
+    async fn find(
+        conn: &Connection,
+        adapter_path: Option<&str>,
+        addr: ClassicAddress,
+    ) -> Result<Self, BluetoothError> {
+        let path =
+            find_device(conn, adapter_path, u64::from(addr), None).await?;
+        let inner = device_proxy(conn, path).await?;
+
+        Ok(ClassicDevice { inner, addr })
+    }
+}
+
+#[async_trait]
+impl api::BleDevice for BleDevice {
+    async fn new(addr: BleAddress) -> Result<Self, BluetoothError> {
+        let conn = Connection::system().await?;
+        Self::with_connection(&conn, addr).await
+    }
+
+    fn name(&self) -> Result<String, BluetoothError> {
+        device_name(&self.inner)
+    }
+
+    fn address(&self) -> BleAddress {
+        self.addr
+    }
+
+    async fn connect(&self) -> Result<(), BluetoothError> {
+        // Subscribe before connecting so the change isn't missed.
+        let mut resolved = self.inner.receive_services_resolved_changed().await;
+        self.inner.connect().await?;
+
+        while !self.inner.services_resolved().await? {
+            if resolved.next().await.is_none() {
+                return Err(BluetoothError::Internal(String::from(
+                    "BlueZ stopped reporting `ServicesResolved` changes.",
+                )));
+            }
+        }
+        Ok(())
+    }
+
+    async fn disconnect(&self) -> Result<(), BluetoothError> {
+        self.inner.disconnect().await?;
+        self.attributes.lock().unwrap().clear();
+        Ok(())
+    }
+
+    async fn discover_services(
+        &self,
+        uuid: Option<BtUuid>,
+    ) -> Result<Vec<GattService>, BluetoothError> {
+        self.check_resolved().await?;
+
+        let mut services = Vec::new();
+        for object in self.gatt_objects(GATT_SERVICE_INTERFACE).await? {
+            if property::<bool>(&object.props, "Primary")? == Some(false) {
+                continue;
+            }
+            let service = GattService::new(object.uuid()?, object.handle()?);
+            if uuid.is_some_and(|uuid| uuid != service.uuid()) {
+                continue;
+            }
+
+            self.attributes
+                .lock()
+                .unwrap()
+                .insert(service.handle(), object.path);
+            services.push(service);
+        }
+
+        Ok(services)
+    }
+
+    async fn discover_characteristics(
+        &self,
+        service: &GattService,
+        uuid: Option<BtUuid>,
+    ) -> Result<Vec<GattCharacteristic>, BluetoothError> {
+        self.check_resolved().await?;
+        let service_path = self.attribute_path(service.handle())?;
+
+        let mut characteristics = Vec::new();
+        for object in self.gatt_objects(GATT_CHARACTERISTIC_INTERFACE).await? {
+            let parent = property::<OwnedObjectPath>(&object.props, "Service")?;
+            if parent.as_ref() != Some(&service_path) {
+                continue;
+            }
+            let flags = property::<Vec<String>>(&object.props, "Flags")?
+                .unwrap_or_default();
+            let characteristic = GattCharacteristic::new(
+                object.uuid()?,
+                object.handle()?,
+                characteristic_properties(&flags),
+            );
+            if uuid.is_some_and(|uuid| uuid != characteristic.uuid()) {
+                continue;
+            }
+
+            self.attributes
+                .lock()
+                .unwrap()
+                .insert(characteristic.handle(), object.path);
+            characteristics.push(characteristic);
+        }
+
+        Ok(characteristics)
+    }
+
+    async fn read(
+        &self,
+        characteristic: &GattCharacteristic,
+    ) -> Result<Vec<u8>, BluetoothError> {
+        self.characteristic(characteristic)
+            .await?
+            .read_value(HashMap::new())
+            .await
+            .map_err(gatt_error)
+    }
+
+    async fn write(
+        &self,
+        characteristic: &GattCharacteristic,
+        value: &[u8],
+        write_type: WriteType,
+    ) -> Result<(), BluetoothError> {
+        let kind = match write_type {
+            WriteType::WithResponse => "request",
+            WriteType::WithoutResponse => "command",
+        };
+        let options = HashMap::from([("type", Value::from(kind))]);
+
+        self.characteristic(characteristic)
+            .await?
+            .write_value(value, options)
+            .await
+            .map_err(gatt_error)
+    }
+
+    /// BlueZ picks notifications over indications by itself when the
+    /// characteristic supports both.
+    async fn subscribe(
+        &self,
+        characteristic: &GattCharacteristic,
+    ) -> Result<NotificationStream, BluetoothError> {
+        if !characteristic
+            .properties()
+            .contains(GattCharacteristicProperties::NOTIFY)
+            && !characteristic
+                .properties()
+                .contains(GattCharacteristicProperties::INDICATE)
+        {
+            return Err(BluetoothError::Gatt(String::from(
+                "characteristic doesn't support notifications or \
+                indications",
+            )));
+        }
+        let proxy = self.characteristic(characteristic).await?;
+
+        // Subscribe to value changes before BlueZ starts notifying.
+        let rule = MatchRule::builder()
+            .msg_type(MessageType::Signal)
+            .interface("org.freedesktop.DBus.Properties")?
+            .member("PropertiesChanged")?
+            .path(proxy.inner().path().to_owned())?
+            .arg(0, GATT_CHARACTERISTIC_INTERFACE)?
+            .build();
+        let stream = MessageStream::for_match_rule(
+            rule,
+            proxy.inner().connection(),
+            None,
+        )
+        .await?;
+        proxy.start_notify().await.map_err(gatt_error)?;
+
+        Ok(NotificationStream::new(Notifications::new(
+            stream,
+            GattCharacteristic1ProxyBlocking::from(proxy.into_inner()),
+        )))
+    }
+
+    /// BlueZ reports the MTU on every characteristic of the device, so this
+    /// reads it from the first one.
+    async fn mtu(&self) -> Result<u16, BluetoothError> {
+        self.check_resolved().await?;
+
+        for object in self.gatt_objects(GATT_CHARACTERISTIC_INTERFACE).await? {
+            if let Some(mtu) = property::<u16>(&object.props, "MTU")? {
+                return Ok(mtu);
+            }
+        }
+        Err(BluetoothError::NotSupported(String::from(
+            "BlueZ doesn't report the MTU of this device",
+        )))
+    }
+
+    async fn pair(
+        &self,
+        protection_level: ProtectionLevel,
+        handler: Arc<dyn PairingHandler>,
+    ) -> Result<PairingResult, BluetoothError> {
+        pair_device(&self.inner, protection_level, handler).await
+    }
+
+    async fn is_paired(&self) -> Result<bool, BluetoothError> {
+        device_paired(&self.inner).await
+    }
+
+    /// BlueZ can only remove a bond by removing the device object, so the
+    /// device has to be discovered again before creating a new `BleDevice`
+    /// for it.
+    async fn unpair(&self) -> Result<(), BluetoothError> {
+        if !device_paired(&self.inner).await? {
+            return Ok(());
+        }
+
+        let adapter = Adapter1Proxy::builder(self.inner.inner().connection())
+            .path(self.inner.adapter().await?)?
+            .build()
+            .await?;
+        adapter.remove_device(self.inner.inner().path()).await?;
+        info!(
+            "Removed BlueZ device {}.",
+            self.inner.inner().path().as_str()
+        );
+        Ok(())
+    }
+
+    async fn bond_state_changes(
+        &self,
+    ) -> Result<BondStateStream, BluetoothError> {
+        let states = watch_device_flag(&self.inner, "Paired").await?;
+        Ok(BondStateStream::new(
+            states.map(|paired| paired.map(bond_state)),
+        ))
+    }
+
+    async fn connection_state(
+        &self,
+    ) -> Result<ConnectionState, BluetoothError> {
+        Ok(connection_state(
+            device_flag(&self.inner, "Connected").await?,
+        ))
+    }
+
+    async fn connection_events(
+        &self,
+    ) -> Result<ConnectionStateStream, BluetoothError> {
+        connection_events(&self.inner).await
+    }
+}
+
+#[async_trait]
+impl api::ClassicDevice for ClassicDevice {
+    async fn new(addr: ClassicAddress) -> Result<Self, BluetoothError> {
+        let conn = Connection::system().await?;
+        Self::with_connection(&conn, addr).await
+    }
+
+    fn name(&self) -> Result<String, BluetoothError> {
+        device_name(&self.inner)
+    }
+
+    fn address(&self) -> ClassicAddress {
+        self.addr
+    }
+
+    /// Classic pairing lets BlueZ pick any association model the device
+    /// supports.
+    async fn pair(
+        &self,
+        handler: Arc<dyn PairingHandler>,
+    ) -> Result<PairingResult, BluetoothError> {
+        pair_device(
+            &self.inner,
+            ProtectionLevel::EncryptionAndAuthentication,
+            handler,
+        )
+        .await
+    }
+
+    async fn connect_rfcomm(
+        &self,
+        service_uuid: BtUuid,
+    ) -> Result<RfcommSocket, BluetoothError> {
+        connect_rfcomm(&self.inner, service_uuid).await
+    }
+
+    async fn connection_state(
+        &self,
+    ) -> Result<ConnectionState, BluetoothError> {
+        Ok(connection_state(
+            device_flag(&self.inner, "Connected").await?,
+        ))
+    }
+
+    async fn connection_events(
+        &self,
+    ) -> Result<ConnectionStateStream, BluetoothError> {
+        connection_events(&self.inner).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{Read, Write};
+
+    use futures::{executor::block_on, AsyncReadExt, AsyncWriteExt};
+
+    use super::*;
+    use crate::{
+        api::{BleDevice as _, ClassicDevice as _},
+        linux::mock::{
+            MockBluez, MockBus, MockCharacteristic, MockDevice, MockError,
+            MockPairingRequest,
+        },
+    };
+
+    const ADDR: u64 = 0x112233445566;
+
+    /// Answers every request with `accept`, providing `pin` and recording the
+    /// PINs it is shown.
+    struct TestHandler {
+        accept: bool,
+        pin: Option<String>,
+        shown: Mutex<Vec<String>>,
+    }
+
+    impl TestHandler {
+        fn new(accept: bool, pin: Option<&str>) -> Arc<Self> {
+            Arc::new(TestHandler {
+                accept,
+                pin: pin.map(String::from),
+                shown: Mutex::new(Vec::new()),
+            })
+        }
+    }
+
+    #[async_trait]
+    impl PairingHandler for TestHandler {
+        async fn confirm(&self) -> bool {
+            self.accept
+        }
+
+        async fn display_pin(&self, pin: &str) {
+            self.shown.lock().unwrap().push(String::from(pin));
+        }
+
+        async fn provide_pin(&self) -> Option<String> {
+            self.pin.clone()
+        }
+
+        async fn confirm_pin_match(&self, pin: &str) -> bool {
+            self.shown.lock().unwrap().push(String::from(pin));
+            self.accept
+        }
+    }
+
+    /// Pair with a mock device that replies to `Pair()` with `pair_error`.
+    fn pair_with(
+        pair_error: Option<MockError>,
+    ) -> Result<PairingResult, BluetoothError> {
+        let mut device = MockDevice::new("11:22:33:44:55:66", "public");
+        device.pair_error = pair_error;
+        pair_device(device, TestHandler::new(true, None))
+    }
+
+    /// Pair with a mock device that makes `request` to `handler`.
+    fn pair_requesting(
+        request: MockPairingRequest,
+        handler: Arc<TestHandler>,
+    ) -> Result<PairingResult, BluetoothError> {
+        let mut device = MockDevice::new("11:22:33:44:55:66", "public");
+        device.pairing_request = Some(request);
+        pair_device(device, handler)
+    }
+
+    fn pair_device(
+        device: MockDevice,
+        handler: Arc<TestHandler>,
+    ) -> Result<PairingResult, BluetoothError> {
+        let bus = MockBus::start();
+        block_on(async {
+            let bluez = MockBluez::start(&bus).await;
+            bluez.add_device(device).await;
+
+            let conn = bus.connect().await;
+            let device = ClassicDevice::with_connection(
+                &conn,
+                ClassicAddress::from(ADDR),
+            )
+            .await
+            .unwrap();
+            device.pair(handler).await
+        })
+    }
+
+    #[test]
+    fn ble_device_new() {
+        let bus = MockBus::start();
+        block_on(async {
+            let bluez = MockBluez::start(&bus).await;
+            bluez
+                .add_device(MockDevice::new("11:22:33:44:55:66", "random"))
+                .await;
+
+            let conn = bus.connect().await;
+            let addr = BleAddress::new(ADDR, BleAddressKind::Random);
+            let device = BleDevice::with_connection(&conn, addr).await.unwrap();
+            assert_eq!(device.address(), addr);
+            assert_eq!(device.name().unwrap(), "Mock Device");
+
+            // Same address, but BlueZ only knows it as a random address.
+            let addr = BleAddress::new(ADDR, BleAddressKind::Public);
+            assert!(matches!(
+                BleDevice::with_connection(&conn, addr).await,
+                Err(BluetoothError::FailedPrecondition(_))
+            ));
+        });
+    }
+
+    #[test]
+    fn ble_device_pair_and_unpair() {
+        let bus = MockBus::start();
+        block_on(async {
+            let bluez = MockBluez::start(&bus).await;
+            let mut device = MockDevice::new("11:22:33:44:55:66", "random");
+            device.pairing_request =
+                Some(MockPairingRequest::RequestConfirmation(123456));
+            let path = bluez.add_device(device).await;
+
+            let conn = bus.connect().await;
+            let addr = BleAddress::new(ADDR, BleAddressKind::Random);
+            let device = BleDevice::with_connection(&conn, addr).await.unwrap();
+            let mut bond_states = device.bond_state_changes().await.unwrap();
+            assert_eq!(
+                bond_states.next().await,
+                Some(Ok(BondState::NotBonded))
+            );
+            assert_eq!(device.is_paired().await, Ok(false));
+
+            let handler = TestHandler::new(true, None);
+            assert_eq!(
+                device
+                    .pair(
+                        ProtectionLevel::EncryptionAndAuthentication,
+                        handler.clone()
+                    )
+                    .await,
+                Ok(PairingResult::Success)
+            );
+            assert_eq!(*handler.shown.lock().unwrap(), ["123456"]);
+            assert_eq!(device.is_paired().await, Ok(true));
+            assert_eq!(bond_states.next().await, Some(Ok(BondState::Bonded)));
+            assert_eq!(
+                device.pair(ProtectionLevel::Encryption, handler).await,
+                Ok(PairingResult::AlreadyPaired)
+            );
+
+            device.unpair().await.unwrap();
+            assert!(!bluez.has_device(&path).await);
+            assert_eq!(device.is_paired().await, Ok(false));
+            assert_eq!(
+                bond_states.next().await,
+                Some(Ok(BondState::NotBonded))
+            );
+        });
+    }
+
+    #[test]
+    fn ble_device_bond_state_changes() {
+        let bus = MockBus::start();
+        block_on(async {
+            let bluez = MockBluez::start(&bus).await;
+            let mut device = MockDevice::new("11:22:33:44:55:66", "random");
+            device.paired = true;
+            let path = bluez.add_device(device).await;
+
+            let conn = bus.connect().await;
+            let addr = BleAddress::new(ADDR, BleAddressKind::Random);
+            let device = BleDevice::with_connection(&conn, addr).await.unwrap();
+            let mut bond_states = device.bond_state_changes().await.unwrap();
+            assert_eq!(bond_states.next().await, Some(Ok(BondState::Bonded)));
+
+            // Unpaired and paired again by another client.
+            bluez.set_paired(&path, false).await;
+            assert_eq!(
+                bond_states.next().await,
+                Some(Ok(BondState::NotBonded))
+            );
+            bluez.set_paired(&path, true).await;
+            assert_eq!(bond_states.next().await, Some(Ok(BondState::Bonded)));
+        });
+    }
+
+    #[test]
+    fn ble_device_connection_events() {
+        let bus = MockBus::start();
+        block_on(async {
+            let bluez = MockBluez::start(&bus).await;
+            let path = bluez
+                .add_device(MockDevice::new("11:22:33:44:55:66", "random"))
+                .await;
+
+            let conn = bus.connect().await;
+            let addr = BleAddress::new(ADDR, BleAddressKind::Random);
+            let device = BleDevice::with_connection(&conn, addr).await.unwrap();
+            let mut states = device.connection_events().await.unwrap();
+            assert_eq!(
+                states.next().await,
+                Some(Ok(ConnectionState::Disconnected))
+            );
+
+            device.connect().await.unwrap();
+            assert_eq!(
+                device.connection_state().await,
+                Ok(ConnectionState::Connected)
+            );
+            assert_eq!(
+                states.next().await,
+                Some(Ok(ConnectionState::Connected))
+            );
+
+            // The device drops the link.
+            bluez.set_connected(&path, false).await;
+            assert_eq!(
+                states.next().await,
+                Some(Ok(ConnectionState::Disconnected))
+            );
+            assert_eq!(
+                device.connection_state().await,
+                Ok(ConnectionState::Disconnected)
+            );
+        });
+    }
+
+    #[test]
+    fn classic_device_connection_events() {
+        let bus = MockBus::start();
+        block_on(async {
+            let bluez = MockBluez::start(&bus).await;
+            let mut device = MockDevice::new("11:22:33:44:55:66", "public");
+            device.connected = true;
+            let path = bluez.add_device(device).await;
+
+            let conn = bus.connect().await;
+            let device = ClassicDevice::with_connection(
+                &conn,
+                ClassicAddress::from(ADDR),
+            )
+            .await
+            .unwrap();
+            let mut states = device.connection_events().await.unwrap();
+            assert_eq!(
+                states.next().await,
+                Some(Ok(ConnectionState::Connected))
+            );
+
+            // Removing the device drops the link too.
+            bluez.remove_device(&path).await;
+            assert_eq!(
+                states.next().await,
+                Some(Ok(ConnectionState::Disconnected))
+            );
+            assert_eq!(
+                device.connection_state().await,
+                Ok(ConnectionState::Disconnected)
+            );
+        });
+    }
+
+    #[test]
+    fn ble_device_gatt() {
+        let bus = MockBus::start();
+        block_on(async {
+            let bluez = MockBluez::start(&bus).await;
+            let path = bluez
+                .add_device(MockDevice::new("11:22:33:44:55:66", "random"))
+                .await;
+            let service = bluez
+                .add_service(&path, "0000fe2c-0000-1000-8000-00805f9b34fb", 10)
+                .await;
+            let characteristic = bluez
+                .add_characteristic(
+                    &service,
+                    MockCharacteristic::new(
+                        "fe2c1234-8366-4814-8eb0-01de32100bea",
+                        &["read", "write", "notify"],
+                        &[1, 2],
+                    ),
+                    12,
+                )
+                .await;
+
+            let conn = bus.connect().await;
+            let addr = BleAddress::new(ADDR, BleAddressKind::Random);
+            let device = BleDevice::with_connection(&conn, addr).await.unwrap();
+            assert!(matches!(
+                device.discover_services(None).await,
+                Err(BluetoothError::FailedPrecondition(_))
+            ));
+            device.connect().await.unwrap();
+            assert_eq!(device.mtu().await.unwrap(), 517);
+
+            let services = device
+                .discover_services(Some(BtUuid::FAST_PAIR))
+                .await
+                .unwrap();
+            assert_eq!(services, vec![GattService::new(BtUuid::FAST_PAIR, 10)]);
+            assert!(device
+                .discover_services(Some(BtUuid::from_u16(0x1234)))
+                .await
+                .unwrap()
+                .is_empty());
+
+            let characteristics = device
+                .discover_characteristics(&services[0], None)
+                .await
+                .unwrap();
+            assert_eq!(characteristics.len(), 1);
+            let found = &characteristics[0];
+            assert_eq!(
+                found.uuid(),
+                BtUuid::from_u128(0xfe2c1234_8366_4814_8eb0_01de32100bea)
+            );
+            assert_eq!(found.handle(), 12);
+            assert_eq!(found.properties().bits(), 0x1a);
+
+            assert_eq!(device.read(found).await.unwrap(), vec![1, 2]);
+            device
+                .write(found, &[3], WriteType::WithResponse)
+                .await
+                .unwrap();
+            assert!(matches!(
+                device.write(found, &[4], WriteType::WithoutResponse).await,
+                Err(BluetoothError::Gatt(_))
+            ));
+            assert_eq!(
+                bluez.characteristic(&characteristic).await.writes,
+                vec![(vec![3], String::from("request"))]
+            );
+
+            let mut notifications = device.subscribe(found).await.unwrap();
+            assert!(bluez.characteristic(&characteristic).await.notifying);
+            bluez.notify(&characteristic, &[5, 6]).await;
+            assert_eq!(
+                notifications.next().await.unwrap().unwrap(),
+                vec![5, 6]
+            );
+
+            // Disconnecting ends the subscription.
+            device.disconnect().await.unwrap();
+            assert!(notifications.next().await.is_none());
+            assert!(matches!(
+                device.read(found).await,
+                Err(BluetoothError::FailedPrecondition(_))
+            ));
+        });
+    }
+
+    #[test]
+    fn classic_device_unknown() {
+        let bus = MockBus::start();
+        block_on(async {
+            let _bluez = MockBluez::start(&bus).await;
+
+            let conn = bus.connect().await;
+            let result = ClassicDevice::with_connection(
+                &conn,
+                ClassicAddress::from(ADDR),
+            )
+            .await;
+            assert!(matches!(
+                result,
+                Err(BluetoothError::FailedPrecondition(_))
+            ));
+        });
+    }
+
+    #[test]
+    fn classic_device_rfcomm() {
+        const UUID: &str = "df21fe2c-2515-4fdb-8886-f12c4d67927c";
+
+        let bus = MockBus::start();
+        block_on(async {
+            let bluez = MockBluez::start(&bus).await;
+            let mut device = MockDevice::new("11:22:33:44:55:66", "public");
+            device.rfcomm_services.push(String::from(UUID));
+            bluez.add_device(device).await;
+
+            let conn = bus.connect().await;
+            let device = ClassicDevice::with_connection(
+                &conn,
+                ClassicAddress::from(ADDR),
+            )
+            .await
+            .unwrap();
+            let mut socket =
+                device.connect_rfcomm(BtUuid::MESSAGE_STREAM).await.unwrap();
+            assert_eq!(bluez.profiles().await, vec![String::from(UUID)]);
+
+            let mut remote = bluez.take_socket(UUID).await;
+            socket.write_all(&[0x03, 0x01]).await.unwrap();
+            let mut buf = [0; 2];
+            remote.read_exact(&mut buf).unwrap();
+            assert_eq!(buf, [0x03, 0x01]);
+            remote.write_all(&[0xff]).unwrap();
+            socket.read_exact(&mut buf[..1]).await.unwrap();
+            assert_eq!(buf[0], 0xff);
+
+            // Dropping the socket closes it and unregisters the profile.
+            drop(socket);
+            assert_eq!(remote.read(&mut buf).unwrap(), 0);
+            assert!(bluez.profiles().await.is_empty());
+        });
+    }
+
+    #[test]
+    fn classic_device_rfcomm_not_available() {
+        let bus = MockBus::start();
+        block_on(async {
+            let bluez = MockBluez::start(&bus).await;
+            bluez
+                .add_device(MockDevice::new("11:22:33:44:55:66", "public"))
+                .await;
+
+            let conn = bus.connect().await;
+            let device = ClassicDevice::with_connection(
+                &conn,
+                ClassicAddress::from(ADDR),
+            )
+            .await
+            .unwrap();
+            assert!(matches!(
+                device.connect_rfcomm(BtUuid::MESSAGE_STREAM).await,
+                Err(BluetoothError::NotSupported(_))
+            ));
+            assert!(bluez.profiles().await.is_empty());
+        });
+    }
+
+    #[test]
+    fn classic_device_pair() {
+        let result = pair_with(None);
+        assert!(matches!(result, Ok(PairingResult::Success)));
+
+        let result = pair_with(Some(MockError::AlreadyExists(String::from(
+            "Already Exists",
+        ))));
+        assert!(matches!(result, Ok(PairingResult::AlreadyPaired)));
+
+        let result =
+            pair_with(Some(MockError::InProgress(String::from("In Progress"))));
+        assert!(matches!(result, Ok(PairingResult::AlreadyInProgress)));
+    }
+
+    #[test]
+    fn classic_device_pair_failure() {
+        let result = pair_with(Some(MockError::AuthenticationFailed(
+            String::from("Authentication Failed"),
+        )));
+        assert!(matches!(result, Err(BluetoothError::PairingFailed(_))));
+
+        let result =
+            pair_with(Some(MockError::NotReady(String::from("Not Ready"))));
+        assert!(matches!(result, Err(BluetoothError::System(_))));
+    }
+
+    #[test]
+    fn classic_device_pair_confirmation() {
+        let handler = TestHandler::new(true, None);
+        let result = pair_requesting(
+            MockPairingRequest::RequestConfirmation(1234),
+            handler.clone(),
+        );
+        assert!(matches!(result, Ok(PairingResult::Success)));
+        assert_eq!(*handler.shown.lock().unwrap(), ["001234"]);
+
+        let result = pair_requesting(
+            MockPairingRequest::RequestConfirmation(1234),
+            TestHandler::new(false, None),
+        );
+        assert!(matches!(result, Err(BluetoothError::PairingFailed(_))));
+
+        let result = pair_requesting(
+            MockPairingRequest::RequestAuthorization,
+            TestHandler::new(true, None),
+        );
+        assert!(matches!(result, Ok(PairingResult::Success)));
+    }
+
+    #[test]
+    fn classic_device_pair_passkey() {
+        let result = pair_requesting(
+            MockPairingRequest::RequestPasskey(42),
+            TestHandler::new(true, Some("000042")),
+        );
+        assert!(matches!(result, Ok(PairingResult::Success)));
+
+        let result = pair_requesting(
+            MockPairingRequest::RequestPasskey(42),
+            TestHandler::new(true, None),
+        );
+        assert!(matches!(result, Err(BluetoothError::PairingFailed(_))));
+
+        let handler = TestHandler::new(true, None);
+        let result = pair_requesting(
+            MockPairingRequest::DisplayPasskey(987654),
+            handler.clone(),
+        );
+        assert!(matches!(result, Ok(PairingResult::Success)));
+        assert_eq!(*handler.shown.lock().unwrap(), ["987654"]);
+    }
+
+    #[test]
+    fn classic_device_pair_pin_code() {
+        let result = pair_requesting(
+            MockPairingRequest::RequestPinCode(String::from("0000")),
+            TestHandler::new(true, Some("0000")),
+        );
+        assert!(matches!(result, Ok(PairingResult::Success)));
+
+        let result = pair_requesting(
+            MockPairingRequest::RequestPinCode(String::from("0000")),
+            TestHandler::new(true, Some("1234")),
+        );
+        assert!(matches!(result, Err(BluetoothError::PairingFailed(_))));
+
+        let handler = TestHandler::new(true, None);
+        let result = pair_requesting(
+            MockPairingRequest::DisplayPinCode(String::from("1234")),
+            handler.clone(),
+        );
+        assert!(matches!(result, Ok(PairingResult::Success)));
+        assert_eq!(*handler.shown.lock().unwrap(), ["1234"]);
+    }
+}
@@ -0,0 +1,79 @@
+// Copyright 2023 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use crate::common::{BluetoothError, PairingResult};
+
+impl From<zbus::Error> for BluetoothError {
+    fn from(err: zbus::Error) -> Self {
+        BluetoothError::System(err.to_string())
+    }
+}
+
+impl From<zbus::fdo::Error> for BluetoothError {
+    fn from(err: zbus::fdo::Error) -> Self {
+        BluetoothError::System(err.to_string())
+    }
+}
+
+impl From<zbus::zvariant::Error> for BluetoothError {
+    fn from(err: zbus::zvariant::Error) -> Self {
+        BluetoothError::Internal(err.to_string())
+    }
+}
+
+/// BlueZ reports pairing outcomes as `org.bluez.Error.*` D-Bus errors instead
+/// of a status enum, so convert the `Pair()` reply into a `PairingResult`.
+/// https://git.kernel.org/pub/scm/bluetooth/bluez.git/tree/doc/org.bluez.Device.rst
+pub(crate) fn pairing_result(
+    reply: zbus::Result<()>,
+) -> Result<PairingResult, BluetoothError> {
+    match reply {
+        Ok(()) => Ok(PairingResult::Success),
+        Err(zbus::Error::MethodError(name, msg, _)) => {
+            let msg = msg.unwrap_or_default();
+            let reason = match name.as_str() {
+                "org.bluez.Error.AlreadyExists" => {
+                    return Ok(PairingResult::AlreadyPaired)
+                }
+                "org.bluez.Error.InProgress" => {
+                    return Ok(PairingResult::AlreadyInProgress)
+                }
+                "org.bluez.Error.AuthenticationCanceled" => {
+                    "the pairing action was canceled before completion."
+                }
+                "org.bluez.Error.AuthenticationFailed"
+                | "org.bluez.Error.AuthenticationRejected" => {
+                    "authentication failed, so the device is not paired."
+                }
+                "org.bluez.Error.AuthenticationTimeout" => {
+                    "the authentication process timed out before it could \
+                    complete."
+                }
+                "org.bluez.Error.ConnectionAttemptFailed" => {
+                    "the device object rejected the connection."
+                }
+                "org.bluez.Error.Failed" => "an unknown failure occurred.",
+                _ => {
+                    return Err(BluetoothError::System(format!(
+                        "{}: {}",
+                        name, msg
+                    )))
+                }
+            };
+
+            Ok(PairingResult::Failure(format!("{} {}", reason, msg)))
+        }
+        Err(err) => Err(BluetoothError::from(err)),
+    }
+}
@@ -0,0 +1,213 @@
+// Copyright 2023 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::{
+    collections::HashMap,
+    pin::Pin,
+    task::{Context, Poll},
+};
+
+use futures::{ready, Stream, StreamExt};
+use tracing::warn;
+use zbus::{zvariant::OwnedValue, Message, MessageStream};
+
+use super::bluez::GattCharacteristic1ProxyBlocking;
+use crate::common::{BluetoothError, GattCharacteristicProperties};
+
+/// Convert the `Flags` property of an `org.bluez.GattCharacteristic1` object
+/// into characteristic properties. Flags that aren't part of the
+/// characteristic declaration (e.g. "encrypt-read") are ignored.
+pub(crate) fn characteristic_properties(
+    flags: &[String],
+) -> GattCharacteristicProperties {
+    let bits = flags
+        .iter()
+        .map(|flag| match flag.as_str() {
+            "broadcast" => GattCharacteristicProperties::BROADCAST,
+            "read" => GattCharacteristicProperties::READ,
+            "write-without-response" => {
+                GattCharacteristicProperties::WRITE_WITHOUT_RESPONSE
+            }
+            "write" => GattCharacteristicProperties::WRITE,
+            "notify" => GattCharacteristicProperties::NOTIFY,
+            "indicate" => GattCharacteristicProperties::INDICATE,
+            "authenticated-signed-writes" => {
+                GattCharacteristicProperties::AUTHENTICATED_SIGNED_WRITES
+            }
+            "extended-properties" => {
+                GattCharacteristicProperties::EXTENDED_PROPERTIES
+            }
+            _ => 0,
+        })
+        .fold(0, |bits, bit| bits | bit);
+
+    GattCharacteristicProperties::from_bits(bits)
+}
+
+/// BlueZ reports ATT errors of GATT operations as `org.bluez.Error.*` D-Bus
+/// errors, so map those to `BluetoothError::Gatt`.
+/// https://git.kernel.org/pub/scm/bluetooth/bluez.git/tree/doc/org.bluez.GattCharacteristic.rst
+pub(crate) fn gatt_error(err: zbus::Error) -> BluetoothError {
+    match err {
+        zbus::Error::MethodError(name, msg, _)
+            if matches!(
+                name.as_str(),
+                "org.bluez.Error.Failed"
+                    | "org.bluez.Error.InProgress"
+                    | "org.bluez.Error.NotPermitted"
+                    | "org.bluez.Error.NotAuthorized"
+                    | "org.bluez.Error.InvalidOffset"
+                    | "org.bluez.Error.InvalidValueLength"
+                    | "org.bluez.Error.NotSupported"
+            ) =>
+        {
+            BluetoothError::Gatt(format!(
+                "{}: {}",
+                name,
+                msg.unwrap_or_default()
+            ))
+        }
+        err => BluetoothError::from(err),
+    }
+}
+
+/// Stream of the values BlueZ reports through `PropertiesChanged` signals of
+/// a characteristic while notifications are on. Notifications are turned off
+/// when dropped.
+pub(crate) struct Notifications {
+    /// Can be polled to consume `PropertiesChanged` signals of the
+    /// characteristic.
+    stream: MessageStream,
+    /// Proxy for the characteristic, or `None` once BlueZ stopped notifying,
+    /// e.g. because the device disconnected.
+    characteristic: Option<GattCharacteristic1ProxyBlocking<'static>>,
+}
+
+impl Notifications {
+    /// Wrap a stream of signals matching `PropertiesChanged` on the
+    /// characteristic, on which `StartNotify()` has been called.
+    pub(crate) fn new(
+        stream: MessageStream,
+        characteristic: GattCharacteristic1ProxyBlocking<'static>,
+    ) -> Self {
+        Notifications {
+            stream,
+            characteristic: Some(characteristic),
+        }
+    }
+
+    /// Extract the notified value from a `PropertiesChanged` signal. Returns
+    /// `Ok(None)` for signals not carrying a value.
+    fn value(
+        &mut self,
+        msg: &Message,
+    ) -> Result<Option<Vec<u8>>, BluetoothError> {
+        let (_, changed, _): (
+            String,
+            HashMap<String, OwnedValue>,
+            Vec<String>,
+        ) = msg.body().deserialize()?;
+
+        if let Some(value) = changed.get("Value") {
+            return Ok(Some(Vec::<u8>::try_from(value.try_clone()?)?));
+        }
+        if let Some(notifying) = changed.get("Notifying") {
+            if !bool::try_from(notifying)? {
+                self.characteristic = None;
+            }
+        }
+        Ok(None)
+    }
+}
+
+impl Stream for Notifications {
+    type Item = Result<Vec<u8>, BluetoothError>;
+
+    fn poll_next(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Self::Item>> {
+        loop {
+            if self.characteristic.is_none() {
+                return Poll::Ready(None);
+            }
+
+            let msg = match ready!(self.stream.poll_next_unpin(cx)) {
+                Some(Ok(msg)) => msg,
+                Some(Err(err)) => return Poll::Ready(Some(Err(err.into()))),
+                None => return Poll::Ready(None),
+            };
+
+            match self.value(&msg).transpose() {
+                Some(value) => return Poll::Ready(Some(value)),
+                None => continue,
+            }
+        }
+    }
+}
+
+impl Drop for Notifications {
+    fn drop(&mut self) {
+        if let Some(characteristic) = &self.characteristic {
+            if let Err(err) = characteristic.stop_notify() {
+                warn!("Failed to stop BlueZ notifications: {}", err);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use zbus::names::ErrorName;
+
+    use super::*;
+
+    #[test]
+    fn properties_from_flags() {
+        let flags = ["read", "notify", "encrypt-read", "write"]
+            .map(String::from)
+            .to_vec();
+        assert_eq!(
+            characteristic_properties(&flags),
+            GattCharacteristicProperties::from_bits(
+                GattCharacteristicProperties::READ
+                    | GattCharacteristicProperties::WRITE
+                    | GattCharacteristicProperties::NOTIFY
+            )
+        );
+    }
+
+    #[test]
+    fn errors() {
+        let error = |name: &str| {
+            gatt_error(zbus::Error::MethodError(
+                ErrorName::try_from(name).unwrap().into(),
+                Some(String::from("message")),
+                Message::method_call("/", "Test")
+                    .unwrap()
+                    .build(&())
+                    .unwrap(),
+            ))
+        };
+
+        assert!(matches!(
+            error("org.bluez.Error.NotPermitted"),
+            BluetoothError::Gatt(_)
+        ));
+        assert!(matches!(
+            error("org.freedesktop.DBus.Error.NoReply"),
+            BluetoothError::System(_)
+        ));
+    }
+}
@@ -0,0 +1,1183 @@
+// Copyright 2023 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+// Mock BlueZ daemon for unit tests. `MockBus` spawns a private D-Bus session
+// bus and `MockBluez` claims `org.bluez` on it, serving just enough of the
+// BlueZ object tree for the Linux backend to run against.
+
+use std::{
+    collections::HashMap,
+    io::{BufRead, BufReader},
+    os::unix::net::UnixStream,
+    process::{Child, Command, Stdio},
+};
+
+use zbus::{
+    connection,
+    export::serde::Serialize,
+    fdo::{ObjectManager, PropertiesProxy},
+    interface,
+    message::Header,
+    names::InterfaceName,
+    object_server::SignalEmitter,
+    zvariant::DynamicType,
+    zvariant::Fd,
+    zvariant::OwnedObjectPath,
+    zvariant::OwnedValue,
+    zvariant::Value,
+    Connection, DBusError, Message, ObjectServer,
+};
+
+use super::bluez::BLUEZ_SERVICE;
+
+/// Object path of the single adapter exposed by `MockBluez`.
+pub(crate) const ADAPTER_PATH: &str = "/org/bluez/hci0";
+
+/// Object path of the profile and agent managers exposed by `MockBluez`.
+const BLUEZ_PATH: &str = "/org/bluez";
+
+/// Interface name of the advertisement objects clients register.
+const ADVERTISEMENT_INTERFACE: &str = "org.bluez.LEAdvertisement1";
+
+/// Interface name of the agent objects clients register.
+const AGENT_INTERFACE: &str = "org.bluez.Agent1";
+
+/// A private D-Bus session bus, killed when dropped.
+pub(crate) struct MockBus {
+    daemon: Child,
+    address: String,
+}
+
+impl MockBus {
+    /// Spawn a new bus.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `dbus-daemon` can't be run, so that the tests relying on
+    /// the mock fail instead of silently passing where it isn't installed.
+    pub(crate) fn start() -> Self {
+        let mut daemon = Command::new("dbus-daemon")
+            .args(["--session", "--nofork", "--print-address"])
+            .stdout(Stdio::piped())
+            .stderr(Stdio::null())
+            .spawn()
+            .expect("dbus-daemon is required to run the mock BlueZ tests");
+
+        let mut address = String::new();
+        BufReader::new(daemon.stdout.take().unwrap())
+            .read_line(&mut address)
+            .expect("dbus-daemon didn't print its address");
+
+        MockBus {
+            daemon,
+            address: address.trim().to_string(),
+        }
+    }
+
+    /// Open a new client connection to the bus.
+    pub(crate) async fn connect(&self) -> Connection {
+        connection::Builder::address(self.address.as_str())
+            .unwrap()
+            .build()
+            .await
+            .unwrap()
+    }
+}
+
+impl Drop for MockBus {
+    fn drop(&mut self) {
+        let _ = self.daemon.kill();
+        let _ = self.daemon.wait();
+    }
+}
+
+/// Errors replied by mock BlueZ methods.
+#[derive(Clone, Debug, DBusError)]
+#[zbus(prefix = "org.bluez.Error")]
+pub(crate) enum MockError {
+    AlreadyExists(String),
+    InProgress(String),
+    AuthenticationFailed(String),
+    AuthenticationRejected(String),
+    Failed(String),
+    NotReady(String),
+    DoesNotExist(String),
+    NotPermitted(String),
+    NotAvailable(String),
+}
+
+/// Mock `org.bluez.Adapter1` object.
+pub(crate) struct MockAdapter {
+    address: String,
+    powered: bool,
+    discovering: bool,
+    discovery_filter: Vec<String>,
+}
+
+impl Default for MockAdapter {
+    fn default() -> Self {
+        MockAdapter {
+            address: String::from("00:11:22:33:44:55"),
+            powered: true,
+            discovering: false,
+            discovery_filter: Vec::new(),
+        }
+    }
+}
+
+#[interface(name = "org.bluez.Adapter1")]
+impl MockAdapter {
+    fn start_discovery(&mut self) {
+        self.discovering = true;
+    }
+
+    fn stop_discovery(&mut self) -> Result<(), MockError> {
+        if !self.discovering {
+            return Err(MockError::Failed(String::from(
+                "No discovery started",
+            )));
+        }
+        self.discovering = false;
+        Ok(())
+    }
+
+    fn set_discovery_filter(&mut self, filter: HashMap<String, OwnedValue>) {
+        self.discovery_filter = filter.into_keys().collect();
+    }
+
+    /// Unexport the device, emitting `InterfacesRemoved`.
+    async fn remove_device(
+        &self,
+        #[zbus(object_server)] server: &ObjectServer,
+        device: OwnedObjectPath,
+    ) -> Result<(), MockError> {
+        match server.remove::<MockDevice, _>(&device).await {
+            Ok(true) => Ok(()),
+            _ => Err(MockError::DoesNotExist(String::from(
+                "Device does not exist",
+            ))),
+        }
+    }
+
+    #[zbus(property)]
+    fn address(&self) -> String {
+        self.address.clone()
+    }
+
+    #[zbus(property)]
+    fn address_type(&self) -> String {
+        String::from("public")
+    }
+
+    #[zbus(property)]
+    fn alias(&self) -> String {
+        String::from("Mock Adapter")
+    }
+
+    #[zbus(property)]
+    fn roles(&self) -> Vec<String> {
+        vec![String::from("central"), String::from("peripheral")]
+    }
+
+    #[zbus(property)]
+    fn powered(&self) -> bool {
+        self.powered
+    }
+
+    #[zbus(property)]
+    fn discovering(&self) -> bool {
+        self.discovering
+    }
+}
+
+/// Mock `org.bluez.LEAdvertisingManager1` object, served next to the adapter.
+#[derive(Default)]
+pub(crate) struct MockAdvertisingManager {
+    /// Properties of registered advertisements, keyed by the unique name of
+    /// their client and their path.
+    advertisements:
+        HashMap<(String, OwnedObjectPath), HashMap<String, OwnedValue>>,
+}
+
+#[interface(name = "org.bluez.LEAdvertisingManager1")]
+impl MockAdvertisingManager {
+    /// Read the properties of the advertisement, like BlueZ does.
+    async fn register_advertisement(
+        &mut self,
+        #[zbus(header)] header: Header<'_>,
+        #[zbus(connection)] conn: &Connection,
+        advertisement: OwnedObjectPath,
+        _options: HashMap<String, OwnedValue>,
+    ) -> Result<(), MockError> {
+        let client = header.sender().unwrap().to_string();
+        let props = PropertiesProxy::builder(conn)
+            .destination(client.clone())
+            .and_then(|builder| builder.path(advertisement.clone()))
+            .map_err(|err| MockError::Failed(err.to_string()))?
+            .build()
+            .await
+            .map_err(|err| MockError::Failed(err.to_string()))?
+            .get_all(InterfaceName::from_static_str_unchecked(
+                ADVERTISEMENT_INTERFACE,
+            ))
+            .await
+            .map_err(|err| MockError::Failed(err.to_string()))?;
+
+        self.advertisements.insert((client, advertisement), props);
+        Ok(())
+    }
+
+    fn unregister_advertisement(
+        &mut self,
+        #[zbus(header)] header: Header<'_>,
+        advertisement: OwnedObjectPath,
+    ) -> Result<(), MockError> {
+        let client = header.sender().unwrap().to_string();
+        match self.advertisements.remove(&(client, advertisement)) {
+            Some(_) => Ok(()),
+            None => Err(MockError::DoesNotExist(String::from(
+                "Advertisement not registered",
+            ))),
+        }
+    }
+
+    #[zbus(property)]
+    fn supported_secondary_channels(&self) -> Vec<String> {
+        vec![
+            String::from("1M"),
+            String::from("2M"),
+            String::from("Coded"),
+        ]
+    }
+
+    #[zbus(property)]
+    fn supported_capabilities(&self) -> HashMap<String, OwnedValue> {
+        HashMap::from([(String::from("MaxAdvLen"), OwnedValue::from(251u8))])
+    }
+}
+
+/// Mock `org.bluez.ProfileManager1` object.
+#[derive(Default)]
+pub(crate) struct MockProfileManager {
+    /// UUIDs of registered profiles, keyed by the unique name of their client
+    /// and their path.
+    profiles: HashMap<(String, OwnedObjectPath), String>,
+    /// Remote ends of the sockets handed to profiles, with their UUID.
+    sockets: Vec<(String, UnixStream)>,
+}
+
+#[interface(name = "org.bluez.ProfileManager1")]
+impl MockProfileManager {
+    fn register_profile(
+        &mut self,
+        #[zbus(header)] header: Header<'_>,
+        profile: OwnedObjectPath,
+        uuid: String,
+        _options: HashMap<String, OwnedValue>,
+    ) {
+        let client = header.sender().unwrap().to_string();
+        self.profiles.insert((client, profile), uuid);
+    }
+
+    fn unregister_profile(
+        &mut self,
+        #[zbus(header)] header: Header<'_>,
+        profile: OwnedObjectPath,
+    ) -> Result<(), MockError> {
+        let client = header.sender().unwrap().to_string();
+        match self.profiles.remove(&(client, profile)) {
+            Some(_) => Ok(()),
+            None => Err(MockError::DoesNotExist(String::from(
+                "Profile not registered",
+            ))),
+        }
+    }
+}
+
+/// Mock `org.bluez.AgentManager1` object.
+#[derive(Default)]
+pub(crate) struct MockAgentManager {
+    /// Registered agents, as the unique name of their client and their path.
+    agents: Vec<(String, OwnedObjectPath)>,
+}
+
+#[interface(name = "org.bluez.AgentManager1")]
+impl MockAgentManager {
+    fn register_agent(
+        &mut self,
+        #[zbus(header)] header: Header<'_>,
+        agent: OwnedObjectPath,
+        _capability: String,
+    ) {
+        let client = header.sender().unwrap().to_string();
+        self.agents.push((client, agent));
+    }
+
+    fn unregister_agent(
+        &mut self,
+        #[zbus(header)] header: Header<'_>,
+        agent: OwnedObjectPath,
+    ) -> Result<(), MockError> {
+        let client = header.sender().unwrap().to_string();
+        match self
+            .agents
+            .iter()
+            .position(|(c, a)| *c == client && *a == agent)
+        {
+            Some(index) => {
+                self.agents.remove(index);
+                Ok(())
+            }
+            None => Err(MockError::DoesNotExist(String::from(
+                "Agent not registered",
+            ))),
+        }
+    }
+}
+
+/// Request a `MockDevice` makes to the agent of the client calling `Pair()`.
+#[derive(Clone, Debug)]
+pub(crate) enum MockPairingRequest {
+    RequestPinCode(String),
+    DisplayPinCode(String),
+    RequestPasskey(u32),
+    DisplayPasskey(u32),
+    RequestConfirmation(u32),
+    RequestAuthorization,
+}
+
+/// Mock `org.bluez.Device1` object.
+#[derive(Clone)]
+pub(crate) struct MockDevice {
+    pub(crate) address: String,
+    pub(crate) address_type: String,
+    pub(crate) name: String,
+    pub(crate) rssi: i16,
+    pub(crate) service_data: HashMap<String, Vec<u8>>,
+    pub(crate) paired: bool,
+    /// Class of Device, or 0 if the device wasn't found by BR/EDR inquiry.
+    pub(crate) class: u32,
+    /// Error replied to `Pair()`, or `None` to pair successfully.
+    pub(crate) pair_error: Option<MockError>,
+    /// Request made to the agent during `Pair()`, if any. Requests for a PIN
+    /// code or passkey carry the one the device expects.
+    pub(crate) pairing_request: Option<MockPairingRequest>,
+    pub(crate) connected: bool,
+    /// UUIDs of the RFCOMM services the device serves.
+    pub(crate) rfcomm_services: Vec<String>,
+    /// Characteristics exported under the device, which stop notifying when
+    /// it disconnects.
+    characteristics: Vec<OwnedObjectPath>,
+    /// Adapter the device is exported under.
+    adapter: OwnedObjectPath,
+}
+
+impl MockDevice {
+    pub(crate) fn new(address: &str, address_type: &str) -> Self {
+        MockDevice {
+            address: String::from(address),
+            address_type: String::from(address_type),
+            name: String::from("Mock Device"),
+            rssi: -60,
+            service_data: HashMap::new(),
+            paired: false,
+            class: 0,
+            pair_error: None,
+            pairing_request: None,
+            connected: false,
+            rfcomm_services: Vec::new(),
+            characteristics: Vec::new(),
+            adapter: OwnedObjectPath::try_from(ADAPTER_PATH).unwrap(),
+        }
+    }
+}
+
+#[interface(name = "org.bluez.Device1")]
+impl MockDevice {
+    async fn pair(
+        &mut self,
+        #[zbus(header)] header: Header<'_>,
+        #[zbus(connection)] conn: &Connection,
+        #[zbus(object_server)] server: &ObjectServer,
+        #[zbus(signal_emitter)] emitter: SignalEmitter<'_>,
+    ) -> Result<(), MockError> {
+        if let Some(err) = &self.pair_error {
+            return Err(err.clone());
+        }
+
+        if let Some(request) = &self.pairing_request {
+            let client = header.sender().unwrap().to_string();
+            let device =
+                OwnedObjectPath::from(header.path().unwrap().to_owned());
+            let manager = server
+                .interface::<_, MockAgentManager>(BLUEZ_PATH)
+                .await
+                .map_err(|err| MockError::Failed(err.to_string()))?;
+            let agent = manager
+                .get()
+                .await
+                .agents
+                .iter()
+                .find(|(agent_client, _)| *agent_client == client)
+                .map(|(_, path)| path.clone());
+            let Some(agent) = agent else {
+                return Err(MockError::AuthenticationRejected(String::from(
+                    "No agent",
+                )));
+            };
+            request_agent(conn, &client, &agent, device, request).await?;
+        }
+
+        self.paired = true;
+        self.paired_changed(&emitter)
+            .await
+            .map_err(|err| MockError::Failed(err.to_string()))
+    }
+
+    /// Hand a new socket to the profile registered for `uuid`, like BlueZ
+    /// does once it connected the RFCOMM channel.
+    async fn connect_profile(
+        &self,
+        #[zbus(header)] header: Header<'_>,
+        #[zbus(connection)] conn: &Connection,
+        #[zbus(object_server)] server: &ObjectServer,
+        uuid: String,
+    ) -> Result<(), MockError> {
+        if !self.rfcomm_services.contains(&uuid) {
+            return Err(MockError::NotAvailable(String::from(
+                "Service not available",
+            )));
+        }
+
+        let device = header.path().unwrap().to_owned();
+        let manager = server
+            .interface::<_, MockProfileManager>(BLUEZ_PATH)
+            .await
+            .map_err(|err| MockError::Failed(err.to_string()))?;
+        let profile = manager
+            .get()
+            .await
+            .profiles
+            .iter()
+            .find(|(_, profile_uuid)| **profile_uuid == uuid)
+            .map(|(profile, _)| profile.clone());
+        let Some((client, path)) = profile else {
+            return Err(MockError::Failed(String::from("No profile")));
+        };
+
+        let (local, remote) = UnixStream::pair().unwrap();
+        conn.call_method(
+            Some(client.as_str()),
+            &path,
+            Some("org.bluez.Profile1"),
+            "NewConnection",
+            &(device, Fd::from(&local), HashMap::<String, Value>::new()),
+        )
+        .await
+        .map_err(|err| MockError::Failed(err.to_string()))?;
+
+        manager.get_mut().await.sockets.push((uuid, remote));
+        Ok(())
+    }
+
+    async fn connect(
+        &mut self,
+        #[zbus(signal_emitter)] emitter: SignalEmitter<'_>,
+    ) -> zbus::fdo::Result<()> {
+        self.connected = true;
+        self.connected_changed(&emitter).await?;
+        self.services_resolved_changed(&emitter).await?;
+        Ok(())
+    }
+
+    async fn disconnect(
+        &mut self,
+        #[zbus(signal_emitter)] emitter: SignalEmitter<'_>,
+        #[zbus(object_server)] server: &ObjectServer,
+    ) -> zbus::fdo::Result<()> {
+        self.connected = false;
+        self.connected_changed(&emitter).await?;
+        self.services_resolved_changed(&emitter).await?;
+
+        for path in &self.characteristics {
+            let iface = server.interface::<_, MockCharacteristic>(path).await?;
+            iface.get_mut().await.notifying = false;
+            iface
+                .get()
+                .await
+                .notifying_changed(iface.signal_emitter())
+                .await?;
+        }
+        Ok(())
+    }
+
+    #[zbus(property)]
+    fn address(&self) -> String {
+        self.address.clone()
+    }
+
+    #[zbus(property)]
+    fn address_type(&self) -> String {
+        self.address_type.clone()
+    }
+
+    #[zbus(property)]
+    fn name(&self) -> String {
+        self.name.clone()
+    }
+
+    #[zbus(property)]
+    fn alias(&self) -> String {
+        self.name.clone()
+    }
+
+    #[zbus(property, name = "RSSI")]
+    fn rssi(&self) -> i16 {
+        self.rssi
+    }
+
+    #[zbus(property)]
+    fn service_data(&self) -> HashMap<String, OwnedValue> {
+        self.service_data
+            .iter()
+            .map(|(uuid, data)| {
+                (
+                    uuid.clone(),
+                    OwnedValue::try_from(Value::from(data.clone())).unwrap(),
+                )
+            })
+            .collect()
+    }
+
+    #[zbus(property)]
+    fn paired(&self) -> bool {
+        self.paired
+    }
+
+    #[zbus(property)]
+    fn class(&self) -> u32 {
+        self.class
+    }
+
+    #[zbus(property)]
+    fn adapter(&self) -> OwnedObjectPath {
+        self.adapter.clone()
+    }
+
+    #[zbus(property)]
+    fn connected(&self) -> bool {
+        self.connected
+    }
+
+    #[zbus(property)]
+    fn services_resolved(&self) -> bool {
+        self.connected
+    }
+}
+
+/// Mock `org.bluez.GattService1` object.
+struct MockGattService {
+    uuid: String,
+    device: OwnedObjectPath,
+    handle: u16,
+}
+
+#[interface(name = "org.bluez.GattService1")]
+impl MockGattService {
+    #[zbus(property, name = "UUID")]
+    fn uuid(&self) -> String {
+        self.uuid.clone()
+    }
+
+    #[zbus(property)]
+    fn primary(&self) -> bool {
+        true
+    }
+
+    #[zbus(property)]
+    fn device(&self) -> OwnedObjectPath {
+        self.device.clone()
+    }
+
+    #[zbus(property)]
+    fn handle(&self) -> u16 {
+        self.handle
+    }
+}
+
+/// Mock `org.bluez.GattCharacteristic1` object.
+#[derive(Clone)]
+pub(crate) struct MockCharacteristic {
+    pub(crate) uuid: String,
+    pub(crate) flags: Vec<String>,
+    pub(crate) value: Vec<u8>,
+    pub(crate) notifying: bool,
+    /// Values written by clients, with the write type they asked for.
+    pub(crate) writes: Vec<(Vec<u8>, String)>,
+    service: OwnedObjectPath,
+    handle: u16,
+}
+
+impl MockCharacteristic {
+    pub(crate) fn new(uuid: &str, flags: &[&str], value: &[u8]) -> Self {
+        MockCharacteristic {
+            uuid: String::from(uuid),
+            flags: flags.iter().map(|flag| String::from(*flag)).collect(),
+            value: value.to_vec(),
+            notifying: false,
+            writes: Vec::new(),
+            service: OwnedObjectPath::default(),
+            handle: 0,
+        }
+    }
+
+    fn check_flag(&self, flag: &str) -> Result<(), MockError> {
+        if self.flags.iter().any(|f| f == flag) {
+            Ok(())
+        } else {
+            Err(MockError::NotPermitted(format!("{} not permitted", flag)))
+        }
+    }
+}
+
+#[interface(name = "org.bluez.GattCharacteristic1")]
+impl MockCharacteristic {
+    fn read_value(
+        &self,
+        _options: HashMap<String, OwnedValue>,
+    ) -> Result<Vec<u8>, MockError> {
+        self.check_flag("read")?;
+        Ok(self.value.clone())
+    }
+
+    fn write_value(
+        &mut self,
+        value: Vec<u8>,
+        options: HashMap<String, OwnedValue>,
+    ) -> Result<(), MockError> {
+        let kind = options
+            .get("type")
+            .and_then(|kind| kind.downcast_ref::<String>().ok())
+            .unwrap_or(String::from("request"));
+        match kind.as_str() {
+            "command" => self.check_flag("write-without-response")?,
+            _ => self.check_flag("write")?,
+        }
+
+        self.writes.push((value, kind));
+        Ok(())
+    }
+
+    async fn start_notify(
+        &mut self,
+        #[zbus(signal_emitter)] emitter: SignalEmitter<'_>,
+    ) -> Result<(), MockError> {
+        self.check_flag("notify")
+            .or_else(|_| self.check_flag("indicate"))?;
+        self.notifying = true;
+        self.notifying_changed(&emitter)
+            .await
+            .map_err(|err| MockError::Failed(err.to_string()))
+    }
+
+    async fn stop_notify(
+        &mut self,
+        #[zbus(signal_emitter)] emitter: SignalEmitter<'_>,
+    ) -> Result<(), MockError> {
+        self.notifying = false;
+        self.notifying_changed(&emitter)
+            .await
+            .map_err(|err| MockError::Failed(err.to_string()))
+    }
+
+    #[zbus(property, name = "UUID")]
+    fn uuid(&self) -> String {
+        self.uuid.clone()
+    }
+
+    #[zbus(property)]
+    fn service(&self) -> OwnedObjectPath {
+        self.service.clone()
+    }
+
+    #[zbus(property)]
+    fn handle(&self) -> u16 {
+        self.handle
+    }
+
+    #[zbus(property)]
+    fn flags(&self) -> Vec<String> {
+        self.flags.clone()
+    }
+
+    #[zbus(property)]
+    fn value(&self) -> Vec<u8> {
+        self.value.clone()
+    }
+
+    #[zbus(property)]
+    fn notifying(&self) -> bool {
+        self.notifying
+    }
+
+    #[zbus(property, name = "MTU")]
+    fn mtu(&self) -> u16 {
+        517
+    }
+}
+
+/// Call `method` of the agent at `path` registered by `client`. Errors are
+/// replied to `Pair()` as rejections, like BlueZ does.
+async fn call_agent<B>(
+    conn: &Connection,
+    client: &str,
+    path: &OwnedObjectPath,
+    method: &str,
+    body: &B,
+) -> Result<Message, MockError>
+where
+    B: Serialize + DynamicType,
+{
+    conn.call_method(Some(client), path, Some(AGENT_INTERFACE), method, body)
+        .await
+        .map_err(|err| MockError::AuthenticationRejected(err.to_string()))
+}
+
+/// Make `request` to the agent at `path` registered by `client`, checking the
+/// PIN code or passkey it replies.
+async fn request_agent(
+    conn: &Connection,
+    client: &str,
+    path: &OwnedObjectPath,
+    device: OwnedObjectPath,
+    request: &MockPairingRequest,
+) -> Result<(), MockError> {
+    match request {
+        MockPairingRequest::RequestPinCode(expected) => {
+            let reply =
+                call_agent(conn, client, path, "RequestPinCode", &(device,))
+                    .await?;
+            let pin: String = reply
+                .body()
+                .deserialize()
+                .map_err(|err| MockError::Failed(err.to_string()))?;
+            if pin != *expected {
+                return Err(MockError::AuthenticationFailed(pin));
+            }
+        }
+        MockPairingRequest::DisplayPinCode(pin) => {
+            let body = (device, pin.as_str());
+            call_agent(conn, client, path, "DisplayPinCode", &body).await?;
+        }
+        MockPairingRequest::RequestPasskey(expected) => {
+            let reply =
+                call_agent(conn, client, path, "RequestPasskey", &(device,))
+                    .await?;
+            let passkey: u32 = reply
+                .body()
+                .deserialize()
+                .map_err(|err| MockError::Failed(err.to_string()))?;
+            if passkey != *expected {
+                return Err(MockError::AuthenticationFailed(
+                    passkey.to_string(),
+                ));
+            }
+        }
+        MockPairingRequest::DisplayPasskey(passkey) => {
+            let body = (device, *passkey, 0u16);
+            call_agent(conn, client, path, "DisplayPasskey", &body).await?;
+        }
+        MockPairingRequest::RequestConfirmation(passkey) => {
+            let body = (device, *passkey);
+            call_agent(conn, client, path, "RequestConfirmation", &body)
+                .await?;
+        }
+        MockPairingRequest::RequestAuthorization => {
+            call_agent(conn, client, path, "RequestAuthorization", &(device,))
+                .await?;
+        }
+    }
+    Ok(())
+}
+
+/// Mock BlueZ daemon serving one adapter, more on demand, and any number of
+/// devices.
+pub(crate) struct MockBluez {
+    conn: Connection,
+}
+
+impl MockBluez {
+    /// Claim `org.bluez` on `bus` and serve the object manager and adapter.
+    pub(crate) async fn start(bus: &MockBus) -> Self {
+        let conn = connection::Builder::address(bus.address.as_str())
+            .unwrap()
+            .name(BLUEZ_SERVICE)
+            .unwrap()
+            .serve_at("/", ObjectManager)
+            .unwrap()
+            .serve_at(ADAPTER_PATH, MockAdapter::default())
+            .unwrap()
+            .serve_at(ADAPTER_PATH, MockAdvertisingManager::default())
+            .unwrap()
+            .serve_at(BLUEZ_PATH, MockProfileManager::default())
+            .unwrap()
+            .serve_at(BLUEZ_PATH, MockAgentManager::default())
+            .unwrap()
+            .build()
+            .await
+            .unwrap();
+
+        MockBluez { conn }
+    }
+
+    /// Export another adapter named `name`, e.g. `hci1`, without an
+    /// advertising manager.
+    pub(crate) async fn add_adapter(
+        &self,
+        name: &str,
+        address: &str,
+    ) -> OwnedObjectPath {
+        let path = format!("{}/{}", BLUEZ_PATH, name);
+        let adapter = MockAdapter {
+            address: String::from(address),
+            ..MockAdapter::default()
+        };
+        self.conn
+            .object_server()
+            .at(path.as_str(), adapter)
+            .await
+            .unwrap();
+
+        OwnedObjectPath::try_from(path).unwrap()
+    }
+
+    /// Export a device under the adapter, emitting `InterfacesAdded`.
+    pub(crate) async fn add_device(
+        &self,
+        device: MockDevice,
+    ) -> OwnedObjectPath {
+        let adapter = OwnedObjectPath::try_from(ADAPTER_PATH).unwrap();
+        self.add_device_to(&adapter, device).await
+    }
+
+    /// Export a device under the adapter at `adapter`, emitting
+    /// `InterfacesAdded`.
+    pub(crate) async fn add_device_to(
+        &self,
+        adapter: &OwnedObjectPath,
+        mut device: MockDevice,
+    ) -> OwnedObjectPath {
+        let path = format!(
+            "{}/dev_{}",
+            adapter.as_str(),
+            device.address.replace(':', "_")
+        );
+        device.adapter = adapter.clone();
+        self.conn
+            .object_server()
+            .at(path.as_str(), device)
+            .await
+            .unwrap();
+
+        OwnedObjectPath::try_from(path).unwrap()
+    }
+
+    /// Simulate a new advertisement from the device at `path`, emitting
+    /// `PropertiesChanged` for its RSSI.
+    pub(crate) async fn advertise(&self, path: &OwnedObjectPath, rssi: i16) {
+        let iface = self
+            .conn
+            .object_server()
+            .interface::<_, MockDevice>(path)
+            .await
+            .unwrap();
+        iface.get_mut().await.rssi = rssi;
+        iface
+            .get()
+            .await
+            .r_s_s_i_changed(iface.signal_emitter())
+            .await
+            .unwrap();
+    }
+
+    /// Simulate the device at `path` being paired or unpaired by another
+    /// client, emitting `PropertiesChanged` for `Paired`.
+    pub(crate) async fn set_paired(
+        &self,
+        path: &OwnedObjectPath,
+        paired: bool,
+    ) {
+        let iface = self
+            .conn
+            .object_server()
+            .interface::<_, MockDevice>(path)
+            .await
+            .unwrap();
+        iface.get_mut().await.paired = paired;
+        iface
+            .get()
+            .await
+            .paired_changed(iface.signal_emitter())
+            .await
+            .unwrap();
+    }
+
+    /// Simulate the device at `path` connecting or disconnecting on its own,
+    /// emitting `PropertiesChanged` for `Connected`.
+    pub(crate) async fn set_connected(
+        &self,
+        path: &OwnedObjectPath,
+        connected: bool,
+    ) {
+        let iface = self
+            .conn
+            .object_server()
+            .interface::<_, MockDevice>(path)
+            .await
+            .unwrap();
+        iface.get_mut().await.connected = connected;
+        iface
+            .get()
+            .await
+            .connected_changed(iface.signal_emitter())
+            .await
+            .unwrap();
+    }
+
+    /// Simulate another client removing the device at `path`, emitting
+    /// `InterfacesRemoved`.
+    pub(crate) async fn remove_device(&self, path: &OwnedObjectPath) {
+        assert!(self
+            .conn
+            .object_server()
+            .remove::<MockDevice, _>(path)
+            .await
+            .unwrap());
+    }
+
+    /// Whether the device at `path` is still exported.
+    pub(crate) async fn has_device(&self, path: &OwnedObjectPath) -> bool {
+        self.conn
+            .object_server()
+            .interface::<_, MockDevice>(path)
+            .await
+            .is_ok()
+    }
+
+    /// Export a primary service under the device at `device`, whose
+    /// declaration is at `handle`.
+    pub(crate) async fn add_service(
+        &self,
+        device: &OwnedObjectPath,
+        uuid: &str,
+        handle: u16,
+    ) -> OwnedObjectPath {
+        let path = format!("{}/service{:04x}", device.as_str(), handle);
+        let service = MockGattService {
+            uuid: String::from(uuid),
+            device: device.clone(),
+            handle,
+        };
+        self.conn
+            .object_server()
+            .at(path.as_str(), service)
+            .await
+            .unwrap();
+
+        OwnedObjectPath::try_from(path).unwrap()
+    }
+
+    /// Export `characteristic` under the service at `service`, with its value
+    /// at `handle`.
+    pub(crate) async fn add_characteristic(
+        &self,
+        service: &OwnedObjectPath,
+        mut characteristic: MockCharacteristic,
+        handle: u16,
+    ) -> OwnedObjectPath {
+        let path = format!("{}/char{:04x}", service.as_str(), handle);
+        characteristic.service = service.clone();
+        characteristic.handle = handle;
+        self.conn
+            .object_server()
+            .at(path.as_str(), characteristic)
+            .await
+            .unwrap();
+
+        // Services are exported right under their device.
+        let device = service.as_str().rsplit_once('/').unwrap().0;
+        let iface = self
+            .conn
+            .object_server()
+            .interface::<_, MockDevice>(device)
+            .await
+            .unwrap();
+        let path = OwnedObjectPath::try_from(path).unwrap();
+        iface.get_mut().await.characteristics.push(path.clone());
+
+        path
+    }
+
+    /// Current state of the characteristic at `path`.
+    pub(crate) async fn characteristic(
+        &self,
+        path: &OwnedObjectPath,
+    ) -> MockCharacteristic {
+        let iface = self
+            .conn
+            .object_server()
+            .interface::<_, MockCharacteristic>(path)
+            .await
+            .unwrap();
+        let characteristic = iface.get().await.clone();
+        characteristic
+    }
+
+    /// Simulate a notification from the characteristic at `path`, emitting
+    /// `PropertiesChanged` for its value.
+    pub(crate) async fn notify(&self, path: &OwnedObjectPath, value: &[u8]) {
+        let iface = self
+            .conn
+            .object_server()
+            .interface::<_, MockCharacteristic>(path)
+            .await
+            .unwrap();
+        iface.get_mut().await.value = value.to_vec();
+        iface
+            .get()
+            .await
+            .value_changed(iface.signal_emitter())
+            .await
+            .unwrap();
+    }
+
+    /// UUIDs of the profiles currently registered by clients.
+    pub(crate) async fn profiles(&self) -> Vec<String> {
+        let iface = self
+            .conn
+            .object_server()
+            .interface::<_, MockProfileManager>(BLUEZ_PATH)
+            .await
+            .unwrap();
+        let profiles = iface.get().await.profiles.values().cloned().collect();
+        profiles
+    }
+
+    /// Take the remote end of the oldest socket handed to a profile for
+    /// `uuid`.
+    pub(crate) async fn take_socket(&self, uuid: &str) -> UnixStream {
+        let iface = self
+            .conn
+            .object_server()
+            .interface::<_, MockProfileManager>(BLUEZ_PATH)
+            .await
+            .unwrap();
+        let mut manager = iface.get_mut().await;
+        let index = manager
+            .sockets
+            .iter()
+            .position(|(socket_uuid, _)| socket_uuid == uuid)
+            .unwrap();
+        manager.sockets.remove(index).1
+    }
+
+    /// Whether a client has started discovery on the adapter.
+    pub(crate) async fn discovering(&self) -> bool {
+        let iface = self
+            .conn
+            .object_server()
+            .interface::<_, MockAdapter>(ADAPTER_PATH)
+            .await
+            .unwrap();
+        let discovering = iface.get().await.discovering;
+        discovering
+    }
+
+    /// Keys of the discovery filter last set on the adapter.
+    pub(crate) async fn discovery_filter(&self) -> Vec<String> {
+        let iface = self
+            .conn
+            .object_server()
+            .interface::<_, MockAdapter>(ADAPTER_PATH)
+            .await
+            .unwrap();
+        let filter = iface.get().await.discovery_filter.clone();
+        filter
+    }
+
+    /// Properties of the advertisements currently registered by clients.
+    pub(crate) async fn advertisements(
+        &self,
+    ) -> Vec<HashMap<String, OwnedValue>> {
+        let iface = self
+            .conn
+            .object_server()
+            .interface::<_, MockAdvertisingManager>(ADAPTER_PATH)
+            .await
+            .unwrap();
+        let advertisements =
+            iface.get().await.advertisements.values().cloned().collect();
+        advertisements
+    }
+
+    /// Switch the adapter on or off, emitting `PropertiesChanged`. Like BlueZ,
+    /// switching it off stops discovery.
+    pub(crate) async fn set_powered(&self, powered: bool) {
+        let iface = self
+            .conn
+            .object_server()
+            .interface::<_, MockAdapter>(ADAPTER_PATH)
+            .await
+            .unwrap();
+        let mut adapter = iface.get_mut().await;
+        adapter.powered = powered;
+        if !powered {
+            adapter.discovering = false;
+        }
+        adapter
+            .powered_changed(iface.signal_emitter())
+            .await
+            .unwrap();
+        adapter
+            .discovering_changed(iface.signal_emitter())
+            .await
+            .unwrap();
+    }
+
+    /// Drop every registered advertisement, calling `Release()` on them like
+    /// BlueZ does when the adapter is powered off.
+    pub(crate) async fn release_advertisements(&self) {
+        let iface = self
+            .conn
+            .object_server()
+            .interface::<_, MockAdvertisingManager>(ADAPTER_PATH)
+            .await
+            .unwrap();
+        let advertisements =
+            std::mem::take(&mut iface.get_mut().await.advertisements);
+
+        for (client, path) in advertisements.into_keys() {
+            self.conn
+                .call_method(
+                    Some(client.as_str()),
+                    &path,
+                    Some(ADVERTISEMENT_INTERFACE),
+                    "Release",
+                    &(),
+                )
+                .await
+                .unwrap();
+        }
+    }
+}
@@ -0,0 +1,32 @@
+// Copyright 2023 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+/// Bluetooth LE module for Linux devices, backed by BlueZ's D-Bus API.
+mod adapter;
+mod address;
+mod advertisement;
+mod advertiser;
+mod agent;
+mod bluez;
+mod bond;
+mod device;
+mod error;
+mod gatt;
+#[cfg(test)]
+mod mock;
+mod rfcomm;
+
+pub use adapter::*;
+pub use advertiser::*;
+pub use device::*;
@@ -0,0 +1,239 @@
+// Copyright 2023 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::{
+    collections::HashMap,
+    io,
+    os::unix::net::UnixStream,
+    pin::Pin,
+    sync::atomic::{AtomicUsize, Ordering},
+    task::{Context, Poll},
+};
+
+use async_io::Async;
+use futures::{
+    channel::mpsc::{self, UnboundedSender},
+    AsyncRead, AsyncWrite, StreamExt,
+};
+use tracing::{info, warn};
+use zbus::{
+    blocking, interface,
+    zvariant::{self, OwnedObjectPath, OwnedValue, Value},
+    DBusError,
+};
+
+use super::bluez::{
+    Device1Proxy, ProfileManager1Proxy, ProfileManager1ProxyBlocking,
+};
+use crate::common::{BluetoothError, BtUuid, RfcommSocket};
+
+/// Prefix of the object paths profiles are exported at. Every connection
+/// registers its own profile, so each gets a new number.
+const PROFILE_PATH_PREFIX: &str = "/com/google/nearby/profile";
+
+/// Number of the next exported profile.
+static NEXT_PROFILE: AtomicUsize = AtomicUsize::new(0);
+
+/// Errors replied to BlueZ by `RfcommProfile`.
+#[derive(Debug, DBusError)]
+#[zbus(prefix = "org.bluez.Error")]
+enum ProfileError {
+    Rejected(String),
+}
+
+/// `org.bluez.Profile1` object exported for BlueZ to hand the RFCOMM
+/// connection to one device over.
+/// https://git.kernel.org/pub/scm/bluetooth/bluez.git/tree/doc/org.bluez.Profile.rst
+struct RfcommProfile {
+    device: OwnedObjectPath,
+    sockets: UnboundedSender<std::os::fd::OwnedFd>,
+}
+
+#[interface(name = "org.bluez.Profile1")]
+impl RfcommProfile {
+    /// Called by BlueZ once it unregistered the profile on its own.
+    fn release(&self) {
+        self.sockets.close_channel();
+    }
+
+    fn new_connection(
+        &self,
+        device: OwnedObjectPath,
+        fd: zvariant::OwnedFd,
+        _properties: HashMap<String, OwnedValue>,
+    ) -> Result<(), ProfileError> {
+        if device != self.device {
+            return Err(ProfileError::Rejected(format!(
+                "unexpected device {}",
+                device.as_str()
+            )));
+        }
+
+        self.sockets
+            .unbounded_send(fd.into())
+            .map_err(|_| ProfileError::Rejected(String::from("not waiting")))
+    }
+
+    /// The socket is closed by dropping it, so there is nothing to do here.
+    fn request_disconnection(&self, _device: OwnedObjectPath) {}
+}
+
+/// Handle on a registered profile, unregistering it when dropped.
+struct Registration {
+    conn: blocking::Connection,
+    manager: ProfileManager1ProxyBlocking<'static>,
+    path: OwnedObjectPath,
+    registered: bool,
+}
+
+impl Drop for Registration {
+    fn drop(&mut self) {
+        if self.registered {
+            match self.manager.unregister_profile(&self.path) {
+                Ok(()) => info!("Unregistered BlueZ profile {}.", *self.path),
+                Err(err) => {
+                    warn!("Failed to unregister BlueZ profile: {}", err)
+                }
+            }
+        }
+
+        if let Err(err) = self
+            .conn
+            .object_server()
+            .remove::<RfcommProfile, _>(&self.path)
+        {
+            warn!("Failed to remove profile object: {}", err);
+        }
+    }
+}
+
+/// RFCOMM socket BlueZ handed over, keeping its profile registered while
+/// open. The socket is closed before the profile is unregistered.
+struct RfcommStream {
+    stream: Async<UnixStream>,
+    _registration: Registration,
+}
+
+impl AsyncRead for RfcommStream {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut [u8],
+    ) -> Poll<io::Result<usize>> {
+        Pin::new(&mut self.stream).poll_read(cx, buf)
+    }
+}
+
+impl AsyncWrite for RfcommStream {
+    fn poll_write(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        Pin::new(&mut self.stream).poll_write(cx, buf)
+    }
+
+    fn poll_flush(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.stream).poll_flush(cx)
+    }
+
+    fn poll_close(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.stream).poll_close(cx)
+    }
+}
+
+/// Open an RFCOMM channel to the service identified by `uuid` on `device`.
+/// BlueZ only hands RFCOMM sockets to external profiles, so this registers a
+/// client profile for the service and asks BlueZ to connect it.
+pub(crate) async fn connect_rfcomm(
+    device: &Device1Proxy<'static>,
+    uuid: BtUuid,
+) -> Result<RfcommSocket, BluetoothError> {
+    let conn = device.inner().connection();
+    let path = OwnedObjectPath::try_from(format!(
+        "{}{}",
+        PROFILE_PATH_PREFIX,
+        NEXT_PROFILE.fetch_add(1, Ordering::Relaxed)
+    ))?;
+    let (sender, mut sockets) = mpsc::unbounded();
+    conn.object_server()
+        .at(
+            &path,
+            RfcommProfile {
+                device: OwnedObjectPath::from(device.inner().path().to_owned()),
+                sockets: sender,
+            },
+        )
+        .await?;
+
+    // Unregisters the profile and removes the object on failure.
+    let manager = ProfileManager1Proxy::new(conn).await?;
+    let mut registration = Registration {
+        conn: blocking::Connection::from(conn.clone()),
+        manager: ProfileManager1ProxyBlocking::from(
+            manager.clone().into_inner(),
+        ),
+        path,
+        registered: false,
+    };
+    let uuid = uuid.to_string();
+    let options = HashMap::from([
+        ("Role", Value::from("client")),
+        ("AutoConnect", Value::from(false)),
+    ]);
+    manager
+        .register_profile(&registration.path, &uuid, options)
+        .await?;
+    registration.registered = true;
+
+    device
+        .connect_profile(&uuid)
+        .await
+        .map_err(|err| connect_error(err, &uuid))?;
+    let fd =
+        sockets
+            .next()
+            .await
+            .ok_or(BluetoothError::Internal(String::from(
+                "BlueZ released the profile before connecting.",
+            )))?;
+    let stream = Async::new(UnixStream::from(fd))?;
+
+    info!("Connected RFCOMM service {}.", uuid);
+    Ok(RfcommSocket::new(RfcommStream {
+        stream,
+        _registration: registration,
+    }))
+}
+
+/// Convert the reply of `ConnectProfile()` into a library error.
+fn connect_error(err: zbus::Error, uuid: &str) -> BluetoothError {
+    match &err {
+        zbus::Error::MethodError(name, _, _)
+            if name.as_str() == "org.bluez.Error.NotAvailable" =>
+        {
+            BluetoothError::NotSupported(format!(
+                "device doesn't serve RFCOMM service {}",
+                uuid
+            ))
+        }
+        _ => BluetoothError::from(err),
+    }
+}
@@ -15,40 +15,1052 @@
 // Specification: https://developers.google.com/nearby/fast-pair/specifications/extensions/messagestream
 // This file should be in sync with fastpair/message_stream/message_stream.h.
 
-use crate::types::packets::{MessageGroup, MessageStreamPacket};
+use std::{
+    collections::VecDeque,
+    future::poll_fn,
+    io,
+    pin::Pin,
+    task::{Context, Poll},
+    time::Duration,
+};
 
-struct BatteryInfo {
+use futures::{ready, AsyncRead, AsyncWrite, Stream};
+use tracing::{info, warn};
+
+use crate::{
+    common::{BleAddress, BleAddressKind, BluetoothError},
+    types::packets::{
+        AcknowledgementCode, BluetoothCode, CompanionAppEventCode,
+        DeviceActionEventCode, DeviceInformationEventCode, MessageGroup,
+        MessageStreamDecoder, MessageStreamPacketRef, SassCode,
+    },
+    types::sass::{Nonce, SassMessage, NONCE_LEN},
+};
+
+const COMPANION_INSTALLED_BIT: u8 = 0x02;
+const SUPPORTS_SILENCE_BIT: u8 = 0x01;
+/// The default active components response.
+const DEFAULT_COMPONENTS: u8 = 0;
+const MODEL_ID_SIZE: usize = 3;
+const BLE_ADDRESS_SIZE: usize = 6;
+/// We don't accept more than `MAX_BATTERY_LEVELS` battery values from the
+/// provider.
+const MAX_BATTERY_LEVELS: usize = 3;
+const UNKNOWN_BATTERY_LEVEL: u8 = 0x7F;
+/// Size of the buffer bytes are read from the transport into.
+const READ_BUFFER_SIZE: usize = 1024;
+
+/// Battery state of one component of the provider.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct BatteryInfo {
     is_charging: bool,
+    level: Option<u8>,
+}
+
+impl BatteryInfo {
+    /// The highest bit represents `is_charging`, the rest the battery level
+    /// (in %).
+    fn from_raw(value: u8) -> Self {
+        let level = value & 0x7F;
+        BatteryInfo {
+            is_charging: value & 0x80 != 0,
+            level: (level != UNKNOWN_BATTERY_LEVEL).then_some(level),
+        }
+    }
+
+    pub fn is_charging(&self) -> bool {
+        self.is_charging
+    }
+
+    /// Retrieve the battery level in percent, if the provider knows it.
+    pub fn level(&self) -> Option<u8> {
+        self.level
+    }
+}
+
+/// Battery state of the components of the provider, as sent in a Battery
+/// Updated message. Components the provider didn't report are `None`.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub struct BatteryLevels {
+    left: Option<BatteryInfo>,
+    right: Option<BatteryInfo>,
+    case: Option<BatteryInfo>,
+}
+
+impl BatteryLevels {
+    pub fn left(&self) -> Option<BatteryInfo> {
+        self.left
+    }
+
+    pub fn right(&self) -> Option<BatteryInfo> {
+        self.right
+    }
+
+    pub fn case(&self) -> Option<BatteryInfo> {
+        self.case
+    }
+}
+
+/// Capabilities of the seeker, sent to the provider with
+/// `MessageStream::send_capabilities()`.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub struct Capabilities {
+    companion_app_installed: bool,
+    supports_silence_mode: bool,
+}
+
+impl Capabilities {
+    pub fn new(
+        companion_app_installed: bool,
+        supports_silence_mode: bool,
+    ) -> Self {
+        Capabilities {
+            companion_app_installed,
+            supports_silence_mode,
+        }
+    }
+
+    pub fn companion_app_installed(&self) -> bool {
+        self.companion_app_installed
+    }
+
+    pub fn supports_silence_mode(&self) -> bool {
+        self.supports_silence_mode
+    }
+
+    fn bits(&self) -> u8 {
+        let mut bits = 0;
+        if self.companion_app_installed {
+            bits |= COMPANION_INSTALLED_BIT;
+        }
+        if self.supports_silence_mode {
+            bits |= SUPPORTS_SILENCE_BIT;
+        }
+        bits
+    }
+}
+
+/// Operating system of the seeker, sent to the provider with
+/// `MessageStream::send_platform_type()`.
+/// This enum should be in sync with `api::DeviceInfo::OsType`.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum PlatformType {
+    Unknown = 0,
+    Android = 1,
+    ChromeOs = 2,
+    Ios = 3,
+    Windows = 4,
+    MacOs = 5,
+}
+
+/// What the session learned about the provider, and told it about the
+/// seeker.
+#[derive(Clone, PartialEq, Debug, Default)]
+pub struct DeviceState {
+    model_id: Option<u32>,
+    ble_address: Option<BleAddress>,
+    battery_levels: Option<BatteryLevels>,
+    remaining_battery_time: Option<Duration>,
+    silence_mode: Option<bool>,
+    capabilities: Option<Capabilities>,
+    platform_type: Option<(PlatformType, u8)>,
+    session_nonce: Option<Nonce>,
+}
+
+impl DeviceState {
+    /// Retrieve the 24-bit Fast Pair model ID of the provider.
+    pub fn model_id(&self) -> Option<u32> {
+        self.model_id
+    }
+
+    /// Retrieve the latest BLE address of the provider, which rotates it.
+    pub fn ble_address(&self) -> Option<BleAddress> {
+        self.ble_address
+    }
+
+    pub fn battery_levels(&self) -> Option<BatteryLevels> {
+        self.battery_levels
+    }
+
+    pub fn remaining_battery_time(&self) -> Option<Duration> {
+        self.remaining_battery_time
+    }
+
+    /// Whether the provider asked the seeker to enable silence mode.
+    pub fn silence_mode(&self) -> Option<bool> {
+        self.silence_mode
+    }
+
+    /// Retrieve the capabilities sent to the provider.
+    pub fn capabilities(&self) -> Option<Capabilities> {
+        self.capabilities
+    }
+
+    /// Retrieve the platform type and custom code sent to the provider.
+    pub fn platform_type(&self) -> Option<(PlatformType, u8)> {
+        self.platform_type
+    }
+
+    /// Retrieve the nonce the provider sent when the session started, which
+    /// SASS messages are authenticated and encrypted with.
+    pub fn session_nonce(&self) -> Option<Nonce> {
+        self.session_nonce
+    }
+}
+
+/// Events received from the provider, in the order they were received.
+#[derive(Clone, PartialEq, Debug)]
+pub enum MessageStreamEvent {
+    SilenceModeChanged(bool),
+    LogBufferFull,
+    ModelId(u32),
+    BleAddressUpdated(BleAddress),
+    BatteryUpdated(BatteryLevels),
+    RemainingBatteryTime(Duration),
+    /// The provider asked the seeker to ring. `acknowledged` is the answer
+    /// of the ring handler, which was sent to the provider as an ACK or NACK.
+    Ring {
+        components: u8,
+        duration: Duration,
+        acknowledged: bool,
+    },
+    /// A SASS message that isn't the answer to a request of this session,
+    /// e.g. a connection status the provider notifies on its own.
+    Sass(SassMessage),
+}
+
+/// Decides whether the seeker rings for the given components and duration.
+type RingHandler = Box<dyn FnMut(u8, Duration) -> bool + Send>;
+
+/// State of a session, apart from the transport and decoder so that packets
+/// borrowed from the decoder can be handled.
+#[derive(Default)]
+struct Session {
+    state: DeviceState,
+    events: VecDeque<MessageStreamEvent>,
+    /// Bytes waiting to be written to the transport, e.g. ACKs.
+    outgoing: Vec<u8>,
+    /// Messages waiting for ACK/NACK, with the result once received.
+    waiting_for_ack: Vec<(MessageGroup, Option<bool>)>,
+    /// Response to the pending active components request, once received.
+    active_components: Option<u8>,
+    /// SASS responses waiting to be received, with the response once
+    /// received.
+    waiting_for_sass: Vec<(SassCode, Option<SassMessage>)>,
+    ring_handler: Option<RingHandler>,
+}
+
+/// A Message Stream session with a provider, over any byte transport, e.g.
+/// an `RfcommSocket` connected to `BtUuid::MESSAGE_STREAM`.
+///
+/// Polling the session as a `Stream` reads messages from the provider,
+/// updates its `DeviceState`, answers the messages that need an ACK or NACK,
+/// and yields the resulting events. Requests waiting for an answer from the
+/// provider keep the events received meanwhile for the stream.
+pub struct MessageStream<T> {
+    transport: T,
+    decoder: MessageStreamDecoder,
+    read_buffer: Vec<u8>,
+    /// Set once the transport reached its end.
+    closed: bool,
+    session: Session,
+}
+
+impl Session {
+    /// Queue a packet to be written to the transport.
+    fn queue(
+        &mut self,
+        group: MessageGroup,
+        additional_data: &[u8],
+    ) -> Result<(), BluetoothError> {
+        MessageStreamPacketRef {
+            group,
+            additional_data,
+        }
+        .encode_into(&mut self.outgoing)
+    }
+
+    /// Take the SASS message with `code`, if received.
+    fn take_sass(&mut self, code: SassCode) -> Option<SassMessage> {
+        let index =
+            self.waiting_for_sass
+                .iter()
+                .position(|(waiting, message)| {
+                    *waiting == code && message.is_some()
+                })?;
+        self.waiting_for_sass.remove(index).1
+    }
+
+    /// Take the result of the ACK/NACK for `group`, if received.
+    fn take_acknowledgement(&mut self, group: MessageGroup) -> Option<bool> {
+        let index =
+            self.waiting_for_ack.iter().position(|(waiting, result)| {
+                *waiting == group && result.is_some()
+            })?;
+        self.waiting_for_ack.remove(index).1
+    }
+
+    fn handle(&mut self, packet: MessageStreamPacketRef<'_>) {
+        info!("Received Message Stream packet {:?}.", packet);
+        let data = packet.additional_data;
+        let handled = match packet.group {
+            MessageGroup::Acknowledgement(code) => {
+                self.handle_acknowledgement(code, data)
+            }
+            MessageGroup::Bluetooth(code) => self.handle_bluetooth(code),
+            MessageGroup::CompanionAppEvent(code) => {
+                self.handle_companion_app_event(code)
+            }
+            MessageGroup::DeviceInformationEvent(code) => {
+                self.handle_device_information_event(code, data)
+            }
+            MessageGroup::DeviceActionEvent(code) => {
+                self.handle_device_action_event(packet.group, code, data)
+            }
+            MessageGroup::Sass(code) => self.handle_sass(code, data),
+            _ => false,
+        };
+
+        if !handled {
+            info!("Unrecognized Message Stream packet {:?}.", packet);
+        }
+    }
+
+    fn handle_device_information_event(
+        &mut self,
+        code: DeviceInformationEventCode,
+        data: &[u8],
+    ) -> bool {
+        match code {
+            DeviceInformationEventCode::ModelId => {
+                if data.len() != MODEL_ID_SIZE {
+                    warn!(
+                        "Model id event size should be {} but is {}",
+                        MODEL_ID_SIZE,
+                        data.len()
+                    );
+                    return false;
+                }
+                let model_id =
+                    u32::from_be_bytes([0, data[0], data[1], data[2]]);
+                self.state.model_id = Some(model_id);
+                self.events.push_back(MessageStreamEvent::ModelId(model_id));
+                true
+            }
+            DeviceInformationEventCode::BleAddressUpdated => {
+                let Ok(address) = <[u8; BLE_ADDRESS_SIZE]>::try_from(data)
+                else {
+                    warn!(
+                        "BLE address updated event size should be {} but is {}",
+                        BLE_ADDRESS_SIZE,
+                        data.len()
+                    );
+                    return false;
+                };
+                let mut bytes = [0; 8];
+                bytes[2..].copy_from_slice(&address);
+                let address = BleAddress::new(
+                    u64::from_be_bytes(bytes),
+                    BleAddressKind::Random,
+                );
+                self.state.ble_address = Some(address);
+                self.events
+                    .push_back(MessageStreamEvent::BleAddressUpdated(address));
+                true
+            }
+            DeviceInformationEventCode::BatteryUpdated => {
+                if data.len() > MAX_BATTERY_LEVELS {
+                    warn!(
+                        "Battery level event size should be <= {} but is {}",
+                        MAX_BATTERY_LEVELS,
+                        data.len()
+                    );
+                    return false;
+                }
+                let level =
+                    |i: usize| data.get(i).copied().map(BatteryInfo::from_raw);
+                let levels = BatteryLevels {
+                    left: level(0),
+                    right: level(1),
+                    case: level(2),
+                };
+                self.state.battery_levels = Some(levels);
+                self.events
+                    .push_back(MessageStreamEvent::BatteryUpdated(levels));
+                true
+            }
+            DeviceInformationEventCode::RemainingBattery => {
+                let minutes = match *data {
+                    [minutes] => u64::from(minutes),
+                    [high, low] => u64::from(u16::from_be_bytes([high, low])),
+                    _ => {
+                        warn!(
+                            "Remaining battery event size should be 1 or 2 \
+                            bytes but is {}",
+                            data.len()
+                        );
+                        return false;
+                    }
+                };
+                let time = Duration::from_secs(minutes * 60);
+                self.state.remaining_battery_time = Some(time);
+                self.events
+                    .push_back(MessageStreamEvent::RemainingBatteryTime(time));
+                true
+            }
+            DeviceInformationEventCode::SessionNonce => {
+                let Ok(nonce) = Nonce::try_from(data) else {
+                    warn!(
+                        "Session nonce event size should be {} but is {}",
+                        NONCE_LEN,
+                        data.len()
+                    );
+                    return false;
+                };
+                self.state.session_nonce = Some(nonce);
+                true
+            }
+            DeviceInformationEventCode::ActiveComponentsResponse => {
+                let components = match *data {
+                    [components] => components,
+                    _ => DEFAULT_COMPONENTS,
+                };
+                self.active_components = Some(components);
+                true
+            }
+            _ => false,
+        }
+    }
+
+    fn handle_acknowledgement(
+        &mut self,
+        code: AcknowledgementCode,
+        data: &[u8],
+    ) -> bool {
+        let result = match code {
+            AcknowledgementCode::Ack => true,
+            AcknowledgementCode::Nack => false,
+            AcknowledgementCode::Unknown(_) => return false,
+        };
+
+        // The additional data of ACK/NACK messages holds the group and code of
+        // the original message, preceded by the fail reason for NACKs.
+        let (group, code) = match *data {
+            [group, code] => (group, code),
+            [reason, group, code] if !result => {
+                info!("NACK fail reason: {}", reason);
+                (group, code)
+            }
+            _ => {
+                info!("ACK/NACK malformed: {:?}", data);
+                return false;
+            }
+        };
+        let group = MessageGroup::from_raw(group, code);
+        info!("Finish call {:?} with result: {}", group, result);
+        if let Some((_, pending)) = self
+            .waiting_for_ack
+            .iter_mut()
+            .find(|(waiting, pending)| *waiting == group && pending.is_none())
+        {
+            *pending = Some(result);
+        }
+        true
+    }
+
+    fn handle_bluetooth(&mut self, code: BluetoothCode) -> bool {
+        let enable = match code {
+            BluetoothCode::EnableSilenceMode => true,
+            BluetoothCode::DisableSilenceMode => false,
+            BluetoothCode::Unknown(_) => return false,
+        };
+
+        self.state.silence_mode = Some(enable);
+        self.events
+            .push_back(MessageStreamEvent::SilenceModeChanged(enable));
+        true
+    }
+
+    fn handle_companion_app_event(
+        &mut self,
+        code: CompanionAppEventCode,
+    ) -> bool {
+        match code {
+            CompanionAppEventCode::LogBufferFull => {
+                self.events.push_back(MessageStreamEvent::LogBufferFull);
+                true
+            }
+            CompanionAppEventCode::Unknown(_) => false,
+        }
+    }
+
+    fn handle_device_action_event(
+        &mut self,
+        group: MessageGroup,
+        code: DeviceActionEventCode,
+        data: &[u8],
+    ) -> bool {
+        match code {
+            DeviceActionEventCode::Ring => {
+                let components = data.first().copied().unwrap_or(0);
+                let minutes = data.get(1).copied().unwrap_or(0);
+                let duration = Duration::from_secs(u64::from(minutes) * 60);
+                let acknowledged = match &mut self.ring_handler {
+                    Some(handler) => handler(components, duration),
+                    None => false,
+                };
+
+                self.send_acknowledgement(group, acknowledged);
+                self.events.push_back(MessageStreamEvent::Ring {
+                    components,
+                    duration,
+                    acknowledged,
+                });
+                true
+            }
+            DeviceActionEventCode::Unknown(_) => false,
+        }
+    }
+
+    fn handle_sass(&mut self, code: SassCode, data: &[u8]) -> bool {
+        let Some(message) = SassMessage::decode(code, data) else {
+            return false;
+        };
+
+        match self
+            .waiting_for_sass
+            .iter_mut()
+            .find(|(waiting, pending)| *waiting == code && pending.is_none())
+        {
+            Some((_, pending)) => *pending = Some(message),
+            None => self.events.push_back(MessageStreamEvent::Sass(message)),
+        }
+        true
+    }
+
+    fn send_acknowledgement(&mut self, group: MessageGroup, ack: bool) {
+        let code = match ack {
+            true => AcknowledgementCode::Ack,
+            false => AcknowledgementCode::Nack,
+        };
+        self.queue(
+            MessageGroup::Acknowledgement(code),
+            &[group.group(), group.code()],
+        )
+        .expect("Sanity check, ACK/NACK packets are 6 bytes long");
+    }
 }
 
-pub struct MessageStream {
-    battery_info: BatteryInfo,
+impl<T: AsyncRead + AsyncWrite + Unpin> MessageStream<T> {
+    /// Start a session over `transport`, connected to the provider.
+    pub fn new(transport: T) -> Self {
+        MessageStream {
+            transport,
+            decoder: MessageStreamDecoder::new(),
+            read_buffer: vec![0; READ_BUFFER_SIZE],
+            closed: false,
+            session: Session::default(),
+        }
+    }
+
+    /// Retrieve what the session knows about the provider so far.
+    pub fn state(&self) -> &DeviceState {
+        &self.session.state
+    }
+
+    /// Set the handler deciding whether to ring when the provider asks the
+    /// seeker to. Without a handler, ring requests are NACKed.
+    pub fn set_ring_handler(
+        &mut self,
+        handler: impl FnMut(u8, Duration) -> bool + Send + 'static,
+    ) {
+        self.session.ring_handler = Some(Box::new(handler));
+    }
+
+    /// Tell the provider about the capabilities of the seeker.
+    pub async fn send_capabilities(
+        &mut self,
+        capabilities: Capabilities,
+    ) -> Result<(), BluetoothError> {
+        self.send(
+            MessageGroup::DeviceInformationEvent(
+                DeviceInformationEventCode::Capabilities,
+            ),
+            &[capabilities.bits()],
+        )
+        .await?;
+        self.session.state.capabilities = Some(capabilities);
+        Ok(())
+    }
+
+    /// Tell the provider about the platform of the seeker, e.g. the Android
+    /// SDK version as `custom_code`.
+    pub async fn send_platform_type(
+        &mut self,
+        platform_type: PlatformType,
+        custom_code: u8,
+    ) -> Result<(), BluetoothError> {
+        self.send(
+            MessageGroup::DeviceInformationEvent(
+                DeviceInformationEventCode::PlatformType,
+            ),
+            &[platform_type as u8, custom_code],
+        )
+        .await?;
+        self.session.state.platform_type = Some((platform_type, custom_code));
+        Ok(())
+    }
+
+    /// Ask the provider for its active components, returning their bitmap.
+    /// There is no timeout, so callers should race this with a timer.
+    pub async fn get_active_components(
+        &mut self,
+    ) -> Result<u8, BluetoothError> {
+        self.session.active_components = None;
+        self.send(
+            MessageGroup::DeviceInformationEvent(
+                DeviceInformationEventCode::ActiveComponentsRequest,
+            ),
+            &[],
+        )
+        .await?;
+
+        self.receive_until(|session| session.active_components.take())
+            .await
+    }
+
+    /// Ask the provider to ring `components` for `duration`, rounded down to
+    /// minutes. Returns whether the provider replied with an ACK. There is
+    /// no timeout, so callers should race this with a timer.
+    pub async fn ring(
+        &mut self,
+        components: u8,
+        duration: Duration,
+    ) -> Result<bool, BluetoothError> {
+        let group =
+            MessageGroup::DeviceActionEvent(DeviceActionEventCode::Ring);
+        let mut data = vec![components];
+        let minutes = u8::try_from(duration.as_secs() / 60).unwrap_or(u8::MAX);
+        if minutes != 0 {
+            data.push(minutes);
+        }
+
+        self.request_acknowledgement(group, &data).await
+    }
+
+    /// Send a message the provider answers with an ACK or NACK, returning
+    /// whether it was an ACK.
+    pub(crate) async fn request_acknowledgement(
+        &mut self,
+        group: MessageGroup,
+        additional_data: &[u8],
+    ) -> Result<bool, BluetoothError> {
+        // An earlier request that was given up on won't be answered anymore.
+        self.session
+            .waiting_for_ack
+            .retain(|(waiting, _)| *waiting != group);
+        self.session.waiting_for_ack.push((group, None));
+        self.send(group, additional_data).await?;
+
+        self.receive_until(|session| session.take_acknowledgement(group))
+            .await
+    }
+
+    /// Send a SASS request, returning the SASS message with code `response`
+    /// the provider answers with.
+    pub(crate) async fn request_sass(
+        &mut self,
+        request: SassMessage,
+        response: SassCode,
+    ) -> Result<SassMessage, BluetoothError> {
+        self.session
+            .waiting_for_sass
+            .retain(|(waiting, _)| *waiting != response);
+        self.session.waiting_for_sass.push((response, None));
+        self.send(MessageGroup::Sass(request.code()), &request.encode())
+            .await?;
+
+        self.receive_until(|session| session.take_sass(response))
+            .await
+    }
+
+    /// Close the transport, after writing the messages waiting to be sent.
+    pub async fn close(&mut self) -> Result<(), BluetoothError> {
+        poll_fn(|cx| self.poll_flush_outgoing(cx)).await?;
+        poll_fn(|cx| Pin::new(&mut self.transport).poll_close(cx)).await?;
+        Ok(())
+    }
+
+    async fn send(
+        &mut self,
+        group: MessageGroup,
+        additional_data: &[u8],
+    ) -> Result<(), BluetoothError> {
+        info!("Sending Message Stream packet {:?}.", group);
+        self.session.queue(group, additional_data)?;
+        poll_fn(|cx| self.poll_flush_outgoing(cx)).await
+    }
+
+    /// Handle packets from the provider until `done` returns a result.
+    async fn receive_until<R>(
+        &mut self,
+        mut done: impl FnMut(&mut Session) -> Option<R>,
+    ) -> Result<R, BluetoothError> {
+        poll_fn(|cx| loop {
+            if let Poll::Ready(Err(err)) = self.poll_flush_outgoing(cx) {
+                return Poll::Ready(Err(err));
+            }
+            if let Some(result) = done(&mut self.session) {
+                return Poll::Ready(Ok(result));
+            }
+
+            match ready!(self.poll_receive(cx)) {
+                Ok(true) => continue,
+                Ok(false) => {
+                    return Poll::Ready(Err(
+                        BluetoothError::FailedPrecondition(String::from(
+                            "Message Stream closed before the provider replied",
+                        )),
+                    ))
+                }
+                Err(err) => return Poll::Ready(Err(err)),
+            }
+        })
+        .await
+    }
+
+    /// Write the bytes waiting to be sent to the transport.
+    fn poll_flush_outgoing(
+        &mut self,
+        cx: &mut Context<'_>,
+    ) -> Poll<Result<(), BluetoothError>> {
+        while !self.session.outgoing.is_empty() {
+            let len = ready!(Pin::new(&mut self.transport)
+                .poll_write(cx, &self.session.outgoing))?;
+            if len == 0 {
+                return Poll::Ready(Err(io::Error::from(
+                    io::ErrorKind::WriteZero,
+                )
+                .into()));
+            }
+            self.session.outgoing.drain(..len);
+        }
+
+        Poll::Ready(Ok(ready!(Pin::new(&mut self.transport).poll_flush(cx))?))
+    }
+
+    /// Handle the next packet from the provider, reading from the transport
+    /// if no complete packet is buffered. Returns `false` once the transport
+    /// reached its end.
+    fn poll_receive(
+        &mut self,
+        cx: &mut Context<'_>,
+    ) -> Poll<Result<bool, BluetoothError>> {
+        loop {
+            if let Some(packet) = self.decoder.next_packet() {
+                self.session.handle(packet);
+                return Poll::Ready(Ok(true));
+            }
+            if self.closed {
+                return Poll::Ready(Ok(false));
+            }
+
+            let len = ready!(Pin::new(&mut self.transport)
+                .poll_read(cx, &mut self.read_buffer))?;
+            if len == 0 {
+                if self.decoder.pending_len() > 0 {
+                    warn!(
+                        "Message Stream closed within a packet, dropping {} \
+                        bytes.",
+                        self.decoder.pending_len()
+                    );
+                }
+                self.closed = true;
+            }
+            self.decoder.push(&self.read_buffer[..len]);
+        }
+    }
 }
 
-impl MessageStream {
-    pub fn on_received(&mut self, packet: MessageStreamPacket) {
-        match packet.group {
-            MessageGroup::Bluetooth(b) => println!("It's Bluetooth {:?}", b),
-            MessageGroup::CompanionAppEvent(c) => println!("It's CompanionAppEvent :{:?}", c),
-            _ => println!("whatever else"),
+impl<T: AsyncRead + AsyncWrite + Unpin> Stream for MessageStream<T> {
+    type Item = Result<MessageStreamEvent, BluetoothError>;
+
+    fn poll_next(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Self::Item>> {
+        let this = self.get_mut();
+        loop {
+            // ACKs queued while handling packets are sent first, but don't
+            // hold back events.
+            if let Poll::Ready(Err(err)) = this.poll_flush_outgoing(cx) {
+                return Poll::Ready(Some(Err(err)));
+            }
+            if let Some(event) = this.session.events.pop_front() {
+                return Poll::Ready(Some(Ok(event)));
+            }
+
+            match ready!(this.poll_receive(cx)) {
+                Ok(true) => continue,
+                Ok(false) => return Poll::Ready(None),
+                Err(err) => return Poll::Ready(Some(Err(err))),
+            }
         }
     }
 }
 
 #[cfg(test)]
 mod tests {
+    use futures::{
+        executor::block_on, join, AsyncReadExt, AsyncWriteExt, StreamExt,
+    };
+
     use super::*;
-    use crate::types::packets::{BluetoothCode, MessageGroup, MessageStreamPacket};
+    use crate::sim::SimSocket;
+
+    const RING_ACK: [u8; 6] = [0xFF, 0x01, 0x00, 0x02, 0x04, 0x01];
+    const RING_NACK: [u8; 6] = [0xFF, 0x02, 0x00, 0x02, 0x04, 0x01];
+
+    async fn read_bytes(socket: &mut SimSocket, len: usize) -> Vec<u8> {
+        let mut bytes = vec![0; len];
+        socket.read_exact(&mut bytes).await.unwrap();
+        bytes
+    }
 
     #[test]
-    fn test_on_received() {
-        let packet = MessageStreamPacket {
-            group: MessageGroup::Bluetooth(BluetoothCode::DisableSilenceMode),
-            additional_data: vec![0, 1, 2],
-        };
-        let mut message = MessageStream {
-            battery_info: BatteryInfo { is_charging: false }
-        };
-        message.on_received(packet);
+    fn message_stream_device_information() {
+        block_on(async {
+            let (local, mut remote) = SimSocket::pair();
+            let mut stream = MessageStream::new(local);
+
+            remote
+                .write_all(&[
+                    // Model ID.
+                    0x03, 0x01, 0x00, 0x03, 0xAA, 0xBB, 0xCC,
+                    // BLE address updated.
+                    0x03, 0x02, 0x00, 0x06, 0x11, 0x22, 0x33, 0x44, 0x55, 0x66,
+                    // Battery updated: left charging at 57%, right unknown.
+                    0x03, 0x03, 0x00, 0x02, 0xB9, 0x7F,
+                    // Remaining battery time, high value.
+                    0x03, 0x04, 0x00, 0x02, 0x01, 0x0F,
+                    // Silence mode enabled.
+                    0x01, 0x01, 0x00, 0x00, // Log buffer full.
+                    0x02, 0x01, 0x00, 0x00,
+                ])
+                .await
+                .unwrap();
+            drop(remote);
+
+            let address =
+                BleAddress::new(0x112233445566, BleAddressKind::Random);
+            let battery = BatteryLevels {
+                left: Some(BatteryInfo {
+                    is_charging: true,
+                    level: Some(57),
+                }),
+                right: Some(BatteryInfo {
+                    is_charging: false,
+                    level: None,
+                }),
+                case: None,
+            };
+            let events: Vec<_> =
+                stream.by_ref().map(Result::unwrap).collect().await;
+            assert_eq!(
+                events,
+                vec![
+                    MessageStreamEvent::ModelId(0xAABBCC),
+                    MessageStreamEvent::BleAddressUpdated(address),
+                    MessageStreamEvent::BatteryUpdated(battery),
+                    MessageStreamEvent::RemainingBatteryTime(
+                        Duration::from_secs(271 * 60)
+                    ),
+                    MessageStreamEvent::SilenceModeChanged(true),
+                    MessageStreamEvent::LogBufferFull,
+                ]
+            );
+
+            let state = stream.state();
+            assert_eq!(state.model_id(), Some(0xAABBCC));
+            assert_eq!(state.ble_address(), Some(address));
+            assert_eq!(state.battery_levels(), Some(battery));
+            assert_eq!(
+                state.remaining_battery_time(),
+                Some(Duration::from_secs(271 * 60))
+            );
+            assert_eq!(state.silence_mode(), Some(true));
+        });
+    }
+
+    #[test]
+    fn message_stream_ignores_malformed_messages() {
+        block_on(async {
+            let (local, mut remote) = SimSocket::pair();
+            let mut stream = MessageStream::new(local);
+
+            remote
+                .write_all(&[
+                    // Model ID with a wrong size.
+                    0x03, 0x01, 0x00, 0x02, 0xAA, 0xBB,
+                    // Battery updated with too many levels.
+                    0x03, 0x03, 0x00, 0x04, 0x01, 0x02, 0x03, 0x04,
+                    // Unknown group.
+                    0x42, 0x01, 0x00, 0x00,
+                    // Remaining battery time.
+                    0x03, 0x04, 0x00, 0x01, 0x10,
+                ])
+                .await
+                .unwrap();
+            drop(remote);
+
+            let events: Vec<_> =
+                stream.by_ref().map(Result::unwrap).collect().await;
+            assert_eq!(
+                events,
+                vec![MessageStreamEvent::RemainingBatteryTime(
+                    Duration::from_secs(16 * 60)
+                )]
+            );
+            assert_eq!(stream.state().model_id(), None);
+            assert_eq!(stream.state().battery_levels(), None);
+        });
+    }
+
+    #[test]
+    fn message_stream_receive_ring() {
+        block_on(async {
+            let (local, mut remote) = SimSocket::pair();
+            let mut stream = MessageStream::new(local);
+
+            // Without a handler, rings are NACKed.
+            remote
+                .write_all(&[0x04, 0x01, 0x00, 0x01, 0x03])
+                .await
+                .unwrap();
+            assert_eq!(
+                stream.next().await.unwrap().unwrap(),
+                MessageStreamEvent::Ring {
+                    components: 0x03,
+                    duration: Duration::ZERO,
+                    acknowledged: false,
+                }
+            );
+            assert_eq!(read_bytes(&mut remote, 6).await, RING_NACK);
+
+            stream.set_ring_handler(|components, duration| {
+                components == 0x01 && duration == Duration::from_secs(120)
+            });
+            remote
+                .write_all(&[0x04, 0x01, 0x00, 0x02, 0x01, 0x02])
+                .await
+                .unwrap();
+            assert_eq!(
+                stream.next().await.unwrap().unwrap(),
+                MessageStreamEvent::Ring {
+                    components: 0x01,
+                    duration: Duration::from_secs(120),
+                    acknowledged: true,
+                }
+            );
+            assert_eq!(read_bytes(&mut remote, 6).await, RING_ACK);
+        });
+    }
+
+    #[test]
+    fn message_stream_ring() {
+        block_on(async {
+            let (local, mut remote) = SimSocket::pair();
+            let mut stream = MessageStream::new(local);
+
+            let (acked, _) =
+                join!(stream.ring(0x01, Duration::from_secs(120)), async {
+                    assert_eq!(
+                        read_bytes(&mut remote, 6).await,
+                        [0x04, 0x01, 0x00, 0x02, 0x01, 0x02]
+                    );
+                    // Events received before the ACK are kept for the stream.
+                    remote
+                        .write_all(&[0x03, 0x01, 0x00, 0x03, 0xAA, 0xBB, 0xCC])
+                        .await
+                        .unwrap();
+                    remote.write_all(&RING_ACK).await.unwrap();
+                });
+            assert!(acked.unwrap());
+            assert_eq!(
+                stream.next().await.unwrap().unwrap(),
+                MessageStreamEvent::ModelId(0xAABBCC)
+            );
+
+            let (acked, _) = join!(stream.ring(0x03, Duration::ZERO), async {
+                assert_eq!(
+                    read_bytes(&mut remote, 5).await,
+                    [0x04, 0x01, 0x00, 0x01, 0x03]
+                );
+                remote.write_all(&RING_NACK).await.unwrap();
+            });
+            assert!(!acked.unwrap());
+
+            drop(remote);
+            assert!(stream.ring(0x01, Duration::ZERO).await.is_err());
+        });
+    }
+
+    #[test]
+    fn message_stream_active_components() {
+        block_on(async {
+            let (local, mut remote) = SimSocket::pair();
+            let mut stream = MessageStream::new(local);
+
+            let (components, _) =
+                join!(stream.get_active_components(), async {
+                    assert_eq!(
+                        read_bytes(&mut remote, 4).await,
+                        [0x03, 0x05, 0x00, 0x00]
+                    );
+                    remote
+                        .write_all(&[0x03, 0x06, 0x00, 0x01, 0x03])
+                        .await
+                        .unwrap();
+                });
+            assert_eq!(components.unwrap(), 0x03);
+
+            let (components, _) =
+                join!(stream.get_active_components(), async {
+                    read_bytes(&mut remote, 4).await;
+                    remote.write_all(&[0x03, 0x06, 0x00, 0x00]).await.unwrap();
+                });
+            assert_eq!(components.unwrap(), DEFAULT_COMPONENTS);
+        });
+    }
+
+    #[test]
+    fn message_stream_send_seeker_information() {
+        block_on(async {
+            let (local, mut remote) = SimSocket::pair();
+            let mut stream = MessageStream::new(local);
+
+            let capabilities = Capabilities::new(true, true);
+            stream.send_capabilities(capabilities).await.unwrap();
+            stream
+                .send_platform_type(PlatformType::Android, 0x1C)
+                .await
+                .unwrap();
+            stream.close().await.unwrap();
+
+            let mut bytes = Vec::new();
+            remote.read_to_end(&mut bytes).await.unwrap();
+            assert_eq!(
+                bytes,
+                [
+                    0x03, 0x07, 0x00, 0x01, 0x03, // Capabilities.
+                    0x03, 0x08, 0x00, 0x02, 0x01, 0x1C, // Platform type.
+                ]
+            );
+            assert_eq!(stream.state().capabilities(), Some(capabilities));
+            assert_eq!(
+                stream.state().platform_type(),
+                Some((PlatformType::Android, 0x1C))
+            );
+        });
     }
 }
@@ -0,0 +1,436 @@
+// Copyright 2023 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+// Specification: https://developers.google.com/nearby/fast-pair/specifications/extensions/sass
+
+use futures::{AsyncRead, AsyncWrite};
+
+use crate::{
+    common::BluetoothError,
+    message_stream::MessageStream,
+    types::{
+        packets::{MessageGroup, SassCode},
+        sass::{
+            authenticate, AccountKey, ConnectionStatus, Nonce, SassCapability,
+            SassMessage, SwitchActiveSourceFlags, SwitchBackAction,
+            SwitchingPreference,
+        },
+    },
+};
+
+/// Smart Audio Source Switching requests to a provider, over its Message
+/// Stream session.
+///
+/// Requests changing the provider's state are authenticated with the account
+/// key the seeker shares with it, and answered with an ACK or NACK, which
+/// their result tells. The provider only accepts them once the seeker
+/// indicated its in-use account key with `indicate_in_use_account_key()`.
+/// Requests wait for the provider to answer without timeout, so callers
+/// should race them with a timer.
+pub struct SassClient<'a, T> {
+    stream: &'a mut MessageStream<T>,
+    account_key: AccountKey,
+}
+
+impl<'a, T: AsyncRead + AsyncWrite + Unpin> SassClient<'a, T> {
+    pub fn new(
+        stream: &'a mut MessageStream<T>,
+        account_key: AccountKey,
+    ) -> Self {
+        SassClient {
+            stream,
+            account_key,
+        }
+    }
+
+    /// Ask the provider for its SASS version and features, e.g. whether
+    /// multipoint is on.
+    pub async fn get_capability(
+        &mut self,
+    ) -> Result<SassCapability, BluetoothError> {
+        match self
+            .stream
+            .request_sass(
+                SassMessage::GetCapability,
+                SassCode::SassNotifyCapability,
+            )
+            .await?
+        {
+            SassMessage::NotifyCapability(capability) => Ok(capability),
+            message => Err(unexpected_response(&message)),
+        }
+    }
+
+    /// Tell the provider which account key this seeker uses, proving it
+    /// knows it.
+    pub async fn indicate_in_use_account_key(
+        &mut self,
+    ) -> Result<bool, BluetoothError> {
+        self.request_authenticated(SassMessage::InUseAccountKey)
+            .await
+    }
+
+    /// Enable or disable connecting the provider to multiple sources.
+    pub async fn set_multipoint_state(
+        &mut self,
+        enable: bool,
+    ) -> Result<bool, BluetoothError> {
+        self.request_authenticated(SassMessage::SetMultipointState { enable })
+            .await
+    }
+
+    pub async fn set_switching_preference(
+        &mut self,
+        preference: SwitchingPreference,
+    ) -> Result<bool, BluetoothError> {
+        self.request_authenticated(SassMessage::SetSwitchingPreference(
+            preference,
+        ))
+        .await
+    }
+
+    pub async fn get_switching_preference(
+        &mut self,
+    ) -> Result<SwitchingPreference, BluetoothError> {
+        match self
+            .stream
+            .request_sass(
+                SassMessage::GetSwitchingPreference,
+                SassCode::SassNotifySwitchingPreference,
+            )
+            .await?
+        {
+            SassMessage::NotifySwitchingPreference(preference) => {
+                Ok(preference)
+            }
+            message => Err(unexpected_response(&message)),
+        }
+    }
+
+    /// Switch the active audio source of the provider to this seeker, or
+    /// away from it, depending on `flags`.
+    pub async fn switch_active_source(
+        &mut self,
+        flags: SwitchActiveSourceFlags,
+    ) -> Result<bool, BluetoothError> {
+        self.request_authenticated(SassMessage::SwitchActiveSource(flags))
+            .await
+    }
+
+    /// Switch the provider back to the source it was switched away from.
+    pub async fn switch_back(
+        &mut self,
+        action: SwitchBackAction,
+    ) -> Result<bool, BluetoothError> {
+        self.request_authenticated(SassMessage::SwitchBack(action))
+            .await
+    }
+
+    /// Ask the provider for its connection status, decrypted with the
+    /// account key.
+    pub async fn get_connection_status(
+        &mut self,
+    ) -> Result<ConnectionStatus, BluetoothError> {
+        let session_nonce = self.session_nonce()?;
+        match self
+            .stream
+            .request_sass(
+                SassMessage::GetConnectionStatus,
+                SassCode::SassNotifyConnectionStatus,
+            )
+            .await?
+        {
+            SassMessage::NotifyConnectionStatus(status) => {
+                status.decrypt(&self.account_key, &session_nonce)
+            }
+            message => Err(unexpected_response(&message)),
+        }
+    }
+
+    /// Tell the provider whether SASS made the connection of this seeker,
+    /// e.g. so that it skips its connection sound.
+    pub async fn notify_sass_initiated_connection(
+        &mut self,
+        initiated_by_sass: bool,
+    ) -> Result<bool, BluetoothError> {
+        self.request_authenticated(SassMessage::NotifySassInitiatedConnection {
+            initiated_by_sass,
+        })
+        .await
+    }
+
+    /// Send data the provider shares with every seeker in its connection
+    /// status and advertisement.
+    pub async fn send_custom_data(
+        &mut self,
+        data: u8,
+    ) -> Result<bool, BluetoothError> {
+        self.request_authenticated(SassMessage::SendCustomData(data))
+            .await
+    }
+
+    /// Tell the provider which source to drop when it needs a connection,
+    /// as a bit field of its bonded sources.
+    pub async fn set_drop_connection_target(
+        &mut self,
+        target: u8,
+    ) -> Result<bool, BluetoothError> {
+        self.request_authenticated(SassMessage::SetDropConnectionTarget(target))
+            .await
+    }
+
+    async fn request_authenticated(
+        &mut self,
+        message: SassMessage,
+    ) -> Result<bool, BluetoothError> {
+        let data = authenticate(
+            &message.encode(),
+            &self.account_key,
+            &self.session_nonce()?,
+            &rand::random(),
+        );
+        self.stream
+            .request_acknowledgement(MessageGroup::Sass(message.code()), &data)
+            .await
+    }
+
+    fn session_nonce(&self) -> Result<Nonce, BluetoothError> {
+        self.stream.state().session_nonce().ok_or_else(|| {
+            BluetoothError::FailedPrecondition(String::from(
+                "Provider didn't send the session nonce",
+            ))
+        })
+    }
+}
+
+fn unexpected_response(message: &SassMessage) -> BluetoothError {
+    BluetoothError::Internal(format!(
+        "Provider answered with unexpected SASS message {:?}",
+        message
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use futures::{
+        executor::block_on, join, AsyncReadExt, AsyncWriteExt, StreamExt,
+    };
+
+    use super::*;
+    use crate::{
+        message_stream::MessageStreamEvent,
+        sim::SimSocket,
+        types::{
+            packets::MessageStreamDecoder,
+            sass::{
+                verify, ActiveDevice, ConnectionState, MultipointSwitchEvent,
+                MultipointSwitchReason, SassCapabilityFlags, SwitchTarget,
+            },
+        },
+    };
+
+    const ACCOUNT_KEY: AccountKey = [0x11; 16];
+    const SESSION_NONCE: Nonce = [0xAB; 8];
+
+    /// Provider end of a session that sent its session nonce.
+    async fn connect() -> (MessageStream<SimSocket>, SimSocket) {
+        let (local, mut remote) = SimSocket::pair();
+        let mut stream = MessageStream::new(local);
+        let mut nonce = vec![0x03, 0x0A, 0x00, 0x08];
+        nonce.extend_from_slice(&SESSION_NONCE);
+        remote.write_all(&nonce).await.unwrap();
+        // Model ID, so that the stream handled the session nonce once it is
+        // received.
+        remote
+            .write_all(&[0x03, 0x01, 0x00, 0x03, 0xAA, 0xBB, 0xCC])
+            .await
+            .unwrap();
+        assert_eq!(
+            stream.next().await.unwrap().unwrap(),
+            MessageStreamEvent::ModelId(0xAABBCC)
+        );
+        (stream, remote)
+    }
+
+    /// Read the next packet the seeker sent, returning its code and
+    /// additional data.
+    async fn read_sass(remote: &mut SimSocket) -> (SassCode, Vec<u8>) {
+        let mut decoder = MessageStreamDecoder::new();
+        loop {
+            if let Some(packet) = decoder.next_packet() {
+                let MessageGroup::Sass(code) = packet.group else {
+                    panic!("Unexpected packet {:?}", packet);
+                };
+                return (code, packet.additional_data.to_vec());
+            }
+            let mut byte = [0];
+            remote.read_exact(&mut byte).await.unwrap();
+            decoder.push(&byte);
+        }
+    }
+
+    #[test]
+    fn sass_client_requests() {
+        block_on(async {
+            let (mut stream, mut remote) = connect().await;
+            let mut client = SassClient::new(&mut stream, ACCOUNT_KEY);
+
+            let (capability, _) = join!(client.get_capability(), async {
+                assert_eq!(
+                    read_sass(&mut remote).await,
+                    (SassCode::SassGetCapability, vec![])
+                );
+                remote
+                    .write_all(&[
+                        0x07, 0x11, 0x00, 0x04, 0x01, 0x01, 0xE0, 0x00,
+                    ])
+                    .await
+                    .unwrap();
+            });
+            let capability = capability.unwrap();
+            assert_eq!(capability.version, 0x0101);
+            assert!(capability.flags.contains(
+                SassCapabilityFlags::SASS_ON
+                    | SassCapabilityFlags::MULTIPOINT_ON
+            ));
+
+            let (preference, _) =
+                join!(client.get_switching_preference(), async {
+                    read_sass(&mut remote).await;
+                    remote
+                        .write_all(&[0x07, 0x22, 0x00, 0x02, 0x90, 0x00])
+                        .await
+                        .unwrap();
+                });
+            assert_eq!(
+                preference.unwrap(),
+                SwitchingPreference::from_bits(0x90)
+            );
+
+            let (status, _) = join!(client.get_connection_status(), async {
+                read_sass(&mut remote).await;
+                let status = ConnectionStatus {
+                    active_device: ActiveDevice::ThisDevice,
+                    connection_state: ConnectionState::from_bits(0xC4),
+                    custom_data: 0x12,
+                    connected_devices: vec![0x03],
+                };
+                let message = SassMessage::NotifyConnectionStatus(
+                    status
+                        .encrypt(&ACCOUNT_KEY, &SESSION_NONCE, &[0x77; 8])
+                        .unwrap(),
+                )
+                .encode();
+                let mut packet = vec![0x07, 0x34, 0x00, message.len() as u8];
+                packet.extend_from_slice(&message);
+                remote.write_all(&packet).await.unwrap();
+            });
+            let status = status.unwrap();
+            assert_eq!(status.active_device, ActiveDevice::ThisDevice);
+            assert_eq!(status.custom_data, 0x12);
+            assert_eq!(status.connected_devices, [0x03]);
+        });
+    }
+
+    #[test]
+    fn sass_client_authenticated_requests() {
+        block_on(async {
+            let (mut stream, mut remote) = connect().await;
+            let mut client = SassClient::new(&mut stream, ACCOUNT_KEY);
+
+            let (acked, _) = join!(client.set_multipoint_state(true), async {
+                let (code, data) = read_sass(&mut remote).await;
+                assert_eq!(code, SassCode::SassSetMultipointState);
+                assert_eq!(
+                    verify(&data, &ACCOUNT_KEY, &SESSION_NONCE),
+                    Some(&[0x01][..])
+                );
+                remote
+                    .write_all(&[0xFF, 0x01, 0x00, 0x02, 0x07, 0x12])
+                    .await
+                    .unwrap();
+            });
+            assert!(acked.unwrap());
+
+            let flags = SwitchActiveSourceFlags::from_bits(
+                SwitchActiveSourceFlags::THIS_DEVICE,
+            );
+            let (acked, _) = join!(client.switch_active_source(flags), async {
+                let (code, data) = read_sass(&mut remote).await;
+                assert_eq!(code, SassCode::SassSwitchActiveSourceCode);
+                assert_eq!(
+                    verify(&data, &ACCOUNT_KEY, &SESSION_NONCE),
+                    Some(&[0x80][..])
+                );
+                // NACK with fail reason "redundant device action".
+                remote
+                    .write_all(&[0xFF, 0x02, 0x00, 0x03, 0x04, 0x07, 0x30])
+                    .await
+                    .unwrap();
+            });
+            assert!(!acked.unwrap());
+
+            let (acked, _) = join!(
+                client.switch_back(SwitchBackAction::SwitchBack),
+                async {
+                    let (code, data) = read_sass(&mut remote).await;
+                    assert_eq!(code, SassCode::SassSwitchBackAudioSource);
+                    assert_eq!(
+                        verify(&data, &ACCOUNT_KEY, &SESSION_NONCE),
+                        Some(&[0x01][..])
+                    );
+                    // Notifications received meanwhile are kept for the
+                    // stream.
+                    remote
+                        .write_all(&[
+                            0x07, 0x32, 0x00, 0x04, 0x01, 0x01, b'P', b'C',
+                        ])
+                        .await
+                        .unwrap();
+                    remote
+                        .write_all(&[0xFF, 0x01, 0x00, 0x02, 0x07, 0x31])
+                        .await
+                        .unwrap();
+                }
+            );
+            assert!(acked.unwrap());
+            assert_eq!(
+                stream.next().await.unwrap().unwrap(),
+                MessageStreamEvent::Sass(
+                    SassMessage::NotifyMultipointSwitchEvent(
+                        MultipointSwitchEvent {
+                            reason: MultipointSwitchReason::A2dp,
+                            target: SwitchTarget::ThisDevice,
+                            device_name: String::from("PC"),
+                        }
+                    )
+                )
+            );
+        });
+    }
+
+    #[test]
+    fn sass_client_without_session_nonce() {
+        block_on(async {
+            let (local, _remote) = SimSocket::pair();
+            let mut stream = MessageStream::new(local);
+            let mut client = SassClient::new(&mut stream, ACCOUNT_KEY);
+
+            assert!(matches!(
+                client.set_multipoint_state(false).await,
+                Err(BluetoothError::FailedPrecondition(_))
+            ));
+        });
+    }
+}
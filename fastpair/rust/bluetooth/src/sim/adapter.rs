@@ -0,0 +1,451 @@
+// Copyright 2023 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::{collections::VecDeque, time::Instant};
+
+use async_trait::async_trait;
+use futures::{
+    channel::mpsc::UnboundedReceiver,
+    future::{self, Either},
+    stream, StreamExt,
+};
+
+use super::{
+    radio::{Script, RADIO_ID},
+    BleDevice, ClassicDevice, SimRadio,
+};
+use crate::{
+    api,
+    common::{
+        sleep_until, AdapterId, AdapterInfo, BleAddress, BleAdvertisement,
+        BleDataTypeId, BluetoothError, BondEventStream, BondedDevice,
+        ClassicAddress, RadioStateStream, ScanFilter, ScanSettings, ScanState,
+        ScanStream, Sleep,
+    },
+};
+
+/// Struct holding the advertisements a scanning `BleAdapter` still has to
+/// deliver.
+struct AdvListener {
+    /// Advertisements injected with `SimRadio::advertise`, and the error
+    /// ending the scan when the radio is switched off.
+    receiver: UnboundedReceiver<Result<BleAdvertisement, BluetoothError>>,
+    /// Scripted advertisements, with the instant they are due.
+    script: VecDeque<(Instant, BleAdvertisement)>,
+    /// Timer for the first scripted advertisement, kept across calls to
+    /// `next()` until it fires.
+    timer: Option<Sleep>,
+}
+
+/// Concrete type implementing `api::BleAdapter`, used for the simulated
+/// platform.
+pub struct BleAdapter {
+    radio: SimRadio,
+}
+
+impl BleAdapter {
+    pub(crate) fn with_radio(radio: SimRadio) -> Self {
+        BleAdapter { radio }
+    }
+}
+
+impl AdvListener {
+    fn new(
+        receiver: UnboundedReceiver<Result<BleAdvertisement, BluetoothError>>,
+        script: Script,
+    ) -> Self {
+        let start = Instant::now();
+        let script = script
+            .into_iter()
+            .map(|(delay, advertisement)| (start + delay, advertisement))
+            .collect();
+
+        AdvListener {
+            receiver,
+            script,
+            timer: None,
+        }
+    }
+
+    /// Wait for the next injected or scripted advertisement, whichever comes
+    /// first. Returns an error once the radio is switched off, then `None`.
+    async fn next(
+        &mut self,
+    ) -> Option<Result<BleAdvertisement, BluetoothError>> {
+        let deadline = match self.script.front() {
+            Some((deadline, _)) => *deadline,
+            None => return self.receiver.next().await,
+        };
+
+        let timer = self.timer.get_or_insert_with(|| sleep_until(deadline));
+        match future::select(self.receiver.next(), timer).await {
+            Either::Left((advertisement, _)) => advertisement,
+            Either::Right(_) => {
+                self.timer = None;
+                self.script
+                    .pop_front()
+                    .map(|(_, advertisement)| Ok(advertisement))
+            }
+        }
+    }
+}
+
+#[async_trait]
+impl api::BleAdapter for BleAdapter {
+    type BleDevice = BleDevice;
+    type ClassicDevice = ClassicDevice;
+
+    async fn default() -> Result<Self, BluetoothError> {
+        Ok(SimRadio::global().adapter())
+    }
+
+    async fn adapters() -> Result<Vec<AdapterInfo>, BluetoothError> {
+        Ok(vec![SimRadio::global().info()])
+    }
+
+    async fn with_id(id: &AdapterId) -> Result<Self, BluetoothError> {
+        match id.as_str() {
+            RADIO_ID => Ok(SimRadio::global().adapter()),
+            _ => Err(BluetoothError::InvalidArgument(format!(
+                "no simulated adapter {}",
+                id
+            ))),
+        }
+    }
+
+    async fn ble_device(
+        &self,
+        addr: BleAddress,
+    ) -> Result<BleDevice, BluetoothError> {
+        self.radio.ble_device(addr)
+    }
+
+    async fn classic_device(
+        &self,
+        addr: ClassicAddress,
+    ) -> Result<ClassicDevice, BluetoothError> {
+        self.radio.classic_device(addr)
+    }
+
+    async fn info(&self) -> Result<AdapterInfo, BluetoothError> {
+        Ok(self.radio.info())
+    }
+
+    async fn radio_state_changes(
+        &self,
+    ) -> Result<RadioStateStream, BluetoothError> {
+        Ok(RadioStateStream::new(self.radio.watch_radio_state()))
+    }
+
+    fn start_scan(
+        &self,
+        filter: &ScanFilter,
+        settings: &ScanSettings,
+        data_selector: &[BleDataTypeId],
+    ) -> Result<ScanStream, BluetoothError> {
+        let (receiver, script) = self.radio.start_scan()?;
+        let listener = AdvListener::new(receiver, script);
+        let state = ScanState::new(filter, settings, data_selector);
+
+        // Dropping the listener drops `receiver`, which unregisters the scan
+        // from the radio.
+        let stream = stream::unfold(
+            (listener, state),
+            |(mut listener, mut state)| async move {
+                loop {
+                    // Injected advertisements may carry any data, so only
+                    // keep what a real platform would have loaded.
+                    let mut advertisement = match listener.next().await? {
+                        Ok(advertisement) => advertisement,
+                        Err(err) => break Some((Err(err), (listener, state))),
+                    };
+                    advertisement.retain_data(state.datatype_ids());
+
+                    if let Some(advertisement) = state.process(advertisement) {
+                        break Some((Ok(advertisement), (listener, state)));
+                    }
+                }
+            },
+        );
+
+        Ok(ScanStream::new(stream))
+    }
+
+    async fn paired_devices(
+        &self,
+    ) -> Result<Vec<BondedDevice>, BluetoothError> {
+        Ok(self.radio.paired_devices())
+    }
+
+    async fn unpair(&self, device: BondedDevice) -> Result<(), BluetoothError> {
+        self.radio.unpair(device);
+        Ok(())
+    }
+
+    async fn bond_events(&self) -> Result<BondEventStream, BluetoothError> {
+        Ok(BondEventStream::new(self.radio.watch_bonds()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use futures::executor::block_on;
+
+    use super::*;
+    use crate::{
+        api::BleAdapter as _,
+        common::{
+            BleAddress, BleAddressKind, BleAdvertisementType, BondEvent,
+            BtUuid, RadioState, ServiceData,
+        },
+        sim::SimPeripheral,
+    };
+
+    fn advertisement(addr: u64) -> BleAdvertisement {
+        let mut ad = BleAdvertisement::new(
+            BleAddress::new(addr, BleAddressKind::Public),
+            Some(-60),
+            Some(-10),
+        );
+        ad.set_service_data_16bit_uuid(vec![ServiceData::new(
+            BtUuid::FAST_PAIR,
+            vec![0x01, 0x02, 0x03],
+        )]);
+        ad
+    }
+
+    #[test]
+    fn scripted_advertisements_in_order() {
+        let radio = SimRadio::new();
+        radio.schedule_advertisement(
+            Duration::from_millis(30),
+            advertisement(2),
+        );
+        radio.schedule_advertisement(Duration::ZERO, advertisement(1));
+
+        let adapter = radio.adapter();
+        let start = Instant::now();
+        let mut scan = adapter
+            .start_scan(&ScanFilter::default(), &ScanSettings::default(), &[])
+            .unwrap();
+
+        let first = block_on(scan.next()).unwrap().unwrap();
+        assert_eq!(u64::from(first.address()), 1);
+        let second = block_on(scan.next()).unwrap().unwrap();
+        assert_eq!(u64::from(second.address()), 2);
+        assert!(start.elapsed() >= Duration::from_millis(30));
+    }
+
+    #[test]
+    fn injected_advertisement_before_scripted() {
+        let radio = SimRadio::new();
+        radio.schedule_advertisement(Duration::from_secs(60), advertisement(2));
+
+        let adapter = radio.adapter();
+        // Not scanning yet, so this advertisement is never received.
+        radio.advertise(advertisement(3));
+        let mut scan = adapter
+            .start_scan(&ScanFilter::default(), &ScanSettings::default(), &[])
+            .unwrap();
+        radio.advertise(advertisement(1));
+
+        let ad = block_on(scan.next()).unwrap().unwrap();
+        assert_eq!(u64::from(ad.address()), 1);
+    }
+
+    #[test]
+    fn scan_applies_selector() {
+        let radio = SimRadio::new();
+        let adapter = radio.adapter();
+        let mut selected = adapter
+            .start_scan(
+                &ScanFilter::default(),
+                &ScanSettings::default(),
+                &[BleDataTypeId::ServiceData16BitUuid],
+            )
+            .unwrap();
+        let mut unselected = adapter
+            .start_scan(&ScanFilter::default(), &ScanSettings::default(), &[])
+            .unwrap();
+        radio.advertise(advertisement(1));
+
+        let ad = block_on(selected.next()).unwrap().unwrap();
+        assert!(ad.service_data_16bit_uuid().is_ok());
+
+        let ad = block_on(unselected.next()).unwrap().unwrap();
+        assert!(ad.service_data_16bit_uuid().is_err());
+        assert_eq!(ad.rssi(), Some(-60));
+        assert_eq!(ad.tx_power(), Some(-10));
+    }
+
+    #[test]
+    fn scan_applies_filter() {
+        let radio = SimRadio::new();
+        let filter = ScanFilter::default().with_service_data(
+            BtUuid::FAST_PAIR,
+            vec![0x01],
+            None,
+        );
+        let mut scan = radio
+            .adapter()
+            .start_scan(&filter, &ScanSettings::default(), &[])
+            .unwrap();
+
+        let mut other = advertisement(1);
+        other.set_service_data_16bit_uuid(vec![ServiceData::new(
+            BtUuid::FAST_PAIR,
+            vec![0x02],
+        )]);
+        radio.advertise(other);
+        radio.advertise(advertisement(2));
+
+        let ad = block_on(scan.next()).unwrap().unwrap();
+        assert_eq!(u64::from(ad.address()), 2);
+        // Only loaded to check the filter.
+        assert!(ad.service_data_16bit_uuid().is_err());
+    }
+
+    #[test]
+    fn scan_applies_settings() {
+        let radio = SimRadio::new();
+        let settings = ScanSettings::default()
+            .with_duplicate_window(Duration::from_secs(60));
+        let mut scan = radio
+            .adapter()
+            .start_scan(&ScanFilter::default(), &settings, &[])
+            .unwrap();
+
+        let mut non_connectable = advertisement(1);
+        non_connectable
+            .set_advertisement_type(BleAdvertisementType::ADV_NONCONN_IND);
+        radio.advertise(non_connectable);
+        let mut connectable = advertisement(2);
+        connectable.set_advertisement_type(BleAdvertisementType::ADV_IND);
+        radio.advertise(connectable.clone());
+        radio.advertise(connectable);
+        radio.advertise(advertisement(3));
+
+        let ad = block_on(scan.next()).unwrap().unwrap();
+        assert_eq!(u64::from(ad.address()), 2);
+        let ad = block_on(scan.next()).unwrap().unwrap();
+        assert_eq!(u64::from(ad.address()), 3);
+    }
+
+    #[test]
+    fn scans_with_combinators() {
+        let radio = SimRadio::new();
+        let first = radio
+            .adapter()
+            .start_scan(&ScanFilter::default(), &ScanSettings::default(), &[])
+            .unwrap();
+        let second = radio
+            .adapter()
+            .start_scan(&ScanFilter::default(), &ScanSettings::default(), &[])
+            .unwrap();
+        for addr in 1..=4 {
+            radio.advertise(advertisement(addr));
+        }
+
+        // Both scans receive every advertisement, keep the odd addresses.
+        let mut addresses = block_on(
+            stream::select(first, second)
+                .map(|ad| u64::from(ad.unwrap().address()))
+                .filter(|addr| future::ready(addr % 2 == 1))
+                .take(4)
+                .collect::<Vec<_>>(),
+        );
+
+        addresses.sort();
+        assert_eq!(addresses, vec![1, 1, 3, 3]);
+    }
+
+    #[test]
+    fn power_off_ends_scan() {
+        let radio = SimRadio::new();
+        radio.schedule_advertisement(Duration::from_secs(60), advertisement(1));
+        let adapter = radio.adapter();
+        let mut scan = adapter
+            .start_scan(&ScanFilter::default(), &ScanSettings::default(), &[])
+            .unwrap();
+
+        radio.set_powered(false);
+        assert!(matches!(
+            block_on(scan.next()),
+            Some(Err(BluetoothError::FailedPrecondition(_)))
+        ));
+        assert!(block_on(scan.next()).is_none());
+        assert!(adapter
+            .start_scan(&ScanFilter::default(), &ScanSettings::default(), &[])
+            .is_err());
+    }
+
+    #[test]
+    fn radio_state_changes_follow_radio() {
+        let radio = SimRadio::new();
+        let adapter = radio.adapter();
+        let mut changes = block_on(adapter.radio_state_changes()).unwrap();
+        assert_eq!(block_on(changes.next()), Some(Ok(RadioState::On)));
+
+        radio.set_powered(false);
+        assert_eq!(block_on(changes.next()), Some(Ok(RadioState::Off)));
+        let info = block_on(adapter.info()).unwrap();
+        assert_eq!(info.address(), radio.address());
+        assert_eq!(info.radio_state(), RadioState::Off);
+    }
+
+    #[test]
+    fn adapters_lists_global_radio() {
+        let adapters = block_on(BleAdapter::adapters()).unwrap();
+        assert_eq!(adapters.len(), 1);
+        assert!(block_on(BleAdapter::with_id(adapters[0].id())).is_ok());
+        assert!(matches!(
+            block_on(BleAdapter::with_id(&AdapterId::from("sim1"))),
+            Err(BluetoothError::InvalidArgument(_))
+        ));
+    }
+
+    #[test]
+    fn devices_are_opened_through_radio() {
+        let radio = SimRadio::new();
+        let addr = BleAddress::new(0x112233445566, BleAddressKind::Public);
+        radio.add_peripheral(SimPeripheral::new(addr, "Buds"));
+
+        let device = block_on(radio.adapter().ble_device(addr)).unwrap();
+        assert_eq!(api::BleDevice::address(&device), addr);
+        assert!(block_on(SimRadio::new().adapter().ble_device(addr)).is_err());
+    }
+
+    #[test]
+    fn bonds_follow_radio() {
+        let radio = SimRadio::new();
+        let addr = BleAddress::new(0x112233445566, BleAddressKind::Public);
+        radio.add_peripheral(SimPeripheral::new(addr, "Buds"));
+        let adapter = radio.adapter();
+        let mut events = block_on(adapter.bond_events()).unwrap();
+        let device = BondedDevice::Ble(addr);
+
+        radio.set_paired(addr, true).unwrap();
+        assert_eq!(block_on(adapter.paired_devices()), Ok(vec![device]));
+        assert_eq!(block_on(events.next()), Some(Ok(BondEvent::Added(device))));
+
+        block_on(adapter.unpair(device)).unwrap();
+        assert_eq!(block_on(adapter.paired_devices()), Ok(vec![]));
+        assert_eq!(
+            block_on(events.next()),
+            Some(Ok(BondEvent::Removed(device)))
+        );
+    }
+}
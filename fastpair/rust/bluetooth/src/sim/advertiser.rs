@@ -0,0 +1,170 @@
+// Copyright 2023 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use async_trait::async_trait;
+
+use super::SimRadio;
+use crate::{
+    api,
+    common::{
+        AdvertisingData, AdvertisingSettings, AdvertisingStream,
+        BleAdvertisement, BleAdvertisementType, BluetoothError,
+    },
+};
+
+/// Concrete type implementing `api::BleAdvertiser`, used for the simulated
+/// platform.
+pub struct BleAdvertiser {
+    radio: SimRadio,
+}
+
+impl BleAdvertiser {
+    pub(crate) fn with_radio(radio: SimRadio) -> Self {
+        BleAdvertiser { radio }
+    }
+}
+
+#[async_trait]
+impl api::BleAdvertiser for BleAdvertiser {
+    async fn default() -> Result<Self, BluetoothError> {
+        Ok(SimRadio::global().advertiser())
+    }
+
+    /// The advertisement is delivered once to every scan of the radio, as a
+    /// non-connectable advertisement like the ones Windows publishes. Scans
+    /// only receive it with `ScanSettings::with_non_connectable()`. The
+    /// interval is ignored.
+    fn start_advertising(
+        &self,
+        data: &AdvertisingData,
+        settings: &AdvertisingSettings,
+    ) -> Result<AdvertisingStream, BluetoothError> {
+        data.check_len(settings)?;
+
+        let bytes = data.to_bytes(settings.tx_power());
+        let mut advertisement = BleAdvertisement::from_raw(
+            self.radio.address(),
+            None,
+            &bytes,
+            &[],
+        )?;
+        advertisement.set_advertisement_type(match settings.extended() {
+            true => BleAdvertisementType::from_bits(0),
+            false => BleAdvertisementType::ADV_NONCONN_IND,
+        });
+
+        let receiver = self.radio.start_advertising(advertisement)?;
+        Ok(AdvertisingStream::new(receiver))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use futures::{executor::block_on, StreamExt};
+
+    use super::*;
+    use crate::{
+        api::{BleAdapter as _, BleAdvertiser as _},
+        common::{
+            AdvertisingStatus, BleDataTypeId, BtUuid, ScanFilter, ScanSettings,
+            ServiceData,
+        },
+    };
+
+    fn fast_pair_data() -> AdvertisingData {
+        AdvertisingData::default()
+            .with_service_data(BtUuid::FAST_PAIR, vec![0x01, 0x02, 0x03])
+    }
+
+    #[test]
+    fn scan_receives_published_advertisement() {
+        let radio = SimRadio::new();
+        let settings = ScanSettings::default().with_non_connectable(true);
+        let mut scan = radio
+            .adapter()
+            .start_scan(
+                &ScanFilter::default(),
+                &settings,
+                &[BleDataTypeId::ServiceData16BitUuid],
+            )
+            .unwrap();
+
+        let mut advertising = radio
+            .advertiser()
+            .start_advertising(
+                &fast_pair_data(),
+                &AdvertisingSettings::default().with_tx_power(-10),
+            )
+            .unwrap();
+        assert_eq!(
+            block_on(advertising.next()),
+            Some(Ok(AdvertisingStatus::Started))
+        );
+
+        let ad = block_on(scan.next()).unwrap().unwrap();
+        assert_eq!(ad.address(), radio.address());
+        assert_eq!(ad.tx_power(), Some(-10));
+        assert_eq!(
+            ad.service_data_16bit_uuid().unwrap(),
+            &vec![ServiceData::new(BtUuid::FAST_PAIR, vec![0x01, 0x02, 0x03])]
+        );
+    }
+
+    #[test]
+    fn start_advertising_too_large() {
+        let radio = SimRadio::new();
+        let data = fast_pair_data().with_manufacturer_data(0x00e0, vec![0; 32]);
+
+        assert!(matches!(
+            radio
+                .advertiser()
+                .start_advertising(&data, &AdvertisingSettings::default()),
+            Err(BluetoothError::InvalidArgument(_))
+        ));
+        assert!(radio
+            .advertiser()
+            .start_advertising(
+                &data,
+                &AdvertisingSettings::default().with_extended(true)
+            )
+            .is_ok());
+    }
+
+    #[test]
+    fn power_off_aborts_advertising() {
+        let radio = SimRadio::new();
+        let advertiser = radio.advertiser();
+        let mut advertising = advertiser
+            .start_advertising(
+                &fast_pair_data(),
+                &AdvertisingSettings::default(),
+            )
+            .unwrap();
+
+        radio.set_powered(false);
+        let statuses = block_on(advertising.by_ref().collect::<Vec<_>>());
+        assert_eq!(statuses.len(), 2);
+        assert_eq!(statuses[0], Ok(AdvertisingStatus::Started));
+        assert!(matches!(
+            statuses[1],
+            Err(BluetoothError::FailedPrecondition(_))
+        ));
+        assert!(advertiser
+            .start_advertising(
+                &fast_pair_data(),
+                &AdvertisingSettings::default()
+            )
+            .is_err());
+    }
+}
@@ -0,0 +1,698 @@
+// Copyright 2023 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::sync::Arc;
+
+use async_trait::async_trait;
+
+use super::SimRadio;
+use crate::{
+    api,
+    common::{
+        BleAddress, BluetoothError, BondStateStream, BondedDevice, BtUuid,
+        ClassicAddress, ConnectionState, ConnectionStateStream,
+        GattCharacteristic, GattService, NotificationStream, PairingHandler,
+        PairingResult, ProtectionLevel, RfcommSocket, WriteType,
+    },
+};
+
+/// Concrete type implementing `api::BleDevice`, used for the simulated
+/// platform.
+pub struct BleDevice {
+    radio: SimRadio,
+    addr: BleAddress,
+}
+
+/// Concrete type implementing `api::ClassicDevice`, used for the simulated
+/// platform.
+pub struct ClassicDevice {
+    radio: SimRadio,
+    addr: ClassicAddress,
+}
+
+impl BleDevice {
+    pub(crate) fn with_radio(
+        radio: SimRadio,
+        addr: BleAddress,
+    ) -> Result<Self, BluetoothError> {
+        let peripheral = radio.peripheral(u64::from(addr))?;
+        if peripheral.address() != addr {
+            return Err(BluetoothError::FailedPrecondition(String::from(
+                "simulated peripheral has a different address type",
+            )));
+        }
+
+        Ok(BleDevice { radio, addr })
+    }
+}
+
+/// Report `PairingResult::Failure` as an error, like real platforms do.
+fn check_pairing_result(
+    status: PairingResult,
+) -> Result<PairingResult, BluetoothError> {
+    match status {
+        PairingResult::Failure(msg) => Err(BluetoothError::PairingFailed(msg)),
+        _ => Ok(status),
+    }
+}
+
+/// Retrieve the connection state of the peripheral registered at `addr`.
+fn connection_state(
+    radio: &SimRadio,
+    addr: u64,
+) -> Result<ConnectionState, BluetoothError> {
+    match radio.peripheral(addr)?.is_connected() {
+        true => Ok(ConnectionState::Connected),
+        false => Ok(ConnectionState::Disconnected),
+    }
+}
+
+impl ClassicDevice {
+    pub(crate) fn with_radio(
+        radio: SimRadio,
+        addr: ClassicAddress,
+    ) -> Result<Self, BluetoothError> {
+        radio.peripheral(u64::from(addr))?;
+
+        Ok(ClassicDevice { radio, addr })
+    }
+}
+
+#[async_trait]
+impl api::BleDevice for BleDevice {
+    async fn new(addr: BleAddress) -> Result<Self, BluetoothError> {
+        SimRadio::global().ble_device(addr)
+    }
+
+    fn name(&self) -> Result<String, BluetoothError> {
+        let peripheral = self.radio.peripheral(u64::from(self.addr))?;
+        Ok(String::from(peripheral.name()))
+    }
+
+    fn address(&self) -> BleAddress {
+        self.addr
+    }
+
+    async fn connect(&self) -> Result<(), BluetoothError> {
+        self.radio.set_connected(u64::from(self.addr), true)
+    }
+
+    async fn disconnect(&self) -> Result<(), BluetoothError> {
+        self.radio.set_connected(u64::from(self.addr), false)
+    }
+
+    async fn discover_services(
+        &self,
+        uuid: Option<BtUuid>,
+    ) -> Result<Vec<GattService>, BluetoothError> {
+        self.radio
+            .with_connected(u64::from(self.addr), |peripheral| {
+                Ok(peripheral.gatt().services(uuid))
+            })
+    }
+
+    async fn discover_characteristics(
+        &self,
+        service: &GattService,
+        uuid: Option<BtUuid>,
+    ) -> Result<Vec<GattCharacteristic>, BluetoothError> {
+        self.radio
+            .with_connected(u64::from(self.addr), |peripheral| {
+                peripheral.gatt().characteristics(service, uuid)
+            })
+    }
+
+    async fn read(
+        &self,
+        characteristic: &GattCharacteristic,
+    ) -> Result<Vec<u8>, BluetoothError> {
+        self.radio
+            .with_connected(u64::from(self.addr), |peripheral| {
+                peripheral.gatt().read(characteristic)
+            })
+    }
+
+    async fn write(
+        &self,
+        characteristic: &GattCharacteristic,
+        value: &[u8],
+        write_type: WriteType,
+    ) -> Result<(), BluetoothError> {
+        self.radio
+            .with_connected(u64::from(self.addr), |peripheral| {
+                peripheral.gatt().write(characteristic, value, write_type)
+            })
+    }
+
+    async fn subscribe(
+        &self,
+        characteristic: &GattCharacteristic,
+    ) -> Result<NotificationStream, BluetoothError> {
+        let receiver = self
+            .radio
+            .with_connected(u64::from(self.addr), |peripheral| {
+                peripheral.gatt().subscribe(characteristic)
+            })?;
+
+        Ok(NotificationStream::new(receiver))
+    }
+
+    async fn mtu(&self) -> Result<u16, BluetoothError> {
+        self.radio
+            .with_connected(u64::from(self.addr), |peripheral| {
+                Ok(peripheral.mtu())
+            })
+    }
+
+    async fn pair(
+        &self,
+        protection_level: ProtectionLevel,
+        handler: Arc<dyn PairingHandler>,
+    ) -> Result<PairingResult, BluetoothError> {
+        let status = self
+            .radio
+            .pair(
+                BondedDevice::Ble(self.addr),
+                protection_level,
+                handler.as_ref(),
+            )
+            .await?;
+        check_pairing_result(status)
+    }
+
+    async fn is_paired(&self) -> Result<bool, BluetoothError> {
+        let peripheral = self.radio.peripheral(u64::from(self.addr))?;
+        Ok(peripheral.is_paired())
+    }
+
+    async fn unpair(&self) -> Result<(), BluetoothError> {
+        self.radio.set_paired(self.addr, false)
+    }
+
+    async fn bond_state_changes(
+        &self,
+    ) -> Result<BondStateStream, BluetoothError> {
+        let receiver = self.radio.watch_bond_state(u64::from(self.addr))?;
+        Ok(BondStateStream::new(receiver))
+    }
+
+    async fn connection_state(
+        &self,
+    ) -> Result<ConnectionState, BluetoothError> {
+        connection_state(&self.radio, u64::from(self.addr))
+    }
+
+    async fn connection_events(
+        &self,
+    ) -> Result<ConnectionStateStream, BluetoothError> {
+        let receiver =
+            self.radio.watch_connection_state(u64::from(self.addr))?;
+        Ok(ConnectionStateStream::new(receiver))
+    }
+}
+
+#[async_trait]
+impl api::ClassicDevice for ClassicDevice {
+    async fn new(addr: ClassicAddress) -> Result<Self, BluetoothError> {
+        SimRadio::global().classic_device(addr)
+    }
+
+    fn name(&self) -> Result<String, BluetoothError> {
+        let peripheral = self.radio.peripheral(u64::from(self.addr))?;
+        Ok(String::from(peripheral.name()))
+    }
+
+    fn address(&self) -> ClassicAddress {
+        self.addr
+    }
+
+    async fn pair(
+        &self,
+        handler: Arc<dyn PairingHandler>,
+    ) -> Result<PairingResult, BluetoothError> {
+        // Classic pairing doesn't require authentication.
+        let status = self
+            .radio
+            .pair(
+                BondedDevice::Classic(self.addr),
+                ProtectionLevel::Encryption,
+                handler.as_ref(),
+            )
+            .await?;
+        check_pairing_result(status)
+    }
+
+    async fn connect_rfcomm(
+        &self,
+        service_uuid: BtUuid,
+    ) -> Result<RfcommSocket, BluetoothError> {
+        let socket = self
+            .radio
+            .connect_rfcomm(u64::from(self.addr), service_uuid)?;
+        Ok(RfcommSocket::new(socket))
+    }
+
+    /// Simulated peripherals have a single link, which `BleDevice::connect()`
+    /// makes.
+    async fn connection_state(
+        &self,
+    ) -> Result<ConnectionState, BluetoothError> {
+        connection_state(&self.radio, u64::from(self.addr))
+    }
+
+    async fn connection_events(
+        &self,
+    ) -> Result<ConnectionStateStream, BluetoothError> {
+        let receiver =
+            self.radio.watch_connection_state(u64::from(self.addr))?;
+        Ok(ConnectionStateStream::new(receiver))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Mutex;
+
+    use futures::{executor::block_on, AsyncReadExt, AsyncWriteExt, StreamExt};
+
+    use super::*;
+    use crate::{
+        api::{BleDevice as _, ClassicDevice as _},
+        common::{BleAddressKind, BondState, GattCharacteristicProperties},
+        sim::{SimCharacteristic, SimPairingMethod, SimPeripheral},
+    };
+
+    const ADDR: u64 = 0x112233445566;
+
+    /// Answers every request with `accept`, providing `pin` and recording the
+    /// requests it gets.
+    struct TestHandler {
+        accept: bool,
+        pin: Option<String>,
+        requests: Mutex<Vec<String>>,
+    }
+
+    impl TestHandler {
+        fn new(accept: bool, pin: Option<&str>) -> Arc<Self> {
+            Arc::new(TestHandler {
+                accept,
+                pin: pin.map(String::from),
+                requests: Mutex::new(Vec::new()),
+            })
+        }
+
+        fn record(&self, request: String) {
+            self.requests.lock().unwrap().push(request);
+        }
+    }
+
+    #[async_trait]
+    impl PairingHandler for TestHandler {
+        async fn confirm(&self) -> bool {
+            self.record(String::from("confirm"));
+            self.accept
+        }
+
+        async fn display_pin(&self, pin: &str) {
+            self.record(format!("display {}", pin));
+        }
+
+        async fn provide_pin(&self) -> Option<String> {
+            self.record(String::from("provide"));
+            self.pin.clone()
+        }
+
+        async fn confirm_pin_match(&self, pin: &str) -> bool {
+            self.record(format!("match {}", pin));
+            self.accept
+        }
+    }
+
+    /// Pair with a peripheral using `method`, answering with `handler`.
+    fn pair_using(
+        method: SimPairingMethod,
+        handler: Arc<TestHandler>,
+    ) -> Result<PairingResult, BluetoothError> {
+        let radio = SimRadio::new();
+        let mut peripheral = SimPeripheral::new(
+            BleAddress::new(ADDR, BleAddressKind::Public),
+            "Buds",
+        );
+        peripheral.set_pairing_method(method);
+        radio.add_peripheral(peripheral);
+
+        let device = radio.classic_device(ClassicAddress::from(ADDR)).unwrap();
+        block_on(device.pair(handler))
+    }
+
+    /// Pair with a peripheral configured to report `result`.
+    fn pair_with(
+        result: Result<PairingResult, BluetoothError>,
+    ) -> Result<PairingResult, BluetoothError> {
+        let radio = SimRadio::new();
+        let mut peripheral = SimPeripheral::new(
+            BleAddress::new(ADDR, BleAddressKind::Public),
+            "Buds",
+        );
+        peripheral.set_pairing_result(result);
+        radio.add_peripheral(peripheral);
+
+        let device = radio.classic_device(ClassicAddress::from(ADDR)).unwrap();
+        block_on(device.pair(TestHandler::new(true, None)))
+    }
+
+    #[test]
+    fn ble_device_name() {
+        let radio = SimRadio::new();
+        let addr = BleAddress::new(ADDR, BleAddressKind::Random);
+        radio.add_peripheral(SimPeripheral::new(addr, "Buds"));
+
+        let device = radio.ble_device(addr).unwrap();
+        assert_eq!(device.address(), addr);
+        assert_eq!(device.name().unwrap(), "Buds");
+
+        let public = BleAddress::new(ADDR, BleAddressKind::Public);
+        assert!(radio.ble_device(public).is_err());
+    }
+
+    #[test]
+    fn connection_events() {
+        let radio = SimRadio::new();
+        let addr = BleAddress::new(ADDR, BleAddressKind::Public);
+        radio.add_peripheral(SimPeripheral::new(addr, "Buds"));
+
+        let device = radio.ble_device(addr).unwrap();
+        let classic = radio.classic_device(ClassicAddress::from(ADDR)).unwrap();
+        block_on(async {
+            let mut states = device.connection_events().await.unwrap();
+            let mut classic_states = classic.connection_events().await.unwrap();
+            assert_eq!(
+                states.next().await,
+                Some(Ok(ConnectionState::Disconnected))
+            );
+            assert_eq!(
+                classic_states.next().await,
+                Some(Ok(ConnectionState::Disconnected))
+            );
+
+            device.connect().await.unwrap();
+            assert_eq!(
+                classic.connection_state().await,
+                Ok(ConnectionState::Connected)
+            );
+            assert_eq!(
+                states.next().await,
+                Some(Ok(ConnectionState::Connected))
+            );
+            assert_eq!(
+                classic_states.next().await,
+                Some(Ok(ConnectionState::Connected))
+            );
+
+            radio.set_powered(false);
+            assert_eq!(
+                device.connection_state().await,
+                Ok(ConnectionState::Disconnected)
+            );
+            assert_eq!(
+                states.next().await,
+                Some(Ok(ConnectionState::Disconnected))
+            );
+        });
+    }
+
+    #[test]
+    fn ble_device_pair() {
+        let radio = SimRadio::new();
+        let addr = BleAddress::new(ADDR, BleAddressKind::Random);
+        let mut peripheral = SimPeripheral::new(addr, "Buds");
+        peripheral.set_pairing_method(SimPairingMethod::ConfirmPinMatch(
+            String::from("012345"),
+        ));
+        radio.add_peripheral(peripheral);
+
+        let device = radio.ble_device(addr).unwrap();
+        block_on(async {
+            let mut bond_states = device.bond_state_changes().await.unwrap();
+            assert_eq!(
+                bond_states.next().await,
+                Some(Ok(BondState::NotBonded))
+            );
+            assert_eq!(device.is_paired().await, Ok(false));
+
+            let handler = TestHandler::new(true, None);
+            assert_eq!(
+                device
+                    .pair(
+                        ProtectionLevel::EncryptionAndAuthentication,
+                        handler.clone()
+                    )
+                    .await,
+                Ok(PairingResult::Success)
+            );
+            assert_eq!(*handler.requests.lock().unwrap(), ["match 012345"]);
+            assert_eq!(device.is_paired().await, Ok(true));
+            assert_eq!(bond_states.next().await, Some(Ok(BondState::Bonded)));
+
+            device.unpair().await.unwrap();
+            assert_eq!(device.is_paired().await, Ok(false));
+            assert_eq!(
+                bond_states.next().await,
+                Some(Ok(BondState::NotBonded))
+            );
+
+            // Paired again from the system settings.
+            radio.set_paired(addr, true).unwrap();
+            assert_eq!(bond_states.next().await, Some(Ok(BondState::Bonded)));
+        });
+    }
+
+    #[test]
+    fn ble_device_pair_protection_level() {
+        let radio = SimRadio::new();
+        let addr = BleAddress::new(ADDR, BleAddressKind::Random);
+        radio.add_peripheral(SimPeripheral::new(addr, "Buds"));
+
+        let device = radio.ble_device(addr).unwrap();
+        let handler = TestHandler::new(true, None);
+        assert!(matches!(
+            block_on(device.pair(
+                ProtectionLevel::EncryptionAndAuthentication,
+                handler.clone()
+            )),
+            Err(BluetoothError::PairingFailed(_))
+        ));
+        assert_eq!(
+            block_on(device.pair(ProtectionLevel::Encryption, handler)),
+            Ok(PairingResult::Success)
+        );
+    }
+
+    #[test]
+    fn ble_device_gatt() {
+        const SERVICE: BtUuid = BtUuid::FAST_PAIR;
+        const CHARACTERISTIC: BtUuid = BtUuid::from_u16(0x1234);
+
+        let radio = SimRadio::new();
+        let addr = BleAddress::new(ADDR, BleAddressKind::Random);
+        let mut peripheral = SimPeripheral::new(addr, "Buds");
+        peripheral.add_service(
+            SERVICE,
+            vec![SimCharacteristic::new(
+                CHARACTERISTIC,
+                GattCharacteristicProperties::WRITE
+                    | GattCharacteristicProperties::NOTIFY,
+                Vec::new(),
+            )],
+        );
+        peripheral.set_mtu(83);
+        radio.add_peripheral(peripheral);
+
+        let device = radio.ble_device(addr).unwrap();
+        block_on(async {
+            assert!(matches!(
+                device.discover_services(None).await,
+                Err(BluetoothError::FailedPrecondition(_))
+            ));
+
+            device.connect().await.unwrap();
+            assert_eq!(device.mtu().await, Ok(83));
+            let service =
+                device.discover_services(Some(SERVICE)).await.unwrap()[0];
+            let characteristic = device
+                .discover_characteristics(&service, Some(CHARACTERISTIC))
+                .await
+                .unwrap()[0];
+
+            let mut notifications =
+                device.subscribe(&characteristic).await.unwrap();
+            device
+                .write(&characteristic, &[0x01, 0x02], WriteType::WithResponse)
+                .await
+                .unwrap();
+            assert_eq!(
+                radio.characteristic_value(addr, CHARACTERISTIC),
+                Some(vec![0x01, 0x02])
+            );
+            assert!(matches!(
+                device
+                    .write(&characteristic, &[0x03], WriteType::WithoutResponse)
+                    .await,
+                Err(BluetoothError::Gatt(_))
+            ));
+
+            radio.notify(addr, CHARACTERISTIC, vec![0x04]).unwrap();
+            assert_eq!(notifications.next().await, Some(Ok(vec![0x04])));
+
+            device.disconnect().await.unwrap();
+            assert_eq!(notifications.next().await, None);
+            assert!(device.read(&characteristic).await.is_err());
+        });
+    }
+
+    #[test]
+    fn classic_device_unknown() {
+        let radio = SimRadio::new();
+        assert!(matches!(
+            radio.classic_device(ClassicAddress::from(ADDR)),
+            Err(BluetoothError::FailedPrecondition(_))
+        ));
+    }
+
+    #[test]
+    fn classic_device_pair_results() {
+        assert_eq!(
+            pair_with(Ok(PairingResult::Success)),
+            Ok(PairingResult::Success)
+        );
+        assert_eq!(
+            pair_with(Ok(PairingResult::AlreadyPaired)),
+            Ok(PairingResult::AlreadyPaired)
+        );
+        assert_eq!(
+            pair_with(Ok(PairingResult::AlreadyInProgress)),
+            Ok(PairingResult::AlreadyInProgress)
+        );
+        assert_eq!(
+            pair_with(Ok(PairingResult::Failure(String::from("rejected")))),
+            Err(BluetoothError::PairingFailed(String::from("rejected")))
+        );
+        assert_eq!(
+            pair_with(Err(BluetoothError::System(String::from("hw")))),
+            Err(BluetoothError::System(String::from("hw")))
+        );
+    }
+
+    #[test]
+    fn classic_device_pair_methods() {
+        let handler = TestHandler::new(true, None);
+        assert_eq!(
+            pair_using(SimPairingMethod::JustWorks, handler.clone()),
+            Ok(PairingResult::Success)
+        );
+        assert_eq!(*handler.requests.lock().unwrap(), ["confirm"]);
+
+        let handler = TestHandler::new(true, None);
+        assert_eq!(
+            pair_using(
+                SimPairingMethod::DisplayPin(String::from("012345")),
+                handler.clone()
+            ),
+            Ok(PairingResult::Success)
+        );
+        assert_eq!(*handler.requests.lock().unwrap(), ["display 012345"]);
+
+        let handler = TestHandler::new(true, Some("012345"));
+        assert_eq!(
+            pair_using(
+                SimPairingMethod::ProvidePin(String::from("012345")),
+                handler.clone()
+            ),
+            Ok(PairingResult::Success)
+        );
+        assert_eq!(*handler.requests.lock().unwrap(), ["provide"]);
+
+        let handler = TestHandler::new(true, None);
+        assert_eq!(
+            pair_using(
+                SimPairingMethod::ConfirmPinMatch(String::from("012345")),
+                handler.clone()
+            ),
+            Ok(PairingResult::Success)
+        );
+        assert_eq!(*handler.requests.lock().unwrap(), ["match 012345"]);
+    }
+
+    #[test]
+    fn classic_device_pair_rejected() {
+        assert!(matches!(
+            pair_using(
+                SimPairingMethod::JustWorks,
+                TestHandler::new(false, None)
+            ),
+            Err(BluetoothError::PairingFailed(_))
+        ));
+        assert!(matches!(
+            pair_using(
+                SimPairingMethod::ProvidePin(String::from("012345")),
+                TestHandler::new(true, None)
+            ),
+            Err(BluetoothError::PairingFailed(_))
+        ));
+        assert!(matches!(
+            pair_using(
+                SimPairingMethod::ConfirmPinMatch(String::from("012345")),
+                TestHandler::new(false, None)
+            ),
+            Err(BluetoothError::PairingFailed(_))
+        ));
+    }
+
+    #[test]
+    fn classic_device_rfcomm() {
+        let radio = SimRadio::new();
+        let mut peripheral = SimPeripheral::new(
+            BleAddress::new(ADDR, BleAddressKind::Public),
+            "Buds",
+        );
+        let mut incoming =
+            peripheral.add_rfcomm_service(BtUuid::MESSAGE_STREAM);
+        radio.add_peripheral(peripheral);
+        let device = radio.classic_device(ClassicAddress::from(ADDR)).unwrap();
+
+        block_on(async {
+            assert!(matches!(
+                device.connect_rfcomm(BtUuid::from_u16(0x1234)).await,
+                Err(BluetoothError::NotSupported(_))
+            ));
+
+            let mut socket =
+                device.connect_rfcomm(BtUuid::MESSAGE_STREAM).await.unwrap();
+            let mut remote = incoming.next().await.unwrap();
+            socket.write_all(&[0x03, 0x01, 0x00, 0x00]).await.unwrap();
+            let mut buf = [0; 4];
+            remote.read_exact(&mut buf).await.unwrap();
+            assert_eq!(buf, [0x03, 0x01, 0x00, 0x00]);
+
+            drop(remote);
+            assert_eq!(socket.read(&mut buf).await.unwrap(), 0);
+
+            radio.set_powered(false);
+            assert!(matches!(
+                device.connect_rfcomm(BtUuid::MESSAGE_STREAM).await,
+                Err(BluetoothError::FailedPrecondition(_))
+            ));
+        });
+    }
+}
@@ -0,0 +1,357 @@
+// Copyright 2023 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use futures::channel::mpsc::{self, UnboundedReceiver, UnboundedSender};
+
+use crate::common::{
+    BluetoothError, BtUuid, GattCharacteristic, GattCharacteristicProperties,
+    GattService, WriteType,
+};
+
+/// Channel delivering the values a characteristic notifies.
+pub(crate) type ValueReceiver =
+    UnboundedReceiver<Result<Vec<u8>, BluetoothError>>;
+
+/// A characteristic served by a `SimPeripheral`.
+#[derive(Clone, Debug)]
+pub struct SimCharacteristic {
+    uuid: BtUuid,
+    properties: GattCharacteristicProperties,
+    value: Vec<u8>,
+}
+
+impl SimCharacteristic {
+    /// Construct a characteristic supporting the operations in `properties`,
+    /// a combination of `GattCharacteristicProperties` bits, with an initial
+    /// `value`.
+    pub fn new(uuid: BtUuid, properties: u8, value: Vec<u8>) -> Self {
+        SimCharacteristic {
+            uuid,
+            properties: GattCharacteristicProperties::from_bits(properties),
+            value,
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+struct CharacteristicEntry {
+    characteristic: GattCharacteristic,
+    value: Vec<u8>,
+    subscribers: Vec<UnboundedSender<Result<Vec<u8>, BluetoothError>>>,
+}
+
+#[derive(Clone, Debug)]
+struct ServiceEntry {
+    service: GattService,
+    characteristics: Vec<CharacteristicEntry>,
+}
+
+/// GATT database of a simulated peripheral. Handles are allocated in order,
+/// like a real server: one for each service declaration, then three for each
+/// characteristic, for its declaration, value and configuration descriptor.
+#[derive(Clone, Debug, Default)]
+pub(crate) struct GattDatabase {
+    services: Vec<ServiceEntry>,
+    next_handle: u16,
+}
+
+impl GattDatabase {
+    pub(crate) fn add_service(
+        &mut self,
+        uuid: BtUuid,
+        characteristics: Vec<SimCharacteristic>,
+    ) {
+        self.next_handle += 1;
+        let service = GattService::new(uuid, self.next_handle);
+
+        let characteristics = characteristics
+            .into_iter()
+            .map(|characteristic| {
+                let handle = self.next_handle + 2;
+                self.next_handle += 3;
+                CharacteristicEntry {
+                    characteristic: GattCharacteristic::new(
+                        characteristic.uuid,
+                        handle,
+                        characteristic.properties,
+                    ),
+                    value: characteristic.value,
+                    subscribers: Vec::new(),
+                }
+            })
+            .collect();
+
+        self.services.push(ServiceEntry {
+            service,
+            characteristics,
+        });
+    }
+
+    pub(crate) fn services(&self, uuid: Option<BtUuid>) -> Vec<GattService> {
+        self.services
+            .iter()
+            .map(|entry| entry.service)
+            .filter(|service| uuid.is_none_or(|uuid| service.uuid() == uuid))
+            .collect()
+    }
+
+    pub(crate) fn characteristics(
+        &self,
+        service: &GattService,
+        uuid: Option<BtUuid>,
+    ) -> Result<Vec<GattCharacteristic>, BluetoothError> {
+        let entry = self
+            .services
+            .iter()
+            .find(|entry| entry.service == *service)
+            .ok_or(BluetoothError::Gatt(format!(
+                "no service with handle {:#06x}",
+                service.handle()
+            )))?;
+
+        Ok(entry
+            .characteristics
+            .iter()
+            .map(|entry| entry.characteristic)
+            .filter(|characteristic| {
+                uuid.is_none_or(|uuid| characteristic.uuid() == uuid)
+            })
+            .collect())
+    }
+
+    pub(crate) fn read(
+        &self,
+        characteristic: &GattCharacteristic,
+    ) -> Result<Vec<u8>, BluetoothError> {
+        let entry = self.find(characteristic)?;
+        check_properties(entry, GattCharacteristicProperties::READ)?;
+
+        Ok(entry.value.clone())
+    }
+
+    pub(crate) fn write(
+        &mut self,
+        characteristic: &GattCharacteristic,
+        value: &[u8],
+        write_type: WriteType,
+    ) -> Result<(), BluetoothError> {
+        let entry = self.find_mut(characteristic)?;
+        check_properties(
+            entry,
+            match write_type {
+                WriteType::WithResponse => GattCharacteristicProperties::WRITE,
+                WriteType::WithoutResponse => {
+                    GattCharacteristicProperties::WRITE_WITHOUT_RESPONSE
+                }
+            },
+        )?;
+
+        entry.value = value.to_vec();
+        Ok(())
+    }
+
+    pub(crate) fn subscribe(
+        &mut self,
+        characteristic: &GattCharacteristic,
+    ) -> Result<ValueReceiver, BluetoothError> {
+        let entry = self.find_mut(characteristic)?;
+        let properties = entry.characteristic.properties();
+        if !properties.contains(GattCharacteristicProperties::NOTIFY)
+            && !properties.contains(GattCharacteristicProperties::INDICATE)
+        {
+            return Err(not_permitted(entry));
+        }
+
+        let (sender, receiver) = mpsc::unbounded();
+        entry.subscribers.push(sender);
+        Ok(receiver)
+    }
+
+    /// Set the value of the first characteristic identified by `uuid`, and
+    /// send it to its subscribers.
+    pub(crate) fn notify(
+        &mut self,
+        uuid: BtUuid,
+        value: Vec<u8>,
+    ) -> Result<(), BluetoothError> {
+        let entry = self
+            .services
+            .iter_mut()
+            .flat_map(|service| service.characteristics.iter_mut())
+            .find(|entry| entry.characteristic.uuid() == uuid)
+            .ok_or(BluetoothError::Gatt(format!(
+                "no characteristic with UUID {}",
+                uuid
+            )))?;
+
+        entry
+            .subscribers
+            .retain(|sender| sender.unbounded_send(Ok(value.clone())).is_ok());
+        entry.value = value;
+        Ok(())
+    }
+
+    /// Retrieve the value of the first characteristic identified by `uuid`.
+    pub(crate) fn value(&self, uuid: BtUuid) -> Option<Vec<u8>> {
+        self.services
+            .iter()
+            .flat_map(|service| service.characteristics.iter())
+            .find(|entry| entry.characteristic.uuid() == uuid)
+            .map(|entry| entry.value.clone())
+    }
+
+    /// End every subscription, as happens when the peripheral disconnects.
+    pub(crate) fn unsubscribe_all(&mut self) {
+        for service in &mut self.services {
+            for entry in &mut service.characteristics {
+                entry.subscribers.clear();
+            }
+        }
+    }
+
+    fn find(
+        &self,
+        characteristic: &GattCharacteristic,
+    ) -> Result<&CharacteristicEntry, BluetoothError> {
+        self.services
+            .iter()
+            .flat_map(|service| service.characteristics.iter())
+            .find(|entry| entry.characteristic == *characteristic)
+            .ok_or(unknown_characteristic(characteristic))
+    }
+
+    fn find_mut(
+        &mut self,
+        characteristic: &GattCharacteristic,
+    ) -> Result<&mut CharacteristicEntry, BluetoothError> {
+        self.services
+            .iter_mut()
+            .flat_map(|service| service.characteristics.iter_mut())
+            .find(|entry| entry.characteristic == *characteristic)
+            .ok_or(unknown_characteristic(characteristic))
+    }
+}
+
+fn check_properties(
+    entry: &CharacteristicEntry,
+    properties: u8,
+) -> Result<(), BluetoothError> {
+    match entry.characteristic.properties().contains(properties) {
+        true => Ok(()),
+        false => Err(not_permitted(entry)),
+    }
+}
+
+fn not_permitted(entry: &CharacteristicEntry) -> BluetoothError {
+    BluetoothError::Gatt(format!(
+        "operation not permitted on characteristic {:#06x}",
+        entry.characteristic.handle()
+    ))
+}
+
+fn unknown_characteristic(
+    characteristic: &GattCharacteristic,
+) -> BluetoothError {
+    BluetoothError::Gatt(format!(
+        "no characteristic with handle {:#06x}",
+        characteristic.handle()
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use futures::StreamExt;
+
+    use super::*;
+
+    const SERVICE: BtUuid = BtUuid::FAST_PAIR;
+    const KEY_BASED_PAIRING: BtUuid = BtUuid::from_u16(0x1234);
+    const MODEL_ID: BtUuid = BtUuid::from_u16(0x1233);
+
+    fn database() -> GattDatabase {
+        let mut database = GattDatabase::default();
+        database.add_service(
+            SERVICE,
+            vec![
+                SimCharacteristic::new(
+                    MODEL_ID,
+                    GattCharacteristicProperties::READ,
+                    vec![0x01, 0x02, 0x03],
+                ),
+                SimCharacteristic::new(
+                    KEY_BASED_PAIRING,
+                    GattCharacteristicProperties::WRITE
+                        | GattCharacteristicProperties::NOTIFY,
+                    Vec::new(),
+                ),
+            ],
+        );
+        database
+    }
+
+    #[test]
+    fn allocates_handles() {
+        let database = database();
+        let services = database.services(None);
+        assert_eq!(services, vec![GattService::new(SERVICE, 1)]);
+        assert!(database.services(Some(BtUuid::from_u16(0xfe2d))).is_empty());
+
+        let handles: Vec<u16> = database
+            .characteristics(&services[0], None)
+            .unwrap()
+            .iter()
+            .map(GattCharacteristic::handle)
+            .collect();
+        assert_eq!(handles, vec![3, 6]);
+    }
+
+    #[test]
+    fn checks_properties() {
+        let mut database = database();
+        let service = database.services(None)[0];
+        let model_id =
+            database.characteristics(&service, Some(MODEL_ID)).unwrap()[0];
+
+        assert_eq!(database.read(&model_id), Ok(vec![0x01, 0x02, 0x03]));
+        assert!(matches!(
+            database.write(&model_id, &[0x04], WriteType::WithResponse),
+            Err(BluetoothError::Gatt(_))
+        ));
+        assert!(matches!(
+            database.subscribe(&model_id),
+            Err(BluetoothError::Gatt(_))
+        ));
+    }
+
+    #[test]
+    fn notifies_subscribers() {
+        let mut database = database();
+        let service = database.services(None)[0];
+        let characteristic = database
+            .characteristics(&service, Some(KEY_BASED_PAIRING))
+            .unwrap()[0];
+        let mut receiver = database.subscribe(&characteristic).unwrap();
+
+        database
+            .write(&characteristic, &[0x01], WriteType::WithResponse)
+            .unwrap();
+        database.notify(KEY_BASED_PAIRING, vec![0x02]).unwrap();
+        database.unsubscribe_all();
+
+        let values =
+            futures::executor::block_on(receiver.by_ref().collect::<Vec<_>>());
+        assert_eq!(values, vec![Ok(vec![0x02])]);
+        assert_eq!(database.value(KEY_BASED_PAIRING), Some(vec![0x02]));
+    }
+}
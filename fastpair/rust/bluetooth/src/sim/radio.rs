@@ -0,0 +1,1005 @@
+// Copyright 2023 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex, OnceLock},
+    time::Duration,
+};
+
+use futures::channel::mpsc::{self, UnboundedReceiver, UnboundedSender};
+
+use super::{
+    gatt::GattDatabase, BleAdapter, BleAdvertiser, BleDevice, ClassicDevice,
+    SimCharacteristic, SimSocket,
+};
+use crate::common::{
+    AdapterId, AdapterInfo, AdvertisingStatus, BleAddress, BleAddressKind,
+    BleAdvertisement, BluetoothError, BondEvent, BondState, BondedDevice,
+    BtUuid, ClassicAddress, ConnectionState, PairingHandler, PairingResult,
+    ProtectionLevel, RadioState,
+};
+
+/// Random static address that radios publish their advertisements from.
+const RADIO_ADDRESS: u64 = 0xc0_00_00_00_00_01;
+
+/// Name adapters opened on a radio report.
+const RADIO_NAME: &str = "Simulated Radio";
+
+/// Identifier adapters opened on a radio report. The simulated platform has a
+/// single adapter, on the global radio.
+pub(crate) const RADIO_ID: &str = "sim0";
+
+/// Largest extended advertising payload.
+/// Bluetooth Core Specification, Vol 4, Part E, Section 7.8.54.
+const MAX_ADVERTISEMENT_LENGTH: usize = 251;
+
+/// Channel delivering the status updates of a published advertisement.
+pub(crate) type StatusSender =
+    UnboundedSender<Result<AdvertisingStatus, BluetoothError>>;
+
+/// ATT MTU of new peripherals, the minimum for LE.
+/// Bluetooth Core Specification, Vol 3, Part F, Section 3.2.8.
+const DEFAULT_MTU: u16 = 23;
+
+/// Channel delivering the bond states of a peripheral.
+type BondStateSender = UnboundedSender<Result<BondState, BluetoothError>>;
+
+/// Channel delivering the connection states of a peripheral.
+type ConnectionStateSender =
+    UnboundedSender<Result<ConnectionState, BluetoothError>>;
+
+/// Channel delivering the bonds a radio gains and loses.
+type BondEventSender = UnboundedSender<Result<BondEvent, BluetoothError>>;
+
+/// Channel delivering the advertisements received by a scan.
+pub(crate) type ScanSender =
+    UnboundedSender<Result<BleAdvertisement, BluetoothError>>;
+
+/// Channel delivering the states of a radio.
+type RadioStateSender = UnboundedSender<Result<RadioState, BluetoothError>>;
+
+/// Association model a `SimPeripheral` pairs with, deciding which request is
+/// made to the `PairingHandler`. Only the models using a PIN authenticate.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum SimPairingMethod {
+    /// Ask `PairingHandler::confirm`.
+    JustWorks,
+    /// Show the PIN with `PairingHandler::display_pin`, which the peripheral
+    /// always enters correctly.
+    DisplayPin(String),
+    /// Ask `PairingHandler::provide_pin` for the PIN the peripheral displays.
+    ProvidePin(String),
+    /// Ask `PairingHandler::confirm_pin_match` whether the PIN matches.
+    ConfirmPinMatch(String),
+}
+
+/// A fake peripheral registered with a `SimRadio`. Devices created for its
+/// address report its name and pairing outcome, and serve its GATT and RFCOMM
+/// services.
+#[derive(Clone, Debug)]
+pub struct SimPeripheral {
+    address: BleAddress,
+    name: String,
+    pairing_result: Result<PairingResult, BluetoothError>,
+    pairing_method: SimPairingMethod,
+    /// The transport the peripheral is bonded over, if any.
+    bond: Option<BondedDevice>,
+    /// Channels of the devices watching the bond state.
+    bond_watchers: Vec<BondStateSender>,
+    connected: bool,
+    /// Channels of the devices watching the connection state.
+    connection_watchers: Vec<ConnectionStateSender>,
+    mtu: u16,
+    gatt: GattDatabase,
+    /// Channels accepting the RFCOMM connections of each service.
+    rfcomm_services: HashMap<BtUuid, UnboundedSender<SimSocket>>,
+}
+
+impl SimPeripheral {
+    /// Construct a new unpaired peripheral that pairs successfully.
+    pub fn new(address: BleAddress, name: &str) -> Self {
+        SimPeripheral {
+            address,
+            name: String::from(name),
+            pairing_result: Ok(PairingResult::Success),
+            pairing_method: SimPairingMethod::JustWorks,
+            bond: None,
+            bond_watchers: Vec::new(),
+            connected: false,
+            connection_watchers: Vec::new(),
+            mtu: DEFAULT_MTU,
+            gatt: GattDatabase::default(),
+            rfcomm_services: HashMap::new(),
+        }
+    }
+
+    /// Serve a primary service with the given characteristics.
+    pub fn add_service(
+        &mut self,
+        uuid: BtUuid,
+        characteristics: Vec<SimCharacteristic>,
+    ) {
+        self.gatt.add_service(uuid, characteristics);
+    }
+
+    /// Serve an RFCOMM service. The peripheral's end of every channel a device
+    /// opens to it is delivered to the returned receiver. Dropping the
+    /// receiver refuses later connections.
+    pub fn add_rfcomm_service(
+        &mut self,
+        uuid: BtUuid,
+    ) -> UnboundedReceiver<SimSocket> {
+        let (sender, receiver) = mpsc::unbounded();
+        self.rfcomm_services.insert(uuid, sender);
+        receiver
+    }
+
+    /// Configure the ATT MTU negotiated when connecting to this peripheral.
+    pub fn set_mtu(&mut self, mtu: u16) {
+        self.mtu = mtu;
+    }
+
+    /// Configure what `ClassicDevice::pair` reports for this peripheral.
+    /// `PairingResult::Failure` is reported as `BluetoothError::PairingFailed`,
+    /// like on real platforms.
+    pub fn set_pairing_result(
+        &mut self,
+        result: Result<PairingResult, BluetoothError>,
+    ) {
+        self.pairing_result = result;
+    }
+
+    /// Configure the association model pairing with this peripheral uses.
+    /// The configured pairing result is only reported once the handler
+    /// accepted pairing.
+    pub fn set_pairing_method(&mut self, method: SimPairingMethod) {
+        self.pairing_method = method;
+    }
+
+    /// Configure whether the peripheral is bonded already, over LE.
+    pub fn set_paired(&mut self, paired: bool) {
+        self.update_bond(paired.then_some(BondedDevice::Ble(self.address)));
+    }
+
+    /// Retrieve the peripheral's address.
+    pub fn address(&self) -> BleAddress {
+        self.address
+    }
+
+    /// Retrieve the peripheral's name.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Whether a device is connected to the peripheral.
+    pub fn is_connected(&self) -> bool {
+        self.connected
+    }
+
+    /// Whether the peripheral is bonded.
+    pub fn is_paired(&self) -> bool {
+        self.bond.is_some()
+    }
+
+    pub(crate) fn mtu(&self) -> u16 {
+        self.mtu
+    }
+
+    pub(crate) fn gatt(&mut self) -> &mut GattDatabase {
+        &mut self.gatt
+    }
+
+    /// Update the bond, notifying the devices watching its state of changes.
+    /// Returns the bond gained or lost, if any.
+    fn update_bond(&mut self, bond: Option<BondedDevice>) -> Option<BondEvent> {
+        let event = match (self.bond, bond) {
+            (None, Some(device)) => BondEvent::Added(device),
+            (Some(device), None) => BondEvent::Removed(device),
+            _ => return None,
+        };
+
+        self.bond = bond;
+        let state = match event {
+            BondEvent::Added(_) => BondState::Bonded,
+            BondEvent::Removed(_) => BondState::NotBonded,
+        };
+        self.bond_watchers
+            .retain(|sender| sender.unbounded_send(Ok(state)).is_ok());
+        Some(event)
+    }
+
+    /// Update the connection state, notifying the devices watching it of
+    /// changes.
+    fn update_connected(&mut self, connected: bool) {
+        if self.connected == connected {
+            return;
+        }
+
+        self.connected = connected;
+        let state = connection_state(connected);
+        self.connection_watchers
+            .retain(|sender| sender.unbounded_send(Ok(state)).is_ok());
+    }
+
+    /// Disconnect, ending every subscription.
+    fn disconnect(&mut self) {
+        self.update_connected(false);
+        self.gatt.unsubscribe_all();
+    }
+}
+
+fn connection_state(connected: bool) -> ConnectionState {
+    match connected {
+        true => ConnectionState::Connected,
+        false => ConnectionState::Disconnected,
+    }
+}
+
+/// Convert whether a radio is powered.
+fn radio_state(powered: bool) -> RadioState {
+    match powered {
+        true => RadioState::On,
+        false => RadioState::Off,
+    }
+}
+
+/// Address the peripheral bonded as `device` is registered at. Peripherals
+/// are registered by address alone, whichever transport they bond over.
+fn peripheral_address(device: BondedDevice) -> u64 {
+    match device {
+        BondedDevice::Ble(addr) => u64::from(addr),
+        BondedDevice::Classic(addr) => u64::from(addr),
+    }
+}
+
+/// Advertisements with their delay relative to the start of a scan.
+pub(crate) type Script = Vec<(Duration, BleAdvertisement)>;
+
+struct SimState {
+    powered: bool,
+    /// Registered peripherals, keyed by their 48-bit address so that both BLE
+    /// and BT Classic addresses can be looked up.
+    peripherals: HashMap<u64, SimPeripheral>,
+    /// Advertisements replayed by every scan, with their delay relative to the
+    /// start of the scan.
+    script: Script,
+    /// Channels of the adapters currently scanning.
+    scanners: Vec<ScanSender>,
+    /// Advertisements published through the radio, with the channel of their
+    /// publisher.
+    advertisers: Vec<(BleAdvertisement, StatusSender)>,
+    /// Channels of the adapters watching bonds.
+    bond_watchers: Vec<BondEventSender>,
+    /// Channels of the adapters watching the radio being switched on and off.
+    radio_watchers: Vec<RadioStateSender>,
+}
+
+impl SimState {
+    fn check_powered(&self) -> Result<(), BluetoothError> {
+        match self.powered {
+            true => Ok(()),
+            false => Err(BluetoothError::FailedPrecondition(String::from(
+                "simulated radio is powered off",
+            ))),
+        }
+    }
+
+    fn peripheral_mut(
+        &mut self,
+        addr: u64,
+    ) -> Result<&mut SimPeripheral, BluetoothError> {
+        self.peripherals.get_mut(&addr).ok_or(
+            BluetoothError::FailedPrecondition(format!(
+                "no simulated peripheral with address {:#014x}",
+                addr
+            )),
+        )
+    }
+
+    /// Deliver an advertisement to every adapter that is currently scanning.
+    fn advertise(&mut self, advertisement: &BleAdvertisement) {
+        self.scanners.retain(|sender| {
+            sender.unbounded_send(Ok(advertisement.clone())).is_ok()
+        });
+    }
+
+    /// Update the bond of the peripheral registered at `addr`, notifying
+    /// every adapter and device watching it of changes.
+    fn update_bond(
+        &mut self,
+        addr: u64,
+        bond: Option<BondedDevice>,
+    ) -> Result<(), BluetoothError> {
+        if let Some(event) = self.peripheral_mut(addr)?.update_bond(bond) {
+            self.bond_watchers
+                .retain(|sender| sender.unbounded_send(Ok(event)).is_ok());
+        }
+        Ok(())
+    }
+}
+
+/// A scriptable virtual radio. Cloning a `SimRadio` yields another handle to
+/// the same radio.
+///
+/// `Platform` uses the radio returned by `SimRadio::global()`. Tests that run
+/// in parallel should instead create their own radio with `SimRadio::new()`
+/// and open adapters and devices through it.
+#[derive(Clone)]
+pub struct SimRadio {
+    state: Arc<Mutex<SimState>>,
+}
+
+impl Default for SimRadio {
+    fn default() -> Self {
+        SimRadio::new()
+    }
+}
+
+impl SimRadio {
+    /// Construct a new powered-on radio with no peripherals.
+    pub fn new() -> Self {
+        SimRadio {
+            state: Arc::new(Mutex::new(SimState {
+                powered: true,
+                peripherals: HashMap::new(),
+                script: Vec::new(),
+                scanners: Vec::new(),
+                advertisers: Vec::new(),
+                bond_watchers: Vec::new(),
+                radio_watchers: Vec::new(),
+            })),
+        }
+    }
+
+    /// Retrieve the process-wide radio used by `Platform`.
+    pub fn global() -> &'static SimRadio {
+        static GLOBAL: OnceLock<SimRadio> = OnceLock::new();
+        GLOBAL.get_or_init(SimRadio::new)
+    }
+
+    /// Register a peripheral, replacing any peripheral with the same address.
+    pub fn add_peripheral(&self, peripheral: SimPeripheral) {
+        let addr = u64::from(peripheral.address);
+        self.state
+            .lock()
+            .unwrap()
+            .peripherals
+            .insert(addr, peripheral);
+    }
+
+    /// Deliver an advertisement to every adapter that is currently scanning.
+    pub fn advertise(&self, advertisement: BleAdvertisement) {
+        self.state.lock().unwrap().advertise(&advertisement);
+    }
+
+    /// Deliver an advertisement `delay` after the start of every future scan.
+    /// Scripted advertisements are delivered in order of their delay.
+    pub fn schedule_advertisement(
+        &self,
+        delay: Duration,
+        advertisement: BleAdvertisement,
+    ) {
+        let mut state = self.state.lock().unwrap();
+        let index = state.script.partition_point(|(at, _)| *at <= delay);
+        state.script.insert(index, (delay, advertisement));
+    }
+
+    /// Pair the peripheral registered at `address` over LE or unpair it, like
+    /// the user would from the system settings.
+    pub fn set_paired(
+        &self,
+        address: BleAddress,
+        paired: bool,
+    ) -> Result<(), BluetoothError> {
+        let bond = paired.then_some(BondedDevice::Ble(address));
+        self.state
+            .lock()
+            .unwrap()
+            .update_bond(u64::from(address), bond)
+    }
+
+    /// Switch the radio on or off. Switching it off ends every active scan,
+    /// advertisement and connection, and makes new ones fail.
+    pub fn set_powered(&self, powered: bool) {
+        let mut state = self.state.lock().unwrap();
+        if state.powered != powered {
+            let radio_state = radio_state(powered);
+            state.radio_watchers.retain(|sender| {
+                sender.unbounded_send(Ok(radio_state)).is_ok()
+            });
+        }
+        state.powered = powered;
+        if !powered {
+            for sender in state.scanners.drain(..) {
+                let _ = sender.unbounded_send(Err(
+                    BluetoothError::FailedPrecondition(String::from(
+                        "simulated radio was powered off",
+                    )),
+                ));
+            }
+            state
+                .peripherals
+                .values_mut()
+                .for_each(SimPeripheral::disconnect);
+            for (_, sender) in state.advertisers.drain(..) {
+                let _ = sender.unbounded_send(Err(
+                    BluetoothError::FailedPrecondition(String::from(
+                        "simulated radio was powered off",
+                    )),
+                ));
+            }
+        }
+    }
+
+    /// Retrieve the address advertisements published through this radio are
+    /// sent from.
+    pub fn address(&self) -> BleAddress {
+        BleAddress::new(RADIO_ADDRESS, BleAddressKind::Random)
+    }
+
+    /// Describe the adapters opened on this radio, which support every
+    /// capability.
+    pub(crate) fn info(&self) -> AdapterInfo {
+        AdapterInfo {
+            id: AdapterId::from(RADIO_ID),
+            address: self.address(),
+            name: String::from(RADIO_NAME),
+            central_role_supported: true,
+            peripheral_role_supported: true,
+            extended_advertising_supported: true,
+            coded_phy_supported: true,
+            max_advertisement_length: MAX_ADVERTISEMENT_LENGTH,
+            radio_state: radio_state(self.state.lock().unwrap().powered),
+        }
+    }
+
+    /// Watch the radio being switched on and off. The current state is
+    /// delivered first.
+    pub(crate) fn watch_radio_state(
+        &self,
+    ) -> UnboundedReceiver<Result<RadioState, BluetoothError>> {
+        let mut state = self.state.lock().unwrap();
+        let (sender, receiver) = mpsc::unbounded();
+        // The receiver is alive, so this can't fail.
+        let _ = sender.unbounded_send(Ok(radio_state(state.powered)));
+        state.radio_watchers.push(sender);
+        receiver
+    }
+
+    /// Open an adapter on this radio.
+    pub fn adapter(&self) -> BleAdapter {
+        BleAdapter::with_radio(self.clone())
+    }
+
+    /// Set the value of the first characteristic identified by `uuid` on the
+    /// peripheral at `addr`, and notify it to every subscribed device.
+    pub fn notify(
+        &self,
+        addr: BleAddress,
+        uuid: BtUuid,
+        value: Vec<u8>,
+    ) -> Result<(), BluetoothError> {
+        let mut state = self.state.lock().unwrap();
+        state
+            .peripheral_mut(u64::from(addr))?
+            .gatt
+            .notify(uuid, value)
+    }
+
+    /// Retrieve the value of the first characteristic identified by `uuid` on
+    /// the peripheral at `addr`, e.g. to check what a device wrote.
+    pub fn characteristic_value(
+        &self,
+        addr: BleAddress,
+        uuid: BtUuid,
+    ) -> Option<Vec<u8>> {
+        let mut state = self.state.lock().unwrap();
+        state.peripheral_mut(u64::from(addr)).ok()?.gatt.value(uuid)
+    }
+
+    /// Open an advertiser on this radio.
+    pub fn advertiser(&self) -> BleAdvertiser {
+        BleAdvertiser::with_radio(self.clone())
+    }
+
+    /// Open the BLE device at `addr`, which must be a registered peripheral.
+    pub fn ble_device(
+        &self,
+        addr: BleAddress,
+    ) -> Result<BleDevice, BluetoothError> {
+        BleDevice::with_radio(self.clone(), addr)
+    }
+
+    /// Open the BT Classic device at `addr`, which must be a registered
+    /// peripheral.
+    pub fn classic_device(
+        &self,
+        addr: ClassicAddress,
+    ) -> Result<ClassicDevice, BluetoothError> {
+        ClassicDevice::with_radio(self.clone(), addr)
+    }
+
+    /// Register a new scanner, returning its channel and the scripted
+    /// advertisements it should replay. Advertisements currently published
+    /// through the radio are replayed first.
+    pub(crate) fn start_scan(
+        &self,
+    ) -> Result<
+        (
+            UnboundedReceiver<Result<BleAdvertisement, BluetoothError>>,
+            Script,
+        ),
+        BluetoothError,
+    > {
+        let mut state = self.state.lock().unwrap();
+        state.check_powered()?;
+
+        let (sender, receiver) = mpsc::unbounded();
+        state.scanners.push(sender);
+
+        state.advertisers.retain(|(_, sender)| !sender.is_closed());
+        let script = state
+            .advertisers
+            .iter()
+            .map(|(advertisement, _)| (Duration::ZERO, advertisement.clone()))
+            .chain(state.script.iter().cloned())
+            .collect();
+
+        Ok((receiver, script))
+    }
+
+    /// Publish an advertisement until the returned channel is closed,
+    /// delivering it to the adapters currently scanning.
+    pub(crate) fn start_advertising(
+        &self,
+        advertisement: BleAdvertisement,
+    ) -> Result<
+        UnboundedReceiver<Result<AdvertisingStatus, BluetoothError>>,
+        BluetoothError,
+    > {
+        let mut state = self.state.lock().unwrap();
+        state.check_powered()?;
+
+        let (sender, receiver) = mpsc::unbounded();
+        sender
+            .unbounded_send(Ok(AdvertisingStatus::Started))
+            .map_err(|err| BluetoothError::Internal(err.to_string()))?;
+        state.advertise(&advertisement);
+        state.advertisers.push((advertisement, sender));
+
+        Ok(receiver)
+    }
+
+    /// Retrieve a copy of the peripheral registered at `addr`.
+    pub(crate) fn peripheral(
+        &self,
+        addr: u64,
+    ) -> Result<SimPeripheral, BluetoothError> {
+        self.state
+            .lock()
+            .unwrap()
+            .peripherals
+            .get(&addr)
+            .cloned()
+            .ok_or(BluetoothError::FailedPrecondition(format!(
+                "no simulated peripheral with address {:#014x}",
+                addr
+            )))
+    }
+
+    /// Connect to or disconnect from the peripheral registered at `addr`.
+    pub(crate) fn set_connected(
+        &self,
+        addr: u64,
+        connected: bool,
+    ) -> Result<(), BluetoothError> {
+        let mut state = self.state.lock().unwrap();
+        if connected {
+            state.check_powered()?;
+        }
+
+        let peripheral = state.peripheral_mut(addr)?;
+        match connected {
+            true => peripheral.update_connected(true),
+            false => peripheral.disconnect(),
+        }
+        Ok(())
+    }
+
+    /// Run `op` on the peripheral registered at `addr`, which must be
+    /// connected.
+    pub(crate) fn with_connected<T>(
+        &self,
+        addr: u64,
+        op: impl FnOnce(&mut SimPeripheral) -> Result<T, BluetoothError>,
+    ) -> Result<T, BluetoothError> {
+        let mut state = self.state.lock().unwrap();
+        let peripheral = state.peripheral_mut(addr)?;
+        if !peripheral.connected {
+            return Err(BluetoothError::FailedPrecondition(String::from(
+                "simulated peripheral isn't connected",
+            )));
+        }
+
+        op(peripheral)
+    }
+
+    /// Open an RFCOMM channel to a service of the peripheral registered at
+    /// `addr`, returning the device's end.
+    pub(crate) fn connect_rfcomm(
+        &self,
+        addr: u64,
+        uuid: BtUuid,
+    ) -> Result<SimSocket, BluetoothError> {
+        let mut state = self.state.lock().unwrap();
+        state.check_powered()?;
+
+        let peripheral = state.peripheral_mut(addr)?;
+        let (local, remote) = SimSocket::pair();
+        match peripheral.rfcomm_services.get(&uuid) {
+            Some(sender) if sender.unbounded_send(remote).is_ok() => Ok(local),
+            _ => Err(BluetoothError::NotSupported(format!(
+                "simulated peripheral doesn't serve RFCOMM service {}",
+                uuid
+            ))),
+        }
+    }
+
+    /// Run the pairing ceremony with the peripheral registered at the
+    /// address of `device`, over its transport, answering its request with
+    /// `handler`.
+    pub(crate) async fn pair(
+        &self,
+        device: BondedDevice,
+        protection_level: ProtectionLevel,
+        handler: &dyn PairingHandler,
+    ) -> Result<PairingResult, BluetoothError> {
+        let addr = peripheral_address(device);
+        let method = {
+            let mut state = self.state.lock().unwrap();
+            let peripheral = state.peripheral_mut(addr)?;
+            if peripheral.is_paired() {
+                return Ok(PairingResult::AlreadyPaired);
+            }
+            peripheral.pairing_method.clone()
+        };
+
+        if method == SimPairingMethod::JustWorks
+            && protection_level == ProtectionLevel::EncryptionAndAuthentication
+        {
+            return Ok(PairingResult::Failure(String::from(
+                "the minimum level of protection is not supported by the \
+                device.",
+            )));
+        }
+
+        // The radio isn't locked while the handler answers.
+        let accepted = match &method {
+            SimPairingMethod::JustWorks => handler.confirm().await,
+            SimPairingMethod::DisplayPin(pin) => {
+                handler.display_pin(pin).await;
+                true
+            }
+            SimPairingMethod::ProvidePin(pin) => {
+                handler.provide_pin().await.as_ref() == Some(pin)
+            }
+            SimPairingMethod::ConfirmPinMatch(pin) => {
+                handler.confirm_pin_match(pin).await
+            }
+        };
+        if !accepted {
+            return Ok(PairingResult::Failure(String::from(
+                "the pairing handler rejected pairing.",
+            )));
+        }
+
+        let mut state = self.state.lock().unwrap();
+        let peripheral = state.peripheral_mut(addr)?;
+        let result = peripheral.pairing_result.clone()?;
+        if result == PairingResult::Success {
+            state.update_bond(addr, Some(device))?;
+        }
+
+        Ok(result)
+    }
+
+    /// Watch the bond state of the peripheral registered at `addr`. The
+    /// current state is delivered first.
+    pub(crate) fn watch_bond_state(
+        &self,
+        addr: u64,
+    ) -> Result<
+        UnboundedReceiver<Result<BondState, BluetoothError>>,
+        BluetoothError,
+    > {
+        let mut state = self.state.lock().unwrap();
+        let peripheral = state.peripheral_mut(addr)?;
+
+        let (sender, receiver) = mpsc::unbounded();
+        let current = match peripheral.is_paired() {
+            true => BondState::Bonded,
+            false => BondState::NotBonded,
+        };
+        sender
+            .unbounded_send(Ok(current))
+            .map_err(|err| BluetoothError::Internal(err.to_string()))?;
+        peripheral.bond_watchers.push(sender);
+
+        Ok(receiver)
+    }
+
+    /// Watch the connection state of the peripheral registered at `addr`. The
+    /// current state is delivered first.
+    pub(crate) fn watch_connection_state(
+        &self,
+        addr: u64,
+    ) -> Result<
+        UnboundedReceiver<Result<ConnectionState, BluetoothError>>,
+        BluetoothError,
+    > {
+        let mut state = self.state.lock().unwrap();
+        let peripheral = state.peripheral_mut(addr)?;
+
+        let (sender, receiver) = mpsc::unbounded();
+        sender
+            .unbounded_send(Ok(connection_state(peripheral.connected)))
+            .map_err(|err| BluetoothError::Internal(err.to_string()))?;
+        peripheral.connection_watchers.push(sender);
+
+        Ok(receiver)
+    }
+
+    /// List the transports the registered peripherals are bonded over.
+    pub(crate) fn paired_devices(&self) -> Vec<BondedDevice> {
+        self.state
+            .lock()
+            .unwrap()
+            .peripherals
+            .values()
+            .filter_map(|peripheral| peripheral.bond)
+            .collect()
+    }
+
+    /// Remove the bond with `device`, if the peripheral registered at its
+    /// address is bonded over its transport.
+    pub(crate) fn unpair(&self, device: BondedDevice) {
+        let mut state = self.state.lock().unwrap();
+        let addr = peripheral_address(device);
+        let bonded = state
+            .peripherals
+            .get(&addr)
+            .is_some_and(|peripheral| peripheral.bond == Some(device));
+        if bonded {
+            // The peripheral exists, so this can't fail.
+            let _ = state.update_bond(addr, None);
+        }
+    }
+
+    /// Watch the bonds the radio gains and loses.
+    pub(crate) fn watch_bonds(
+        &self,
+    ) -> UnboundedReceiver<Result<BondEvent, BluetoothError>> {
+        let (sender, receiver) = mpsc::unbounded();
+        self.state.lock().unwrap().bond_watchers.push(sender);
+        receiver
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use async_trait::async_trait;
+    use futures::executor::block_on;
+
+    use super::*;
+
+    fn advertisement(addr: u64) -> BleAdvertisement {
+        BleAdvertisement::new(
+            BleAddress::new(addr, BleAddressKind::Public),
+            Some(-60),
+            None,
+        )
+    }
+
+    #[test]
+    fn schedule_advertisement_keeps_order() {
+        let radio = SimRadio::new();
+        radio.schedule_advertisement(
+            Duration::from_millis(20),
+            advertisement(2),
+        );
+        radio.schedule_advertisement(
+            Duration::from_millis(10),
+            advertisement(1),
+        );
+        radio.schedule_advertisement(
+            Duration::from_millis(20),
+            advertisement(3),
+        );
+
+        let (_, script) = radio.start_scan().unwrap();
+        let addrs: Vec<u64> = script
+            .iter()
+            .map(|(_, ad)| u64::from(ad.address()))
+            .collect();
+        assert_eq!(addrs, vec![1, 2, 3]);
+    }
+
+    #[test]
+    fn start_scan_powered_off() {
+        let radio = SimRadio::new();
+        radio.set_powered(false);
+        assert!(matches!(
+            radio.start_scan(),
+            Err(BluetoothError::FailedPrecondition(_))
+        ));
+    }
+
+    #[test]
+    fn power_changes_notify_watchers() {
+        let radio = SimRadio::new();
+        let mut changes = radio.watch_radio_state();
+        radio.set_powered(true);
+        radio.set_powered(false);
+        radio.set_powered(false);
+        radio.set_powered(true);
+
+        let states: Vec<RadioState> =
+            std::iter::from_fn(|| changes.try_recv().ok())
+                .map(Result::unwrap)
+                .collect();
+        assert_eq!(
+            states,
+            vec![RadioState::On, RadioState::Off, RadioState::On]
+        );
+        assert_eq!(radio.info().radio_state(), RadioState::On);
+    }
+
+    #[test]
+    fn scan_replays_published_advertisements() {
+        let radio = SimRadio::new();
+        radio.schedule_advertisement(Duration::ZERO, advertisement(2));
+        let _published = radio.start_advertising(advertisement(1)).unwrap();
+        // Dropping the channel stops the advertisement.
+        drop(radio.start_advertising(advertisement(3)).unwrap());
+
+        let (_, script) = radio.start_scan().unwrap();
+        let addrs: Vec<u64> = script
+            .iter()
+            .map(|(_, ad)| u64::from(ad.address()))
+            .collect();
+        assert_eq!(addrs, vec![1, 2]);
+    }
+
+    /// Accepts every request, providing `PIN`.
+    struct AcceptAll;
+
+    const PIN: &str = "123456";
+
+    #[async_trait]
+    impl PairingHandler for AcceptAll {
+        async fn confirm(&self) -> bool {
+            true
+        }
+
+        async fn display_pin(&self, _pin: &str) {}
+
+        async fn provide_pin(&self) -> Option<String> {
+            Some(String::from(PIN))
+        }
+
+        async fn confirm_pin_match(&self, _pin: &str) -> bool {
+            true
+        }
+    }
+
+    #[test]
+    fn pair_marks_peripheral_paired() {
+        let radio = SimRadio::new();
+        let addr = BleAddress::new(0x112233445566, BleAddressKind::Public);
+        radio.add_peripheral(SimPeripheral::new(addr, "Buds"));
+
+        let device =
+            BondedDevice::Classic(ClassicAddress::from(u64::from(addr)));
+        block_on(async {
+            assert_eq!(
+                radio
+                    .pair(device, ProtectionLevel::Encryption, &AcceptAll)
+                    .await,
+                Ok(PairingResult::Success)
+            );
+            assert_eq!(
+                radio
+                    .pair(device, ProtectionLevel::Encryption, &AcceptAll)
+                    .await,
+                Ok(PairingResult::AlreadyPaired)
+            );
+            assert_eq!(radio.paired_devices(), vec![device]);
+            assert!(matches!(
+                radio
+                    .pair(
+                        BondedDevice::Ble(BleAddress::new(
+                            0xAABBCCDDEEFF,
+                            BleAddressKind::Public
+                        )),
+                        ProtectionLevel::Encryption,
+                        &AcceptAll
+                    )
+                    .await,
+                Err(BluetoothError::FailedPrecondition(_))
+            ));
+        });
+    }
+
+    #[test]
+    fn pair_checks_provided_pin() {
+        let radio = SimRadio::new();
+        let addr = BleAddress::new(0x112233445566, BleAddressKind::Public);
+        let mut peripheral = SimPeripheral::new(addr, "Buds");
+        peripheral.set_pairing_method(SimPairingMethod::ProvidePin(
+            String::from("654321"),
+        ));
+        radio.add_peripheral(peripheral);
+
+        let device = BondedDevice::Ble(addr);
+        assert!(matches!(
+            block_on(radio.pair(
+                device,
+                ProtectionLevel::Encryption,
+                &AcceptAll
+            )),
+            Ok(PairingResult::Failure(_))
+        ));
+    }
+
+    #[test]
+    fn pair_checks_protection_level() {
+        let radio = SimRadio::new();
+        let addr = BleAddress::new(0x112233445566, BleAddressKind::Random);
+        radio.add_peripheral(SimPeripheral::new(addr, "Buds"));
+
+        assert!(matches!(
+            block_on(radio.pair(
+                BondedDevice::Ble(addr),
+                ProtectionLevel::EncryptionAndAuthentication,
+                &AcceptAll
+            )),
+            Ok(PairingResult::Failure(_))
+        ));
+        assert!(!radio.peripheral(u64::from(addr)).unwrap().is_paired());
+    }
+
+    #[test]
+    fn unpair_only_removes_matching_bond() {
+        let radio = SimRadio::new();
+        let addr = BleAddress::new(0x112233445566, BleAddressKind::Public);
+        let mut peripheral = SimPeripheral::new(addr, "Buds");
+        peripheral.set_paired(true);
+        radio.add_peripheral(peripheral);
+        let mut events = radio.watch_bonds();
+
+        radio.unpair(BondedDevice::Classic(ClassicAddress::from(u64::from(
+            addr,
+        ))));
+        assert_eq!(radio.paired_devices(), vec![BondedDevice::Ble(addr)]);
+
+        radio.unpair(BondedDevice::Ble(addr));
+        assert!(radio.paired_devices().is_empty());
+        assert_eq!(
+            events.try_recv(),
+            Ok(Ok(BondEvent::Removed(BondedDevice::Ble(addr))))
+        );
+        assert!(events.try_recv().is_err());
+    }
+}
@@ -0,0 +1,157 @@
+// Copyright 2023 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::{
+    io,
+    pin::Pin,
+    task::{Context, Poll},
+};
+
+use futures::{
+    channel::mpsc::{self, UnboundedReceiver, UnboundedSender},
+    ready, AsyncRead, AsyncWrite, StreamExt,
+};
+
+/// One end of an in-memory byte stream, standing in for an RFCOMM channel.
+/// Bytes written to one end are read from the other. Closing or dropping an
+/// end makes reads of the other end return 0 bytes.
+#[derive(Debug)]
+pub struct SimSocket {
+    /// Sends written bytes to the other end, or `None` once closed.
+    sender: Option<UnboundedSender<Vec<u8>>>,
+    receiver: UnboundedReceiver<Vec<u8>>,
+    /// Bytes received but not read yet.
+    pending: Vec<u8>,
+}
+
+impl SimSocket {
+    /// Construct both ends of a new stream.
+    pub fn pair() -> (SimSocket, SimSocket) {
+        let (a_sender, b_receiver) = mpsc::unbounded();
+        let (b_sender, a_receiver) = mpsc::unbounded();
+
+        (
+            SimSocket {
+                sender: Some(a_sender),
+                receiver: a_receiver,
+                pending: Vec::new(),
+            },
+            SimSocket {
+                sender: Some(b_sender),
+                receiver: b_receiver,
+                pending: Vec::new(),
+            },
+        )
+    }
+}
+
+impl AsyncRead for SimSocket {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut [u8],
+    ) -> Poll<io::Result<usize>> {
+        while self.pending.is_empty() && !buf.is_empty() {
+            match ready!(self.receiver.poll_next_unpin(cx)) {
+                Some(bytes) => self.pending = bytes,
+                None => return Poll::Ready(Ok(0)),
+            }
+        }
+
+        let len = buf.len().min(self.pending.len());
+        buf[..len].copy_from_slice(&self.pending[..len]);
+        self.pending.drain(..len);
+        Poll::Ready(Ok(len))
+    }
+}
+
+impl AsyncWrite for SimSocket {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        _cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        let Some(sender) = &self.sender else {
+            return Poll::Ready(Err(io::ErrorKind::NotConnected.into()));
+        };
+
+        match sender.unbounded_send(buf.to_vec()) {
+            Ok(()) => Poll::Ready(Ok(buf.len())),
+            Err(_) => Poll::Ready(Err(io::ErrorKind::BrokenPipe.into())),
+        }
+    }
+
+    fn poll_flush(
+        self: Pin<&mut Self>,
+        _cx: &mut Context<'_>,
+    ) -> Poll<io::Result<()>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn poll_close(
+        mut self: Pin<&mut Self>,
+        _cx: &mut Context<'_>,
+    ) -> Poll<io::Result<()>> {
+        self.sender = None;
+        Poll::Ready(Ok(()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use futures::{executor::block_on, AsyncReadExt, AsyncWriteExt};
+
+    use super::*;
+
+    #[test]
+    fn pair_transfers_bytes() {
+        let (mut a, mut b) = SimSocket::pair();
+        block_on(async {
+            a.write_all(&[1, 2, 3]).await.unwrap();
+            a.write_all(&[4]).await.unwrap();
+
+            // Reads may span or split writes.
+            let mut buf = [0; 2];
+            b.read_exact(&mut buf).await.unwrap();
+            assert_eq!(buf, [1, 2]);
+            b.read_exact(&mut buf).await.unwrap();
+            assert_eq!(buf, [3, 4]);
+
+            b.write_all(&[5]).await.unwrap();
+            let mut buf = [0; 1];
+            a.read_exact(&mut buf).await.unwrap();
+            assert_eq!(buf, [5]);
+        });
+    }
+
+    #[test]
+    fn close_ends_stream() {
+        let (mut a, mut b) = SimSocket::pair();
+        block_on(async {
+            a.write_all(&[1]).await.unwrap();
+            a.close().await.unwrap();
+            assert!(a.write_all(&[2]).await.is_err());
+
+            let mut received = Vec::new();
+            b.read_to_end(&mut received).await.unwrap();
+            assert_eq!(received, vec![1]);
+
+            drop(a);
+            assert_eq!(
+                b.write_all(&[3]).await.unwrap_err().kind(),
+                io::ErrorKind::BrokenPipe
+            );
+        });
+    }
+}
@@ -15,20 +15,53 @@
 // Specification: https://developers.google.com/nearby/fast-pair/specifications/extensions/messagestream
 // This file should be in sync with fastpair/message_stream/message.h.
 
+use crate::common::BluetoothError;
 
-#[derive(Debug)]
-pub enum BluetoothCode {
+/// Length of the header preceding the additional data of every packet: group
+/// (1 byte), code (1 byte) and additional data length (2 bytes, big-endian).
+pub const HEADER_LEN: usize = 4;
+
+/// Declare the message codes of a group, with conversions from and to their
+/// value on the wire. Codes this library doesn't know about are kept as
+/// `Unknown` rather than dropped.
+macro_rules! message_codes {
+    ($name:ident { $($variant:ident = $value:literal,)* }) => {
+        #[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+        pub enum $name {
+            $($variant,)*
+            Unknown(u8),
+        }
+
+        impl From<u8> for $name {
+            fn from(code: u8) -> Self {
+                match code {
+                    $($value => $name::$variant,)*
+                    _ => $name::Unknown(code),
+                }
+            }
+        }
+
+        impl From<$name> for u8 {
+            fn from(code: $name) -> Self {
+                match code {
+                    $($name::$variant => $value,)*
+                    $name::Unknown(code) => code,
+                }
+            }
+        }
+    };
+}
+
+message_codes!(BluetoothCode {
     EnableSilenceMode = 0x01,
     DisableSilenceMode = 0x02,
-}
+});
 
-#[derive(Debug)]
-pub enum CompanionAppEventCode {
+message_codes!(CompanionAppEventCode {
     LogBufferFull = 0x01,
-}
+});
 
-#[derive(Debug)]
-pub enum DeviceInformationEventCode {
+message_codes!(DeviceInformationEventCode {
     ModelId = 0x01,
     BleAddressUpdated = 0x02,
     BatteryUpdated = 0x03,
@@ -38,10 +71,9 @@ pub enum DeviceInformationEventCode {
     Capabilities = 0x07,
     PlatformType = 0x08,
     SessionNonce = 0x0A,
-}
+});
 
-#[derive(Debug)]
-pub enum SassCode {
+message_codes!(SassCode {
     Acknowledgement = 0xFF,
     SassGetCapability = 0x10,
     SassNotifyCapability = 0x11,
@@ -58,20 +90,18 @@ pub enum SassCode {
     SassInUseAccountKey = 0x41,
     SassSendCustomData = 0x42,
     SassSetDropConnectionTarget = 0x43,
-}
+});
 
-#[derive(Debug)]
-pub enum DeviceActionEventCode {
-    Ring = 1,
-}
+message_codes!(DeviceActionEventCode {
+    Ring = 0x01,
+});
 
-#[derive(Debug)]
-pub enum AcknowledgementCode {
-    Ack = 1,
-    Nack = 2,
-}
+message_codes!(AcknowledgementCode {
+    Ack = 0x01,
+    Nack = 0x02,
+});
 
-#[derive(Debug)]
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
 pub enum MessageGroup {
     Bluetooth(BluetoothCode),
     CompanionAppEvent(CompanionAppEventCode),
@@ -79,11 +109,358 @@ pub enum MessageGroup {
     DeviceActionEvent(DeviceActionEventCode),
     Sass(SassCode),
     Acknowledgement(AcknowledgementCode),
+    /// A group this library doesn't know about, with its raw group and code.
+    Unknown(u8, u8),
 }
 
+impl MessageGroup {
+    /// Interpret the group and code bytes of a packet header.
+    pub fn from_raw(group: u8, code: u8) -> Self {
+        match group {
+            0x01 => MessageGroup::Bluetooth(code.into()),
+            0x02 => MessageGroup::CompanionAppEvent(code.into()),
+            0x03 => MessageGroup::DeviceInformationEvent(code.into()),
+            0x04 => MessageGroup::DeviceActionEvent(code.into()),
+            0x07 => MessageGroup::Sass(code.into()),
+            0xFF => MessageGroup::Acknowledgement(code.into()),
+            _ => MessageGroup::Unknown(group, code),
+        }
+    }
+
+    /// Retrieve the group byte of the packet header.
+    pub fn group(&self) -> u8 {
+        match self {
+            MessageGroup::Bluetooth(_) => 0x01,
+            MessageGroup::CompanionAppEvent(_) => 0x02,
+            MessageGroup::DeviceInformationEvent(_) => 0x03,
+            MessageGroup::DeviceActionEvent(_) => 0x04,
+            MessageGroup::Sass(_) => 0x07,
+            MessageGroup::Acknowledgement(_) => 0xFF,
+            MessageGroup::Unknown(group, _) => *group,
+        }
+    }
+
+    /// Retrieve the code byte of the packet header.
+    pub fn code(&self) -> u8 {
+        match *self {
+            MessageGroup::Bluetooth(code) => code.into(),
+            MessageGroup::CompanionAppEvent(code) => code.into(),
+            MessageGroup::DeviceInformationEvent(code) => code.into(),
+            MessageGroup::DeviceActionEvent(code) => code.into(),
+            MessageGroup::Sass(code) => code.into(),
+            MessageGroup::Acknowledgement(code) => code.into(),
+            MessageGroup::Unknown(_, code) => code,
+        }
+    }
+}
 
 /// A packet that is sent over the RFCOMM Message Stream.
+#[derive(Clone, PartialEq, Eq, Debug)]
 pub struct MessageStreamPacket {
     pub group: MessageGroup,
     pub additional_data: Vec<u8>,
-}
\ No newline at end of file
+}
+
+/// A packet borrowing its additional data from the buffer it was decoded
+/// from.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct MessageStreamPacketRef<'a> {
+    pub group: MessageGroup,
+    pub additional_data: &'a [u8],
+}
+
+impl MessageStreamPacket {
+    pub fn new(group: MessageGroup, additional_data: Vec<u8>) -> Self {
+        MessageStreamPacket {
+            group,
+            additional_data,
+        }
+    }
+
+    pub fn as_ref(&self) -> MessageStreamPacketRef<'_> {
+        MessageStreamPacketRef {
+            group: self.group,
+            additional_data: &self.additional_data,
+        }
+    }
+
+    /// Encode the packet as sent over the wire.
+    pub fn encode(&self) -> Result<Vec<u8>, BluetoothError> {
+        self.as_ref().encode()
+    }
+}
+
+impl<'a> MessageStreamPacketRef<'a> {
+    /// Decode the packet at the start of `bytes`, returning it along with the
+    /// number of bytes it spans. Returns `None` if `bytes` only hold part of
+    /// the packet.
+    pub fn decode(bytes: &'a [u8]) -> Option<(Self, usize)> {
+        let header = bytes.get(..HEADER_LEN)?;
+        let len = HEADER_LEN
+            + usize::from(u16::from_be_bytes([header[2], header[3]]));
+        let additional_data = bytes.get(HEADER_LEN..len)?;
+
+        Some((
+            MessageStreamPacketRef {
+                group: MessageGroup::from_raw(header[0], header[1]),
+                additional_data,
+            },
+            len,
+        ))
+    }
+
+    /// Append the packet as sent over the wire to `out`. Fails if the
+    /// additional data doesn't fit the 2-byte length field.
+    pub fn encode_into(&self, out: &mut Vec<u8>) -> Result<(), BluetoothError> {
+        let len = u16::try_from(self.additional_data.len()).map_err(|_| {
+            BluetoothError::InvalidArgument(format!(
+                "{} bytes of additional data don't fit a Message Stream \
+                packet",
+                self.additional_data.len()
+            ))
+        })?;
+
+        out.reserve(HEADER_LEN + self.additional_data.len());
+        out.extend_from_slice(&[self.group.group(), self.group.code()]);
+        out.extend_from_slice(&len.to_be_bytes());
+        out.extend_from_slice(self.additional_data);
+        Ok(())
+    }
+
+    /// Encode the packet as sent over the wire.
+    pub fn encode(&self) -> Result<Vec<u8>, BluetoothError> {
+        let mut out = Vec::new();
+        self.encode_into(&mut out)?;
+        Ok(out)
+    }
+
+    pub fn to_owned(&self) -> MessageStreamPacket {
+        MessageStreamPacket::new(self.group, self.additional_data.to_vec())
+    }
+}
+
+/// Splits the bytes read from a Message Stream into packets. Reads may hold
+/// several packets, or split one across reads, so bytes are buffered until
+/// the packets they belong to are complete.
+#[derive(Default, Debug)]
+pub struct MessageStreamDecoder {
+    buffer: Vec<u8>,
+    /// Offset of the first byte of `buffer` not decoded yet.
+    start: usize,
+}
+
+impl MessageStreamDecoder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Append bytes read from the stream.
+    pub fn push(&mut self, bytes: &[u8]) {
+        // Drop the bytes of packets already decoded.
+        self.buffer.drain(..self.start);
+        self.start = 0;
+        self.buffer.extend_from_slice(bytes);
+    }
+
+    /// Decode the next complete packet, borrowing its additional data from
+    /// the decoder. Returns `None` until more bytes are pushed.
+    pub fn next_packet(&mut self) -> Option<MessageStreamPacketRef<'_>> {
+        let (packet, len) =
+            MessageStreamPacketRef::decode(&self.buffer[self.start..])?;
+        self.start += len;
+        Some(packet)
+    }
+
+    /// Number of buffered bytes belonging to packets not complete yet.
+    pub fn pending_len(&self) -> usize {
+        self.buffer.len() - self.start
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Every group and code listed in fastpair/message_stream/message.h.
+    fn known_groups() -> Vec<(MessageGroup, u8, u8)> {
+        use MessageGroup::*;
+
+        vec![
+            (Bluetooth(BluetoothCode::EnableSilenceMode), 0x01, 0x01),
+            (Bluetooth(BluetoothCode::DisableSilenceMode), 0x01, 0x02),
+            (
+                CompanionAppEvent(CompanionAppEventCode::LogBufferFull),
+                0x02,
+                0x01,
+            ),
+            (
+                DeviceInformationEvent(DeviceInformationEventCode::ModelId),
+                0x03,
+                0x01,
+            ),
+            (
+                DeviceInformationEvent(
+                    DeviceInformationEventCode::BleAddressUpdated,
+                ),
+                0x03,
+                0x02,
+            ),
+            (
+                DeviceInformationEvent(
+                    DeviceInformationEventCode::BatteryUpdated,
+                ),
+                0x03,
+                0x03,
+            ),
+            (
+                DeviceInformationEvent(
+                    DeviceInformationEventCode::RemainingBattery,
+                ),
+                0x03,
+                0x04,
+            ),
+            (
+                DeviceInformationEvent(
+                    DeviceInformationEventCode::ActiveComponentsRequest,
+                ),
+                0x03,
+                0x05,
+            ),
+            (
+                DeviceInformationEvent(
+                    DeviceInformationEventCode::ActiveComponentsResponse,
+                ),
+                0x03,
+                0x06,
+            ),
+            (
+                DeviceInformationEvent(
+                    DeviceInformationEventCode::Capabilities,
+                ),
+                0x03,
+                0x07,
+            ),
+            (
+                DeviceInformationEvent(
+                    DeviceInformationEventCode::PlatformType,
+                ),
+                0x03,
+                0x08,
+            ),
+            (
+                DeviceInformationEvent(
+                    DeviceInformationEventCode::SessionNonce,
+                ),
+                0x03,
+                0x0A,
+            ),
+            (DeviceActionEvent(DeviceActionEventCode::Ring), 0x04, 0x01),
+            (Sass(SassCode::SassGetCapability), 0x07, 0x10),
+            (Sass(SassCode::SassNotifyCapability), 0x07, 0x11),
+            (Sass(SassCode::SassSetMultipointState), 0x07, 0x12),
+            (Sass(SassCode::SassSetSwitchingPreference), 0x07, 0x20),
+            (Sass(SassCode::SassGetSwitchingPreference), 0x07, 0x21),
+            (Sass(SassCode::SassNotifySwitchingPreference), 0x07, 0x22),
+            (Sass(SassCode::SassSwitchActiveSourceCode), 0x07, 0x30),
+            (Sass(SassCode::SassSwitchBackAudioSource), 0x07, 0x31),
+            (Sass(SassCode::SassNotifyMultipointSwitchEvent), 0x07, 0x32),
+            (Sass(SassCode::SassGetConnectionStatus), 0x07, 0x33),
+            (Sass(SassCode::SassNotifyConnectionStatus), 0x07, 0x34),
+            (
+                Sass(SassCode::SassNotifySassInitiatedConnection),
+                0x07,
+                0x40,
+            ),
+            (Sass(SassCode::SassInUseAccountKey), 0x07, 0x41),
+            (Sass(SassCode::SassSendCustomData), 0x07, 0x42),
+            (Sass(SassCode::SassSetDropConnectionTarget), 0x07, 0x43),
+            (Acknowledgement(AcknowledgementCode::Ack), 0xFF, 0x01),
+            (Acknowledgement(AcknowledgementCode::Nack), 0xFF, 0x02),
+        ]
+    }
+
+    #[test]
+    fn round_trip_every_code() {
+        for (group, raw_group, raw_code) in known_groups() {
+            let packet = MessageStreamPacket::new(group, vec![0xAA, 0xBB]);
+            let bytes = packet.encode().unwrap();
+            assert_eq!(
+                bytes,
+                vec![raw_group, raw_code, 0x00, 0x02, 0xAA, 0xBB]
+            );
+
+            let (decoded, len) =
+                MessageStreamPacketRef::decode(&bytes).unwrap();
+            assert_eq!(decoded, packet.as_ref());
+            assert_eq!(len, bytes.len());
+        }
+    }
+
+    #[test]
+    fn truncated_packets() {
+        for (group, _, _) in known_groups() {
+            let bytes = MessageStreamPacket::new(group, vec![0x01, 0x02, 0x03])
+                .encode()
+                .unwrap();
+            for len in 0..bytes.len() {
+                assert_eq!(MessageStreamPacketRef::decode(&bytes[..len]), None);
+            }
+        }
+    }
+
+    #[test]
+    fn unknown_values_are_kept() {
+        let bytes = [0x05, 0x09, 0x00, 0x01, 0x42];
+        let (packet, _) = MessageStreamPacketRef::decode(&bytes).unwrap();
+        assert_eq!(packet.group, MessageGroup::Unknown(0x05, 0x09));
+        assert_eq!(packet.encode().unwrap(), bytes.to_vec());
+
+        let bytes = [0x03, 0x09, 0x00, 0x00];
+        let (packet, _) = MessageStreamPacketRef::decode(&bytes).unwrap();
+        assert_eq!(
+            packet.group,
+            MessageGroup::DeviceInformationEvent(
+                DeviceInformationEventCode::Unknown(0x09)
+            )
+        );
+        assert_eq!(packet.encode().unwrap(), bytes.to_vec());
+    }
+
+    #[test]
+    fn encode_too_long() {
+        let packet = MessageStreamPacket::new(
+            MessageGroup::Sass(SassCode::SassSendCustomData),
+            vec![0; 0x10000],
+        );
+        assert!(matches!(
+            packet.encode(),
+            Err(BluetoothError::InvalidArgument(_))
+        ));
+    }
+
+    #[test]
+    fn decoder_splits_and_joins_reads() {
+        let ring = MessageGroup::DeviceActionEvent(DeviceActionEventCode::Ring);
+        let ack = MessageGroup::Acknowledgement(AcknowledgementCode::Ack);
+        let mut decoder = MessageStreamDecoder::new();
+
+        // Two packets and the start of a third in one read.
+        decoder.push(&[0x04, 0x01, 0x00, 0x01, 0x01, 0xFF, 0x01, 0x00, 0x00]);
+        decoder.push(&[0x04, 0x01, 0x00]);
+        let packet = decoder.next_packet().unwrap();
+        assert_eq!(packet.group, ring);
+        assert_eq!(packet.additional_data, &[0x01]);
+        assert_eq!(decoder.next_packet().unwrap().group, ack);
+        assert_eq!(decoder.next_packet(), None);
+        assert_eq!(decoder.pending_len(), 3);
+
+        decoder.push(&[0x02, 0x03]);
+        assert_eq!(decoder.next_packet(), None);
+        decoder.push(&[0x04]);
+        let packet = decoder.next_packet().unwrap();
+        assert_eq!(packet.group, ring);
+        assert_eq!(packet.additional_data, &[0x03, 0x04]);
+        assert_eq!(decoder.next_packet(), None);
+        assert_eq!(decoder.pending_len(), 0);
+    }
+}
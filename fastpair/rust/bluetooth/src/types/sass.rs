@@ -0,0 +1,864 @@
+// Copyright 2023 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+// Specification: https://developers.google.com/nearby/fast-pair/specifications/extensions/sass
+
+use aes::{
+    cipher::{generic_array::GenericArray, BlockEncrypt, KeyInit},
+    Aes128,
+};
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+
+use crate::{common::BluetoothError, types::packets::SassCode};
+
+pub const ACCOUNT_KEY_LEN: usize = 16;
+pub const NONCE_LEN: usize = 8;
+/// Length of the truncated HMAC-SHA256 authenticating seeker requests.
+pub const MAC_LEN: usize = 8;
+/// Version of the SASS protocol implemented here, major and minor byte.
+pub const SASS_VERSION: u16 = 0x0101;
+
+/// Data of the Indicate In-Use Account Key message.
+const IN_USE_ACCOUNT_KEY_DATA: &[u8] = b"in-use";
+/// Length of the AES block the connection status is encrypted with.
+const AES_BLOCK_LEN: usize = 16;
+
+/// Fast Pair account key, shared by the provider and the seeker's account.
+pub type AccountKey = [u8; ACCOUNT_KEY_LEN];
+
+/// Nonce of a Message Stream session, sent by the provider on connection, or
+/// of a single message.
+pub type Nonce = [u8; NONCE_LEN];
+
+/// Bit field of the SASS features of a provider, from Notify Capability.
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug, Default)]
+pub struct SassCapabilityFlags(u16);
+
+impl SassCapabilityFlags {
+    pub const SASS_ON: u16 = 1 << 15;
+    pub const MULTIPOINT_CONFIGURABLE: u16 = 1 << 14;
+    pub const MULTIPOINT_ON: u16 = 1 << 13;
+    pub const ON_HEAD_DETECTION_SUPPORTED: u16 = 1 << 12;
+    pub const ON_HEAD_DETECTION_ENABLED: u16 = 1 << 11;
+
+    pub fn from_bits(bits: u16) -> Self {
+        SassCapabilityFlags(bits)
+    }
+
+    pub fn bits(&self) -> u16 {
+        self.0
+    }
+
+    /// Whether every flag set in `bits` is set.
+    pub fn contains(&self, bits: u16) -> bool {
+        self.0 & bits == bits
+    }
+}
+
+/// Payload of Notify Capability, the answer to Get Capability.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct SassCapability {
+    pub version: u16,
+    pub flags: SassCapabilityFlags,
+}
+
+/// Bit field telling, for each pair of audio profiles, whether the provider
+/// switches to a new source requesting the first profile while the current
+/// source uses the second one.
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug, Default)]
+pub struct SwitchingPreference(u8);
+
+impl SwitchingPreference {
+    pub const A2DP_VS_A2DP: u8 = 1 << 7;
+    pub const HFP_VS_HFP: u8 = 1 << 6;
+    pub const A2DP_VS_HFP: u8 = 1 << 5;
+    pub const HFP_VS_A2DP: u8 = 1 << 4;
+
+    pub fn from_bits(bits: u8) -> Self {
+        SwitchingPreference(bits)
+    }
+
+    pub fn bits(&self) -> u8 {
+        self.0
+    }
+
+    /// Whether every preference set in `bits` is set.
+    pub fn contains(&self, bits: u8) -> bool {
+        self.0 & bits == bits
+    }
+}
+
+/// Bit field of options of Switch Active Audio Source.
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug, Default)]
+pub struct SwitchActiveSourceFlags(u8);
+
+impl SwitchActiveSourceFlags {
+    /// Switch to the seeker sending the request, rather than to the other
+    /// connected source.
+    pub const THIS_DEVICE: u8 = 1 << 7;
+    /// Resume playing on the new source if it was playing before being
+    /// switched away from.
+    pub const RESUME_PLAYING: u8 = 1 << 6;
+    /// Reject SCO on the source switched away from.
+    pub const REJECT_SCO: u8 = 1 << 5;
+    /// Disconnect Bluetooth from the source switched away from.
+    pub const DISCONNECT_PREVIOUS: u8 = 1 << 4;
+
+    pub fn from_bits(bits: u8) -> Self {
+        SwitchActiveSourceFlags(bits)
+    }
+
+    pub fn bits(&self) -> u8 {
+        self.0
+    }
+
+    /// Whether every flag set in `bits` is set.
+    pub fn contains(&self, bits: u8) -> bool {
+        self.0 & bits == bits
+    }
+}
+
+/// Payload of Switch Back, asking the provider to reconnect the audio source
+/// it was switched away from.
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub enum SwitchBackAction {
+    SwitchBack = 0x01,
+    SwitchBackAndResume = 0x02,
+}
+
+/// Why the provider switched its active audio source.
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub enum MultipointSwitchReason {
+    Unspecified = 0x00,
+    A2dp = 0x01,
+    Hfp = 0x02,
+}
+
+/// Which source the provider switched to, relative to the seeker receiving
+/// the notification.
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub enum SwitchTarget {
+    ThisDevice = 0x01,
+    AnotherDevice = 0x02,
+}
+
+/// Payload of Notify Multipoint Switch Event.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct MultipointSwitchEvent {
+    pub reason: MultipointSwitchReason,
+    pub target: SwitchTarget,
+    /// Name of the source switched to, or the last 4 hex digits of its
+    /// address if the provider doesn't know its name.
+    pub device_name: String,
+}
+
+/// Whether the seeker receiving a connection status is the active audio
+/// source of the provider.
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub enum ActiveDevice {
+    NotThisDevice = 0x00,
+    ThisDevice = 0x01,
+    /// A seeker not supporting SASS is the active source.
+    NonSassDevice = 0x02,
+}
+
+/// Audio connection of the provider, as the low nibble of its connection
+/// state.
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub enum AudioConnectionState {
+    NoConnection,
+    Paging,
+    NoDataTransfer,
+    NonAudioDataTransfer,
+    A2dp,
+    A2dpWithAvrcp,
+    Hfp,
+    LeMediaWithoutControl,
+    LeMediaWithControl,
+    LeCall,
+    LeBis,
+    DisabledConnectionSwitch,
+    /// A value the specification reserves.
+    Reserved(u8),
+}
+
+impl From<u8> for AudioConnectionState {
+    fn from(value: u8) -> Self {
+        match value {
+            0 => AudioConnectionState::NoConnection,
+            1 => AudioConnectionState::Paging,
+            2 => AudioConnectionState::NoDataTransfer,
+            3 => AudioConnectionState::NonAudioDataTransfer,
+            4 => AudioConnectionState::A2dp,
+            5 => AudioConnectionState::A2dpWithAvrcp,
+            6 => AudioConnectionState::Hfp,
+            7 => AudioConnectionState::LeMediaWithoutControl,
+            8 => AudioConnectionState::LeMediaWithControl,
+            9 => AudioConnectionState::LeCall,
+            10 => AudioConnectionState::LeBis,
+            15 => AudioConnectionState::DisabledConnectionSwitch,
+            value => AudioConnectionState::Reserved(value),
+        }
+    }
+}
+
+/// Connection state byte of the provider, also advertised in its SASS
+/// advertisement.
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug, Default)]
+pub struct ConnectionState(u8);
+
+impl ConnectionState {
+    pub const ON_HEAD: u8 = 1 << 7;
+    /// The provider can accept another connection without dropping one.
+    pub const AVAILABLE: u8 = 1 << 6;
+    /// Switching connections isn't allowed in focus mode.
+    pub const FOCUS_MODE: u8 = 1 << 5;
+    /// The current connection wasn't made by the user.
+    pub const AUTO_RECONNECTED: u8 = 1 << 4;
+
+    const AUDIO_STATE_MASK: u8 = 0x0F;
+
+    pub fn from_bits(bits: u8) -> Self {
+        ConnectionState(bits)
+    }
+
+    pub fn bits(&self) -> u8 {
+        self.0
+    }
+
+    /// Whether every flag set in `bits` is set.
+    pub fn contains(&self, bits: u8) -> bool {
+        self.0 & bits == bits
+    }
+
+    pub fn audio_state(&self) -> AudioConnectionState {
+        (self.0 & Self::AUDIO_STATE_MASK).into()
+    }
+}
+
+/// Connection status of the provider, once decrypted.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct ConnectionStatus {
+    pub active_device: ActiveDevice,
+    pub connection_state: ConnectionState,
+    /// Data the seekers sent with Send Custom Data.
+    pub custom_data: u8,
+    /// Bit field of the bonded sources, set for the connected ones.
+    pub connected_devices: Vec<u8>,
+}
+
+impl ConnectionStatus {
+    /// Encrypt the status with `account_key`, as the provider does.
+    pub fn encrypt(
+        &self,
+        account_key: &AccountKey,
+        session_nonce: &Nonce,
+        message_nonce: &Nonce,
+    ) -> Result<EncryptedConnectionStatus, BluetoothError> {
+        let mut encrypted =
+            vec![self.connection_state.bits(), self.custom_data];
+        encrypted.extend_from_slice(&self.connected_devices);
+        if encrypted.len() > AES_BLOCK_LEN {
+            return Err(BluetoothError::InvalidArgument(format!(
+                "connection status can't encrypt {} connected devices bytes",
+                self.connected_devices.len()
+            )));
+        }
+        apply_keystream(
+            &mut encrypted,
+            account_key,
+            session_nonce,
+            message_nonce,
+        );
+
+        Ok(EncryptedConnectionStatus {
+            active_device: self.active_device,
+            encrypted,
+            message_nonce: *message_nonce,
+        })
+    }
+}
+
+/// Payload of Notify Connection Status. Everything but the active device
+/// flag is encrypted with the in-use account key.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct EncryptedConnectionStatus {
+    pub active_device: ActiveDevice,
+    pub encrypted: Vec<u8>,
+    pub message_nonce: Nonce,
+}
+
+impl EncryptedConnectionStatus {
+    /// Decrypt the status with the account key the session uses, and the
+    /// session nonce the provider sent when the session started. Fails with
+    /// `BluetoothError::MalformedData` unless 2 to 16 bytes are encrypted.
+    pub fn decrypt(
+        &self,
+        account_key: &AccountKey,
+        session_nonce: &Nonce,
+    ) -> Result<ConnectionStatus, BluetoothError> {
+        if !(2..=AES_BLOCK_LEN).contains(&self.encrypted.len()) {
+            return Err(BluetoothError::MalformedData(format!(
+                "connection status has {} encrypted bytes",
+                self.encrypted.len()
+            )));
+        }
+
+        let mut decrypted = self.encrypted.clone();
+        apply_keystream(
+            &mut decrypted,
+            account_key,
+            session_nonce,
+            &self.message_nonce,
+        );
+
+        Ok(ConnectionStatus {
+            active_device: self.active_device,
+            connection_state: ConnectionState(decrypted[0]),
+            custom_data: decrypted[1],
+            connected_devices: decrypted[2..].to_vec(),
+        })
+    }
+}
+
+/// A SASS message, every code of the group with its decoded payload.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum SassMessage {
+    GetCapability,
+    NotifyCapability(SassCapability),
+    SetMultipointState {
+        enable: bool,
+    },
+    SetSwitchingPreference(SwitchingPreference),
+    GetSwitchingPreference,
+    NotifySwitchingPreference(SwitchingPreference),
+    SwitchActiveSource(SwitchActiveSourceFlags),
+    SwitchBack(SwitchBackAction),
+    NotifyMultipointSwitchEvent(MultipointSwitchEvent),
+    GetConnectionStatus,
+    NotifyConnectionStatus(EncryptedConnectionStatus),
+    NotifySassInitiatedConnection {
+        initiated_by_sass: bool,
+    },
+    InUseAccountKey,
+    SendCustomData(u8),
+    /// Bit field of the bonded sources, set for the one to drop when the
+    /// provider needs a free connection.
+    SetDropConnectionTarget(u8),
+}
+
+impl SassMessage {
+    pub fn code(&self) -> SassCode {
+        match self {
+            SassMessage::GetCapability => SassCode::SassGetCapability,
+            SassMessage::NotifyCapability(_) => SassCode::SassNotifyCapability,
+            SassMessage::SetMultipointState { .. } => {
+                SassCode::SassSetMultipointState
+            }
+            SassMessage::SetSwitchingPreference(_) => {
+                SassCode::SassSetSwitchingPreference
+            }
+            SassMessage::GetSwitchingPreference => {
+                SassCode::SassGetSwitchingPreference
+            }
+            SassMessage::NotifySwitchingPreference(_) => {
+                SassCode::SassNotifySwitchingPreference
+            }
+            SassMessage::SwitchActiveSource(_) => {
+                SassCode::SassSwitchActiveSourceCode
+            }
+            SassMessage::SwitchBack(_) => SassCode::SassSwitchBackAudioSource,
+            SassMessage::NotifyMultipointSwitchEvent(_) => {
+                SassCode::SassNotifyMultipointSwitchEvent
+            }
+            SassMessage::GetConnectionStatus => {
+                SassCode::SassGetConnectionStatus
+            }
+            SassMessage::NotifyConnectionStatus(_) => {
+                SassCode::SassNotifyConnectionStatus
+            }
+            SassMessage::NotifySassInitiatedConnection { .. } => {
+                SassCode::SassNotifySassInitiatedConnection
+            }
+            SassMessage::InUseAccountKey => SassCode::SassInUseAccountKey,
+            SassMessage::SendCustomData(_) => SassCode::SassSendCustomData,
+            SassMessage::SetDropConnectionTarget(_) => {
+                SassCode::SassSetDropConnectionTarget
+            }
+        }
+    }
+
+    /// Whether the seeker authenticates the message with a message nonce
+    /// and MAC, see `authenticate()`.
+    pub fn is_authenticated(&self) -> bool {
+        matches!(
+            self,
+            SassMessage::SetMultipointState { .. }
+                | SassMessage::SetSwitchingPreference(_)
+                | SassMessage::SwitchActiveSource(_)
+                | SassMessage::SwitchBack(_)
+                | SassMessage::NotifySassInitiatedConnection { .. }
+                | SassMessage::InUseAccountKey
+                | SassMessage::SendCustomData(_)
+                | SassMessage::SetDropConnectionTarget(_)
+        )
+    }
+
+    /// Encode the additional data of the message, without the message nonce
+    /// and MAC of authenticated messages.
+    pub fn encode(&self) -> Vec<u8> {
+        match self {
+            SassMessage::GetCapability
+            | SassMessage::GetSwitchingPreference
+            | SassMessage::GetConnectionStatus => Vec::new(),
+            SassMessage::NotifyCapability(capability) => {
+                let mut data = capability.version.to_be_bytes().to_vec();
+                data.extend_from_slice(&capability.flags.bits().to_be_bytes());
+                data
+            }
+            SassMessage::SetMultipointState { enable } => {
+                vec![u8::from(*enable)]
+            }
+            SassMessage::SetSwitchingPreference(preference)
+            | SassMessage::NotifySwitchingPreference(preference) => {
+                // The second byte is reserved for more preferences.
+                vec![preference.bits(), 0]
+            }
+            SassMessage::SwitchActiveSource(flags) => vec![flags.bits()],
+            SassMessage::SwitchBack(action) => vec![*action as u8],
+            SassMessage::NotifyMultipointSwitchEvent(event) => {
+                let mut data = vec![event.reason as u8, event.target as u8];
+                data.extend_from_slice(event.device_name.as_bytes());
+                data
+            }
+            SassMessage::NotifyConnectionStatus(status) => {
+                let mut data = vec![status.active_device as u8];
+                data.extend_from_slice(&status.encrypted);
+                data.extend_from_slice(&status.message_nonce);
+                data
+            }
+            SassMessage::NotifySassInitiatedConnection {
+                initiated_by_sass,
+            } => {
+                vec![u8::from(!initiated_by_sass)]
+            }
+            SassMessage::InUseAccountKey => IN_USE_ACCOUNT_KEY_DATA.to_vec(),
+            SassMessage::SendCustomData(data) => vec![*data],
+            SassMessage::SetDropConnectionTarget(target) => vec![*target],
+        }
+    }
+
+    /// Decode the additional data of a message with the given code. The
+    /// message nonce and MAC of authenticated messages must have been
+    /// checked and removed with `verify()`. Returns `None` for unknown codes
+    /// and malformed data.
+    pub fn decode(code: SassCode, data: &[u8]) -> Option<Self> {
+        let message = match (code, data) {
+            (SassCode::SassGetCapability, []) => SassMessage::GetCapability,
+            (
+                SassCode::SassNotifyCapability,
+                &[version_high, version_low, flags_high, flags_low],
+            ) => SassMessage::NotifyCapability(SassCapability {
+                version: u16::from_be_bytes([version_high, version_low]),
+                flags: SassCapabilityFlags(u16::from_be_bytes([
+                    flags_high, flags_low,
+                ])),
+            }),
+            (SassCode::SassSetMultipointState, &[enable]) => {
+                SassMessage::SetMultipointState {
+                    enable: bool_from_byte(enable)?,
+                }
+            }
+            (SassCode::SassSetSwitchingPreference, &[preference, _]) => {
+                SassMessage::SetSwitchingPreference(SwitchingPreference(
+                    preference,
+                ))
+            }
+            (SassCode::SassGetSwitchingPreference, []) => {
+                SassMessage::GetSwitchingPreference
+            }
+            (SassCode::SassNotifySwitchingPreference, &[preference, _]) => {
+                SassMessage::NotifySwitchingPreference(SwitchingPreference(
+                    preference,
+                ))
+            }
+            (SassCode::SassSwitchActiveSourceCode, &[flags]) => {
+                SassMessage::SwitchActiveSource(SwitchActiveSourceFlags(flags))
+            }
+            (SassCode::SassSwitchBackAudioSource, &[action]) => {
+                SassMessage::SwitchBack(match action {
+                    0x01 => SwitchBackAction::SwitchBack,
+                    0x02 => SwitchBackAction::SwitchBackAndResume,
+                    _ => return None,
+                })
+            }
+            (
+                SassCode::SassNotifyMultipointSwitchEvent,
+                &[reason, target, ref name @ ..],
+            ) => SassMessage::NotifyMultipointSwitchEvent(
+                MultipointSwitchEvent {
+                    reason: match reason {
+                        0x01 => MultipointSwitchReason::A2dp,
+                        0x02 => MultipointSwitchReason::Hfp,
+                        _ => MultipointSwitchReason::Unspecified,
+                    },
+                    target: match target {
+                        0x01 => SwitchTarget::ThisDevice,
+                        0x02 => SwitchTarget::AnotherDevice,
+                        _ => return None,
+                    },
+                    device_name: String::from_utf8_lossy(name).into_owned(),
+                },
+            ),
+            (SassCode::SassGetConnectionStatus, []) => {
+                SassMessage::GetConnectionStatus
+            }
+            (
+                SassCode::SassNotifyConnectionStatus,
+                &[active_device, ref rest @ ..],
+            ) => {
+                // At least the connection state and custom data are
+                // encrypted, followed by the message nonce.
+                if rest.len() < 2 + NONCE_LEN
+                    || rest.len() > AES_BLOCK_LEN + NONCE_LEN
+                {
+                    return None;
+                }
+                let (encrypted, message_nonce) =
+                    rest.split_at(rest.len() - NONCE_LEN);
+                SassMessage::NotifyConnectionStatus(EncryptedConnectionStatus {
+                    active_device: match active_device {
+                        0x00 => ActiveDevice::NotThisDevice,
+                        0x01 => ActiveDevice::ThisDevice,
+                        0x02 => ActiveDevice::NonSassDevice,
+                        _ => return None,
+                    },
+                    encrypted: encrypted.to_vec(),
+                    message_nonce: message_nonce.try_into().ok()?,
+                })
+            }
+            (SassCode::SassNotifySassInitiatedConnection, &[flag]) => {
+                SassMessage::NotifySassInitiatedConnection {
+                    initiated_by_sass: !bool_from_byte(flag)?,
+                }
+            }
+            (SassCode::SassInUseAccountKey, IN_USE_ACCOUNT_KEY_DATA) => {
+                SassMessage::InUseAccountKey
+            }
+            (SassCode::SassSendCustomData, &[data]) => {
+                SassMessage::SendCustomData(data)
+            }
+            (SassCode::SassSetDropConnectionTarget, &[target]) => {
+                SassMessage::SetDropConnectionTarget(target)
+            }
+            _ => return None,
+        };
+
+        Some(message)
+    }
+}
+
+/// Append the message nonce and the MAC proving the seeker knows
+/// `account_key` to the additional data of an authenticated message.
+pub fn authenticate(
+    data: &[u8],
+    account_key: &AccountKey,
+    session_nonce: &Nonce,
+    message_nonce: &Nonce,
+) -> Vec<u8> {
+    let mac = message_mac(data, account_key, session_nonce, message_nonce)
+        .finalize()
+        .into_bytes();
+
+    let mut authenticated = data.to_vec();
+    authenticated.extend_from_slice(message_nonce);
+    authenticated.extend_from_slice(&mac[..MAC_LEN]);
+    authenticated
+}
+
+/// Check the MAC of the additional data of an authenticated message,
+/// returning the data without the message nonce and MAC if it matches.
+pub fn verify<'a>(
+    data: &'a [u8],
+    account_key: &AccountKey,
+    session_nonce: &Nonce,
+) -> Option<&'a [u8]> {
+    let data_len = data.len().checked_sub(NONCE_LEN + MAC_LEN)?;
+    let (data, authentication) = data.split_at(data_len);
+    let (message_nonce, mac) = authentication.split_at(NONCE_LEN);
+    let message_nonce = message_nonce.try_into().ok()?;
+
+    message_mac(data, account_key, session_nonce, message_nonce)
+        .verify_truncated_left(mac)
+        .ok()?;
+    Some(data)
+}
+
+/// HMAC-SHA256 over the session nonce, message nonce and data, keyed with
+/// the account key.
+fn message_mac(
+    data: &[u8],
+    account_key: &AccountKey,
+    session_nonce: &Nonce,
+    message_nonce: &Nonce,
+) -> Hmac<Sha256> {
+    let mut mac = <Hmac<Sha256> as Mac>::new_from_slice(account_key)
+        .expect("Sanity check, HMAC accepts keys of any length");
+    mac.update(session_nonce);
+    mac.update(message_nonce);
+    mac.update(data);
+    mac
+}
+
+/// XOR `data` with the AES-128 encryption of the session and message nonces,
+/// which both encrypts and decrypts the connection status.
+fn apply_keystream(
+    data: &mut [u8],
+    account_key: &AccountKey,
+    session_nonce: &Nonce,
+    message_nonce: &Nonce,
+) {
+    let mut block = GenericArray::from([0; AES_BLOCK_LEN]);
+    block[..NONCE_LEN].copy_from_slice(session_nonce);
+    block[NONCE_LEN..].copy_from_slice(message_nonce);
+    Aes128::new(GenericArray::from_slice(account_key))
+        .encrypt_block(&mut block);
+
+    for (byte, key) in data.iter_mut().zip(block) {
+        *byte ^= key;
+    }
+}
+
+fn bool_from_byte(value: u8) -> Option<bool> {
+    match value {
+        0x00 => Some(false),
+        0x01 => Some(true),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Vectors of the SASS tests of the embedded provider, see
+    // embedded/client/tests/smoke_test.cc.
+    const ACCOUNT_KEY: AccountKey = [
+        0x04, 20, 21, 22, 23, 24, 25, 26, 27, 28, 29, 30, 31, 32, 33, 34,
+    ];
+    const SESSION_NONCE: Nonce = [0xAB; NONCE_LEN];
+    const MESSAGE_NONCE: Nonce =
+        [0xC0, 0xC1, 0xC2, 0xC3, 0xC4, 0xC5, 0xC6, 0xC7];
+
+    #[test]
+    fn sass_authenticate() {
+        assert_eq!(
+            authenticate(
+                &SassMessage::InUseAccountKey.encode(),
+                &ACCOUNT_KEY,
+                &SESSION_NONCE,
+                &MESSAGE_NONCE
+            ),
+            [
+                b'i', b'n', b'-', b'u', b's', b'e', 0xC0, 0xC1, 0xC2, 0xC3,
+                0xC4, 0xC5, 0xC6, 0xC7, 0x70, 0x69, 0xd6, 0xc5, 0x06, 0xa4,
+                0x94, 0x32
+            ]
+        );
+
+        let authenticated = authenticate(
+            &SassMessage::SetMultipointState { enable: true }.encode(),
+            &ACCOUNT_KEY,
+            &SESSION_NONCE,
+            &MESSAGE_NONCE,
+        );
+        assert_eq!(
+            authenticated,
+            [
+                0x01, 0xC0, 0xC1, 0xC2, 0xC3, 0xC4, 0xC5, 0xC6, 0xC7, 0xb7,
+                0x2f, 0x61, 0xb4, 0xe8, 0x92, 0xe1, 0x44
+            ]
+        );
+
+        assert_eq!(
+            verify(&authenticated, &ACCOUNT_KEY, &SESSION_NONCE),
+            Some(&[0x01][..])
+        );
+        let mut other_key = ACCOUNT_KEY;
+        other_key[1] ^= 0xFF;
+        assert_eq!(verify(&authenticated, &other_key, &SESSION_NONCE), None);
+        assert_eq!(
+            verify(&authenticated[1..], &ACCOUNT_KEY, &SESSION_NONCE),
+            None
+        );
+        assert_eq!(verify(&[0; 15], &ACCOUNT_KEY, &SESSION_NONCE), None);
+    }
+
+    #[test]
+    fn sass_connection_status() {
+        let data = [
+            0x00, 0xaf, 0x75, 0x14, 0xAB, 0xAB, 0xAB, 0xAB, 0xAB, 0xAB, 0xAB,
+            0xAB,
+        ];
+        let Some(SassMessage::NotifyConnectionStatus(encrypted)) =
+            SassMessage::decode(SassCode::SassNotifyConnectionStatus, &data)
+        else {
+            panic!("Connection status should decode");
+        };
+
+        let status = encrypted.decrypt(&ACCOUNT_KEY, &SESSION_NONCE).unwrap();
+        assert_eq!(
+            status,
+            ConnectionStatus {
+                active_device: ActiveDevice::NotThisDevice,
+                connection_state: ConnectionState::from_bits(0x85),
+                custom_data: 0,
+                connected_devices: vec![0x09],
+            }
+        );
+        assert!(status.connection_state.contains(ConnectionState::ON_HEAD));
+        assert!(!status.connection_state.contains(ConnectionState::AVAILABLE));
+        assert_eq!(
+            status.connection_state.audio_state(),
+            AudioConnectionState::A2dpWithAvrcp
+        );
+
+        let reencrypted = status
+            .encrypt(&ACCOUNT_KEY, &SESSION_NONCE, &[0xAB; NONCE_LEN])
+            .unwrap();
+        assert_eq!(reencrypted, encrypted);
+        assert_eq!(
+            SassMessage::NotifyConnectionStatus(reencrypted).encode(),
+            data
+        );
+
+        // The encrypted part is at least 2 and at most 16 bytes long.
+        assert_eq!(
+            SassMessage::decode(
+                SassCode::SassNotifyConnectionStatus,
+                &data[..10]
+            ),
+            None
+        );
+        let too_long = ConnectionStatus {
+            connected_devices: vec![0; 15],
+            ..status
+        };
+        assert!(too_long
+            .encrypt(&ACCOUNT_KEY, &SESSION_NONCE, &MESSAGE_NONCE)
+            .is_err());
+        for len in [0, 1, 17] {
+            let malformed = EncryptedConnectionStatus {
+                encrypted: vec![0; len],
+                ..encrypted.clone()
+            };
+            assert!(matches!(
+                malformed.decrypt(&ACCOUNT_KEY, &SESSION_NONCE),
+                Err(BluetoothError::MalformedData(_))
+            ));
+        }
+    }
+
+    #[test]
+    fn sass_message_encoding() {
+        let messages = [
+            (SassMessage::GetCapability, vec![]),
+            (
+                SassMessage::NotifyCapability(SassCapability {
+                    version: SASS_VERSION,
+                    flags: SassCapabilityFlags::from_bits(
+                        SassCapabilityFlags::SASS_ON
+                            | SassCapabilityFlags::MULTIPOINT_ON,
+                    ),
+                }),
+                vec![0x01, 0x01, 0xA0, 0x00],
+            ),
+            (
+                SassMessage::SetMultipointState { enable: false },
+                vec![0x00],
+            ),
+            (
+                SassMessage::SetSwitchingPreference(
+                    SwitchingPreference::from_bits(
+                        SwitchingPreference::A2DP_VS_A2DP,
+                    ),
+                ),
+                vec![0x80, 0x00],
+            ),
+            (SassMessage::GetSwitchingPreference, vec![]),
+            (
+                SassMessage::NotifySwitchingPreference(
+                    SwitchingPreference::from_bits(0x30),
+                ),
+                vec![0x30, 0x00],
+            ),
+            (
+                SassMessage::SwitchActiveSource(
+                    SwitchActiveSourceFlags::from_bits(
+                        SwitchActiveSourceFlags::THIS_DEVICE
+                            | SwitchActiveSourceFlags::RESUME_PLAYING,
+                    ),
+                ),
+                vec![0xC0],
+            ),
+            (
+                SassMessage::SwitchBack(SwitchBackAction::SwitchBackAndResume),
+                vec![0x02],
+            ),
+            (
+                SassMessage::NotifyMultipointSwitchEvent(
+                    MultipointSwitchEvent {
+                        reason: MultipointSwitchReason::Hfp,
+                        target: SwitchTarget::AnotherDevice,
+                        device_name: String::from("Pixel"),
+                    },
+                ),
+                vec![0x02, 0x02, b'P', b'i', b'x', b'e', b'l'],
+            ),
+            (SassMessage::GetConnectionStatus, vec![]),
+            (
+                SassMessage::NotifySassInitiatedConnection {
+                    initiated_by_sass: true,
+                },
+                vec![0x00],
+            ),
+            (SassMessage::InUseAccountKey, b"in-use".to_vec()),
+            (SassMessage::SendCustomData(0x42), vec![0x42]),
+            (SassMessage::SetDropConnectionTarget(0x04), vec![0x04]),
+        ];
+
+        for (message, data) in messages {
+            assert_eq!(message.encode(), data, "{:?}", message);
+            assert_eq!(
+                SassMessage::decode(message.code(), &data),
+                Some(message)
+            );
+        }
+
+        assert_eq!(
+            SassMessage::decode(SassCode::SassGetCapability, &[0]),
+            None
+        );
+        assert_eq!(
+            SassMessage::decode(SassCode::SassSetMultipointState, &[0x02]),
+            None
+        );
+        assert_eq!(
+            SassMessage::decode(SassCode::SassSwitchBackAudioSource, &[0x03]),
+            None
+        );
+        assert_eq!(SassMessage::decode(SassCode::Unknown(0x50), &[]), None);
+    }
+}
@@ -14,8 +14,12 @@
 
 use async_trait::async_trait;
 
-use super::BleDevice;
-use crate::{api, common::BluetoothError, BleAdvertisement, BleDataTypeId};
+use super::{BleDevice, ClassicDevice};
+use crate::{
+    api, common::BluetoothError, AdapterId, AdapterInfo, BleAddress,
+    BleDataTypeId, BondEventStream, BondedDevice, ClassicAddress,
+    RadioStateStream, ScanFilter, ScanSettings, ScanStream,
+};
 
 /// Concrete type implementing `Adapter`, used for unsupported devices.
 /// Every method should panic.
@@ -23,23 +27,66 @@ pub struct BleAdapter;
 
 #[async_trait]
 impl api::BleAdapter for BleAdapter {
+    type BleDevice = BleDevice;
+    type ClassicDevice = ClassicDevice;
+
     async fn default() -> Result<Self, BluetoothError> {
         panic!("Unsupported target platform.");
     }
 
-    fn start_scan(&mut self) -> Result<(), BluetoothError> {
+    async fn adapters() -> Result<Vec<AdapterInfo>, BluetoothError> {
+        panic!("Unsupported target platform.");
+    }
+
+    async fn with_id(id: &AdapterId) -> Result<Self, BluetoothError> {
+        panic!("Unsupported target platform.");
+    }
+
+    async fn ble_device(
+        &self,
+        addr: BleAddress,
+    ) -> Result<BleDevice, BluetoothError> {
+        panic!("Unsupported target platform.");
+    }
+
+    async fn classic_device(
+        &self,
+        addr: ClassicAddress,
+    ) -> Result<ClassicDevice, BluetoothError> {
         panic!("Unsupported target platform.");
     }
 
-    fn stop_scan(&mut self) -> Result<(), BluetoothError> {
+    async fn info(&self) -> Result<AdapterInfo, BluetoothError> {
         panic!("Unsupported target platform.");
     }
 
-    async fn next_advertisement(
-        &mut self,
-        datatype_selector: Option<&Vec<BleDataTypeId>>,
-    ) -> Result<BleAdvertisement, BluetoothError> {
-        panic!("Unsupported target platform");
+    async fn radio_state_changes(
+        &self,
+    ) -> Result<RadioStateStream, BluetoothError> {
+        panic!("Unsupported target platform.");
+    }
+
+    fn start_scan(
+        &self,
+        filter: &ScanFilter,
+        settings: &ScanSettings,
+        data_selector: &[BleDataTypeId],
+    ) -> Result<ScanStream, BluetoothError> {
+        panic!("Unsupported target platform.");
+    }
+
+    async fn paired_devices(
+        &self,
+    ) -> Result<Vec<BondedDevice>, BluetoothError> {
+        panic!("Unsupported target platform.");
+    }
+
+    async fn unpair(&self, device: BondedDevice) -> Result<(), BluetoothError> {
+        panic!("Unsupported target platform.");
+    }
+
+    async fn bond_events(&self) -> Result<BondEventStream, BluetoothError> {
+        panic!("Unsupported target platform.");
     }
 }
 
@@ -0,0 +1,42 @@
+// Copyright 2023 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use async_trait::async_trait;
+
+use crate::{
+    api,
+    common::{
+        AdvertisingData, AdvertisingSettings, AdvertisingStream,
+        BluetoothError,
+    },
+};
+
+/// Concrete type implementing `api::BleAdvertiser` for unsupported platforms.
+/// Every method should panic.
+pub struct BleAdvertiser;
+
+#[async_trait]
+impl api::BleAdvertiser for BleAdvertiser {
+    async fn default() -> Result<Self, BluetoothError> {
+        panic!("Unsupported target platform.");
+    }
+
+    fn start_advertising(
+        &self,
+        data: &AdvertisingData,
+        settings: &AdvertisingSettings,
+    ) -> Result<AdvertisingStream, BluetoothError> {
+        panic!("Unsupported target platform.");
+    }
+}
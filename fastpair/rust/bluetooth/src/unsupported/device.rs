@@ -12,11 +12,18 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+use std::sync::Arc;
+
 use async_trait::async_trait;
 
 use crate::{
     api,
-    common::{BleAddress, BluetoothError, ClassicAddress, PairingResult},
+    common::{
+        BleAddress, BluetoothError, BondStateStream, BtUuid, ClassicAddress,
+        ConnectionState, ConnectionStateStream, GattCharacteristic,
+        GattService, NotificationStream, PairingHandler, PairingResult,
+        ProtectionLevel, RfcommSocket, WriteType,
+    },
 };
 
 /// Concrete type implementing `api::BleDevice` for unsupported platforms.
@@ -36,6 +43,90 @@ impl api::BleDevice for BleDevice {
     fn address(&self) -> BleAddress {
         panic!("Unsupported target platform.");
     }
+
+    async fn connect(&self) -> Result<(), BluetoothError> {
+        panic!("Unsupported target platform.");
+    }
+
+    async fn disconnect(&self) -> Result<(), BluetoothError> {
+        panic!("Unsupported target platform.");
+    }
+
+    async fn discover_services(
+        &self,
+        uuid: Option<BtUuid>,
+    ) -> Result<Vec<GattService>, BluetoothError> {
+        panic!("Unsupported target platform.");
+    }
+
+    async fn discover_characteristics(
+        &self,
+        service: &GattService,
+        uuid: Option<BtUuid>,
+    ) -> Result<Vec<GattCharacteristic>, BluetoothError> {
+        panic!("Unsupported target platform.");
+    }
+
+    async fn read(
+        &self,
+        characteristic: &GattCharacteristic,
+    ) -> Result<Vec<u8>, BluetoothError> {
+        panic!("Unsupported target platform.");
+    }
+
+    async fn write(
+        &self,
+        characteristic: &GattCharacteristic,
+        value: &[u8],
+        write_type: WriteType,
+    ) -> Result<(), BluetoothError> {
+        panic!("Unsupported target platform.");
+    }
+
+    async fn subscribe(
+        &self,
+        characteristic: &GattCharacteristic,
+    ) -> Result<NotificationStream, BluetoothError> {
+        panic!("Unsupported target platform.");
+    }
+
+    async fn mtu(&self) -> Result<u16, BluetoothError> {
+        panic!("Unsupported target platform.");
+    }
+
+    async fn pair(
+        &self,
+        protection_level: ProtectionLevel,
+        handler: Arc<dyn PairingHandler>,
+    ) -> Result<PairingResult, BluetoothError> {
+        panic!("Unsupported target platform.");
+    }
+
+    async fn is_paired(&self) -> Result<bool, BluetoothError> {
+        panic!("Unsupported target platform.");
+    }
+
+    async fn unpair(&self) -> Result<(), BluetoothError> {
+        panic!("Unsupported target platform.");
+    }
+
+    async fn bond_state_changes(
+        &self,
+    ) -> Result<BondStateStream, BluetoothError> {
+        panic!("Unsupported target platform.");
+    }
+
+    async fn connection_state(
+        &self,
+    ) -> Result<ConnectionState, BluetoothError> {
+        panic!("Unsupported target platform.");
+    }
+
+    async fn connection_events(
+        &self,
+    ) -> Result<ConnectionStateStream, BluetoothError> {
+        panic!("Unsupported target platform.");
+    }
 }
 
 /// Concrete type implementing `api::ClassicDevice` for unsupported platforms.
@@ -56,7 +147,29 @@ impl api::ClassicDevice for ClassicDevice {
         panic!("Unsupported target platform.");
     }
 
-    async fn pair(&self) -> Result<PairingResult, BluetoothError> {
+    async fn pair(
+        &self,
+        handler: Arc<dyn PairingHandler>,
+    ) -> Result<PairingResult, BluetoothError> {
+        panic!("Unsupported target platform.");
+    }
+
+    async fn connect_rfcomm(
+        &self,
+        service_uuid: BtUuid,
+    ) -> Result<RfcommSocket, BluetoothError> {
+        panic!("Unsupported target platform.");
+    }
+
+    async fn connection_state(
+        &self,
+    ) -> Result<ConnectionState, BluetoothError> {
+        panic!("Unsupported target platform.");
+    }
+
+    async fn connection_events(
+        &self,
+    ) -> Result<ConnectionStateStream, BluetoothError> {
         panic!("Unsupported target platform.");
     }
 }
@@ -14,7 +14,9 @@
 
 /// Bluetooth LE module for unsupported devices. Every method panics.
 mod adapter;
+mod advertiser;
 mod device;
 
 pub use adapter::*;
+pub use advertiser::*;
 pub use device::*;
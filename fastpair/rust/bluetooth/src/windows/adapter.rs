@@ -12,22 +12,28 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
-use std::sync::Arc;
+use std::{
+    pin::Pin,
+    sync::Arc,
+    task::{Context, Poll},
+};
 
 use async_trait::async_trait;
-use futures::{channel::mpsc::Receiver, StreamExt};
+use futures::{channel::mpsc::Receiver, ready, Stream, StreamExt};
 use tracing::{error, info, warn};
 use windows::{
+    core::{IInspectable, GUID, HSTRING},
+
     Devices::Bluetooth::{
         Advertisement::{
+            // Pattern of bytes that received advertisements must contain to
+            // pass a watcher's `AdvertisementFilter`.
+            // https://learn.microsoft.com/en-us/uwp/api/windows.devices.bluetooth.advertisement.bluetoothleadvertisementbytepattern?view=winrt-22621
+            BluetoothLEAdvertisementBytePattern,
             // Struct that receives Bluetooth Low Energy (LE) advertisements.
             // https://learn.microsoft.com/en-us/uwp/api/windows.devices.bluetooth.advertisement.bluetoothleadvertisementwatcher?view=winrt-22621
             BluetoothLEAdvertisementReceivedEventArgs,
 
-            // Enum describing the type of advertisement (connectable, directed, etc).
-            // https://learn.microsoft.com/en-us/uwp/api/windows.devices.bluetooth.bluetoothaddresstype?view=winrt-22621
-            BluetoothLEAdvertisementType,
-
             // Provides data for a Received event on a `BluetoothLEAdvertisementWatcher`.
             // Instance is created when the Received event occurs in the watcher struct.
             // https://learn.microsoft.com/en-us/uwp/api/windows.devices.bluetooth.advertisement.bluetoothleadvertisementreceivedeventargs?view=winrt-22621
@@ -46,38 +52,82 @@ use windows::{
         // Bluetooth adapter.
         // https://learn.microsoft.com/en-us/uwp/api/windows.devices.bluetooth.bluetoothadapter?view=winrt-22621
         BluetoothAdapter,
+
+        // Tuple struct describing why a Bluetooth operation failed, e.g. why a
+        // watcher stopped.
+        // https://learn.microsoft.com/en-us/uwp/api/windows.devices.bluetooth.bluetootherror?view=winrt-22621
+        BluetoothError as WinBluetoothError,
+    },
+    // Struct describing a device found by enumeration, used to look up the
+    // adapter's name.
+    // https://learn.microsoft.com/en-us/uwp/api/windows.devices.enumeration.deviceinformation?view=winrt-22621
+    Devices::Enumeration::DeviceInformation,
+    Devices::Radios::{
+        // Struct representing the radio of an adapter, which the user can
+        // switch on and off.
+        // https://learn.microsoft.com/en-us/uwp/api/windows.devices.radios.radio?view=winrt-22621
+        Radio,
+
+        // Tuple struct describing whether a radio is switched on.
+        // https://learn.microsoft.com/en-us/uwp/api/windows.devices.radios.radiostate?view=winrt-22621
+        RadioState as WinRadioState,
     },
     // Wraps a closure for handling events associated with a struct
     // (e.g. Received and Stopped events in BluetoothLEAdvertisementWatcher).
     // https://learn.microsoft.com/en-us/uwp/api/windows.foundation.typedeventhandler-2?view=winrt-22621
-    Foundation::TypedEventHandler,
+    Foundation::{EventRegistrationToken, TypedEventHandler},
+
+    // Struct for writing data to a Windows stream, used to build `IBuffer`s.
+    // https://learn.microsoft.com/en-us/uwp/api/windows.storage.streams.datawriter?view=winrt-22621
+    Storage::Streams::DataWriter,
 };
 
+use super::{
+    bond::{paired_devices, unpair_device, BondListener},
+    device::{send_state, state_channel, StateReceiver},
+    BleDevice, ClassicDevice,
+};
 use crate::{
     api,
-    common::{BleAdvertisement, BleDataTypeId, BluetoothError},
+    common::{
+        AdapterId, AdapterInfo, BleAddress, BleAddressKind,
+        BleAdvertisement, BleDataTypeId, BluetoothError, BondEventStream,
+        BondedDevice, ClassicAddress, RadioState, RadioStateStream, ScanFilter, ScanMode,
+        ScanSettings, ScanState, ScanStream,
+    },
 };
 
 /// Struct holding the necessary fields for listening to and handling incoming
-/// BLE advertisements.
+/// BLE advertisements. Stops the watcher when dropped.
 struct AdvListener {
     /// Holds callback for sending received advertisement events to `receiver`.
     watcher: BluetoothLEAdvertisementWatcher,
-    /// Can be polled to consume incoming advertisement events.
-    receiver: Receiver<BluetoothLEAdvertisementReceivedEventArgs>,
+    /// Can be polled to consume incoming advertisement events, and the error
+    /// the watcher stopped with if the radio was switched off.
+    receiver: Receiver<
+        Result<BluetoothLEAdvertisementReceivedEventArgs, BluetoothError>,
+    >,
+    /// Checks the filter and settings in software, on top of the watcher.
+    state: ScanState,
+}
+
+/// Struct holding the handler sending the states of a radio to `receiver`.
+/// Removes the handler when dropped.
+struct RadioWatcher {
+    radio: Radio,
+    token: EventRegistrationToken,
+    receiver: StateReceiver<RadioState>,
 }
 
 /// Concrete type implementing `api::BleAdapter`, used for Windows BLE.
 pub struct BleAdapter {
     inner: BluetoothAdapter,
-    listener: Option<AdvListener>,
+    radio: Radio,
 }
 
-#[async_trait]
-impl api::BleAdapter for BleAdapter {
-    async fn default() -> Result<Self, BluetoothError> {
-        let inner = BluetoothAdapter::GetDefaultAsync()?.await?;
-
+impl BleAdapter {
+    /// Wrap `inner`, which must support LE and the central role.
+    async fn with_inner(inner: BluetoothAdapter) -> Result<Self, BluetoothError> {
         if !inner.IsLowEnergySupported()? {
             return Err(BluetoothError::NotSupported(String::from(
                 "LE transport type",
@@ -89,23 +139,144 @@ impl api::BleAdapter for BleAdapter {
             )));
         }
 
-        Ok(BleAdapter {
-            inner,
-            listener: None,
+        let radio = inner.GetRadioAsync()?.await?;
+
+        Ok(BleAdapter { inner, radio })
+    }
+}
+
+/// List the device IDs of the adapters of the system, default adapter first.
+async fn adapter_ids() -> Result<Vec<HSTRING>, BluetoothError> {
+    let default = BluetoothAdapter::GetDefaultAsync()?.await?.DeviceId()?;
+
+    // The collection can't be held across an await.
+    let mut ids: Vec<HSTRING> =
+        DeviceInformation::FindAllAsyncAqsFilter(&BluetoothAdapter::GetDeviceSelector()?)?
+            .await?
+            .into_iter()
+            .map(|info| info.Id())
+            .collect::<Result<_, _>>()?;
+    ids.sort_by_key(|id| *id != default);
+    Ok(ids)
+}
+
+/// Windows only runs one Bluetooth adapter at a time, and always scans and
+/// opens devices through it. Other adapters can be listed and inspected, but
+/// scans and devices of the adapters they open still use the running one.
+#[async_trait]
+impl api::BleAdapter for BleAdapter {
+    type BleDevice = BleDevice;
+    type ClassicDevice = ClassicDevice;
+
+    async fn default() -> Result<Self, BluetoothError> {
+        Self::with_inner(BluetoothAdapter::GetDefaultAsync()?.await?).await
+    }
+
+    async fn adapters() -> Result<Vec<AdapterInfo>, BluetoothError> {
+        let mut adapters = Vec::new();
+        for id in adapter_ids().await? {
+            let inner = BluetoothAdapter::FromIdAsync(&id)?.await?;
+            adapters.push(Self::with_inner(inner).await?.info().await?);
+        }
+        Ok(adapters)
+    }
+
+    async fn with_id(id: &AdapterId) -> Result<Self, BluetoothError> {
+        let id = HSTRING::from(id.as_str());
+        if !adapter_ids().await?.contains(&id) {
+            return Err(BluetoothError::InvalidArgument(format!(
+                "no adapter {}",
+                id
+            )));
+        }
+        Self::with_inner(BluetoothAdapter::FromIdAsync(&id)?.await?).await
+    }
+
+    async fn ble_device(&self, addr: BleAddress) -> Result<BleDevice, BluetoothError> {
+        <BleDevice as api::BleDevice>::new(addr).await
+    }
+
+    async fn classic_device(
+        &self,
+        addr: ClassicAddress,
+    ) -> Result<ClassicDevice, BluetoothError> {
+        <ClassicDevice as api::ClassicDevice>::new(addr).await
+    }
+
+    /// Windows 0.48 can't query the adapter's Coded PHY support, so it is
+    /// reported as unsupported.
+    async fn info(&self) -> Result<AdapterInfo, BluetoothError> {
+        let device = DeviceInformation::CreateFromIdAsync(&self.inner.DeviceId()?)?.await?;
+
+        Ok(AdapterInfo {
+            id: AdapterId::from(self.inner.DeviceId()?.to_string_lossy()),
+            address: BleAddress::new(self.inner.BluetoothAddress()?, BleAddressKind::Public),
+            name: device.Name()?.to_string_lossy(),
+            central_role_supported: self.inner.IsCentralRoleSupported()?,
+            peripheral_role_supported: self.inner.IsPeripheralRoleSupported()?,
+            extended_advertising_supported: self.inner.IsExtendedAdvertisingSupported()?,
+            coded_phy_supported: false,
+            max_advertisement_length: self.inner.MaxAdvertisementDataLength()? as usize,
+            radio_state: radio_state(self.radio.State()?),
         })
     }
 
-    fn start_scan(&mut self) -> Result<(), BluetoothError> {
+    async fn radio_state_changes(&self) -> Result<RadioStateStream, BluetoothError> {
+        let (sender, receiver) = state_channel(radio_state(self.radio.State()?))?;
+        let handler_sender = sender.clone();
+        let token = self.radio.StateChanged(&TypedEventHandler::new(
+            move |radio: &Option<Radio>, _: &Option<IInspectable>| {
+                if let Some(radio) = radio {
+                    send_state(&handler_sender, radio_state(radio.State()?));
+                }
+                Ok(())
+            },
+        ))?;
+
+        // Removes the handler if reading the state fails.
+        let watcher = RadioWatcher {
+            radio: self.radio.clone(),
+            token,
+            receiver,
+        };
+        send_state(&sender, radio_state(self.radio.State()?));
+        Ok(RadioStateStream::new(watcher))
+    }
+
+    /// Windows picks its own scan timing, so the interval and window hints of
+    /// `settings` are ignored.
+    fn start_scan(
+        &self,
+        filter: &ScanFilter,
+        settings: &ScanSettings,
+        data_selector: &[BleDataTypeId],
+    ) -> Result<ScanStream, BluetoothError> {
+        if self.radio.State()? != WinRadioState::On {
+            return Err(BluetoothError::FailedPrecondition(String::from(
+                "Bluetooth is turned off.",
+            )));
+        }
+
         let watcher = BluetoothLEAdvertisementWatcher::new()?;
-        match watcher.SetScanningMode(BluetoothLEScanningMode::Active) {
+        set_watcher_filter(&watcher, filter)?;
+
+        let mode = match settings.mode() {
+            ScanMode::Passive => BluetoothLEScanningMode::Passive,
+            ScanMode::Active => BluetoothLEScanningMode::Active,
+        };
+        match watcher.SetScanningMode(mode) {
             Ok(_) => (),
             Err(err) => {
-                warn!("Failed to turn on active scanning. Error: {}", err)
+                warn!("Failed to set {:?} scanning. Error: {}", mode, err)
             }
         };
 
-        if self.inner.IsExtendedAdvertisingSupported()? {
-            watcher.SetAllowExtendedAdvertisements(true)?;
+        if settings.extended() {
+            if self.inner.IsExtendedAdvertisingSupported()? {
+                watcher.SetAllowExtendedAdvertisements(true)?;
+            } else {
+                warn!("Extended advertising isn't supported by the adapter.");
+            }
         }
 
         // `futures::channel::mpsc` is like `std::sync::mpsc` but `impl Stream`.
@@ -128,7 +299,7 @@ impl api::BleAdapter for BleAdapter {
                             match sender
                                 .lock()
                                 .unwrap()
-                                .try_send(event_args.clone())
+                                .try_send(Ok(event_args.clone()))
                             {
                                 Ok(_) => (),
                                 Err(err) => {
@@ -148,12 +319,28 @@ impl api::BleAdapter for BleAdapter {
         let stopped_handler = TypedEventHandler::new(
             // Move `sender` into closure.
             move |_watcher,
-                  _event_args: &Option<
+                  event_args: &Option<
                 BluetoothLEAdvertisementWatcherStoppedEventArgs,
             >| {
                 // Drop `sender`, closing the channel.
-                let _sender = sender.take();
+                let sender = sender.take();
                 info!("Watcher stopped receiving BLE advertisements.");
+
+                // Report the radio being switched off before the stream
+                // ends.
+                if let (Some(sender), Some(event_args)) = (sender, event_args) {
+                    match event_args.Error()? {
+                        WinBluetoothError::RadioNotAvailable
+                        | WinBluetoothError::DisabledByUser => {
+                            let _ = sender.lock().unwrap().try_send(Err(
+                                BluetoothError::FailedPrecondition(String::from(
+                                    "Bluetooth was turned off.",
+                                )),
+                            ));
+                        }
+                        _ => (),
+                    }
+                }
                 Ok(())
             },
         );
@@ -162,58 +349,158 @@ impl api::BleAdapter for BleAdapter {
         watcher.Stopped(&stopped_handler)?;
         watcher.Start()?;
 
-        self.listener = Some(AdvListener { watcher, receiver });
+        Ok(ScanStream::new(AdvListener {
+            watcher,
+            receiver,
+            state: ScanState::new(filter, settings, data_selector),
+        }))
+    }
 
-        Ok(())
+    /// Windows lists the devices paired over LE and BT Classic separately,
+    /// so dual-mode devices paired over both are listed twice.
+    async fn paired_devices(&self) -> Result<Vec<BondedDevice>, BluetoothError> {
+        Ok(paired_devices()
+            .await?
+            .into_iter()
+            .map(|(_, device)| device)
+            .collect())
     }
 
-    fn stop_scan(&mut self) -> Result<(), BluetoothError> {
-        if let Some(listener) = self.listener.take() {
-            listener.watcher.Stop()?;
-            Ok(())
-        } else {
-            Err(BluetoothError::FailedPrecondition(String::from(
-                "device scanning hasn't started, please call `start_scan()`",
-            )))
+    async fn unpair(&self, device: BondedDevice) -> Result<(), BluetoothError> {
+        for (info, paired) in paired_devices().await? {
+            if paired == device {
+                unpair_device(info.Pairing()?).await?;
+            }
         }
+        Ok(())
+    }
+
+    async fn bond_events(&self) -> Result<BondEventStream, BluetoothError> {
+        Ok(BondEventStream::new(BondListener::new().await?))
     }
+}
+
+/// Push the criteria of `filter` that Windows supports down to `watcher`, so
+/// the OS drops most non-matching advertisements. Every criterion is still
+/// checked in software. The RSSI is only checked there, as Windows' signal
+/// strength filter adds hysteresis and timeouts on top of the threshold. So
+/// is service data: devices may send it under any of the 16, 32 and 128-bit
+/// forms of its UUID, while a byte pattern only matches one of them.
+fn set_watcher_filter(
+    watcher: &BluetoothLEAdvertisementWatcher,
+    filter: &ScanFilter,
+) -> Result<(), BluetoothError> {
+    let advertisement_filter = watcher.AdvertisementFilter()?;
+    let advertisement = advertisement_filter.Advertisement()?;
+    let byte_patterns = advertisement_filter.BytePatterns()?;
+
+    if let Some(uuid) = filter.service_uuid() {
+        advertisement
+            .ServiceUuids()?
+            .Append(GUID::from_u128(uuid.as_u128()))?;
+    }
+
+    if let Some(name) = filter.local_name() {
+        advertisement.SetLocalName(&HSTRING::from(name))?;
+    }
+
+    if let Some(company_id) = filter.manufacturer_id() {
+        byte_patterns.Append(&byte_pattern(
+            BleDataTypeId::ManufacturerSpecificData,
+            &company_id.to_le_bytes(),
+        )?)?;
+    }
+
+    Ok(())
+}
+
+/// Build a pattern matching AD structures of type `datatype_id` whose data
+/// starts with `data`.
+fn byte_pattern(
+    datatype_id: BleDataTypeId,
+    data: &[u8],
+) -> Result<BluetoothLEAdvertisementBytePattern, BluetoothError> {
+    let writer = DataWriter::new()?;
+    writer.WriteBytes(data)?;
+
+    let pattern = BluetoothLEAdvertisementBytePattern::new()?;
+    pattern.SetDataType(datatype_id as u8)?;
+    pattern.SetOffset(0)?;
+    pattern.SetData(&writer.DetachBuffer()?)?;
 
-    async fn next_advertisement(
+    Ok(pattern)
+}
+
+impl AdvListener {
+    /// Convert a Received event into an advertisement. Returns `None` for
+    /// advertisements the filter or settings exclude.
+    fn advertisement(
         &mut self,
-        datatype_selector: Option<&Vec<BleDataTypeId>>,
-    ) -> Result<BleAdvertisement, BluetoothError> {
-        if let Some(listener) = &mut self.listener {
-            let stream = &mut listener.receiver;
-            // We don't want the end-user to receive empty devices, so this is a
-            // loop to catch and skip trivial errors from advertisements that
-            // can't be turned into devices.
-            loop {
-                let event_args =
-                    stream.next().await.ok_or(BluetoothError::Internal(
-                        String::from("Event returned from stream is None."),
-                    ))?;
-
-                match event_args.AdvertisementType()? {
-                    BluetoothLEAdvertisementType::NonConnectableUndirected => {
-                        ()
-                    }
-                    _ => {
-                        let mut advertisement =
-                            BleAdvertisement::try_from(&event_args)?;
+        event_args: &BluetoothLEAdvertisementReceivedEventArgs,
+    ) -> Result<Option<BleAdvertisement>, BluetoothError> {
+        let mut advertisement = BleAdvertisement::try_from(event_args)?;
+        advertisement.load_data(event_args, self.state.datatype_ids())?;
 
-                        if let Some(datatype_selector) = datatype_selector {
-                            advertisement
-                                .load_data(&event_args, datatype_selector)?;
-                        }
+        Ok(self.state.process(advertisement))
+    }
+}
 
-                        break Ok(advertisement);
-                    }
-                }
+impl Stream for AdvListener {
+    type Item = Result<BleAdvertisement, BluetoothError>;
+
+    fn poll_next(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Self::Item>> {
+        // Loop to skip advertisements that aren't delivered to the end-user.
+        // The channel closes once the watcher stops, ending the stream.
+        loop {
+            let event_args = match ready!(self.receiver.poll_next_unpin(cx)) {
+                Some(Ok(event_args)) => event_args,
+                Some(Err(err)) => return Poll::Ready(Some(Err(err))),
+                None => return Poll::Ready(None),
+            };
+
+            match self.advertisement(&event_args).transpose() {
+                Some(advertisement) => return Poll::Ready(Some(advertisement)),
+                None => continue,
             }
-        } else {
-            Err(BluetoothError::FailedPrecondition(String::from(
-                "device scanning hasn't started, please call `start_scan()`",
-            )))
+        }
+    }
+}
+
+/// Convert the state of a Windows radio. Radios disabled by the system count
+/// as switched off.
+fn radio_state(state: WinRadioState) -> RadioState {
+    match state {
+        WinRadioState::On => RadioState::On,
+        _ => RadioState::Off,
+    }
+}
+
+impl Stream for RadioWatcher {
+    type Item = Result<RadioState, BluetoothError>;
+
+    fn poll_next(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Self::Item>> {
+        self.receiver.poll_next_unpin(cx)
+    }
+}
+
+impl Drop for RadioWatcher {
+    fn drop(&mut self) {
+        if let Err(err) = self.radio.RemoveStateChanged(self.token) {
+            warn!("Failed to remove radio state handler. Error: {}", err);
+        }
+    }
+}
+
+impl Drop for AdvListener {
+    fn drop(&mut self) {
+        if let Err(err) = self.watcher.Stop() {
+            warn!("Failed to stop BLE advertisement watcher. Error: {}", err);
         }
     }
 }
@@ -18,11 +18,12 @@ use windows::{
     Devices::Bluetooth::Advertisement::{
         BluetoothLEAdvertisementDataSection,
         BluetoothLEAdvertisementReceivedEventArgs,
+        BluetoothLEAdvertisementType,
     },
 
-    // Struct representing an immutable view into a vector.
-    // https://learn.microsoft.com/en-us/uwp/api/windows.foundation.collections.ivectorview-1?view=winrt-22621
-    Foundation::Collections::IVectorView,
+    // Struct representing a random-access collection of elements.
+    // https://learn.microsoft.com/en-us/uwp/api/windows.foundation.collections.ivector-1?view=winrt-22621
+    Foundation::Collections::IVector,
 
     // Struct for reading data from a Windows stream, like an IVectorView.
     // https://learn.microsoft.com/en-us/uwp/api/windows.storage.streams.datareader?view=winrt-22621
@@ -30,8 +31,8 @@ use windows::{
 };
 
 use crate::common::{
-    BleAddress, BleAddressKind, BleAdvertisement, BleDataTypeId,
-    BluetoothError, ServiceData,
+    AdStructure, BleAddress, BleAddressKind, BleAdvertisement,
+    BleAdvertisementType, BleDataTypeId, BluetoothError,
 };
 
 impl TryFrom<&BluetoothLEAdvertisementReceivedEventArgs> for BleAdvertisement {
@@ -52,10 +53,63 @@ impl TryFrom<&BluetoothLEAdvertisementReceivedEventArgs> for BleAdvertisement {
             Err(_) => None,
         };
 
-        Ok(BleAdvertisement::new(addr, rssi, tx_power))
+        let mut advertisement = BleAdvertisement::new(addr, rssi, tx_power);
+        advertisement.set_advertisement_type(advertisement_type(adv)?);
+
+        Ok(advertisement)
     }
 }
 
+/// Build the advertisement type from the event properties, falling back to
+/// the legacy PDU type on systems without them (before Windows 10 2004).
+fn advertisement_type(
+    adv: &BluetoothLEAdvertisementReceivedEventArgs,
+) -> Result<BleAdvertisementType, BluetoothError> {
+    let kind = adv.AdvertisementType()?;
+
+    if let (Ok(connectable), Ok(scannable), Ok(directed), Ok(scan_response)) = (
+        adv.IsConnectable(),
+        adv.IsScannable(),
+        adv.IsDirected(),
+        adv.IsScanResponse(),
+    ) {
+        let bits = [
+            (connectable, BleAdvertisementType::CONNECTABLE),
+            (scannable, BleAdvertisementType::SCANNABLE),
+            (directed, BleAdvertisementType::DIRECTED),
+            (scan_response, BleAdvertisementType::SCAN_RESPONSE),
+            (
+                kind != BluetoothLEAdvertisementType::Extended,
+                BleAdvertisementType::LEGACY,
+            ),
+        ]
+        .iter()
+        .filter(|(set, _)| *set)
+        .fold(0, |bits, (_, bit)| bits | bit);
+
+        return Ok(BleAdvertisementType::from_bits(bits));
+    }
+
+    Ok(match kind {
+        BluetoothLEAdvertisementType::ConnectableUndirected => {
+            BleAdvertisementType::ADV_IND
+        }
+        BluetoothLEAdvertisementType::ConnectableDirected => {
+            BleAdvertisementType::ADV_DIRECT_IND
+        }
+        BluetoothLEAdvertisementType::ScannableUndirected => {
+            BleAdvertisementType::ADV_SCAN_IND
+        }
+        BluetoothLEAdvertisementType::NonConnectableUndirected => {
+            BleAdvertisementType::ADV_NONCONN_IND
+        }
+        BluetoothLEAdvertisementType::ScanResponse => {
+            BleAdvertisementType::SCAN_RSP
+        }
+        _ => BleAdvertisementType::from_bits(0),
+    })
+}
+
 impl BleAdvertisement {
     /// Load data of selected data types into self by parsing the raw Windows
     /// advertisement.
@@ -65,42 +119,44 @@ impl BleAdvertisement {
         adv: &BluetoothLEAdvertisementReceivedEventArgs,
         datatype_ids: &[BleDataTypeId],
     ) -> Result<(), BluetoothError> {
-        let adv = adv.Advertisement()?;
-
-        for datatype_id in datatype_ids {
-            // Note `raw_data_sections` is `!Send` and `!Sync`. This means
-            // processing must occur in a synchronous environment. The compiler
-            // will complain if parsing is done in an async function.
-            let raw_data_sections =
-                adv.GetSectionsByType((*datatype_id) as u8)?;
-            match datatype_id {
-                BleDataTypeId::ServiceData16BitUuid => {
-                    let service_data =
-                        parse_service_data_16bit_uuid(raw_data_sections)?;
-                    self.set_service_data_16bit_uuid(service_data)
-                }
-            };
-        }
+        // Note `DataSections()` is `!Send` and `!Sync`. This means processing
+        // must occur in a synchronous environment. The compiler will complain
+        // if parsing is done in an async function.
+        let sections =
+            read_data_sections(adv.Advertisement()?.DataSections()?)?;
+        let structures = sections
+            .iter()
+            .map(|(data_type, data)| AdStructure::new(*data_type, data))
+            .collect::<Vec<_>>();
+        self.load_ad_structures(&structures, datatype_ids)?;
+
+        // Windows keeps every section it received, including the ones of
+        // unknown types, so they make up the payload sent over the air.
+        let raw_data = sections.iter().flat_map(|(data_type, data)| {
+            // The length covers the type byte too.
+            [data.len() as u8 + 1, *data_type]
+                .into_iter()
+                .chain(data.iter().copied())
+        });
+        self.set_raw_data(raw_data.collect());
 
         Ok(())
     }
 }
 
-/// Parse the advertisement's service data.
+/// Copy the AD structures Windows already split the advertisement into, so
+/// they can be handed to the platform-independent parser.
 /// Further Reading:
 /// * `BleMedium::AdvertisementReceivedHandler` under
 /// github.com/google/nearby/internal/platform/implementation/windows_ble/ble_medium.cc.
-/// * Bluetooth Supplement to the Core Specification, Part A, Section 1.11.
-/// * go/fast_pair_windows_data_parse.
 #[inline]
-fn parse_service_data_16bit_uuid(
-    raw_data_sections: IVectorView<BluetoothLEAdvertisementDataSection>,
-) -> Result<Vec<ServiceData<u16>>, BluetoothError> {
-    let mut data_vec = Vec::new();
+fn read_data_sections(
+    raw_data_sections: IVector<BluetoothLEAdvertisementDataSection>,
+) -> Result<Vec<(u8, Vec<u8>)>, BluetoothError> {
+    let mut sections = Vec::new();
 
     for raw_data in raw_data_sections {
         let data_reader = DataReader::FromBuffer(&raw_data.Data()?)?;
-        let uuid = data_reader.ReadUInt16()?;
 
         let unconsumed_buffer_len =
             data_reader.UnconsumedBufferLength()? as usize;
@@ -108,8 +164,8 @@ fn parse_service_data_16bit_uuid(
         let mut data = vec![0u8; unconsumed_buffer_len];
         data_reader.ReadBytes(&mut data)?;
 
-        data_vec.push(ServiceData::new(uuid, data));
+        sections.push((raw_data.DataType()?, data));
     }
 
-    Ok(data_vec)
+    Ok(sections)
 }
@@ -0,0 +1,234 @@
+// Copyright 2023 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::{
+    pin::Pin,
+    task::{Context, Poll},
+};
+
+use async_trait::async_trait;
+use futures::{
+    channel::mpsc::{self, UnboundedReceiver},
+    Stream, StreamExt,
+};
+use tracing::{info, warn};
+use windows::{
+    core::ComInterface,
+
+    Devices::Bluetooth::{
+        Advertisement::{
+            // Struct representing a raw AD structure of an advertisement.
+            // https://learn.microsoft.com/en-us/uwp/api/windows.devices.bluetooth.advertisement.bluetoothleadvertisementdatasection?view=winrt-22621
+            BluetoothLEAdvertisementDataSection,
+
+            // Struct that sends Bluetooth Low Energy (LE) advertisements.
+            // https://learn.microsoft.com/en-us/uwp/api/windows.devices.bluetooth.advertisement.bluetoothleadvertisementpublisher?view=winrt-22621
+            BluetoothLEAdvertisementPublisher,
+
+            // Defines constants that specify the state of a publisher.
+            // https://learn.microsoft.com/en-us/uwp/api/windows.devices.bluetooth.advertisement.bluetoothleadvertisementpublisherstatus?view=winrt-22621
+            BluetoothLEAdvertisementPublisherStatus,
+
+            // Provides data for a StatusChanged event on a `BluetoothLEAdvertisementPublisher`.
+            // https://learn.microsoft.com/en-us/uwp/api/windows.devices.bluetooth.advertisement.bluetoothleadvertisementpublisherstatuschangedeventargs?view=winrt-22621
+            BluetoothLEAdvertisementPublisherStatusChangedEventArgs,
+
+            // Struct representing the manufacturer data of an advertisement.
+            // https://learn.microsoft.com/en-us/uwp/api/windows.devices.bluetooth.advertisement.bluetoothlemanufacturerdata?view=winrt-22621
+            BluetoothLEManufacturerData,
+        },
+        // Struct for obtaining global constant information about a computer's
+        // Bluetooth adapter.
+        // https://learn.microsoft.com/en-us/uwp/api/windows.devices.bluetooth.bluetoothadapter?view=winrt-22621
+        BluetoothAdapter,
+    },
+
+    // Wraps a closure for handling events associated with a struct
+    // (e.g. StatusChanged events in BluetoothLEAdvertisementPublisher).
+    // https://learn.microsoft.com/en-us/uwp/api/windows.foundation.typedeventhandler-2?view=winrt-22621
+    Foundation::{IReference, PropertyValue, TypedEventHandler},
+
+    // Struct for writing data to a Windows stream, used to build `IBuffer`s.
+    // https://learn.microsoft.com/en-us/uwp/api/windows.storage.streams.datawriter?view=winrt-22621
+    Storage::Streams::{DataWriter, IBuffer},
+};
+
+use crate::{
+    api,
+    common::{
+        AdvertisingData, AdvertisingSettings, AdvertisingStatus,
+        AdvertisingStream, BleDataTypeId, BluetoothError,
+    },
+};
+
+/// Struct holding a started publisher and the channel its status updates are
+/// sent to. Stops the publisher when dropped.
+struct PublisherListener {
+    publisher: BluetoothLEAdvertisementPublisher,
+    receiver: UnboundedReceiver<Result<AdvertisingStatus, BluetoothError>>,
+}
+
+/// Concrete type implementing `api::BleAdvertiser`, used for Windows BLE.
+pub struct BleAdvertiser {
+    inner: BluetoothAdapter,
+}
+
+#[async_trait]
+impl api::BleAdvertiser for BleAdvertiser {
+    async fn default() -> Result<Self, BluetoothError> {
+        let inner = BluetoothAdapter::GetDefaultAsync()?.await?;
+
+        if !inner.IsLowEnergySupported()? {
+            return Err(BluetoothError::NotSupported(String::from(
+                "LE transport type",
+            )));
+        }
+        if !inner.IsPeripheralRoleSupported()? {
+            return Err(BluetoothError::NotSupported(String::from(
+                "peripheral role",
+            )));
+        }
+
+        Ok(BleAdvertiser { inner })
+    }
+
+    /// Windows publishes non-connectable advertisements and picks its own
+    /// interval, so the interval of `settings` is ignored.
+    fn start_advertising(
+        &self,
+        data: &AdvertisingData,
+        settings: &AdvertisingSettings,
+    ) -> Result<AdvertisingStream, BluetoothError> {
+        data.check_len(settings)?;
+
+        let publisher = BluetoothLEAdvertisementPublisher::new()?;
+        let advertisement = publisher.Advertisement()?;
+        for manufacturer_data in data.manufacturer_data() {
+            advertisement.ManufacturerData()?.Append(
+                &BluetoothLEManufacturerData::Create(
+                    manufacturer_data.company_id(),
+                    &buffer(manufacturer_data.data())?,
+                )?,
+            )?;
+        }
+        for (datatype_id, section) in data.ad_sections() {
+            if datatype_id != BleDataTypeId::ManufacturerSpecificData {
+                advertisement.DataSections()?.Append(
+                    &BluetoothLEAdvertisementDataSection::Create(
+                        datatype_id as u8,
+                        &buffer(&section)?,
+                    )?,
+                )?;
+            }
+        }
+
+        if settings.extended() {
+            if !self.inner.IsExtendedAdvertisingSupported()? {
+                return Err(BluetoothError::NotSupported(String::from(
+                    "extended advertising",
+                )));
+            }
+            publisher.SetUseExtendedAdvertisement(true)?;
+        }
+        if let Some(tx_power) = settings.tx_power() {
+            let tx_power = PropertyValue::CreateInt16(i16::from(tx_power))?
+                .cast::<IReference<i16>>()?;
+            publisher.SetPreferredTransmitPowerLevelInDBm(&tx_power)?;
+            publisher.SetIncludeTransmitPowerLevel(true)?;
+        }
+        if settings.interval().is_some() {
+            info!("Windows picks its own advertising interval, ignoring it.");
+        }
+
+        // The handler owns the only sender, and drops it once the publisher
+        // stopped, ending the stream.
+        let (sender, receiver) = mpsc::unbounded();
+        let mut sender = Some(sender);
+        let status_handler = TypedEventHandler::new(
+            move |_publisher,
+                  event_args: &Option<
+                BluetoothLEAdvertisementPublisherStatusChangedEventArgs,
+            >| {
+                if let Some(event_args) = event_args {
+                    let status = match event_args.Status()? {
+                        BluetoothLEAdvertisementPublisherStatus::Waiting => {
+                            Some(Ok(AdvertisingStatus::Waiting))
+                        }
+                        BluetoothLEAdvertisementPublisherStatus::Started => {
+                            Some(Ok(AdvertisingStatus::Started))
+                        }
+                        BluetoothLEAdvertisementPublisherStatus::Stopped => {
+                            Some(Ok(AdvertisingStatus::Stopped))
+                        }
+                        BluetoothLEAdvertisementPublisherStatus::Aborted => {
+                            Some(Err(BluetoothError::from(event_args.Error()?)))
+                        }
+                        _ => None,
+                    };
+
+                    let Some(status) = status else { return Ok(()) };
+                    let ended = matches!(
+                        status,
+                        Ok(AdvertisingStatus::Stopped) | Err(_)
+                    );
+                    if let Some(sender) = &sender {
+                        let _ = sender.unbounded_send(status);
+                    }
+                    if ended {
+                        // Drop `sender`, closing the channel.
+                        sender = None;
+                        info!("Publisher stopped sending BLE advertisements.");
+                    }
+                }
+
+                Ok(())
+            },
+        );
+
+        publisher.StatusChanged(&status_handler)?;
+        publisher.Start()?;
+
+        Ok(AdvertisingStream::new(PublisherListener {
+            publisher,
+            receiver,
+        }))
+    }
+}
+
+/// Copy `bytes` into a Windows buffer.
+fn buffer(bytes: &[u8]) -> Result<IBuffer, BluetoothError> {
+    let writer = DataWriter::new()?;
+    writer.WriteBytes(bytes)?;
+
+    Ok(writer.DetachBuffer()?)
+}
+
+impl Stream for PublisherListener {
+    type Item = Result<AdvertisingStatus, BluetoothError>;
+
+    fn poll_next(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Self::Item>> {
+        self.receiver.poll_next_unpin(cx)
+    }
+}
+
+impl Drop for PublisherListener {
+    fn drop(&mut self) {
+        if let Err(err) = self.publisher.Stop() {
+            warn!("Failed to stop BLE advertisement publisher. Error: {}", err);
+        }
+    }
+}
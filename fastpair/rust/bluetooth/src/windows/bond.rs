@@ -0,0 +1,321 @@
+// Copyright 2023 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::{
+    collections::HashMap,
+    pin::Pin,
+    sync::{Arc, Mutex},
+    task::{Context, Poll},
+};
+
+use futures::{
+    channel::mpsc::{self, UnboundedReceiver, UnboundedSender},
+    executor::block_on,
+    Stream, StreamExt,
+};
+use tracing::warn;
+use windows::{
+    core::HSTRING,
+    Devices::{
+        Bluetooth::{
+            // Struct representing a remote BT Classic device.
+            // https://learn.microsoft.com/en-us/uwp/api/windows.devices.bluetooth.bluetoothdevice?view=winrt-22621
+            BluetoothDevice,
+
+            // Struct representing a remote Bluetooth LE device.
+            // https://learn.microsoft.com/en-us/uwp/api/windows.devices.bluetooth.bluetoothledevice?view=winrt-22621
+            BluetoothLEDevice,
+        },
+        Enumeration::{
+            // Struct describing a device found by enumeration.
+            // https://learn.microsoft.com/en-us/uwp/api/windows.devices.enumeration.deviceinformation?view=winrt-22621
+            DeviceInformation,
+
+            // Struct for pairing with and unpairing from a device.
+            // https://learn.microsoft.com/en-us/uwp/api/windows.devices.enumeration.deviceinformationpairing?view=winrt-22621
+            DeviceInformationPairing,
+
+            // Struct identifying a device a watcher no longer finds.
+            // https://learn.microsoft.com/en-us/uwp/api/windows.devices.enumeration.deviceinformationupdate?view=winrt-22621
+            DeviceInformationUpdate,
+
+            // Tuple struct describing the outcome of unpairing.
+            // https://learn.microsoft.com/en-us/uwp/api/windows.devices.enumeration.deviceunpairingresultstatus?view=winrt-22621
+            DeviceUnpairingResultStatus,
+
+            // Struct raising events as devices matching a filter come and go.
+            // https://learn.microsoft.com/en-us/uwp/api/windows.devices.enumeration.devicewatcher?view=winrt-22621
+            DeviceWatcher,
+        },
+    },
+    // Wraps a closure for handling the Added and Removed events of a
+    // `DeviceWatcher`.
+    // https://learn.microsoft.com/en-us/uwp/api/windows.foundation.typedeventhandler-2?view=winrt-22621
+    Foundation::{EventRegistrationToken, TypedEventHandler},
+};
+
+use crate::common::{
+    BleAddress, BleAddressKind, BluetoothError, BondEvent, BondedDevice,
+    ClassicAddress,
+};
+
+/// Transport Windows enumerates paired devices of. Each has its own device
+/// selector and device class.
+#[derive(Clone, Copy, Debug)]
+enum Transport {
+    Classic,
+    Le,
+}
+
+/// Bonded devices found so far, keyed by device ID, since the Removed event
+/// only identifies a device by its ID.
+type BondedDevices = Arc<Mutex<HashMap<String, BondedDevice>>>;
+
+/// Struct holding a watcher of the paired devices of each transport and the
+/// channel their event handlers send bond events to. Stops watching when
+/// dropped.
+pub(crate) struct BondListener {
+    watchers: Vec<PairedWatcher>,
+    receiver: UnboundedReceiver<Result<BondEvent, BluetoothError>>,
+}
+
+/// Watcher of the devices paired over one transport, with the tokens of its
+/// event handlers.
+struct PairedWatcher {
+    watcher: DeviceWatcher,
+    added_token: EventRegistrationToken,
+    updated_token: EventRegistrationToken,
+    removed_token: EventRegistrationToken,
+}
+
+impl Transport {
+    /// Build the AQS filter matching the devices paired over the transport.
+    fn paired_selector(self) -> Result<HSTRING, BluetoothError> {
+        Ok(match self {
+            Transport::Classic => {
+                BluetoothDevice::GetDeviceSelectorFromPairingState(true)?
+            }
+            Transport::Le => {
+                BluetoothLEDevice::GetDeviceSelectorFromPairingState(true)?
+            }
+        })
+    }
+
+    /// Look up the address of the device with ID `id`.
+    async fn bonded_device(
+        self,
+        id: &HSTRING,
+    ) -> Result<BondedDevice, BluetoothError> {
+        match self {
+            Transport::Classic => {
+                let device = BluetoothDevice::FromIdAsync(id)?.await?;
+                Ok(BondedDevice::Classic(ClassicAddress::from(
+                    device.BluetoothAddress()?,
+                )))
+            }
+            Transport::Le => {
+                let device = BluetoothLEDevice::FromIdAsync(id)?.await?;
+                let kind =
+                    BleAddressKind::try_from(device.BluetoothAddressType()?)?;
+                Ok(BondedDevice::Ble(BleAddress::new(
+                    device.BluetoothAddress()?,
+                    kind,
+                )))
+            }
+        }
+    }
+}
+
+/// List the devices paired over either transport, with the information
+/// Windows enumerated them with.
+pub(crate) async fn paired_devices(
+) -> Result<Vec<(DeviceInformation, BondedDevice)>, BluetoothError> {
+    let mut devices = Vec::new();
+    for transport in [Transport::Classic, Transport::Le] {
+        // The collection can't be held across an await.
+        let infos: Vec<DeviceInformation> =
+            DeviceInformation::FindAllAsyncAqsFilter(
+                &transport.paired_selector()?,
+            )?
+            .await?
+            .into_iter()
+            .collect();
+        for info in infos {
+            let device = transport.bonded_device(&info.Id()?).await?;
+            devices.push((info, device));
+        }
+    }
+    Ok(devices)
+}
+
+/// Unpair the device `pairing` belongs to. Succeeds if it isn't paired.
+pub(crate) async fn unpair_device(
+    pairing: DeviceInformationPairing,
+) -> Result<(), BluetoothError> {
+    let res = pairing.UnpairAsync()?.await?;
+    match res.Status()? {
+        DeviceUnpairingResultStatus::Unpaired
+        | DeviceUnpairingResultStatus::AlreadyUnpaired => Ok(()),
+        DeviceUnpairingResultStatus::AccessDenied => {
+            Err(BluetoothError::FailedPrecondition(String::from(
+                "the caller doesn't have permission to unpair the device.",
+            )))
+        }
+        status => Err(BluetoothError::System(format!(
+            "unpairing failed with status {:?}",
+            status
+        ))),
+    }
+}
+
+impl BondListener {
+    /// Start watching the devices paired over either transport. Watchers
+    /// report every paired device once they start, so the devices paired
+    /// already are looked up first and only changes are sent.
+    pub(crate) async fn new() -> Result<Self, BluetoothError> {
+        let bonded: BondedDevices = Arc::new(Mutex::new(
+            paired_devices()
+                .await?
+                .into_iter()
+                .map(|(info, device)| {
+                    Ok((info.Id()?.to_string_lossy(), device))
+                })
+                .collect::<Result<_, BluetoothError>>()?,
+        ));
+        let (sender, receiver) = mpsc::unbounded();
+
+        let mut listener = BondListener {
+            watchers: Vec::new(),
+            receiver,
+        };
+        for transport in [Transport::Classic, Transport::Le] {
+            listener.watch(transport, bonded.clone(), sender.clone())?;
+        }
+        Ok(listener)
+    }
+
+    /// Start a watcher of the devices paired over `transport`.
+    fn watch(
+        &mut self,
+        transport: Transport,
+        bonded: BondedDevices,
+        sender: UnboundedSender<Result<BondEvent, BluetoothError>>,
+    ) -> Result<(), BluetoothError> {
+        let watcher = DeviceInformation::CreateWatcherAqsFilter(
+            &transport.paired_selector()?,
+        )?;
+
+        let added_bonded = bonded.clone();
+        let added_sender = sender.clone();
+        let added_token = watcher.Added(&TypedEventHandler::new(
+            move |_watcher: &Option<DeviceWatcher>,
+                  info: &Option<DeviceInformation>| {
+                if let Some(info) = info {
+                    let id = info.Id()?;
+                    if added_bonded
+                        .lock()
+                        .unwrap()
+                        .contains_key(&id.to_string_lossy())
+                    {
+                        return Ok(());
+                    }
+
+                    // Windows calls handlers on a thread pool, so this can
+                    // block.
+                    let event =
+                        block_on(transport.bonded_device(&id)).map(|device| {
+                            added_bonded
+                                .lock()
+                                .unwrap()
+                                .insert(id.to_string_lossy(), device);
+                            BondEvent::Added(device)
+                        });
+                    let _ = added_sender.unbounded_send(event);
+                }
+                Ok(())
+            },
+        ))?;
+        // Watchers only report the devices paired after their initial
+        // enumeration if every event has a handler, so updates are ignored
+        // rather than left unhandled.
+        let updated_token = watcher.Updated(&TypedEventHandler::new(
+            |_watcher: &Option<DeviceWatcher>,
+             _update: &Option<DeviceInformationUpdate>| Ok(()),
+        ))?;
+        let removed_token = watcher.Removed(&TypedEventHandler::new(
+            move |_watcher: &Option<DeviceWatcher>,
+                  update: &Option<DeviceInformationUpdate>| {
+                if let Some(update) = update {
+                    let id = update.Id()?.to_string_lossy();
+                    if let Some(device) = bonded.lock().unwrap().remove(&id) {
+                        let _ = sender
+                            .unbounded_send(Ok(BondEvent::Removed(device)));
+                    }
+                }
+                Ok(())
+            },
+        ))?;
+        watcher.Start()?;
+
+        self.watchers.push(PairedWatcher {
+            watcher,
+            added_token,
+            updated_token,
+            removed_token,
+        });
+        Ok(())
+    }
+}
+
+impl Stream for BondListener {
+    type Item = Result<BondEvent, BluetoothError>;
+
+    fn poll_next(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Self::Item>> {
+        self.receiver.poll_next_unpin(cx)
+    }
+}
+
+impl Drop for BondListener {
+    fn drop(&mut self) {
+        for PairedWatcher {
+            watcher,
+            added_token,
+            updated_token,
+            removed_token,
+        } in &self.watchers
+        {
+            if let Err(err) = watcher.RemoveAdded(*added_token) {
+                warn!("Failed to remove device added handler. Error: {}", err);
+            }
+            if let Err(err) = watcher.RemoveUpdated(*updated_token) {
+                warn!(
+                    "Failed to remove device updated handler. Error: {}",
+                    err
+                );
+            }
+            if let Err(err) = watcher.RemoveRemoved(*removed_token) {
+                warn!(
+                    "Failed to remove device removed handler. Error: {}",
+                    err
+                );
+            }
+            if let Err(err) = watcher.Stop() {
+                warn!("Failed to stop device watcher. Error: {}", err);
+            }
+        }
+    }
+}
@@ -12,14 +12,35 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+use std::{
+    collections::HashMap,
+    pin::Pin,
+    sync::{Arc, Mutex},
+    task::{Context, Poll},
+};
+
 use async_trait::async_trait;
+use futures::{
+    channel::mpsc::{self, UnboundedReceiver, UnboundedSender},
+    future::{self, Either},
+    Stream, StreamExt,
+};
 use tracing::{info, warn};
 use windows::{
+    core::{GUID, HSTRING},
     Devices::{
         Bluetooth::{
             // Tuple struct describing the type of address (public, random, unspecified).
             // https://learn.microsoft.com/en-us/uwp/api/windows.devices.bluetooth.bluetoothaddresstype?view=winrt-22621
             BluetoothAddressType,
+
+            // Tuple struct selecting whether values are read from the system cache or the device.
+            // https://learn.microsoft.com/en-us/uwp/api/windows.devices.bluetooth.bluetoothcachemode?view=winrt-22621
+            BluetoothCacheMode,
+
+            // Tuple struct describing whether a device is connected.
+            // https://learn.microsoft.com/en-us/uwp/api/windows.devices.bluetooth.bluetoothconnectionstatus?view=winrt-22621
+            BluetoothConnectionStatus,
             
             // Struct for interacting with a discovered BT Classic device.
             // https://learn.microsoft.com/en-us/uwp/api/windows.devices.bluetooth.bluetoothdevice?view=winrt-22621
@@ -28,33 +49,158 @@ use windows::{
             // Struct for interacting with a discovered BLE device.
             // https://learn.microsoft.com/en-us/uwp/api/windows.devices.bluetooth.bluetoothledevice?view=winrt-22621
             BluetoothLEDevice,
+
+            GenericAttributeProfile::{
+                // Struct for interacting with a characteristic of a remote device.
+                // https://learn.microsoft.com/en-us/uwp/api/windows.devices.bluetooth.genericattributeprofile.gattcharacteristic?view=winrt-22621
+                GattCharacteristic as WinGattCharacteristic,
+
+                // Tuple struct for the values of the Client Characteristic Configuration descriptor.
+                // https://learn.microsoft.com/en-us/uwp/api/windows.devices.bluetooth.genericattributeprofile.gattclientcharacteristicconfigurationdescriptorvalue?view=winrt-22621
+                GattClientCharacteristicConfigurationDescriptorValue,
+
+                // Struct for interacting with a service of a remote device.
+                // https://learn.microsoft.com/en-us/uwp/api/windows.devices.bluetooth.genericattributeprofile.gattdeviceservice?view=winrt-22621
+                GattDeviceService,
+
+                // Struct holding the GATT connection to a device open.
+                // https://learn.microsoft.com/en-us/uwp/api/windows.devices.bluetooth.genericattributeprofile.gattsession?view=winrt-22621
+                GattSession,
+
+                // Struct for retrieving data about a ValueChanged event.
+                // https://learn.microsoft.com/en-us/uwp/api/windows.devices.bluetooth.genericattributeprofile.gattvaluechangedeventargs?view=winrt-22621
+                GattValueChangedEventArgs,
+
+                // Tuple struct selecting between a Write Request and a Write Command.
+                // https://learn.microsoft.com/en-us/uwp/api/windows.devices.bluetooth.genericattributeprofile.gattwriteoption?view=winrt-22621
+                GattWriteOption,
+            },
         },
         Enumeration::{
+            // Struct describing a device found by enumeration or a watcher.
+            // https://learn.microsoft.com/en-us/uwp/api/windows.devices.enumeration.deviceinformation?view=winrt-22621
+            DeviceInformation,
+
             // Struct for custom pairing with a device.
             // https://learn.microsoft.com/en-us/uwp/api/windows.devices.enumeration.deviceinformationcustompairing?view=winrt-22621
             DeviceInformationCustomPairing,
+
+            // Struct for pairing with and unpairing from a device.
+            // https://learn.microsoft.com/en-us/uwp/api/windows.devices.enumeration.deviceinformationpairing?view=winrt-22621
+            DeviceInformationPairing,
+
+            // Struct identifying a device a watcher no longer finds.
+            // https://learn.microsoft.com/en-us/uwp/api/windows.devices.enumeration.deviceinformationupdate?view=winrt-22621
+            DeviceInformationUpdate,
             
             // Tuple struct to indicate the kinds of pairing supported by the application.
             // https://learn.microsoft.com/en-us/uwp/api/windows.devices.enumeration.devicepairingkinds?view=winrt-22621
             DevicePairingKinds,
+
+            // Tuple struct for the minimum protection pairing must achieve.
+            // https://learn.microsoft.com/en-us/uwp/api/windows.devices.enumeration.devicepairingprotectionlevel?view=winrt-22621
+            DevicePairingProtectionLevel,
             
             // Struct for retrieving data about a PairingRequested event.
             // https://learn.microsoft.com/en-us/uwp/api/windows.devices.enumeration.devicepairingrequestedeventargs?view=winrt-22621
             DevicePairingRequestedEventArgs,
+
+            // Struct holding the outcome of pairing.
+            // https://learn.microsoft.com/en-us/uwp/api/windows.devices.enumeration.devicepairingresult?view=winrt-22621
+            DevicePairingResult,
+
+            // Struct raising events as devices matching a filter come and go.
+            // https://learn.microsoft.com/en-us/uwp/api/windows.devices.enumeration.devicewatcher?view=winrt-22621
+            DeviceWatcher,
         },
     },
-    // Wraps a closure for handling events associated with a struct
-    // (e.g. PairingRequested event in `DeviceInformationCustomPairing`).
-    // https://learn.microsoft.com/en-us/uwp/api/windows.foundation.typedeventhandler-2?view=winrt-22621
-    Foundation::TypedEventHandler,
+    Foundation::{
+        // Struct delaying the completion of an event until its handler is done.
+        // https://learn.microsoft.com/en-us/uwp/api/windows.foundation.deferral?view=winrt-22621
+        Deferral,
+
+        EventRegistrationToken,
+
+        // Interface of an asynchronous operation returning a result.
+        // https://learn.microsoft.com/en-us/uwp/api/windows.foundation.iasyncoperation-1?view=winrt-22621
+        IAsyncOperation,
+
+        // Wraps a closure for handling events associated with a struct
+        // (e.g. PairingRequested event in `DeviceInformationCustomPairing`).
+        // https://learn.microsoft.com/en-us/uwp/api/windows.foundation.typedeventhandler-2?view=winrt-22621
+        TypedEventHandler,
+    },
+
+    // Structs for copying data in and out of Windows buffers.
+    // https://learn.microsoft.com/en-us/uwp/api/windows.storage.streams.datareader?view=winrt-22621
+    Storage::Streams::{DataReader, DataWriter, IBuffer},
 };
 
-use crate::{api, common::{BleAddress, ClassicAddress, BluetoothError, PairingResult}};
+use super::{bond::unpair_device, error::gatt_status, rfcomm::connect_rfcomm};
+use crate::{
+    api,
+    common::{
+        BleAddress, BluetoothError, BondState, BondStateStream, BtUuid, ClassicAddress,
+        ConnectionState, ConnectionStateStream, GattCharacteristic, GattCharacteristicProperties, GattService,
+        NotificationStream, PairingHandler, PairingResult, ProtectionLevel,
+        RfcommSocket, WriteType,
+    },
+};
 
 /// Concrete type implementing `Device`, used for Windows BLE.
 pub struct BleDevice {
     inner: BluetoothLEDevice,
     addr: BleAddress,
+    /// Keeps the device connected while `connect()` is in effect.
+    session: Mutex<Option<GattSession>>,
+    /// Services and characteristics discovered so far, keyed by their handle.
+    services: Mutex<HashMap<u16, GattDeviceService>>,
+    characteristics: Mutex<HashMap<u16, WinGattCharacteristic>>,
+}
+
+/// Sender shared by the event handlers of a subscription, dropped once the
+/// device disconnects.
+type ValueSender = Arc<Mutex<Option<UnboundedSender<Result<Vec<u8>, BluetoothError>>>>>;
+
+/// Struct holding the event handlers of a subscription and the channel they
+/// send values to. Unsubscribes when dropped.
+struct Subscription {
+    device: BluetoothLEDevice,
+    characteristic: WinGattCharacteristic,
+    value_token: EventRegistrationToken,
+    status_token: EventRegistrationToken,
+    receiver: UnboundedReceiver<Result<Vec<u8>, BluetoothError>>,
+}
+
+/// Sender shared by the event handlers of a `BondWatcher`,
+/// `ConnectionWatcher` or `RadioWatcher`, with the last state it sent.
+pub(crate) type StateSender<T> = Arc<Mutex<(T, UnboundedSender<Result<T, BluetoothError>>)>>;
+
+/// Receiving end of a `StateSender`.
+pub(crate) type StateReceiver<T> = UnboundedReceiver<Result<T, BluetoothError>>;
+
+/// Struct holding a watcher of the paired LE devices and the channel its event
+/// handlers send the bond states of one device to. Stops watching when dropped.
+struct BondWatcher {
+    watcher: DeviceWatcher,
+    added_token: EventRegistrationToken,
+    updated_token: EventRegistrationToken,
+    removed_token: EventRegistrationToken,
+    receiver: UnboundedReceiver<Result<BondState, BluetoothError>>,
+}
+
+/// Device whose connection status a `ConnectionWatcher` follows.
+enum ConnectionSource {
+    Le(BluetoothLEDevice),
+    Classic(BluetoothDevice),
+}
+
+/// Struct holding the ConnectionStatusChanged handler of a device and the
+/// channel it sends connection states to. Removes the handler when dropped.
+struct ConnectionWatcher {
+    source: ConnectionSource,
+    token: EventRegistrationToken,
+    receiver: UnboundedReceiver<Result<ConnectionState, BluetoothError>>,
 }
 
 /// Concrete type implementing `Device`, used for Windows Bluetooth Classic.
@@ -74,7 +220,13 @@ impl api::BleDevice for BleDevice {
         )?
         .await?;
 
-        Ok(BleDevice { inner, addr })
+        Ok(BleDevice {
+            inner,
+            addr,
+            session: Mutex::new(None),
+            services: Mutex::new(HashMap::new()),
+            characteristics: Mutex::new(HashMap::new()),
+        })
     }
 
     fn name(&self) -> Result<String, BluetoothError> {
@@ -84,6 +236,606 @@ impl api::BleDevice for BleDevice {
     fn address(&self) -> BleAddress {
         self.addr
     }
+
+    async fn connect(&self) -> Result<(), BluetoothError> {
+        let session = GattSession::FromDeviceIdAsync(&self.inner.BluetoothDeviceId()?)?.await?;
+        session.SetMaintainConnection(true)?;
+        *self.session.lock().unwrap() = Some(session);
+
+        // Windows connects on demand, so resolving the services connects.
+        let result = self
+            .inner
+            .GetGattServicesWithCacheModeAsync(BluetoothCacheMode::Uncached)?
+            .await?;
+        gatt_status(result.Status()?, None)
+    }
+
+    async fn disconnect(&self) -> Result<(), BluetoothError> {
+        // Windows disconnects once every object holding the connection is
+        // closed.
+        self.characteristics.lock().unwrap().clear();
+        for (_, service) in self.services.lock().unwrap().drain() {
+            service.Close()?;
+        }
+        if let Some(session) = self.session.lock().unwrap().take() {
+            session.SetMaintainConnection(false)?;
+            session.Close()?;
+        }
+        Ok(())
+    }
+
+    async fn discover_services(
+        &self,
+        uuid: Option<BtUuid>,
+    ) -> Result<Vec<GattService>, BluetoothError> {
+        self.check_connected()?;
+
+        let result = match uuid {
+            Some(uuid) => self.inner.GetGattServicesForUuidWithCacheModeAsync(
+                GUID::from_u128(uuid.as_u128()),
+                BluetoothCacheMode::Uncached,
+            )?,
+            None => self
+                .inner
+                .GetGattServicesWithCacheModeAsync(BluetoothCacheMode::Uncached)?,
+        }
+        .await?;
+        gatt_status(result.Status()?, protocol_error(result.ProtocolError()))?;
+
+        let mut services = Vec::new();
+        for service in result.Services()? {
+            let found = GattService::new(BtUuid::from_u128(service.Uuid()?.to_u128()), service.AttributeHandle()?);
+            self.services.lock().unwrap().insert(found.handle(), service);
+            services.push(found);
+        }
+
+        Ok(services)
+    }
+
+    async fn discover_characteristics(
+        &self,
+        service: &GattService,
+        uuid: Option<BtUuid>,
+    ) -> Result<Vec<GattCharacteristic>, BluetoothError> {
+        self.check_connected()?;
+        let service = self
+            .services
+            .lock()
+            .unwrap()
+            .get(&service.handle())
+            .cloned()
+            .ok_or(not_discovered(service.handle()))?;
+
+        let result = match uuid {
+            Some(uuid) => service.GetCharacteristicsForUuidWithCacheModeAsync(
+                GUID::from_u128(uuid.as_u128()),
+                BluetoothCacheMode::Uncached,
+            )?,
+            None => service
+                .GetCharacteristicsWithCacheModeAsync(BluetoothCacheMode::Uncached)?,
+        }
+        .await?;
+        gatt_status(result.Status()?, protocol_error(result.ProtocolError()))?;
+
+        let mut characteristics = Vec::new();
+        for characteristic in result.Characteristics()? {
+            // The low byte of Windows' properties is the declaration's bit
+            // field, the rest are extended properties.
+            let found = GattCharacteristic::new(
+                BtUuid::from_u128(characteristic.Uuid()?.to_u128()),
+                characteristic.AttributeHandle()?,
+                GattCharacteristicProperties::from_bits(
+                    characteristic.CharacteristicProperties()?.0 as u8,
+                ),
+            );
+            self.characteristics
+                .lock()
+                .unwrap()
+                .insert(found.handle(), characteristic);
+            characteristics.push(found);
+        }
+
+        Ok(characteristics)
+    }
+
+    async fn read(
+        &self,
+        characteristic: &GattCharacteristic,
+    ) -> Result<Vec<u8>, BluetoothError> {
+        let result = self
+            .characteristic(characteristic)?
+            .ReadValueWithCacheModeAsync(BluetoothCacheMode::Uncached)?
+            .await?;
+        gatt_status(result.Status()?, protocol_error(result.ProtocolError()))?;
+
+        read_buffer(&result.Value()?)
+    }
+
+    async fn write(
+        &self,
+        characteristic: &GattCharacteristic,
+        value: &[u8],
+        write_type: WriteType,
+    ) -> Result<(), BluetoothError> {
+        let option = match write_type {
+            WriteType::WithResponse => GattWriteOption::WriteWithResponse,
+            WriteType::WithoutResponse => GattWriteOption::WriteWithoutResponse,
+        };
+
+        // The buffer isn't `Send`, so it must not be held across the await.
+        let operation = self
+            .characteristic(characteristic)?
+            .WriteValueWithResultAndOptionAsync(&write_buffer(value)?, option)?;
+        let result = operation.await?;
+        gatt_status(result.Status()?, protocol_error(result.ProtocolError()))
+    }
+
+    async fn subscribe(
+        &self,
+        characteristic: &GattCharacteristic,
+    ) -> Result<NotificationStream, BluetoothError> {
+        let properties = characteristic.properties();
+        let config = if properties.contains(GattCharacteristicProperties::NOTIFY) {
+            GattClientCharacteristicConfigurationDescriptorValue::Notify
+        } else if properties.contains(GattCharacteristicProperties::INDICATE) {
+            GattClientCharacteristicConfigurationDescriptorValue::Indicate
+        } else {
+            return Err(BluetoothError::Gatt(String::from(
+                "characteristic doesn't support notifications or indications",
+            )));
+        };
+        let characteristic = self.characteristic(characteristic)?;
+
+        // Both handlers share the sender, and the status handler drops it
+        // once the device disconnects, ending the stream.
+        let (sender, receiver) = mpsc::unbounded();
+        let sender: ValueSender = Arc::new(Mutex::new(Some(sender)));
+
+        let value_sender = sender.clone();
+        let value_token = characteristic.ValueChanged(&TypedEventHandler::new(
+            move |_characteristic, event_args: &Option<GattValueChangedEventArgs>| {
+                if let Some(event_args) = event_args {
+                    let value = read_buffer(&event_args.CharacteristicValue()?);
+                    if let Some(sender) = &*value_sender.lock().unwrap() {
+                        let _ = sender.unbounded_send(value);
+                    }
+                }
+                Ok(())
+            },
+        ))?;
+        let status_token = self.inner.ConnectionStatusChanged(&TypedEventHandler::new(
+            move |device: &Option<BluetoothLEDevice>, _| {
+                if let Some(device) = device {
+                    if device.ConnectionStatus()? == BluetoothConnectionStatus::Disconnected {
+                        *sender.lock().unwrap() = None;
+                    }
+                }
+                Ok(())
+            },
+        ))?;
+
+        // Removes the handlers if enabling the subscription fails.
+        let subscription = Subscription {
+            device: self.inner.clone(),
+            characteristic,
+            value_token,
+            status_token,
+            receiver,
+        };
+        let result = subscription
+            .characteristic
+            .WriteClientCharacteristicConfigurationDescriptorWithResultAsync(config)?
+            .await?;
+        gatt_status(result.Status()?, protocol_error(result.ProtocolError()))?;
+
+        Ok(NotificationStream::new(subscription))
+    }
+
+    async fn mtu(&self) -> Result<u16, BluetoothError> {
+        match &*self.session.lock().unwrap() {
+            Some(session) => Ok(session.MaxPduSize()?),
+            None => Err(not_connected()),
+        }
+    }
+
+    async fn pair(
+        &self,
+        protection_level: ProtectionLevel,
+        handler: Arc<dyn PairingHandler>,
+    ) -> Result<PairingResult, BluetoothError> {
+        let protection_level = match protection_level {
+            ProtectionLevel::Encryption => DevicePairingProtectionLevel::Encryption,
+            ProtectionLevel::EncryptionAndAuthentication => {
+                DevicePairingProtectionLevel::EncryptionAndAuthentication
+            }
+        };
+        let pair_info = self.inner.DeviceInformation()?.Pairing()?;
+        pair_device(pair_info, protection_level, handler).await
+    }
+
+    async fn is_paired(&self) -> Result<bool, BluetoothError> {
+        Ok(self.inner.DeviceInformation()?.Pairing()?.IsPaired()?)
+    }
+
+    async fn unpair(&self) -> Result<(), BluetoothError> {
+        unpair_device(self.inner.DeviceInformation()?.Pairing()?).await
+    }
+
+    /// Windows doesn't report bond changes of a device, so this watches the
+    /// set of paired LE devices, which the device joins and leaves.
+    async fn bond_state_changes(
+        &self,
+    ) -> Result<BondStateStream, BluetoothError> {
+        let id = self.inner.DeviceId()?;
+        let current = match self.inner.DeviceInformation()?.Pairing()?.IsPaired()? {
+            true => BondState::Bonded,
+            false => BondState::NotBonded,
+        };
+        let (sender, receiver) = state_channel(current)?;
+
+        let watcher = DeviceInformation::CreateWatcherAqsFilter(
+            &BluetoothLEDevice::GetDeviceSelectorFromPairingState(true)?,
+        )?;
+        let added_id = id.clone();
+        let added_sender = sender.clone();
+        let added_token = watcher.Added(&TypedEventHandler::new(
+            move |_watcher: &Option<DeviceWatcher>, info: &Option<DeviceInformation>| {
+                if let Some(info) = info {
+                    if info.Id()? == added_id {
+                        send_state(&added_sender, BondState::Bonded);
+                    }
+                }
+                Ok(())
+            },
+        ))?;
+        // Watchers only report the devices paired after their initial
+        // enumeration if every event has a handler.
+        let updated_token = watcher.Updated(&TypedEventHandler::new(
+            |_watcher: &Option<DeviceWatcher>, _update: &Option<DeviceInformationUpdate>| Ok(()),
+        ))?;
+        let removed_token = watcher.Removed(&TypedEventHandler::new(
+            move |_watcher: &Option<DeviceWatcher>, update: &Option<DeviceInformationUpdate>| {
+                if let Some(update) = update {
+                    if update.Id()? == id {
+                        send_state(&sender, BondState::NotBonded);
+                    }
+                }
+                Ok(())
+            },
+        ))?;
+        watcher.Start()?;
+
+        Ok(BondStateStream::new(BondWatcher {
+            watcher,
+            added_token,
+            updated_token,
+            removed_token,
+            receiver,
+        }))
+    }
+
+    async fn connection_state(&self) -> Result<ConnectionState, BluetoothError> {
+        Ok(connection_state(self.inner.ConnectionStatus()?))
+    }
+
+    async fn connection_events(
+        &self,
+    ) -> Result<ConnectionStateStream, BluetoothError> {
+        let (sender, receiver) = state_channel(connection_state(self.inner.ConnectionStatus()?))?;
+        let handler_sender = sender.clone();
+        let token = self.inner.ConnectionStatusChanged(&TypedEventHandler::new(
+            move |device: &Option<BluetoothLEDevice>, _| {
+                if let Some(device) = device {
+                    send_state(&handler_sender, connection_state(device.ConnectionStatus()?));
+                }
+                Ok(())
+            },
+        ))?;
+
+        // Removes the handler if reading the status fails.
+        let watcher = ConnectionWatcher {
+            source: ConnectionSource::Le(self.inner.clone()),
+            token,
+            receiver,
+        };
+        send_state(&sender, connection_state(self.inner.ConnectionStatus()?));
+        Ok(ConnectionStateStream::new(watcher))
+    }
+}
+
+impl BleDevice {
+    /// Fail unless `connect()` has been called.
+    fn check_connected(&self) -> Result<(), BluetoothError> {
+        match &*self.session.lock().unwrap() {
+            Some(_) => Ok(()),
+            None => Err(not_connected()),
+        }
+    }
+
+    /// Look up the Windows object of a characteristic found by discovery.
+    fn characteristic(
+        &self,
+        characteristic: &GattCharacteristic,
+    ) -> Result<WinGattCharacteristic, BluetoothError> {
+        self.check_connected()?;
+        self.characteristics
+            .lock()
+            .unwrap()
+            .get(&characteristic.handle())
+            .cloned()
+            .ok_or(not_discovered(characteristic.handle()))
+    }
+}
+
+fn not_connected() -> BluetoothError {
+    BluetoothError::FailedPrecondition(String::from(
+        "device isn't connected, please call `connect()`",
+    ))
+}
+
+fn not_discovered(handle: u16) -> BluetoothError {
+    BluetoothError::FailedPrecondition(format!(
+        "attribute {:#06x} hasn't been discovered",
+        handle
+    ))
+}
+
+/// Retrieve the ATT error code Windows reports alongside a failed GATT
+/// operation, if any.
+fn protocol_error(
+    error: windows::core::Result<windows::Foundation::IReference<u8>>,
+) -> Option<u8> {
+    error.and_then(|error| error.Value()).ok()
+}
+
+/// Copy the contents of a Windows buffer.
+fn read_buffer(buffer: &IBuffer) -> Result<Vec<u8>, BluetoothError> {
+    let reader = DataReader::FromBuffer(buffer)?;
+    let mut data = vec![0u8; reader.UnconsumedBufferLength()? as usize];
+    reader.ReadBytes(&mut data)?;
+
+    Ok(data)
+}
+
+/// Copy `bytes` into a Windows buffer.
+fn write_buffer(bytes: &[u8]) -> Result<IBuffer, BluetoothError> {
+    let writer = DataWriter::new()?;
+    writer.WriteBytes(bytes)?;
+
+    Ok(writer.DetachBuffer()?)
+}
+
+/// Pair with the device `pair_info` belongs to, so the link gets at least
+/// `protection_level`, answering the requests of the pairing ceremony with
+/// `handler`.
+async fn pair_device(
+    pair_info: DeviceInformationPairing,
+    protection_level: DevicePairingProtectionLevel,
+    handler: Arc<dyn PairingHandler>,
+) -> Result<PairingResult, BluetoothError> {
+    if pair_info.IsPaired()? {
+        info!("Device already paired");
+        Ok(PairingResult::AlreadyPaired)
+    } else if !pair_info.CanPair()? {
+        info!("Device can't pair");
+        Err(BluetoothError::PairingFailed(String::from("device can't pair")))
+    } else {
+        let custom = pair_info.Custom()?;
+        // The event is raised on a worker thread, which mustn't wait for the
+        // handler: its future may need the caller's executor. The request is
+        // deferred and answered by `answer_requests()` instead.
+        let (sender, requests) = mpsc::unbounded();
+        let token = custom.PairingRequested(&TypedEventHandler::new(
+            move |_custom: &Option<DeviceInformationCustomPairing>,
+                  event_args: &Option<DevicePairingRequestedEventArgs>| {
+                match event_args {
+                    Some(event_args) => {
+                        let deferral = event_args.GetDeferral()?;
+                        if let Err(err) =
+                            sender.unbounded_send((event_args.clone(), deferral))
+                        {
+                            err.into_inner().1.Complete()?;
+                        }
+                    }
+                    None => warn!("Empty pairing event arguments"),
+                }
+                Ok(())
+            },
+        ))?;
+
+        let pairing = custom.PairWithProtectionLevelAsync(
+            DevicePairingKinds::ConfirmOnly
+                | DevicePairingKinds::ProvidePin
+                | DevicePairingKinds::ConfirmPinMatch
+                | DevicePairingKinds::DisplayPin,
+            protection_level,
+        );
+        let res = match pairing {
+            Ok(pairing) => answer_requests(pairing, requests, &*handler).await,
+            Err(err) => Err(BluetoothError::from(err)),
+        };
+        // Release the handler, whether pairing succeeded or not.
+        custom.RemovePairingRequested(token)?;
+        let status = PairingResult::from(res?.Status()?);
+
+        match status {
+            PairingResult::Failure(msg) => Err(BluetoothError::PairingFailed(msg)),
+            _ => Ok(status),
+        }
+    }
+}
+
+/// Wait for `pairing` to complete, answering the requests of the pairing
+/// ceremony it raises with `handler` meanwhile.
+async fn answer_requests(
+    mut pairing: IAsyncOperation<DevicePairingResult>,
+    mut requests: UnboundedReceiver<(DevicePairingRequestedEventArgs, Deferral)>,
+    handler: &dyn PairingHandler,
+) -> Result<DevicePairingResult, BluetoothError> {
+    loop {
+        match future::select(requests.next(), &mut pairing).await {
+            Either::Left((Some((event_args, deferral)), _)) => {
+                // Not accepting the request rejects pairing.
+                if let Err(err) = answer_request(&event_args, handler).await {
+                    warn!("Failed to answer pairing request: {}", err);
+                }
+                deferral.Complete()?;
+            }
+            Either::Left((None, _)) => return Ok(pairing.await?),
+            Either::Right((res, _)) => return Ok(res?),
+        }
+    }
+}
+
+/// Ask `handler` to answer the pairing request of `event_args`, accepting it
+/// if it agrees.
+async fn answer_request(
+    event_args: &DevicePairingRequestedEventArgs,
+    handler: &dyn PairingHandler,
+) -> windows::core::Result<()> {
+    match event_args.PairingKind()? {
+        DevicePairingKinds::ConfirmOnly => {
+            if handler.confirm().await {
+                event_args.Accept()?;
+            }
+            Ok(())
+        }
+        DevicePairingKinds::DisplayPin => {
+            let pin = event_args.Pin()?.to_string_lossy();
+            handler.display_pin(&pin).await;
+            event_args.Accept()
+        }
+        DevicePairingKinds::ProvidePin => {
+            if let Some(pin) = handler.provide_pin().await {
+                event_args.AcceptWithPin(&HSTRING::from(pin))?;
+            }
+            Ok(())
+        }
+        DevicePairingKinds::ConfirmPinMatch => {
+            let pin = event_args.Pin()?.to_string_lossy();
+            if handler.confirm_pin_match(&pin).await {
+                event_args.Accept()?;
+            }
+            Ok(())
+        }
+        kind => {
+            warn!("Unsupported pairing kind {:?}", kind);
+            Ok(())
+        }
+    }
+}
+
+/// Create a channel for the states of a watcher, sending `current` first.
+pub(crate) fn state_channel<T: Copy>(
+    current: T,
+) -> Result<(StateSender<T>, StateReceiver<T>), BluetoothError> {
+    let (sender, receiver) = mpsc::unbounded();
+    sender
+        .unbounded_send(Ok(current))
+        .map_err(|err| BluetoothError::Internal(err.to_string()))?;
+    Ok((Arc::new(Mutex::new((current, sender))), receiver))
+}
+
+/// Send `state` unless it was the last state sent. Device watchers report
+/// every paired device once they start, including the one already reported,
+/// and states may change between reading the current one and registering a
+/// handler.
+pub(crate) fn send_state<T: Copy + PartialEq>(sender: &StateSender<T>, state: T) {
+    let mut sender = sender.lock().unwrap();
+    if sender.0 != state {
+        sender.0 = state;
+        let _ = sender.1.unbounded_send(Ok(state));
+    }
+}
+
+fn connection_state(status: BluetoothConnectionStatus) -> ConnectionState {
+    match status {
+        BluetoothConnectionStatus::Connected => ConnectionState::Connected,
+        _ => ConnectionState::Disconnected,
+    }
+}
+
+impl Stream for Subscription {
+    type Item = Result<Vec<u8>, BluetoothError>;
+
+    fn poll_next(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Self::Item>> {
+        self.receiver.poll_next_unpin(cx)
+    }
+}
+
+impl Drop for Subscription {
+    fn drop(&mut self) {
+        if let Err(err) = self.characteristic.RemoveValueChanged(self.value_token) {
+            warn!("Failed to remove GATT value handler. Error: {}", err);
+        }
+        if let Err(err) = self.device.RemoveConnectionStatusChanged(self.status_token) {
+            warn!("Failed to remove connection status handler. Error: {}", err);
+        }
+
+        // Started without waiting for completion, since this can't block.
+        if let Err(err) = self
+            .characteristic
+            .WriteClientCharacteristicConfigurationDescriptorAsync(
+                GattClientCharacteristicConfigurationDescriptorValue::None,
+            )
+        {
+            warn!("Failed to unsubscribe from characteristic. Error: {}", err);
+        }
+    }
+}
+
+impl Stream for BondWatcher {
+    type Item = Result<BondState, BluetoothError>;
+
+    fn poll_next(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Self::Item>> {
+        self.receiver.poll_next_unpin(cx)
+    }
+}
+
+impl Drop for BondWatcher {
+    fn drop(&mut self) {
+        if let Err(err) = self.watcher.RemoveAdded(self.added_token) {
+            warn!("Failed to remove device added handler. Error: {}", err);
+        }
+        if let Err(err) = self.watcher.RemoveUpdated(self.updated_token) {
+            warn!("Failed to remove device updated handler. Error: {}", err);
+        }
+        if let Err(err) = self.watcher.RemoveRemoved(self.removed_token) {
+            warn!("Failed to remove device removed handler. Error: {}", err);
+        }
+        if let Err(err) = self.watcher.Stop() {
+            warn!("Failed to stop device watcher. Error: {}", err);
+        }
+    }
+}
+
+impl Stream for ConnectionWatcher {
+    type Item = Result<ConnectionState, BluetoothError>;
+
+    fn poll_next(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Self::Item>> {
+        self.receiver.poll_next_unpin(cx)
+    }
+}
+
+impl Drop for ConnectionWatcher {
+    fn drop(&mut self) {
+        let result = match &self.source {
+            ConnectionSource::Le(device) => device.RemoveConnectionStatusChanged(self.token),
+            ConnectionSource::Classic(device) => device.RemoveConnectionStatusChanged(self.token),
+        };
+        if let Err(err) = result {
+            warn!("Failed to remove connection status handler. Error: {}", err);
+        }
+    }
 }
 
 #[async_trait]
@@ -107,52 +859,47 @@ impl api::ClassicDevice for ClassicDevice {
         self.addr
     }
 
-    async fn pair(&self) -> Result<PairingResult, BluetoothError> {
+    async fn pair(
+        &self,
+        handler: Arc<dyn PairingHandler>,
+    ) -> Result<PairingResult, BluetoothError> {
         let pair_info = self.inner.DeviceInformation()?.Pairing()?;
-        if pair_info.IsPaired()? {
-            info!("Device already paired");
-            Ok(PairingResult::AlreadyPaired)
-        } else if !pair_info.CanPair()? {
-            info!("Device can't pair");
-            Err(BluetoothError::PairingFailed(String::from("device can't pair")))
-        } else {  
-            let custom = pair_info.Custom()?;
-            custom.PairingRequested(&TypedEventHandler::new(
-                |_custom: &Option<DeviceInformationCustomPairing>, 
-                event_args: &Option<DevicePairingRequestedEventArgs>,
-                |  {
-                    if let Some(event_args) = event_args {
-                        match event_args.PairingKind()? {
-                            DevicePairingKinds::ConfirmOnly => {
-                                event_args.Accept()                            
-                            }
-                            _ => {
-                                warn!("Unsupported pairing kind {:?}", event_args.PairingKind());
-                                Ok(())
-                            }
-                        }
-                    } else {
-                        warn!("Empty pairing event arguments");
-                        Ok(())
-                    }
+        pair_device(pair_info, DevicePairingProtectionLevel::Default, handler).await
+    }
 
-                },
-            ))?;
-            let res = custom
-                .PairAsync(
-                    DevicePairingKinds::ConfirmOnly
-                        | DevicePairingKinds::ProvidePin
-                        | DevicePairingKinds::ConfirmPinMatch
-                        | DevicePairingKinds::DisplayPin,
-                )?
-                .await?;
-            let status = PairingResult::from(res.Status()?);
-
-            match status {
-                PairingResult::Failure(msg) => Err(BluetoothError::PairingFailed(msg)),
-                _ => Ok(status),
-            }
-        }
+    async fn connect_rfcomm(
+        &self,
+        service_uuid: BtUuid,
+    ) -> Result<RfcommSocket, BluetoothError> {
+        connect_rfcomm(&self.inner, service_uuid).await
+    }
+
+    async fn connection_state(&self) -> Result<ConnectionState, BluetoothError> {
+        Ok(connection_state(self.inner.ConnectionStatus()?))
+    }
+
+    async fn connection_events(
+        &self,
+    ) -> Result<ConnectionStateStream, BluetoothError> {
+        let (sender, receiver) = state_channel(connection_state(self.inner.ConnectionStatus()?))?;
+        let handler_sender = sender.clone();
+        let token = self.inner.ConnectionStatusChanged(&TypedEventHandler::new(
+            move |device: &Option<BluetoothDevice>, _| {
+                if let Some(device) = device {
+                    send_state(&handler_sender, connection_state(device.ConnectionStatus()?));
+                }
+                Ok(())
+            },
+        ))?;
+
+        // Removes the handler if reading the status fails.
+        let watcher = ConnectionWatcher {
+            source: ConnectionSource::Classic(self.inner.clone()),
+            token,
+            receiver,
+        };
+        send_state(&sender, connection_state(self.inner.ConnectionStatus()?));
+        Ok(ConnectionStateStream::new(watcher))
     }
 }
 
@@ -12,7 +12,13 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
-use windows::Devices::Enumeration::DevicePairingResultStatus;
+use windows::Devices::{
+    Bluetooth::{
+        BluetoothError as WinBluetoothError,
+        GenericAttributeProfile::GattCommunicationStatus,
+    },
+    Enumeration::DevicePairingResultStatus,
+};
 
 use crate::common::{BluetoothError, PairingResult};
 
@@ -22,6 +28,68 @@ impl From<windows::core::Error> for BluetoothError {
     }
 }
 
+// https://learn.microsoft.com/en-us/uwp/api/windows.devices.bluetooth.bluetootherror?view=winrt-22621
+impl From<WinBluetoothError> for BluetoothError {
+    fn from(err: WinBluetoothError) -> Self {
+        match err {
+            WinBluetoothError::RadioNotAvailable => {
+                BluetoothError::FailedPrecondition(String::from(
+                    "the Bluetooth radio is not available.",
+                ))
+            }
+            WinBluetoothError::ResourceInUse => {
+                BluetoothError::FailedPrecondition(String::from(
+                    "the operation cannot be serviced because the necessary resources are currently in use.",
+                ))
+            }
+            WinBluetoothError::DisabledByPolicy
+            | WinBluetoothError::DisabledByUser
+            | WinBluetoothError::ConsentRequired => {
+                BluetoothError::FailedPrecondition(String::from(
+                    "the operation is disabled by policy or by the user.",
+                ))
+            }
+            WinBluetoothError::NotSupported
+            | WinBluetoothError::TransportNotSupported => {
+                BluetoothError::NotSupported(String::from(
+                    "the operation is not supported by the Bluetooth adapter.",
+                ))
+            }
+            _ => BluetoothError::System(format!(
+                "Bluetooth operation failed with error {}.",
+                err.0
+            )),
+        }
+    }
+}
+
+/// Windows reports the outcome of GATT operations as a status, along with the
+/// ATT error code if the device rejected the operation.
+/// https://learn.microsoft.com/en-us/uwp/api/windows.devices.bluetooth.genericattributeprofile.gattcommunicationstatus?view=winrt-22621
+pub(crate) fn gatt_status(
+    status: GattCommunicationStatus,
+    protocol_error: Option<u8>,
+) -> Result<(), BluetoothError> {
+    match status {
+        GattCommunicationStatus::Success => Ok(()),
+        GattCommunicationStatus::Unreachable => {
+            Err(BluetoothError::FailedPrecondition(String::from(
+                "the device is unreachable.",
+            )))
+        }
+        GattCommunicationStatus::ProtocolError => Err(BluetoothError::Gatt(
+            format!("ATT error {:#04x}.", protocol_error.unwrap_or_default()),
+        )),
+        GattCommunicationStatus::AccessDenied => Err(BluetoothError::Gatt(
+            String::from("access to the attribute is denied."),
+        )),
+        _ => Err(BluetoothError::System(format!(
+            "GATT operation failed with status {}.",
+            status.0
+        ))),
+    }
+}
+
 // https://learn.microsoft.com/en-us/uwp/api/windows.devices.enumeration.devicepairingresultstatus?view=winrt-22621
 impl From<DevicePairingResultStatus> for PairingResult {
     fn from(status: DevicePairingResultStatus) -> Self {
@@ -16,10 +16,14 @@
 mod adapter;
 mod address;
 mod advertisement;
+mod advertiser;
+mod bond;
 mod device;
 mod error;
+mod rfcomm;
 
 pub use adapter::*;
 pub use address::*;
 pub use advertisement::*;
+pub use advertiser::*;
 pub use device::*;
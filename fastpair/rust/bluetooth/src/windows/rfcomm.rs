@@ -0,0 +1,228 @@
+// Copyright 2023 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::{
+    future::Future,
+    io,
+    pin::Pin,
+    task::{Context, Poll},
+};
+
+use futures::{ready, AsyncRead, AsyncWrite};
+use tracing::{info, warn};
+use windows::{
+    core::GUID,
+
+    Devices::Bluetooth::{
+        // Tuple struct selecting whether values are read from the system cache or the device.
+        // https://learn.microsoft.com/en-us/uwp/api/windows.devices.bluetooth.bluetoothcachemode?view=winrt-22621
+        BluetoothCacheMode,
+
+        // Struct for interacting with a discovered BT Classic device.
+        // https://learn.microsoft.com/en-us/uwp/api/windows.devices.bluetooth.bluetoothdevice?view=winrt-22621
+        BluetoothDevice,
+
+        // Tuple struct describing the outcome of a Bluetooth operation.
+        // https://learn.microsoft.com/en-us/uwp/api/windows.devices.bluetooth.bluetootherror?view=winrt-22621
+        BluetoothError as WinBluetoothError,
+
+        // Struct identifying an RFCOMM service by its UUID.
+        // https://learn.microsoft.com/en-us/uwp/api/windows.devices.bluetooth.rfcomm.rfcommserviceid?view=winrt-22621
+        Rfcomm::RfcommServiceId,
+    },
+
+    Foundation::{IAsyncOperation, IAsyncOperationWithProgress},
+
+    // Struct for a TCP or RFCOMM stream socket.
+    // https://learn.microsoft.com/en-us/uwp/api/windows.networking.sockets.streamsocket?view=winrt-22621
+    Networking::Sockets::StreamSocket,
+
+    // Structs for copying data in and out of Windows buffers.
+    // https://learn.microsoft.com/en-us/uwp/api/windows.storage.streams.buffer?view=winrt-22621
+    Storage::Streams::{
+        Buffer, DataReader, DataWriter, IBuffer, InputStreamOptions,
+    },
+};
+
+use crate::common::{BluetoothError, BtUuid, RfcommSocket};
+
+/// Adapts the asynchronous operations of a `StreamSocket` to `AsyncRead` and
+/// `AsyncWrite`, by keeping the pending operation of each direction around
+/// until it completes.
+struct RfcommStream {
+    socket: StreamSocket,
+    read: Option<IAsyncOperationWithProgress<IBuffer, u32>>,
+    write: Option<IAsyncOperationWithProgress<u32, u32>>,
+    flush: Option<IAsyncOperation<bool>>,
+    /// Bytes read but not returned yet, if a read asked for fewer bytes than
+    /// the pending operation.
+    pending: Vec<u8>,
+}
+
+/// Open an RFCOMM channel to the service identified by `uuid` on `device`.
+pub(crate) async fn connect_rfcomm(
+    device: &BluetoothDevice,
+    uuid: BtUuid,
+) -> Result<RfcommSocket, BluetoothError> {
+    let result = device
+        .GetRfcommServicesForIdWithCacheModeAsync(
+            &RfcommServiceId::FromUuid(GUID::from_u128(uuid.as_u128()))?,
+            BluetoothCacheMode::Uncached,
+        )?
+        .await?;
+    let error = result.Error()?;
+    if error != WinBluetoothError::Success {
+        return Err(BluetoothError::from(error));
+    }
+
+    // Windows objects that aren't `Send` must not be held across the await.
+    let socket = StreamSocket::new()?;
+    let connect = {
+        let services = result.Services()?;
+        if services.Size()? == 0 {
+            return Err(BluetoothError::NotSupported(format!(
+                "device doesn't serve RFCOMM service {}",
+                uuid
+            )));
+        }
+        let service = services.GetAt(0)?;
+        socket.ConnectWithProtectionLevelAsync(
+            &service.ConnectionHostName()?,
+            &service.ConnectionServiceName()?,
+            service.ProtectionLevel()?,
+        )?
+    };
+    connect.await?;
+
+    info!("Connected RFCOMM service {}.", uuid);
+    Ok(RfcommSocket::new(RfcommStream {
+        socket,
+        read: None,
+        write: None,
+        flush: None,
+        pending: Vec::new(),
+    }))
+}
+
+fn io_error(err: windows::core::Error) -> io::Error {
+    io::Error::other(err)
+}
+
+impl AsyncRead for RfcommStream {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut [u8],
+    ) -> Poll<io::Result<usize>> {
+        if self.pending.is_empty() {
+            if self.read.is_none() {
+                let len = u32::try_from(buf.len()).unwrap_or(u32::MAX);
+                let operation = self
+                    .socket
+                    .InputStream()
+                    .and_then(|input| {
+                        input.ReadAsync(
+                            &Buffer::Create(len)?,
+                            len,
+                            InputStreamOptions::Partial,
+                        )
+                    })
+                    .map_err(io_error)?;
+                self.read = Some(operation);
+            }
+
+            let operation = self.read.as_mut().unwrap();
+            let result = ready!(Pin::new(operation).poll(cx));
+            self.read = None;
+
+            // An empty buffer means the device closed the channel.
+            let buffer = result.map_err(io_error)?;
+            let reader = DataReader::FromBuffer(&buffer).map_err(io_error)?;
+            let mut data = vec![
+                0u8;
+                reader.UnconsumedBufferLength().map_err(io_error)?
+                    as usize
+            ];
+            reader.ReadBytes(&mut data).map_err(io_error)?;
+            self.pending = data;
+        }
+
+        let len = buf.len().min(self.pending.len());
+        buf[..len].copy_from_slice(&self.pending[..len]);
+        self.pending.drain(..len);
+        Poll::Ready(Ok(len))
+    }
+}
+
+impl AsyncWrite for RfcommStream {
+    /// Callers must pass the same `buf` again while the write is pending, as
+    /// it was already handed to Windows.
+    fn poll_write(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        if self.write.is_none() {
+            let operation = self
+                .socket
+                .OutputStream()
+                .and_then(|output| {
+                    let writer = DataWriter::new()?;
+                    writer.WriteBytes(buf)?;
+                    output.WriteAsync(&writer.DetachBuffer()?)
+                })
+                .map_err(io_error)?;
+            self.write = Some(operation);
+        }
+
+        let operation = self.write.as_mut().unwrap();
+        let result = ready!(Pin::new(operation).poll(cx));
+        self.write = None;
+        Poll::Ready(result.map(|len| len as usize).map_err(io_error))
+    }
+
+    fn poll_flush(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<io::Result<()>> {
+        if self.flush.is_none() {
+            let operation = self
+                .socket
+                .OutputStream()
+                .and_then(|output| output.FlushAsync())
+                .map_err(io_error)?;
+            self.flush = Some(operation);
+        }
+
+        let operation = self.flush.as_mut().unwrap();
+        let result = ready!(Pin::new(operation).poll(cx));
+        self.flush = None;
+        Poll::Ready(result.map(|_| ()).map_err(io_error))
+    }
+
+    fn poll_close(
+        self: Pin<&mut Self>,
+        _cx: &mut Context<'_>,
+    ) -> Poll<io::Result<()>> {
+        Poll::Ready(self.socket.Close().map_err(io_error))
+    }
+}
+
+impl Drop for RfcommStream {
+    fn drop(&mut self) {
+        if let Err(err) = self.socket.Close() {
+            warn!("Failed to close RFCOMM socket. Error: {}", err);
+        }
+    }
+}
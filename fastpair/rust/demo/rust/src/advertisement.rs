@@ -12,7 +12,7 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
-use bluetooth::{BleAddress, BleAdvertisement, ServiceData};
+use bluetooth::{BleAddress, BleAdvertisement, BtUuid, ServiceData};
 
 use crate::{decoder::FpDecoder, error::FpError, fetcher::FpFetcher};
 
@@ -35,7 +35,7 @@ impl FpPairingAdvertisement {
     /// Create a new Fast Pair advertisement instance.
     pub(crate) fn new(
         adv: BleAdvertisement,
-        service_data: &ServiceData<u16>,
+        service_data: &ServiceData,
         fetcher: &Box<dyn FpFetcher>,
     ) -> Result<Self, FpError> {
         let rssi = adv.rssi().ok_or(FpError::ContractViolation(String::from(
@@ -155,7 +155,7 @@ mod tests {
 
         let raw_data = vec![3, 2, 1];
         let expected_model_id = "197121"; // (3 << 16) + (2 << 8) + 1.
-        let service_data = ServiceData::new(0x123 as u16, raw_data);
+        let service_data = ServiceData::new(BtUuid::FAST_PAIR, raw_data);
 
         let image_url = String::from("image_url");
         let device_name = String::from("name");
@@ -178,7 +178,7 @@ mod tests {
         let ble_adv = BleAdvertisement::new(addr, None, Some(10));
 
         let raw_data = vec![3, 2, 1];
-        let service_data = ServiceData::new(0x123 as u16, raw_data);
+        let service_data = ServiceData::new(BtUuid::FAST_PAIR, raw_data);
 
         let device_info = Ok(DeviceInfo::new(
             String::from("image_url"),
@@ -198,7 +198,7 @@ mod tests {
         let ble_adv = BleAdvertisement::new(addr, Some(-60), None);
 
         let raw_data = vec![3, 2, 1];
-        let service_data = ServiceData::new(0x123 as u16, raw_data);
+        let service_data = ServiceData::new(BtUuid::FAST_PAIR, raw_data);
 
         let device_info = Ok(DeviceInfo::new(
             String::from("image_url"),
@@ -218,7 +218,7 @@ mod tests {
         let ble_adv = BleAdvertisement::new(addr, Some(-60), Some(10));
 
         let raw_data = vec![3, 2, 1];
-        let service_data = ServiceData::new(0x123 as u16, raw_data);
+        let service_data = ServiceData::new(BtUuid::FAST_PAIR, raw_data);
 
         let fetcher: Box<dyn FpFetcher> = Box::new(FpFetcherMock::new(Err(FpError::Test)));
 
@@ -12,14 +12,22 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
-use std::{collections::HashMap, sync::RwLock, time::Duration};
+use std::{
+    collections::HashMap,
+    env,
+    sync::{Arc, RwLock},
+    time::Duration,
+};
 
 use bluetooth::{
     api::{BleAdapter, ClassicDevice},
-    BleAdvertisement, BleDataTypeId, ClassicAddress, PairingResult, Platform, ServiceData,
+    capture::{Recorder, ReplayAdapter},
+    BleAdvertisement, BleDataTypeId, BtUuid, ClassicAddress, PairingHandler,
+    PairingResult, ScanFilter, ScanSettings, ServiceData,
 };
+use async_trait::async_trait;
 use flutter_rust_bridge::StreamSink;
-use futures::executor;
+use futures::{executor, StreamExt};
 use tracing::{info, warn};
 use ttl_cache::TtlCache;
 
@@ -63,7 +71,7 @@ async fn update_best_device(best_adv: FpPairingAdvertisement) {
 #[inline]
 fn new_best_fp_advertisement(
     advertisement: BleAdvertisement,
-    service_data: &ServiceData<u16>,
+    service_data: &ServiceData,
     fetcher: &Box<dyn FpFetcher>,
     latest_advertisement_map: &mut HashMap<String, FpPairingAdvertisement>,
 ) -> Option<FpPairingAdvertisement> {
@@ -71,7 +79,7 @@ fn new_best_fp_advertisement(
     let uuid = service_data.uuid();
 
     // This is not a Fast Pair device.
-    if uuid != 0x2cfe {
+    if uuid != BtUuid::FAST_PAIR {
         return None;
     }
 
@@ -139,60 +147,126 @@ fn init_cache() {
     *cache = Some(TtlCache::new(16));
 }
 
+// Path of a capture to replay instead of scanning, e.g. to debug the ranking
+// of devices offline.
+const REPLAY_ENV: &str = "FASTPAIR_REPLAY";
+
+// Path to record the advertisements received while scanning to.
+const RECORD_ENV: &str = "FASTPAIR_RECORD";
+
 /// Sets up initial constructs and infinitely polls for advertisements.
 pub fn init() {
-    const JSON_PATH: &str = "./local";
-
     let run = async {
         info!("start making adapter");
 
-        let mut adapter = Platform::default_adapter().await.unwrap();
-        adapter.start_scan().unwrap();
-
-        init_cache();
-
-        let mut latest_advertisement_map = HashMap::new();
-        let datatype_selector = vec![BleDataTypeId::ServiceData16BitUuid];
-        let fetcher: Box<dyn FpFetcher> = Box::new(FpFetcherFs::new(String::from(JSON_PATH)));
-
-        loop {
-            // Retrieve the next received advertisement.
-            let advertisement = adapter
-                .next_advertisement(Some(&datatype_selector))
-                .await
-                .unwrap();
-
-            for service_data in advertisement.service_data_16bit_uuid().unwrap() {
-                if let Some(best_adv) = new_best_fp_advertisement(
-                    advertisement.clone(),
-                    service_data,
-                    &fetcher,
-                    &mut latest_advertisement_map,
-                ) {
-                    update_best_device(best_adv).await;
-                }
-            }
+        if let Ok(path) = env::var(REPLAY_ENV) {
+            info!("replaying {}", path);
+            poll_advertisements(ReplayAdapter::open(path).unwrap()).await;
+        } else if let Ok(path) = env::var(RECORD_ENV) {
+            info!("recording to {}", path);
+            let adapter = default_adapter().await;
+            poll_advertisements(Recorder::create(adapter, path).unwrap()).await;
+        } else {
+            poll_advertisements(default_adapter().await).await;
         }
     };
 
     executor::block_on(run)
 }
 
+/// Opens the system's default adapter, or the simulated radio's with the `sim`
+/// feature.
+#[cfg(not(feature = "sim"))]
+async fn default_adapter() -> impl BleAdapter {
+    bluetooth::Platform::default_adapter().await.unwrap()
+}
+
+/// Opens the system's default adapter, or the simulated radio's with the `sim`
+/// feature.
+#[cfg(feature = "sim")]
+async fn default_adapter() -> impl BleAdapter {
+    bluetooth::sim::BleAdapter::default().await.unwrap()
+}
+
+/// Displays the best device advertising to `adapter` until scanning stops.
+async fn poll_advertisements(adapter: impl BleAdapter) {
+    const JSON_PATH: &str = "./local";
+
+    // Only receive advertisements carrying Fast Pair service data.
+    let filter = ScanFilter::default().with_service_data(BtUuid::FAST_PAIR, Vec::new(), None);
+    let mut scan = adapter
+        .start_scan(
+            &filter,
+            &ScanSettings::default(),
+            &[BleDataTypeId::ServiceData16BitUuid],
+        )
+        .unwrap();
+
+    init_cache();
+
+    let mut latest_advertisement_map = HashMap::new();
+    let fetcher: Box<dyn FpFetcher> = Box::new(FpFetcherFs::new(String::from(JSON_PATH)));
+
+    // Retrieve received advertisements until scanning stops.
+    while let Some(advertisement) = scan.next().await {
+        let advertisement = advertisement.unwrap();
+
+        for service_data in advertisement.service_data_16bit_uuid().unwrap() {
+            if let Some(best_adv) = new_best_fp_advertisement(
+                advertisement.clone(),
+                service_data,
+                &fetcher,
+                &mut latest_advertisement_map,
+            ) {
+                update_best_device(best_adv).await;
+            }
+        }
+    }
+}
+
 /// Sets up `StreamSink` for Dart-Rust FFI.
 pub fn event_stream(s: StreamSink<Option<[String; 2]>>) {
     let mut stream = DEVICE_STREAM.write().unwrap();
     *stream = Some(s);
 }
 
+/// Accepts Just Works pairing only, since the UI can't show or enter PINs yet.
+struct ConfirmOnlyHandler;
+
+#[async_trait]
+impl PairingHandler for ConfirmOnlyHandler {
+    async fn confirm(&self) -> bool {
+        true
+    }
+
+    async fn display_pin(&self, pin: &str) {
+        warn!("Can't display PIN {}", pin);
+    }
+
+    async fn provide_pin(&self) -> Option<String> {
+        warn!("Can't provide a PIN");
+        None
+    }
+
+    async fn confirm_pin_match(&self, _pin: &str) -> bool {
+        warn!("Can't confirm the PIN matches");
+        false
+    }
+}
+
 /// Attempt classic pairing with currently displayed device.
 pub fn pair() -> String {
     let result = match CURR_DEVICE_ADV.read().unwrap().as_ref() {
         Some(adv) => {
             let run = async {
                 let classic_addr = ClassicAddress::try_from(adv.address()).unwrap();
-                let classic_device = Platform::new_classic_device(classic_addr).await.unwrap();
+                let classic_device = default_adapter()
+                    .await
+                    .classic_device(classic_addr)
+                    .await
+                    .unwrap();
 
-                match classic_device.pair().await {
+                match classic_device.pair(Arc::new(ConfirmOnlyHandler)).await {
                     Ok(result) => match result {
                         PairingResult::Success => String::from("Pairing success!"),
                         PairingResult::AlreadyPaired => {
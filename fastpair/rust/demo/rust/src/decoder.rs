@@ -12,7 +12,7 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
-use bluetooth::ServiceData;
+use bluetooth::{BtUuid, ServiceData};
 
 use crate::error::FpError;
 
@@ -26,8 +26,8 @@ impl FpDecoder {
     /// * Length == 3: entire payload is the model ID
     /// * Length > 3: first byte specifies the length of the model ID, in bytes.
     /// Currently unavailable in Fast Pair devices and not supported.
-    pub(crate) fn get_model_id_from_service_data<U: Copy>(
-        service_data: &ServiceData<U>,
+    pub(crate) fn get_model_id_from_service_data(
+        service_data: &ServiceData,
     ) -> Result<Vec<u8>, FpError> {
         const MIN_MODEL_ID_LENGTH: usize = 3;
         let data = service_data.data();
@@ -58,7 +58,7 @@ mod tests {
     #[test]
     fn test_get_model_id_valid() {
         // Valid scenario: Length == 3
-        let uuid: u16 = 0x1234;
+        let uuid = BtUuid::FAST_PAIR;
         let data = vec![0xAA, 0xBB, 0xCC];
         let service_data = ServiceData::new(uuid, data.clone());
         let result = FpDecoder::get_model_id_from_service_data(&service_data);
@@ -69,7 +69,7 @@ mod tests {
     #[test]
     fn test_get_model_id_invalid() {
         // Invalid scenario: Length < 3
-        let uuid: u16 = 0x1234;
+        let uuid = BtUuid::FAST_PAIR;
         let data = vec![0xAA, 0xBB];
         let service_data = ServiceData::new(uuid, data);
         let result = FpDecoder::get_model_id_from_service_data(&service_data);
@@ -80,7 +80,7 @@ mod tests {
     #[test]
     fn test_get_model_id_unsupported() {
         // Unsupported scenario: Length > 3
-        let uuid: u16 = 0x1234;
+        let uuid = BtUuid::FAST_PAIR;
         let data = vec![0xAA, 0xBB, 0xCC, 0xDD];
         let service_data = ServiceData::new(uuid, data);
         let result = FpDecoder::get_model_id_from_service_data(&service_data);